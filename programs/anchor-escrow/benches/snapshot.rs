@@ -0,0 +1,113 @@
+//! Snapshot throughput against `MockRpc`, so pipeline changes can be
+//! compared without an RPC endpoint: `cargo bench --features client`.
+//! Criterion reports mints per second; allocations per mint are printed
+//! before each size is measured.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use {
+    anchor_escrow::{
+        rpc::MockRpc,
+        snapshot::{derive_cmv2_pda, stream_nftholders, PipelineConfig, Source},
+    },
+    anchor_lang::prelude::Pubkey,
+    common::{metadata_account_data, metadata_address, mint_account_data, token_account_data},
+    criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput},
+    solana_sdk::account::Account,
+    std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+const SIZES: &[usize] = &[1_000, 10_000, 100_000];
+
+/// Counts every allocation the process makes.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// A fixed key per kind of account and index, so every run snapshots the
+/// same accounts.
+fn key(kind: u8, index: usize) -> Pubkey {
+    let mut bytes = [kind; 32];
+    bytes[..8].copy_from_slice(&(index as u64).to_le_bytes());
+    Pubkey::new_from_array(bytes)
+}
+
+/// A candy machine v2 collection of `size` items, each held by its own
+/// wallet.
+fn synthetic_collection(size: usize) -> (MockRpc, Source) {
+    let candy_machine = key(0, size);
+    let creator = derive_cmv2_pda(&candy_machine);
+    let update_authority = key(0, size + 1);
+
+    let mut rpc = MockRpc::new();
+    for index in 0..size {
+        let (mint, owner, token_account) = (key(1, index), key(2, index), key(3, index));
+        rpc.add_account(mint, Account::from(mint_account_data(&update_authority, 1, 0)));
+        rpc.add_account(
+            metadata_address(&mint),
+            Account::from(metadata_account_data(&update_authority, &mint, &creator, index)),
+        );
+        rpc.add_account(token_account, Account::from(token_account_data(&mint, &owner, 1)));
+    }
+
+    let source = Source::CandyMachine {
+        address: candy_machine.to_string(),
+        update_authority: None,
+    };
+    (rpc, source)
+}
+
+fn snapshot(rpc: &MockRpc, source: &Source) -> usize {
+    let mut holders = 0;
+    stream_nftholders(rpc, source, &PipelineConfig::default(), |_| {
+        holders += 1;
+        Ok(())
+    })
+    .unwrap();
+    holders
+}
+
+fn snapshot_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot");
+    group.sample_size(10);
+    for &size in SIZES {
+        let (rpc, source) = synthetic_collection(size);
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        assert_eq!(snapshot(&rpc, &source), size);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "snapshot/{}: {} allocations, {} per mint",
+            size,
+            allocations,
+            allocations / size
+        );
+
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| snapshot(&rpc, &source))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, snapshot_throughput);
+criterion_main!(benches);
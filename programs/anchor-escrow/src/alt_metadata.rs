@@ -0,0 +1,301 @@
+use {
+    crate::{enrich::get_multiple_accounts_chunked, metadata::parse_metadata, rpc::RpcBackend},
+    anchor_lang::prelude::Pubkey,
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    std::{convert::TryInto, str::FromStr},
+};
+
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EtRmr8rpRFsUDwkrqGzE";
+pub const INSCRIPTION_PROGRAM_ID: &str = "1NSCRfGeyo7wPUazGbaPBUsTM49e1k2aXewHGARfzSo";
+
+/// Token-2022 pads mints to the size of a token account, then stores the
+/// account type and the extensions.
+const EXTENSIONS_START: usize = 165 + 1;
+const METADATA_POINTER_EXTENSION: u16 = 18;
+const TOKEN_METADATA_EXTENSION: u16 = 19;
+
+/// Where metadata found without a Metaplex metadata account came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataSource {
+    /// Token-2022 metadata pointer, to the mint's own metadata extension or
+    /// to another account.
+    MetadataPointer,
+    /// Metaplex inscription of the item's JSON.
+    Inscription,
+}
+
+/// Name, symbol and URI of an item without a Metaplex metadata account.
+#[derive(Debug, Clone)]
+pub struct AltMetadata {
+    pub source: MetadataSource,
+    /// Account the metadata was read from.
+    pub address: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+fn trimmed(s: &str) -> String {
+    s.trim_matches(char::from(0)).trim().to_string()
+}
+
+/// The value of extension `extension_type` of a Token-2022 mint.
+fn extension(mint_data: &[u8], extension_type: u16) -> Option<&[u8]> {
+    let mut offset = EXTENSIONS_START;
+    while let Some(header) = mint_data.get(offset..offset + 4) {
+        let kind = u16::from_le_bytes(header[..2].try_into().ok()?);
+        let len = u16::from_le_bytes(header[2..].try_into().ok()?) as usize;
+        let value = mint_data.get(offset + 4..offset + 4 + len)?;
+        if kind == extension_type {
+            return Some(value);
+        }
+        // Zeroed space past the last extension.
+        if kind == 0 {
+            return None;
+        }
+        offset += 4 + len;
+    }
+    None
+}
+
+/// Where a Token-2022 mint's metadata pointer points, if it has one.
+pub fn metadata_pointer(mint_data: &[u8]) -> Option<Pubkey> {
+    let value = extension(mint_data, METADATA_POINTER_EXTENSION)?;
+    let address = Pubkey::new(value.get(32..64)?);
+    Some(address).filter(|address| *address != Pubkey::default())
+}
+
+fn read_string(data: &[u8], offset: &mut usize) -> Option<String> {
+    let len = u32::from_le_bytes(data.get(*offset..*offset + 4)?.try_into().ok()?) as usize;
+    let bytes = data.get(*offset + 4..*offset + 4 + len)?;
+    *offset += 4 + len;
+    Some(trimmed(&String::from_utf8_lossy(bytes)))
+}
+
+/// Name, symbol and URI of a Token-2022 token metadata extension, stored
+/// after its update authority and mint.
+fn parse_token_metadata(value: &[u8]) -> Option<(String, String, String)> {
+    let mut offset = 64;
+    let name = read_string(value, &mut offset)?;
+    let symbol = read_string(value, &mut offset)?;
+    let uri = read_string(value, &mut offset)?;
+    Some((name, symbol, uri))
+}
+
+/// Metadata of a Token-2022 mint whose pointer leads to the mint itself.
+/// `None` when it points elsewhere, see `resolve_pointed`.
+pub fn embedded_metadata(mint: &Pubkey, mint_data: &[u8]) -> Option<AltMetadata> {
+    if metadata_pointer(mint_data)? != *mint {
+        return None;
+    }
+    let (name, symbol, uri) =
+        parse_token_metadata(extension(mint_data, TOKEN_METADATA_EXTENSION)?)?;
+    Some(AltMetadata {
+        source: MetadataSource::MetadataPointer,
+        address: *mint,
+        name,
+        symbol,
+        uri,
+    })
+}
+
+/// Metadata at the account a metadata pointer leads to: a Metaplex metadata
+/// account, or another Token-2022 mint carrying the metadata extension.
+pub fn resolve_pointed(address: &Pubkey, data: &[u8]) -> Option<AltMetadata> {
+    let (name, symbol, uri) = match parse_metadata(data) {
+        Ok(metadata) => (
+            trimmed(&metadata.data.name),
+            trimmed(&metadata.data.symbol),
+            trimmed(&metadata.data.uri),
+        ),
+        Err(_) => parse_token_metadata(extension(data, TOKEN_METADATA_EXTENSION)?)?,
+    };
+    Some(AltMetadata {
+        source: MetadataSource::MetadataPointer,
+        address: *address,
+        name,
+        symbol,
+        uri,
+    })
+}
+
+/// Inscription account of `mint`.
+pub fn inscription_address(mint: &Pubkey) -> Pubkey {
+    let program_id = Pubkey::from_str(INSCRIPTION_PROGRAM_ID).unwrap();
+    let seeds = &[b"Inscription".as_ref(), program_id.as_ref(), mint.as_ref()];
+    Pubkey::find_program_address(seeds, &program_id).0
+}
+
+#[derive(Deserialize)]
+struct InscribedJson {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    symbol: String,
+    #[serde(default)]
+    image: String,
+}
+
+/// Metadata of an inscription holding the item's JSON. The JSON is on chain,
+/// so the image stands in for the URI.
+pub fn parse_inscription(address: &Pubkey, data: &[u8]) -> Option<AltMetadata> {
+    let json: InscribedJson = serde_json::from_slice(data).ok()?;
+    if json.name.is_empty() {
+        return None;
+    }
+    Some(AltMetadata {
+        source: MetadataSource::Inscription,
+        address: *address,
+        name: trimmed(&json.name),
+        symbol: trimmed(&json.symbol),
+        uri: json.image,
+    })
+}
+
+/// Metadata of each of `mints` (address and account data) lacking a
+/// Metaplex metadata account: from a Token-2022 metadata pointer, else from
+/// an inscription. `None` for mints with neither.
+pub fn resolve_alt_metadata(
+    client: &dyn RpcBackend,
+    mints: &[(Pubkey, &[u8])],
+) -> Result<Vec<Option<AltMetadata>>> {
+    // One lookup per mint whose metadata is not embedded: the account the
+    // pointer leads to, or the inscription.
+    let lookups: Vec<Option<(Pubkey, bool)>> = mints
+        .iter()
+        .map(|(mint, data)| match metadata_pointer(data) {
+            Some(address) if address == *mint => None,
+            Some(address) => Some((address, true)),
+            None => Some((inscription_address(mint), false)),
+        })
+        .collect();
+    let addresses: Vec<Pubkey> = lookups
+        .iter()
+        .flatten()
+        .map(|(address, _)| *address)
+        .collect();
+    let mut fetched = get_multiple_accounts_chunked(client, &addresses)?.into_iter();
+
+    let mut resolved = Vec::with_capacity(mints.len());
+    for ((mint, data), lookup) in mints.iter().zip(lookups) {
+        let metadata = match lookup {
+            None => embedded_metadata(mint, data),
+            Some((address, pointed)) => match fetched.next().flatten() {
+                Some(account) if pointed => resolve_pointed(&address, &account),
+                Some(account) => parse_inscription(&address, &account),
+                None => None,
+            },
+        };
+        resolved.push(metadata);
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::rpc::MockRpc, solana_sdk::account::Account};
+
+    /// A Token-2022 mint with the given extensions, past a zeroed base.
+    fn mint_data(extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0; EXTENSIONS_START - 1];
+        data.push(1);
+        for (kind, value) in extensions {
+            data.extend_from_slice(&kind.to_le_bytes());
+            data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            data.extend_from_slice(value);
+        }
+        data
+    }
+
+    fn pointer(address: &Pubkey) -> (u16, Vec<u8>) {
+        let mut value = vec![0; 32];
+        value.extend_from_slice(address.as_ref());
+        (METADATA_POINTER_EXTENSION, value)
+    }
+
+    fn token_metadata(mint: &Pubkey, name: &str) -> (u16, Vec<u8>) {
+        let mut value = vec![0; 32];
+        value.extend_from_slice(mint.as_ref());
+        for field in &[name, "SYM\0", "https://example.com/1.json"] {
+            value.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            value.extend_from_slice(field.as_bytes());
+        }
+        (TOKEN_METADATA_EXTENSION, value)
+    }
+
+    #[test]
+    fn reads_embedded_metadata() {
+        let mint = Pubkey::new_unique();
+        let data = mint_data(&[pointer(&mint), token_metadata(&mint, "Item #1")]);
+        let metadata = embedded_metadata(&mint, &data).unwrap();
+        assert_eq!(metadata.source, MetadataSource::MetadataPointer);
+        assert_eq!(metadata.address, mint);
+        assert_eq!(metadata.name, "Item #1");
+        assert_eq!(metadata.symbol, "SYM");
+        assert_eq!(metadata.uri, "https://example.com/1.json");
+    }
+
+    #[test]
+    fn follows_pointers_elsewhere() {
+        let (mint, elsewhere) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = mint_data(&[pointer(&elsewhere)]);
+        assert_eq!(metadata_pointer(&data), Some(elsewhere));
+        assert!(embedded_metadata(&mint, &data).is_none());
+
+        let pointed = mint_data(&[token_metadata(&elsewhere, "Item #2")]);
+        let metadata = resolve_pointed(&elsewhere, &pointed).unwrap();
+        assert_eq!(
+            (metadata.address, metadata.name.as_str()),
+            (elsewhere, "Item #2")
+        );
+    }
+
+    #[test]
+    fn ignores_unset_pointers_and_plain_mints() {
+        assert_eq!(
+            metadata_pointer(&mint_data(&[pointer(&Pubkey::default())])),
+            None
+        );
+        assert_eq!(metadata_pointer(&[0; 82]), None);
+    }
+
+    #[test]
+    fn parses_inscriptions() {
+        let address = Pubkey::new_unique();
+        let json = br#"{"name": "Inscribed #3", "symbol": "INS", "image": "data:image/png"}"#;
+        let metadata = parse_inscription(&address, json).unwrap();
+        assert_eq!(metadata.source, MetadataSource::Inscription);
+        assert_eq!(metadata.name, "Inscribed #3");
+        assert_eq!(metadata.uri, "data:image/png");
+
+        assert!(parse_inscription(&address, br#"{"symbol": "INS"}"#).is_none());
+        assert!(parse_inscription(&address, b"\x89PNG").is_none());
+    }
+
+    #[test]
+    fn resolves_inscriptions_of_mints_without_pointer() {
+        let (inscribed, bare) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut rpc = MockRpc::new();
+        rpc.add_account(
+            inscription_address(&inscribed),
+            Account {
+                lamports: 1,
+                data: br#"{"name": "Inscribed"}"#.to_vec(),
+                owner: Pubkey::from_str(INSCRIPTION_PROGRAM_ID).unwrap(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+
+        let plain: &[u8] = &[0; 82];
+        let resolved = resolve_alt_metadata(&rpc, &[(inscribed, plain), (bare, plain)]).unwrap();
+        assert_eq!(resolved[0].as_ref().unwrap().name, "Inscribed");
+        assert_eq!(
+            resolved[0].as_ref().unwrap().address,
+            inscription_address(&inscribed)
+        );
+        assert!(resolved[1].is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use {
+    crate::snapshot::Holder,
+    serde::Serialize,
+    std::collections::{BTreeMap, HashMap},
+};
+
+/// Ownership changes between two consecutive snapshots.
+#[derive(Debug, Serialize)]
+pub struct Turnover {
+    pub from: usize,
+    pub to: usize,
+    /// Mints present in both snapshots.
+    pub supply: usize,
+    pub changed_hands: usize,
+    pub percent: f64,
+}
+
+/// A wallet that got back a mint it had already given away, across the
+/// snapshots analyzed.
+#[derive(Debug, Serialize)]
+pub struct CyclingWallet {
+    pub wallet: String,
+    /// Number of times a mint came back to the wallet.
+    pub cycles: usize,
+    pub mints: Vec<String>,
+    /// Wallets that held the mints in between.
+    pub counterparties: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyticsReport {
+    pub snapshots: usize,
+    pub turnover: Vec<Turnover>,
+    pub cycling_wallets: Vec<CyclingWallet>,
+}
+
+fn owners_by_mint(holders: &[Holder]) -> HashMap<&str, &str> {
+    holders
+        .iter()
+        .map(|h| (h.mint_account.as_str(), h.owner_wallet.as_str()))
+        .collect()
+}
+
+pub fn turnover(from: usize, before: &[Holder], after: &[Holder]) -> Turnover {
+    let before = owners_by_mint(before);
+    let after = owners_by_mint(after);
+
+    let mut supply = 0;
+    let mut changed_hands = 0;
+    for (mint, owner) in &after {
+        if let Some(previous) = before.get(mint) {
+            supply += 1;
+            if previous != owner {
+                changed_hands += 1;
+            }
+        }
+    }
+
+    Turnover {
+        from,
+        to: from + 1,
+        supply,
+        changed_hands,
+        percent: if supply == 0 {
+            0.0
+        } else {
+            changed_hands as f64 * 100.0 / supply as f64
+        },
+    }
+}
+
+/// Flags wallets that a mint returns to, e.g. A -> B -> A, at least
+/// `min_cycles` times. Snapshots must be in chronological order.
+pub fn cycling_wallets(snapshots: &[Vec<Holder>], min_cycles: usize) -> Vec<CyclingWallet> {
+    let mut histories: HashMap<&str, Vec<&str>> = HashMap::new();
+    for holders in snapshots {
+        for holder in holders {
+            let history = histories.entry(holder.mint_account.as_str()).or_default();
+            if history.last() != Some(&holder.owner_wallet.as_str()) {
+                history.push(holder.owner_wallet.as_str());
+            }
+        }
+    }
+
+    let mut flagged: BTreeMap<&str, CyclingWallet> = BTreeMap::new();
+    for (mint, history) in &histories {
+        for (index, owner) in history.iter().enumerate() {
+            let returned = match history[index + 1..].iter().position(|o| o == owner) {
+                Some(offset) => index + 1 + offset,
+                None => continue,
+            };
+            let entry = flagged.entry(*owner).or_insert_with(|| CyclingWallet {
+                wallet: owner.to_string(),
+                cycles: 0,
+                mints: Vec::new(),
+                counterparties: Vec::new(),
+            });
+            entry.cycles += 1;
+            if !entry.mints.iter().any(|m| m == mint) {
+                entry.mints.push(mint.to_string());
+            }
+            for counterparty in &history[index + 1..returned] {
+                if !entry.counterparties.iter().any(|c| c == counterparty) {
+                    entry.counterparties.push(counterparty.to_string());
+                }
+            }
+        }
+    }
+
+    let mut wallets: Vec<CyclingWallet> = flagged
+        .into_values()
+        .filter(|wallet| wallet.cycles >= min_cycles)
+        .collect();
+    wallets.sort_by(|a, b| b.cycles.cmp(&a.cycles));
+    wallets
+}
+
+/// Turnover between each pair of consecutive snapshots and the wallets
+/// cycling mints across all of them.
+pub fn analyze(snapshots: &[Vec<Holder>], min_cycles: usize) -> AnalyticsReport {
+    AnalyticsReport {
+        snapshots: snapshots.len(),
+        turnover: snapshots
+            .windows(2)
+            .enumerate()
+            .map(|(index, pair)| turnover(index, &pair[0], &pair[1]))
+            .collect(),
+        cycling_wallets: cycling_wallets(snapshots, min_cycles),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::snapshot::test_holder, serde_json::json};
+
+    fn snapshot(owners: &[(&str, &str)]) -> Vec<Holder> {
+        owners
+            .iter()
+            .map(|(mint, owner)| test_holder(json!({"mint_account": mint, "owner_wallet": owner})))
+            .collect()
+    }
+
+    #[test]
+    fn turnover_counts_only_mints_in_both_snapshots() {
+        let before = snapshot(&[("kept", "a"), ("sold", "a"), ("burned", "a")]);
+        let after = snapshot(&[("kept", "a"), ("sold", "b"), ("minted", "c")]);
+        let changes = turnover(4, &before, &after);
+        assert_eq!((changes.from, changes.to), (4, 5));
+        assert_eq!(changes.supply, 2);
+        assert_eq!(changes.changed_hands, 1);
+        assert!((changes.percent - 50.0).abs() < f64::EPSILON);
+
+        let disjoint = turnover(0, &before, &snapshot(&[("other", "a")]));
+        assert_eq!(disjoint.supply, 0);
+        assert!(disjoint.percent.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn turnover_is_measured_between_consecutive_snapshots() {
+        let snapshots = [
+            snapshot(&[("mint", "a")]),
+            snapshot(&[("mint", "b")]),
+            snapshot(&[("mint", "b")]),
+        ];
+        let report = analyze(&snapshots, 1);
+        let windows: Vec<_> = report.turnover.iter().map(|t| (t.from, t.changed_hands)).collect();
+        assert_eq!(windows, [(0, 1), (1, 0)]);
+        assert!(analyze(&snapshots[..1], 1).turnover.is_empty());
+        assert!(analyze(&[], 1).turnover.is_empty());
+    }
+
+    #[test]
+    fn wallets_selling_and_buying_back_are_flagged() {
+        let snapshots: Vec<Vec<Holder>> = ["a", "b", "b", "a", "c", "a"]
+            .iter()
+            .map(|owner| snapshot(&[("mint", owner), ("held", "d")]))
+            .collect();
+        let wallets = cycling_wallets(&snapshots, 1);
+        assert_eq!(wallets.len(), 1);
+        assert_eq!(wallets[0].wallet, "a");
+        assert_eq!(wallets[0].cycles, 2);
+        assert_eq!(wallets[0].mints, ["mint"]);
+        assert_eq!(wallets[0].counterparties, ["b", "c"]);
+
+        assert!(cycling_wallets(&snapshots, 3).is_empty());
+        assert!(cycling_wallets(&snapshots[..3], 1).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use {
+    crate::{
+        config::OutputConfig, encryption::sealed_path, output::write_sealed_json,
+        snapshot::Holder,
+    },
+    anyhow::Result,
+    rand::RngCore,
+    serde::{Deserialize, Serialize},
+    solana_sdk::hash::hashv,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// A fresh random salt, hex encoded.
+pub fn random_salt() -> String {
+    let mut salt = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Salted SHA-256 of `value`, shortened to 128 bits. Without the salt the
+/// original address cannot be recovered by hashing candidate wallets.
+pub fn pseudonym(salt: &str, value: &str) -> String {
+    let hash = hashv(&[salt.as_bytes(), value.as_bytes()]);
+    hash.as_ref()[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Whether `value` has the shape of a `pseudonym`, 32 lowercase hex digits.
+pub fn is_pseudonym(value: &str) -> bool {
+    value.len() == 32 && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Kept apart from the published output; it reverses the pseudonyms.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PseudonymMapping {
+    pub salt: String,
+    /// Pseudonym to wallet.
+    pub wallets: BTreeMap<String, String>,
+}
+
+/// Replaces every address on the holders with a pseudonym. Mints and token
+/// accounts are hashed too, as either leads back to the owner on chain, and
+/// enrichment fields are left unset for the same reason: names tell the
+/// items apart as well as their mints, and the exact holding time identifies
+/// the transfer. Holding counts per pseudonym stay intact.
+pub fn pseudonymize(salt: &str, holders: &[Holder]) -> (Vec<Holder>, PseudonymMapping) {
+    let mut wallets = BTreeMap::new();
+    let holders = holders
+        .iter()
+        .map(|holder| {
+            let owner = pseudonym(salt, &holder.owner_wallet);
+            wallets.insert(owner.clone(), holder.owner_wallet.clone());
+            Holder {
+                owner_wallet: owner,
+                associated_token_address: pseudonym(salt, &holder.associated_token_address),
+                mint_account: pseudonym(salt, &holder.mint_account),
+                metadata_account: pseudonym(salt, &holder.metadata_account),
+                frozen: holder.frozen,
+                owner_kind: holder.owner_kind,
+                holding_days: holder.holding_days,
+                multisig: holder.multisig.as_ref().map(|multisig| pseudonym(salt, multisig)),
+                master_edition: holder.master_edition.as_ref().map(|m| pseudonym(salt, m)),
+                edition_number: holder.edition_number,
+                amount: holder.amount.clone(),
+                resolved_by: holder.resolved_by,
+                listed: holder.listed,
+                listed_with: holder.listed_with.clone(),
+                image: holder.image.clone(),
+                metadata_sha256: holder.metadata_sha256.clone(),
+                metadata_changed: holder.metadata_changed,
+                custodian: holder.custodian.clone(),
+                custodian_country: holder.custodian_country.clone(),
+                cluster: holder.cluster.clone(),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    let mapping = PseudonymMapping {
+        salt: salt.to_string(),
+        wallets,
+    };
+    (holders, mapping)
+}
+
+/// Writes the mapping next to `output`, the snapshot path before any
+/// `.age`, as `<output>.mapping.json`. It reverses the pseudonyms, so it is
+/// encrypted to the recipients of `config` like the snapshot, if it has any.
+/// Returns the path written.
+pub fn write_mapping(
+    output: &Path,
+    config: &OutputConfig,
+    mapping: &PseudonymMapping,
+) -> Result<PathBuf> {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".mapping.json");
+    let path = sealed_path(Path::new(&path), &config.encrypt_to);
+    write_sealed_json(&path, config, mapping)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::snapshot::test_holder, serde_json::json, std::fs::File};
+
+    #[test]
+    fn pseudonyms_are_stable_for_a_salt_only() {
+        let salt = random_salt();
+        assert_eq!(pseudonym(&salt, "wallet"), pseudonym(&salt, "wallet"));
+        assert_ne!(pseudonym(&salt, "wallet"), pseudonym(&salt, "other"));
+        assert_ne!(pseudonym(&salt, "wallet"), pseudonym(&random_salt(), "wallet"));
+        assert!(is_pseudonym(&pseudonym(&salt, "wallet")));
+        assert!(!is_pseudonym("wallet"));
+    }
+
+    #[test]
+    fn holders_keep_their_counts_but_not_their_addresses() {
+        let holders = [
+            test_holder(json!({"mint_account": "one", "name": "Item #1"})),
+            test_holder(json!({"mint_account": "two", "holding_since": 1_650_000_000})),
+        ];
+        let (pseudonymized, mapping) = pseudonymize("salt", &holders);
+        assert_eq!(pseudonymized[0].owner_wallet, pseudonymized[1].owner_wallet);
+        assert_ne!(pseudonymized[0].mint_account, pseudonymized[1].mint_account);
+        assert_eq!(pseudonymized[0].name, None);
+        assert_eq!(pseudonymized[1].holding_since, None);
+        assert_eq!(mapping.wallets[&pseudonymized[0].owner_wallet], "owner");
+    }
+
+    #[test]
+    fn mappings_are_read_back_as_written() {
+        let (_, mapping) = pseudonymize("salt", &[test_holder(json!({}))]);
+        let output = std::env::temp_dir().join(format!("mapping-{}.json", std::process::id()));
+        let path = write_mapping(&output, &OutputConfig::default(), &mapping).unwrap();
+        let read: PseudonymMapping = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(path.to_string_lossy().ends_with(".json.mapping.json"));
+        assert_eq!(read.salt, mapping.salt);
+        assert_eq!(read.wallets, mapping.wallets);
+    }
+}
@@ -0,0 +1,240 @@
+use {
+    crate::{
+        das::{AssetPage, AssetProof},
+        http_sender::response_error,
+        limiter::RateLimiter,
+        metrics::RpcMetrics,
+        rpc::RpcBackend,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    reqwest::{blocking::Client, header::CONTENT_TYPE, StatusCode},
+    serde_json::{json, Value},
+    solana_client::{
+        client_error::ClientError,
+        rpc_client::RpcClient,
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_response::{RpcConfirmedTransactionStatusWithSignature, RpcKeyedAccount},
+    },
+    solana_sdk::{account::Account, hash::Hash, signature::Signature},
+    solana_transaction_status::EncodedConfirmedTransaction,
+    std::{
+        str::FromStr,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    },
+};
+
+/// Sends batched getProgramAccounts queries as JSON-RPC batch requests of up
+/// to `batch_size` calls each, saving a round trip per query. Everything
+/// else goes through `rpc` one call at a time. `client` should be the one
+/// `rpc` sends through, so both share a connection pool.
+pub struct BatchingRpc {
+    pub rpc: RpcClient,
+    pub batch_size: usize,
+    /// Shared with `rpc`'s sender; every response is reported to it.
+    pub limiter: RateLimiter,
+    client: Client,
+    url: String,
+    request_id: AtomicU64,
+    metrics: Arc<RpcMetrics>,
+}
+
+impl BatchingRpc {
+    pub fn new(
+        rpc: RpcClient,
+        client: Client,
+        url: String,
+        metrics: Arc<RpcMetrics>,
+        limiter: RateLimiter,
+        batch_size: usize,
+    ) -> Self {
+        BatchingRpc {
+            rpc,
+            batch_size,
+            limiter,
+            client,
+            url,
+            request_id: AtomicU64::new(0),
+            metrics,
+        }
+    }
+
+    fn send_batch(
+        &self,
+        program_id: &Pubkey,
+        configs: Vec<RpcProgramAccountsConfig>,
+    ) -> Result<Vec<Result<Vec<(Pubkey, Account)>>>> {
+        let first_id = self.request_id.fetch_add(configs.len() as u64, Ordering::Relaxed);
+        let requests: Vec<Value> = configs
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut config)| {
+                // The commitment `rpc` would fill in when sending it alone.
+                config.account_config.commitment.get_or_insert(self.rpc.commitment());
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": first_id + index as u64,
+                    "method": "getProgramAccounts",
+                    "params": [program_id.to_string(), config],
+                })
+            })
+            .collect();
+        let count = requests.len();
+
+        // Wrapped like the stock sender's errors, so a 429 is classified as
+        // rate limiting and retried accordingly.
+        let response = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Value::Array(requests).to_string())
+            .send()
+            .map_err(ClientError::from)?;
+        let status = response.status();
+        self.limiter.observe(status, response.headers());
+        let response = response.error_for_status().map_err(ClientError::from)?;
+        let text = response.text().map_err(ClientError::from)?;
+        self.metrics.record("getProgramAccounts", count as u64, text.len() as u64);
+        let responses: Vec<Value> = serde_json::from_str(&text)?;
+
+        // Servers may answer a batch in any order.
+        let mut results: Vec<Option<Result<Vec<(Pubkey, Account)>>>> =
+            (0..count).map(|_| None).collect();
+        for response in responses {
+            let index = response["id"]
+                .as_u64()
+                .and_then(|id| id.checked_sub(first_id))
+                .map(|index| index as usize)
+                .filter(|index| *index < count)
+                .ok_or(anyhow!("Unexpected id in batch response: {}", response["id"]))?;
+            results[index] = Some(decode_call(status, response));
+        }
+
+        let missing = || Err(anyhow!("Batch response is missing the call!"));
+        Ok(results
+            .into_iter()
+            .map(|accounts| accounts.unwrap_or_else(missing))
+            .collect())
+    }
+}
+
+/// The accounts one call of a batch found. Errors are wrapped like the stock
+/// sender's, so each call's is classified, and retried, on its own.
+fn decode_call(status: StatusCode, mut response: Value) -> Result<Vec<(Pubkey, Account)>> {
+    if let Some(err) = response_error(status, &response.to_string()) {
+        return Err(ClientError::from(err).into());
+    }
+    let keyed: Vec<RpcKeyedAccount> =
+        serde_json::from_value(response["result"].take()).map_err(ClientError::from)?;
+    keyed
+        .into_iter()
+        .map(|RpcKeyedAccount { pubkey, account }| {
+            let decoded = account
+                .decode()
+                .ok_or(anyhow!("Account {} could not be decoded!", pubkey))?;
+            Ok((Pubkey::from_str(&pubkey)?, decoded))
+        })
+        .collect()
+}
+
+impl RpcBackend for BatchingRpc {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        RpcBackend::get_program_accounts_with_config(&self.rpc, program_id, config)
+    }
+
+    fn get_program_accounts_batch(
+        &self,
+        program_id: &Pubkey,
+        configs: Vec<RpcProgramAccountsConfig>,
+    ) -> Result<Vec<Result<Vec<(Pubkey, Account)>>>> {
+        if self.batch_size <= 1 {
+            return Ok(configs
+                .into_iter()
+                .map(|config| self.get_program_accounts_with_config(program_id, config))
+                .collect());
+        }
+
+        let mut results = Vec::with_capacity(configs.len());
+        for chunk in configs.chunks(self.batch_size) {
+            results.extend(self.send_batch(program_id, chunk.to_vec())?);
+        }
+        Ok(results)
+    }
+
+    fn batch_size(&self) -> usize {
+        self.batch_size.max(1)
+    }
+
+    fn metrics(&self) -> Option<&RpcMetrics> {
+        Some(&self.metrics)
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        RpcBackend::get_multiple_accounts(&self.rpc, pubkeys)
+    }
+
+    fn get_slot(&self) -> Result<u64> {
+        RpcBackend::get_slot(&self.rpc)
+    }
+
+    fn get_genesis_hash(&self) -> Result<Hash> {
+        RpcBackend::get_genesis_hash(&self.rpc)
+    }
+
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        RpcBackend::get_signatures_for_address(&self.rpc, address, before, limit)
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction> {
+        RpcBackend::get_transaction(&self.rpc, signature)
+    }
+
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof> {
+        RpcBackend::get_asset_proof(&self.rpc, asset_id)
+    }
+
+    fn get_assets_by_collection(&self, collection: &Pubkey, page: usize) -> Result<AssetPage> {
+        RpcBackend::get_assets_by_collection(&self.rpc, collection, page)
+    }
+
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+        RpcBackend::get_token_largest_accounts(&self.rpc, mint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::retry_policy::{classify, FailureClass},
+    };
+
+    #[test]
+    fn calls_fail_on_their_own_terms() {
+        let unhealthy = json!({"jsonrpc": "2.0", "id": 0, "error": {
+            "code": -32005, "message": "Node is behind", "data": {"numSlotsBehind": 3}}});
+        let err = decode_call(StatusCode::OK, unhealthy).unwrap_err();
+        assert_eq!(classify(&err), FailureClass::ServerError);
+
+        let invalid = json!({"jsonrpc": "2.0", "id": 1, "error": {
+            "code": -32602, "message": "Invalid param"}});
+        let err = decode_call(StatusCode::OK, invalid).unwrap_err();
+        assert_eq!(classify(&err), FailureClass::Other);
+        assert!(err.to_string().contains("Invalid param"));
+
+        let found = json!({"jsonrpc": "2.0", "id": 2, "result": []});
+        assert!(decode_call(StatusCode::OK, found).unwrap().is_empty());
+    }
+}
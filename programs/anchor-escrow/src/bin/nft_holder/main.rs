@@ -0,0 +1,351 @@
+mod opt;
+mod process;
+mod status;
+#[cfg(feature = "tui")]
+mod tui;
+
+use {
+    anchor_escrow::{
+        batch::BatchingRpc,
+        client::ProgramClient,
+        config::Config,
+        http_sender::{http_client, rpc_client},
+        limiter::RateLimiter,
+        metrics::{RpcMetrics, UsageReport},
+        snapshot::HolderMode,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    log::LevelFilter,
+    opt::{Command, Opt, ProgramSubcommands, SigningOpt, WalletSubcommands},
+    process::{
+        process_broadcast, process_close_empty, process_fixtures, process_mint, process_program,
+        process_snapshot, process_wallet,
+    },
+    solana_clap_utils::keypair::signer_from_path,
+    solana_client::rpc_client::RpcClient,
+    solana_remote_wallet::remote_wallet::maybe_wallet_manager,
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        hash::Hash,
+        signature::{Signature, Signer},
+    },
+    status::ExitStatus,
+    std::{io, process, str::FromStr, sync::Arc},
+    structopt::{clap::ArgMatches, StructOpt},
+};
+
+#[cfg(feature = "tui")]
+use {
+    anchor_escrow::progress::Progress,
+    tui::{Dashboard, LogBuffer},
+};
+
+fn apply_overrides(config: &mut Config, opt: &Opt) {
+    if let Some(rpc) = &opt.rpc {
+        config.rpc.url = Some(rpc.clone());
+    }
+    if let Some(commitment) = opt.commitment {
+        config.rpc.commitment = commitment;
+    }
+    if let Some(keypair) = &opt.keypair {
+        config.keypair = Some(keypair.clone());
+    }
+    if let Some(timeout) = opt.timeout {
+        config.rpc.connection.timeout_secs = timeout;
+    }
+    if let Some(connect_timeout) = opt.connect_timeout {
+        config.rpc.connection.connect_timeout_secs = connect_timeout;
+    }
+    if let Some(keepalive) = opt.keepalive {
+        config.rpc.connection.tcp_keepalive_secs = Some(keepalive).filter(|secs| *secs > 0);
+    }
+    if let Some(rate_limit) = opt.rate_limit {
+        config.rpc.rate_limit = rate_limit;
+    }
+    if let Some(batch_size) = opt.batch_size {
+        config.rpc.batch_size = batch_size;
+    }
+    if let Some(workers) = opt.workers {
+        config.snapshot.pipeline.workers = workers;
+    }
+    if let Some(frozen) = opt.frozen {
+        config.filters.frozen = frozen;
+    }
+    if let Some(custodial) = opt.custodial {
+        config.filters.custodial = custodial;
+    }
+    if opt.quarantine_spoofs {
+        config.snapshot.pipeline.quarantine_spoofs = true;
+    }
+    if opt.first_minter {
+        config.snapshot.pipeline.mode = HolderMode::FirstMinter;
+    }
+    if let Some(resolve) = opt.resolve {
+        config.snapshot.pipeline.resolve = resolve;
+    }
+    if opt.resized_fallback {
+        config.snapshot.pipeline.resized_fallback = true;
+    }
+    if opt.pseudonymize {
+        config.output.pseudonymize = true;
+    }
+    if opt.group_by_owner {
+        config.output.group_by_owner = true;
+    }
+    if opt.group_by_edition {
+        config.output.group_by_edition = true;
+        config.enrich.editions = true;
+    }
+    if opt.cluster_in_name {
+        config.output.cluster_in_name = true;
+    }
+    config.output.encrypt_to.extend(opt.encrypt_to.iter().cloned());
+    if opt.enrich_owners {
+        config.enrich.owners = true;
+    }
+    if opt.enrich_sol_domains {
+        config.enrich.sol_domains = true;
+    }
+    if opt.enrich_hold_duration {
+        config.enrich.hold_duration = true;
+    }
+    if opt.enrich_names {
+        config.enrich.names = true;
+    }
+    if opt.enrich_editions {
+        config.enrich.editions = true;
+    }
+    if opt.enrich_listings {
+        config.enrich.listings = true;
+    }
+    if opt.enrich_images {
+        config.enrich.images = true;
+    }
+    if opt.enrich_metadata_hashes {
+        config.enrich.metadata_hashes = true;
+    }
+    if let Some(baseline) = &opt.metadata_baseline {
+        config.enrich.metadata_hashes = true;
+        config.enrich.metadata_baseline = Some(baseline.clone());
+    }
+    if let Some(multisig) = opt.multisig {
+        config.enrich.multisig = multisig;
+    }
+}
+
+fn init_logger(level: Option<LevelFilter>) {
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = level {
+        logger.filter_level(level);
+    }
+    logger.init();
+}
+
+fn print_usage(report: &UsageReport) {
+    for (method, usage) in &report.methods {
+        println!(
+            "{}: {} calls, {:.2} MB, {:.1} credits",
+            method,
+            usage.calls,
+            usage.bytes as f64 / (1024.0 * 1024.0),
+            usage.credits
+        );
+    }
+    println!(
+        "RPC total: {} calls, {:.2} MB, {:.1} credits",
+        report.calls,
+        report.bytes as f64 / (1024.0 * 1024.0),
+        report.credits
+    );
+}
+
+/// Accepts anything `solana-keygen` does: a keypair file, `usb://ledger`
+/// for a hardware wallet, or `prompt://` to enter a seed phrase.
+fn resolve_signer(matches: &ArgMatches, path: &str) -> Result<Box<dyn Signer>> {
+    let path = shellexpand::tilde(path).to_string();
+    let mut wallet_manager = maybe_wallet_manager()?;
+    signer_from_path(matches, &path, "keypair", &mut wallet_manager)
+        .map_err(|e| anyhow!("Failed to load signer {}: {}", path, e))
+}
+
+fn program_client(
+    matches: &ArgMatches,
+    signing: &SigningOpt,
+    config: &Config,
+    rpc: RpcClient,
+) -> Result<ProgramClient> {
+    // Checked here as `signer_from_path` panics on malformed ones.
+    for signer in &signing.signer {
+        match signer.split_once('=') {
+            Some((pubkey, signature))
+                if Pubkey::from_str(pubkey).is_ok() && Signature::from_str(signature).is_ok() => {}
+            _ => return Err(anyhow!("Signer {} is not PUBKEY=SIGNATURE!", signer)),
+        }
+    }
+    let signer = resolve_signer(matches, &config.keypair_path())?;
+    let mut client = ProgramClient::new(rpc, signer);
+    client.retry_policy = config.rpc.retry.clone();
+    client.nonce_accounts = signing
+        .nonce
+        .iter()
+        .map(|nonce| Pubkey::from_str(nonce))
+        .collect::<Result<_, _>>()?;
+    if let Some(authority) = &signing.nonce_authority {
+        client.nonce_authority = Some(resolve_signer(matches, authority)?);
+    }
+    client.sign_only = signing.sign_only.clone();
+    client.blockhashes = signing
+        .blockhash
+        .iter()
+        .map(|blockhash| Hash::from_str(blockhash))
+        .collect::<Result<_, _>>()?;
+    Ok(client)
+}
+
+fn run(matches: &ArgMatches, opt: Opt) -> Result<()> {
+    #[cfg(feature = "tui")]
+    let logs = match opt.tui {
+        true => Some(LogBuffer::install(opt.log_level.unwrap_or(LevelFilter::Info))?),
+        false => None,
+    };
+    #[cfg(feature = "tui")]
+    if logs.is_none() {
+        init_logger(opt.log_level);
+    }
+    #[cfg(not(feature = "tui"))]
+    init_logger(opt.log_level);
+
+    if let Command::Completions { shell } = opt.cmd {
+        Opt::clap().gen_completions_to("nft-holder", shell, &mut io::stdout());
+        return Ok(());
+    }
+
+    let mut config = match &opt.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    apply_overrides(&mut config, &opt);
+
+    // One pooled HTTP client, tally and rate limit for all RPC traffic.
+    let http = http_client(&config.rpc.connection);
+    let metrics = Arc::new(RpcMetrics::new());
+    let limiter = RateLimiter::new(config.rpc.rate_limit);
+    let rpc = rpc_client(
+        http.clone(),
+        config.rpc_url(),
+        metrics.clone(),
+        limiter.clone(),
+        CommitmentConfig {
+            commitment: config.rpc.commitment,
+        },
+    );
+    let costs = config.rpc.costs.clone();
+    let confirm_above = match opt.signing.yes {
+        true => None,
+        false => Some(config.admin.confirm_above_sol),
+    };
+
+    let result = match opt.cmd {
+        Command::Program {
+            program_subcommands: ProgramSubcommands::Broadcast { file },
+        } => process_broadcast(&rpc, &file, opt.json),
+        Command::Program {
+            program_subcommands,
+        } => {
+            let client = program_client(matches, &opt.signing, &config, rpc)?;
+            process_program(&client, program_subcommands, confirm_above, opt.json)
+        }
+        Command::Snapshot {
+            snapshot_subcommands,
+        } => {
+            let rpc = BatchingRpc::new(
+                rpc,
+                http,
+                config.rpc_url(),
+                metrics.clone(),
+                limiter,
+                config.rpc.batch_size,
+            );
+            #[cfg(feature = "tui")]
+            let dashboard = match &logs {
+                Some(logs) => {
+                    let progress = Arc::new(Progress::new());
+                    config.snapshot.pipeline.progress = Some(progress.clone());
+                    Some(Dashboard::start(progress, metrics.clone(), logs.clone())?)
+                }
+                None => None,
+            };
+            let result = process_snapshot(rpc, config, snapshot_subcommands, opt.json);
+            #[cfg(feature = "tui")]
+            if let Some(dashboard) = dashboard {
+                dashboard.stop()?;
+            }
+            result
+        }
+        Command::Wallet {
+            wallet_subcommands: WalletSubcommands::CloseEmpty { report, dry_run },
+        } => {
+            let client = program_client(matches, &opt.signing, &config, rpc)?;
+            process_close_empty(&client, &report, dry_run, confirm_above, opt.json)
+        }
+        Command::Wallet {
+            wallet_subcommands,
+        } => {
+            let rpc = BatchingRpc::new(
+                rpc,
+                http,
+                config.rpc_url(),
+                metrics.clone(),
+                limiter,
+                config.rpc.batch_size,
+            );
+            process_wallet(rpc, config, opt.config.as_deref(), wallet_subcommands, opt.json)
+        }
+        Command::Mint {
+            mint_subcommands,
+        } => {
+            let rpc = BatchingRpc::new(
+                rpc,
+                http,
+                config.rpc_url(),
+                metrics.clone(),
+                limiter,
+                config.rpc.batch_size,
+            );
+            process_mint(rpc, config, mint_subcommands, opt.json)
+        }
+        Command::Fixtures {
+            fixtures_subcommands,
+        } => {
+            let client = program_client(matches, &opt.signing, &config, rpc)?;
+            process_fixtures(&client, fixtures_subcommands, confirm_above, opt.json)
+        }
+        Command::Completions { .. } => unreachable!(),
+    };
+
+    // Also printed for failed runs, which cost credits all the same.
+    let report = metrics.report(&costs);
+    if report.calls > 0 && opt.json {
+        // Kept off stdout, which holds the command's report.
+        eprintln!("{}", serde_json::to_string(&report)?);
+    } else if report.calls > 0 {
+        print_usage(&report);
+    }
+    result
+}
+
+/// Runs the command, then ends with a JSON `ExitStatus` line on stderr and
+/// the matching exit code, see `ExitCode`.
+fn main() {
+    let matches = Opt::clap().get_matches();
+    let opt = Opt::from_clap(&matches);
+
+    let result = run(&matches, opt);
+    if let Err(err) = &result {
+        eprintln!("Error: {:?}", err);
+    }
+    let status = ExitStatus::new(&result);
+    eprintln!("{}", serde_json::to_string(&status).unwrap());
+    process::exit(status.exit_code);
+}
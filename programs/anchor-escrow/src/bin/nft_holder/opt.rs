@@ -0,0 +1,723 @@
+use {
+    anchor_escrow::{
+        collections::SplitBy,
+        config::{CustodialPolicy, FrozenPolicy},
+        enrich::MultisigPolicy,
+        output::{Compression, Explorer, OutputFormat},
+        snapshot::ResolveMethod,
+    },
+    log::LevelFilter,
+    solana_sdk::commitment_config::CommitmentLevel,
+    structopt::{clap::Shell, StructOpt},
+};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "nft-holder",
+    about = "NFT holder snapshots and spin wheel administration",
+    after_help = "EXIT CODES:
+    0  success
+    1  other failure, e.g. a bad config file
+    2  RPC failure
+    3  partial results: output written, items or collections missing
+    4  invalid collection, e.g. no metadata lists the creator
+    5  rate limited by the RPC provider
+
+The last line on stderr is a JSON status object with the same information."
+)]
+pub struct Opt {
+    /// TOML, YAML or JSON config file; flags override its values
+    #[structopt(long, global = true)]
+    pub config: Option<String>,
+
+    /// RPC endpoint url [default: SOLANA_RPC_URL, then the Solana CLI config]
+    #[structopt(short, long, global = true)]
+    pub rpc: Option<String>,
+
+    /// Commitment of reads and confirmations: processed, confirmed or
+    /// finalized
+    #[structopt(long, global = true)]
+    pub commitment: Option<CommitmentLevel>,
+
+    /// Log level: error, warn, info, debug or trace [default: RUST_LOG, then
+    /// error]
+    #[structopt(long, global = true)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Print the command's report as JSON instead of text
+    #[structopt(long, global = true)]
+    pub json: bool,
+
+    /// Show a live dashboard during snapshots; other output follows once
+    /// it closes
+    #[cfg(feature = "tui")]
+    #[structopt(long, global = true)]
+    pub tui: bool,
+
+    /// Admin signer: keypair file, usb://ledger or prompt:// for a seed phrase
+    /// [default: from the Solana CLI config]
+    #[structopt(short, long, global = true)]
+    pub keypair: Option<String>,
+
+    #[structopt(flatten)]
+    pub signing: SigningOpt,
+
+    /// Overall RPC request timeout in seconds
+    #[structopt(long, global = true)]
+    pub timeout: Option<u64>,
+
+    /// RPC connect timeout in seconds
+    #[structopt(long, global = true)]
+    pub connect_timeout: Option<u64>,
+
+    /// TCP keep-alive interval in seconds, 0 to disable
+    #[structopt(long, global = true)]
+    pub keepalive: Option<u64>,
+
+    /// Requests per second
+    #[structopt(long, global = true)]
+    pub rate_limit: Option<u32>,
+
+    /// Token account lookups per JSON-RPC batch request
+    #[structopt(long, global = true)]
+    pub batch_size: Option<usize>,
+
+    /// Threads resolving holders
+    #[structopt(long, global = true)]
+    pub workers: Option<usize>,
+
+    /// Holders with a frozen token account: include, exclude or tag
+    #[structopt(long, global = true)]
+    pub frozen: Option<FrozenPolicy>,
+
+    /// Holders that are exchange custodial wallets listed in
+    /// filters.custodians: include, exclude or tag
+    #[structopt(long, global = true)]
+    pub custodial: Option<CustodialPolicy>,
+
+    /// Leave items that look spoofed out of snapshots, see `snapshot verify`
+    #[structopt(long, global = true)]
+    pub quarantine_spoofs: bool,
+
+    /// Report each item's first holder, its minter, instead of the current
+    /// one; reads every item's transaction history
+    #[structopt(long, global = true)]
+    pub first_minter: bool,
+
+    /// How current holders are looked up: gpa or largest_accounts, which
+    /// falls back to gpa for mints it finds no holder for
+    #[structopt(long, global = true)]
+    pub resolve: Option<ResolveMethod>,
+
+    /// Also find items whose metadata was trimmed by Metaplex's resize, by
+    /// scanning the update authority's items when the creator filter finds
+    /// fewer
+    #[structopt(long, global = true)]
+    pub resized_fallback: bool,
+
+    /// Replace addresses in the output with salted hashes, writing the
+    /// mapping to <output>.mapping.json
+    #[structopt(long, global = true)]
+    pub pseudonymize: bool,
+
+    /// Write one entry per owner with its items nested, instead of one per
+    /// item
+    #[structopt(long, global = true)]
+    pub group_by_owner: bool,
+
+    /// Write one entry per master edition with its prints nested; implies
+    /// --enrich-editions
+    #[structopt(long, global = true)]
+    pub group_by_edition: bool,
+
+    /// Name outputs after the cluster too, e.g. holders.devnet.json
+    #[structopt(long, global = true)]
+    pub cluster_in_name: bool,
+
+    /// Encrypt output files to this age public key (age1...), adding .age
+    /// to their names; repeat for several recipients
+    #[structopt(long, global = true, number_of_values = 1)]
+    pub encrypt_to: Vec<String>,
+
+    /// Tag holders with whether the owner is a wallet or a program (PDA)
+    #[structopt(long, global = true)]
+    pub enrich_owners: bool,
+
+    /// Add each holder's favourite .sol domain
+    #[structopt(long, global = true)]
+    pub enrich_sol_domains: bool,
+
+    /// Add how long each holder has held the item, from its token account
+    /// history
+    #[structopt(long, global = true)]
+    pub enrich_hold_duration: bool,
+
+    /// Add each item's on-chain name
+    #[structopt(long, global = true)]
+    pub enrich_names: bool,
+
+    /// Add the master edition and print number of editioned items
+    #[structopt(long, global = true)]
+    pub enrich_editions: bool,
+
+    /// Flag items listed on a marketplace and count them per wallet
+    #[structopt(long, global = true)]
+    pub enrich_listings: bool,
+
+    /// Add each item's image from its off-chain JSON
+    #[structopt(long, global = true)]
+    pub enrich_images: bool,
+
+    /// Add a hash of each item's off-chain JSON
+    #[structopt(long, global = true)]
+    pub enrich_metadata_hashes: bool,
+
+    /// Flag items whose off-chain JSON hash differs from this earlier
+    /// snapshot's; implies --enrich-metadata-hashes
+    #[structopt(long, global = true)]
+    pub metadata_baseline: Option<String>,
+
+    /// Items held by SPL multisigs: ignore, attribute (list the signers) or
+    /// expand (one holder per signer)
+    #[structopt(long, global = true)]
+    pub multisig: Option<MultisigPolicy>,
+
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
+
+/// The collection a command reads, overriding `snapshot.source` in the
+/// config when given.
+#[derive(Debug, StructOpt)]
+pub struct SourceOpt {
+    /// Update authority of the collection; with --creator, items must match
+    /// both
+    #[structopt(short, long)]
+    pub update_authority: Option<String>,
+
+    /// Creator (or candy machine id with --v2) of the collection
+    #[structopt(short, long)]
+    pub creator: Option<String>,
+
+    /// Position of the creator in the creators array
+    #[structopt(short, long, default_value = "0")]
+    pub position: usize,
+
+    /// Treat --creator as a candy machine v2 id
+    #[structopt(long)]
+    pub v2: bool,
+}
+
+/// How program transactions are signed, for signing offline.
+#[derive(Debug, StructOpt)]
+pub struct SigningOpt {
+    /// Durable nonce account to sign program transactions with instead of a
+    /// recent blockhash; repeat to give one per transaction with --sign-only
+    #[structopt(long, global = true, number_of_values = 1)]
+    pub nonce: Vec<String>,
+
+    /// Signer of the nonce accounts' authority [default: the keypair]
+    #[structopt(long, global = true)]
+    pub nonce_authority: Option<String>,
+
+    /// Append signed program transactions to this file instead of sending
+    /// them, for `program broadcast`
+    #[structopt(long, global = true)]
+    pub sign_only: Option<String>,
+
+    /// Blockhash to sign with instead of asking the RPC, so --sign-only
+    /// works offline: the one stored in each --nonce account, in the same
+    /// order, or else a recent one
+    #[structopt(long, global = true, number_of_values = 1)]
+    pub blockhash: Vec<String>,
+
+    /// Signature PUBKEY=SIGNATURE another signer made of the same
+    /// transaction with --sign-only; give that signer as its pubkey
+    #[structopt(long, global = true, number_of_values = 1)]
+    pub signer: Vec<String>,
+
+    /// Run batch program operations estimated to cost more than
+    /// admin.confirm_above_sol
+    #[structopt(long, global = true)]
+    pub yes: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Program administration
+    Program {
+        #[structopt(subcommand)]
+        program_subcommands: ProgramSubcommands,
+    },
+    /// Holder snapshots
+    Snapshot {
+        #[structopt(subcommand)]
+        snapshot_subcommands: SnapshotSubcommands,
+    },
+    /// Look into a single wallet
+    Wallet {
+        #[structopt(subcommand)]
+        wallet_subcommands: WalletSubcommands,
+    },
+    /// Look into a single NFT
+    Mint {
+        #[structopt(subcommand)]
+        mint_subcommands: MintSubcommands,
+    },
+    /// Test data on devnet or a local validator
+    Fixtures {
+        #[structopt(subcommand)]
+        fixtures_subcommands: FixturesSubcommands,
+    },
+    /// Print a completion script: bash, zsh, fish, powershell or elvish
+    Completions { shell: Shell },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ProgramSubcommands {
+    /// Create the global state account
+    Init,
+    /// Register spin items from a JSON file
+    SetItems {
+        /// JSON array of { "mint", "reward_account", "ratio", "amount" }, one per item index
+        #[structopt(short, long)]
+        items_file: String,
+    },
+    /// Top up a spin item's reward vault
+    FundTreasury {
+        /// Reward token vault to fund
+        #[structopt(short, long)]
+        vault: String,
+
+        /// Token account the rewards are taken from
+        #[structopt(short, long)]
+        source: String,
+
+        /// Amount in base units
+        #[structopt(short, long)]
+        amount: u64,
+    },
+    /// Send the transactions written with --sign-only, in order
+    Broadcast {
+        /// File written with --sign-only
+        file: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SnapshotSubcommands {
+    /// Snapshot the holders of one collection
+    Holders {
+        #[structopt(flatten)]
+        source: SourceOpt,
+
+        /// Fungible SPL mint to snapshot the token holders of, instead of a
+        /// collection
+        #[structopt(long, conflicts_with_all = &["update-authority", "creator"])]
+        mint: Option<String>,
+
+        /// With --mint, leave out balances below this, in UI units
+        #[structopt(long, requires = "mint")]
+        min_balance: Option<f64>,
+
+        /// Output file
+        #[structopt(short, long)]
+        output: Option<String>,
+
+        /// Output format, json, csv, jsonl or bin
+        #[structopt(short, long)]
+        format: Option<OutputFormat>,
+
+        /// Compress the output, gzip or zstd
+        #[structopt(long)]
+        compress: Option<Compression>,
+
+        /// Add explorer link columns to CSV output: solscan, solana_explorer
+        /// or solana_fm
+        #[structopt(long)]
+        explorer_links: Option<Explorer>,
+
+        /// Also write the empty token accounts found as JSON, for
+        /// `wallet close-empty`
+        #[structopt(long)]
+        empty_accounts: Option<String>,
+
+        /// Write a manifest of the RPC endpoint, calls, slots and settings
+        /// used, for audits and `snapshot replay`
+        #[structopt(long)]
+        manifest: Option<String>,
+
+        /// Write the items that failed all retries as JSON, for
+        /// --retry-failed
+        #[structopt(long)]
+        dead_letter: Option<String>,
+
+        /// Look up only the items in this dead-letter file and merge their
+        /// holders into the snapshot at --output; items failing again are
+        /// written back, or to --dead-letter
+        #[structopt(long, conflicts_with_all = &["mint", "manifest", "empty-accounts"])]
+        retry_failed: Option<String>,
+
+        /// Keep holders packed in memory, for fungible snapshots of hundreds
+        /// of thousands of accounts. Filters other than min_holdings apply as
+        /// holders are found; there is no enrichment, and only the snapshot
+        /// files are written
+        #[structopt(long, conflicts_with_all = &["manifest", "retry-failed"])]
+        bulk: bool,
+    },
+    /// Take a snapshot again with the settings of a manifest; holders differ
+    /// where items changed hands since
+    Replay {
+        /// Manifest written by `snapshot holders --manifest`
+        manifest: String,
+
+        /// The snapshot the manifest was written for, to list the mints whose
+        /// holders differ
+        #[structopt(long)]
+        against: Option<String>,
+
+        /// Output file
+        #[structopt(short, long)]
+        output: Option<String>,
+
+        /// With --against, write the differing mints as JSON
+        #[structopt(long, requires = "against")]
+        differences: Option<String>,
+    },
+    /// Snapshot every collection listed in the config file
+    Collections {
+        /// Directory for the per-collection outputs and report
+        #[structopt(short, long)]
+        output_dir: Option<String>,
+
+        /// Output format, json, csv, jsonl or bin
+        #[structopt(short, long)]
+        format: Option<OutputFormat>,
+
+        /// Compress the output, gzip or zstd
+        #[structopt(long)]
+        compress: Option<Compression>,
+
+        /// Add explorer link columns to CSV output: solscan, solana_explorer
+        /// or solana_fm
+        #[structopt(long)]
+        explorer_links: Option<Explorer>,
+    },
+    /// Snapshot every item of an update authority shared by several
+    /// collections with one scan, writing each collection's holders to its
+    /// own file
+    Authority {
+        /// Update authority address
+        update_authority: String,
+
+        /// Split the items by verified collection (falling back to the
+        /// symbol) or by symbol
+        #[structopt(long, default_value = "collection")]
+        split_by: SplitBy,
+
+        /// Directory for the per-collection outputs and report
+        #[structopt(short, long)]
+        output_dir: Option<String>,
+
+        /// Output format, json, csv, jsonl or bin
+        #[structopt(short, long)]
+        format: Option<OutputFormat>,
+
+        /// Compress the output, gzip or zstd
+        #[structopt(long)]
+        compress: Option<Compression>,
+    },
+    /// Turnover and wash trading signals across snapshots of one collection
+    Analyze {
+        /// Snapshot files, oldest first; .gz and .zst are read transparently
+        #[structopt(required = true, min_values = 2)]
+        snapshots: Vec<String>,
+
+        /// Flag wallets a mint returned to at least this many times
+        #[structopt(long, default_value = "1")]
+        min_cycles: usize,
+
+        /// Write the full report as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Check a collection's mint count, candy machine redemptions and
+    /// resolved holders against each other
+    Reconcile {
+        #[structopt(flatten)]
+        source: SourceOpt,
+
+        /// Write the full report, including the affected mints, as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// List items whose symbol, URI or collection deviates from the rest of
+    /// the collection
+    Verify {
+        #[structopt(flatten)]
+        source: SourceOpt,
+
+        /// Write the flagged items as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Report the token metadata layout of every item, from v1.0 to
+    /// programmable NFTs
+    MetadataVersions {
+        #[structopt(flatten)]
+        source: SourceOpt,
+
+        /// Write the layout of every metadata account as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Report which config lines of a candy machine v2 have been minted
+    /// and which are still available
+    CandyMachine {
+        /// Candy machine v2 id
+        candy_machine: String,
+
+        /// Holder snapshot of the collection, to add the owner of each
+        /// minted item
+        #[structopt(long)]
+        holders: Option<String>,
+
+        /// Write the full report as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Group holders likely controlled by one entity and report effective
+    /// concentration
+    Clusters {
+        /// Snapshot file; .gz and .zst are read transparently
+        snapshot: String,
+
+        /// Ignore funders shared by more wallets than this, e.g. exchanges
+        #[structopt(long)]
+        max_shared_funder: Option<usize>,
+
+        /// Write the full report as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Total the royalties owed and paid to each creator over the recent
+    /// sales of a snapshot's items, one row per creator for a payout
+    Royalties {
+        /// Snapshot file whose mints to scan; .gz and .zst are read
+        /// transparently
+        snapshot: String,
+
+        /// Transactions read per mint and per metadata account, newest
+        /// first
+        #[structopt(long, default_value = "1000")]
+        limit: usize,
+
+        /// Output file [default: royalties.json or royalties.csv]
+        #[structopt(short, long)]
+        output: Option<String>,
+
+        /// Output format, json, csv or jsonl
+        #[structopt(short, long)]
+        format: Option<OutputFormat>,
+    },
+    /// Export voter weights for a DAO vote, one row per wallet
+    Weights {
+        /// Snapshot file; .gz and .zst are read transparently
+        snapshot: String,
+
+        /// Voting power per NFT held
+        #[structopt(long)]
+        power_per_nft: Option<u64>,
+
+        /// Cap on any one wallet's voting power
+        #[structopt(long)]
+        max_power: Option<u64>,
+
+        /// Output file [default: weights.json or weights.csv]
+        #[structopt(short, long)]
+        output: Option<String>,
+
+        /// Output format, json, csv or jsonl
+        #[structopt(short, long)]
+        format: Option<OutputFormat>,
+    },
+    /// Check a snapshot file for duplicate holders, items with several
+    /// holders and malformed addresses; fails if any are found
+    Validate {
+        /// Snapshot file; .gz and .zst are read transparently
+        snapshot: String,
+
+        /// Write the problems found as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Convert a snapshot between formats, e.g. to .bin for fast repeated
+    /// reads; formats and compression go by the file extensions
+    Convert {
+        /// Snapshot file; .gz and .zst are read transparently
+        snapshot: String,
+
+        /// File to write, e.g. holders.bin or holders.csv.gz
+        output: String,
+    },
+    /// Decrypt a snapshot file encrypted with --encrypt-to
+    Decrypt {
+        /// Encrypted file, e.g. holders.json.age
+        snapshot: String,
+
+        /// age identity file holding the secret key, as age-keygen writes it
+        #[structopt(short, long)]
+        identity: String,
+
+        /// File to write [default: the snapshot without .age]
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Build an index of a snapshot's holders by mint, for `mint who-holds`
+    Index {
+        /// Snapshot file; .gz and .zst are read transparently
+        snapshot: String,
+
+        /// Index file to write
+        #[structopt(short, long, default_value = "holders.idx")]
+        output: String,
+    },
+    /// Export the DAS asset proof of every holder of a compressed
+    /// collection, for claim and transfer tooling
+    Proofs {
+        /// Snapshot of a compressed collection, with asset ids as mints
+        #[structopt(required_unless = "collection")]
+        snapshot: Option<String>,
+
+        /// Collection mint; snapshot its compressed assets from the DAS
+        /// index instead of reading a snapshot file
+        #[structopt(long, conflicts_with = "snapshot")]
+        collection: Option<String>,
+
+        /// Output file
+        #[structopt(short, long, default_value = "proofs.json")]
+        output: String,
+    },
+    /// Score wallets by the NFTs and fungible tokens they hold, joining two
+    /// snapshots
+    Combine {
+        /// NFT holder snapshot
+        nft_snapshot: String,
+
+        /// Fungible token snapshot, from `snapshot holders --mint`
+        token_snapshot: String,
+
+        /// Score per NFT held
+        #[structopt(long)]
+        nft_weight: Option<f64>,
+
+        /// Score per token held, in UI units
+        #[structopt(long)]
+        token_weight: Option<f64>,
+
+        /// Tokens counted per wallet at most
+        #[structopt(long)]
+        max_tokens: Option<f64>,
+
+        /// Output file [default: scores.json or scores.csv]
+        #[structopt(short, long)]
+        output: Option<String>,
+
+        /// Output format, json, csv or jsonl
+        #[structopt(short, long)]
+        format: Option<OutputFormat>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum WalletSubcommands {
+    /// List every NFT the wallet holds, grouped by collection
+    Audit {
+        /// Wallet address
+        wallet: String,
+
+        /// Write the full audit as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Poll the wallets of the watch config and report items of its
+    /// collections they acquire or dispose of, until stopped; edits to the
+    /// [watch] section of --config apply without a restart
+    Watch,
+    /// Close the keypair's empty token accounts listed in a report from
+    /// `snapshot holders --empty-accounts`, recovering their rent
+    CloseEmpty {
+        /// Empty accounts report
+        report: String,
+
+        /// List the accounts without closing them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum MintSubcommands {
+    /// Replay the mint's transfers and list the owners it passed through,
+    /// e.g. to check provenance before accepting a deposit
+    History {
+        /// Mint address
+        mint: String,
+
+        /// Transactions read, newest first
+        #[structopt(long, default_value = "1000")]
+        limit: usize,
+
+        /// Write the history as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Look the mint's holders up in an index built with `snapshot index`,
+    /// without any RPC requests
+    WhoHolds {
+        /// Mint address
+        mint: String,
+
+        /// Index file
+        #[structopt(short, long, default_value = "holders.idx")]
+        index: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum FixturesSubcommands {
+    /// Mint a small test collection with the keypair as update authority
+    /// and verified creator, e.g. to snapshot against; refused on mainnet
+    MintCollection {
+        /// Items to mint
+        #[structopt(long, default_value = "10")]
+        size: usize,
+
+        #[structopt(long, default_value = "Fixture")]
+        name: String,
+
+        #[structopt(long, default_value = "FIX")]
+        symbol: String,
+
+        /// Metadata URI of the items, "{}" standing for the item number
+        #[structopt(long, default_value = "https://example.com/fixture/{}.json")]
+        uri: String,
+
+        /// Royalties in basis points
+        #[structopt(long, default_value = "500")]
+        seller_fee_basis_points: u16,
+
+        /// Also mint a collection NFT and verify every item in it
+        #[structopt(long)]
+        collection_nft: bool,
+
+        /// Spread the items over this many new wallets instead of keeping
+        /// them in the keypair's
+        #[structopt(long, default_value = "0")]
+        holders: usize,
+
+        /// Write the minted addresses as JSON
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+}
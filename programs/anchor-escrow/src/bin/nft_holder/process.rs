@@ -0,0 +1,1540 @@
+use {
+    crate::opt::{
+        FixturesSubcommands, MintSubcommands, ProgramSubcommands, SnapshotSubcommands,
+        SourceOpt, WalletSubcommands,
+    },
+    anchor_escrow::{
+        analytics::analyze,
+        anonymize::random_salt,
+        batch::BatchingRpc,
+        bulk::{write_table, HolderTable},
+        candy_machine::{get_config_lines, mint_status},
+        chunked::ChunkedRpc,
+        client::{find_state_address, read_signed, set_item, submit, CostEstimate, ProgramClient},
+        clusters::{cluster_holders, find_links},
+        combined::{combined_scores, write_scores},
+        collections::{snapshot_collections, snapshot_update_authority, CombinedReport},
+        config::{Config, OutputConfig},
+        das::{compressed_holders, holder_proofs, write_proofs},
+        encryption::{parse_recipients, read_identity, sealed_path, unseal_file},
+        enrich::{enrich, listing_stats, ListingStats},
+        filters::{custodial_counts, FilterChain, HolderFilter},
+        fixtures::{account_sizes, mint_collection, plan_collection, CollectionFixture},
+        governance::{voter_weights, write_weights},
+        holder_index::{write_index, HolderIndex},
+        limiter::RateLimitedRpc,
+        manifest::{holders_sha256, replay_differences, rpc_endpoint, Manifest, ReplayDifference},
+        metadata::{metadata_versions, MetadataVersion},
+        output::{
+            check_cluster, check_same_cluster, cluster_path, compression_of, format_of,
+            open_reader, read_holders, write_holders, write_sealed_json,
+        },
+        provenance::ownership_history,
+        reconcile::reconcile,
+        retry_policy::RetryingRpc,
+        royalties::{royalty_report, write_payouts},
+        rpc::{detect_cluster, Cluster, RpcBackend},
+        sink::{write_to_sinks, written_path},
+        validate::validate_holders,
+        snapshot::{
+            get_source_accounts, retry_failed, stream_nftholders, verified_metadata, EmptyAccount,
+            FailedItem, Holder, SnapshotError, SnapshotResult, Source,
+        },
+        verify::find_spoofs,
+        wallet::{audit_wallet, close_empty_instructions, GroupedBy, CLOSES_PER_TRANSACTION},
+        watch::{watch, ChangeKind},
+    },
+    anchor_lang::{
+        prelude::Pubkey,
+        solana_program::{instruction::Instruction, program_pack::Pack},
+    },
+    anyhow::{anyhow, Result},
+    log::{error, warn},
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::{
+        native_token::lamports_to_sol,
+        signature::{Keypair, Signature, Signer},
+    },
+    spl_token::state::Account as TokenAccount,
+    std::{
+        collections::{BTreeMap, HashSet},
+        convert::TryFrom,
+        fs::{self, File},
+        path::{Path, PathBuf},
+        str::FromStr,
+        time::SystemTime,
+    },
+};
+
+#[cfg(feature = "gpa-cache")]
+use anchor_escrow::cache::CachedRpc;
+
+#[derive(Debug, Deserialize)]
+struct ItemConfig {
+    mint: String,
+    reward_account: String,
+    ratio: u8,
+    amount: u64,
+}
+
+/// A transaction sent, or signed with --sign-only, for `--json`.
+#[derive(Serialize)]
+struct SentTransaction {
+    signature: String,
+    /// What it did, e.g. the item set or the accounts closed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    item_index: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_vault: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts: Option<usize>,
+}
+
+impl SentTransaction {
+    fn new(signature: Signature) -> Self {
+        SentTransaction {
+            signature: signature.to_string(),
+            item_index: None,
+            token_vault: None,
+            accounts: None,
+        }
+    }
+}
+
+/// The outcome of a program or wallet command sending transactions.
+#[derive(Serialize)]
+struct SendReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimate: Option<CostEstimate>,
+    sent: Vec<SentTransaction>,
+}
+
+/// Prints what a batch operation will cost, unless the command prints JSON,
+/// and refuses to go on when that is more than `confirm_above` SOL; `None`
+/// once confirmed with --yes.
+fn confirm_cost(estimate: &CostEstimate, confirm_above: Option<f64>, json: bool) -> Result<()> {
+    let total = lamports_to_sol(estimate.total_lamports());
+    if !json {
+        println!(
+            "{} transactions, {} signatures: {} SOL in fees and {} SOL in rent",
+            estimate.transactions,
+            estimate.signatures,
+            lamports_to_sol(estimate.fee_lamports),
+            lamports_to_sol(estimate.rent_lamports)
+        );
+    }
+    match confirm_above {
+        Some(limit) if total > limit => Err(anyhow!(
+            "Estimated cost of {} SOL is above {} SOL, pass --yes to go ahead!",
+            total,
+            limit
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn process_program(
+    client: &ProgramClient,
+    cmd: ProgramSubcommands,
+    confirm_above: Option<f64>,
+    json: bool,
+) -> Result<()> {
+    match cmd {
+        ProgramSubcommands::Init => {
+            let (state, _) = find_state_address(&client.program_id);
+            let signature = client.initialize(client.payer.as_ref())?;
+            if json {
+                return print_json(&SendReport {
+                    estimate: None,
+                    sent: vec![SentTransaction::new(signature)],
+                });
+            }
+            println!("Initialized state {}: {}", state, signature);
+        }
+        ProgramSubcommands::SetItems { items_file } => {
+            let f = File::open(&items_file)?;
+            let items: Vec<ItemConfig> = serde_json::from_reader(f)?;
+
+            // Every item gets a token vault; the vault addresses do not
+            // change the cost.
+            let owner = client.payer.pubkey();
+            let mut transactions = Vec::with_capacity(items.len());
+            for (index, item) in items.iter().enumerate() {
+                let index = item_index(index)?;
+                transactions.push(vec![set_item(
+                    &client.program_id,
+                    &owner,
+                    &Pubkey::from_str(&item.mint)?,
+                    &Pubkey::from_str(&item.reward_account)?,
+                    &Pubkey::new_unique(),
+                    index,
+                    item.ratio,
+                    item.amount,
+                )?]);
+            }
+            let vaults = vec![TokenAccount::LEN; items.len()];
+            let estimate = client.estimate(&transactions, &vaults)?;
+            confirm_cost(&estimate, confirm_above, json)?;
+
+            let mut sent = Vec::with_capacity(items.len());
+            for (index, item) in items.iter().enumerate() {
+                let index = item_index(index)?;
+                let (token_vault, signature) = client.set_item(
+                    client.payer.as_ref(),
+                    &Pubkey::from_str(&item.mint)?,
+                    &Pubkey::from_str(&item.reward_account)?,
+                    index,
+                    item.ratio,
+                    item.amount,
+                )?;
+                if !json {
+                    println!("Item {} vault {}: {}", index, token_vault, signature);
+                }
+                sent.push(SentTransaction {
+                    item_index: Some(index),
+                    token_vault: Some(token_vault.to_string()),
+                    ..SentTransaction::new(signature)
+                });
+            }
+            if json {
+                return print_json(&SendReport {
+                    estimate: Some(estimate),
+                    sent,
+                });
+            }
+        }
+        ProgramSubcommands::FundTreasury {
+            vault,
+            source,
+            amount,
+        } => {
+            let signature = client.fund_token_vault(
+                client.payer.as_ref(),
+                &Pubkey::from_str(&source)?,
+                &Pubkey::from_str(&vault)?,
+                amount,
+            )?;
+            if json {
+                return print_json(&SendReport {
+                    estimate: None,
+                    sent: vec![SentTransaction::new(signature)],
+                });
+            }
+            println!("Funded {} with {}: {}", vault, amount, signature);
+        }
+        ProgramSubcommands::Broadcast { file } => process_broadcast(&client.rpc, &file, json)?,
+    }
+
+    Ok(())
+}
+
+/// The on-chain index of the `index`th item, which is stored as a u8.
+fn item_index(index: usize) -> Result<u8> {
+    u8::try_from(index).map_err(|_| anyhow!("Item {} is past the last index {}!", index, u8::MAX))
+}
+
+/// Sends transactions signed with --sign-only, stopping at the first that
+/// fails, as later ones may depend on it. With `json`, those sent before it
+/// are printed all the same.
+pub fn process_broadcast(rpc: &RpcClient, file: &str, json: bool) -> Result<()> {
+    let transactions = read_signed(Path::new(file))?;
+    let mut sent = Vec::with_capacity(transactions.len());
+    for (index, tx) in transactions.iter().enumerate() {
+        let signature = match submit(rpc, tx) {
+            Ok(signature) => signature,
+            Err(err) => {
+                if json {
+                    print_json(&SendReport {
+                        estimate: None,
+                        sent,
+                    })?;
+                }
+                let total = transactions.len();
+                return Err(anyhow!("Transaction {} of {} failed: {}", index + 1, total, err));
+            }
+        };
+        if !json {
+            println!("Sent {}", signature);
+        }
+        sent.push(SentTransaction::new(signature));
+    }
+    if json {
+        return print_json(&SendReport {
+            estimate: None,
+            sent,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "gpa-cache"))]
+type SnapshotBackend = ChunkedRpc<RetryingRpc<RateLimitedRpc<BatchingRpc>>>;
+#[cfg(feature = "gpa-cache")]
+type SnapshotBackend = CachedRpc<ChunkedRpc<RetryingRpc<RateLimitedRpc<BatchingRpc>>>>;
+
+// Retries go through the limiter too, so a struggling endpoint is not hit
+// harder than the configured rate. Chunking sits outside both, so every
+// sub-query is limited and retried on its own. The gPA cache sits outside
+// everything, so a hit skips the whole query.
+#[cfg_attr(not(feature = "gpa-cache"), allow(clippy::let_and_return))]
+fn snapshot_backend(rpc: BatchingRpc, config: &Config) -> SnapshotBackend {
+    // The limiter the senders report provider rate limit headers to.
+    let limiter = rpc.limiter.clone();
+    let backend = ChunkedRpc::new(
+        RetryingRpc::new(
+            RateLimitedRpc::new(rpc, limiter),
+            config.rpc.retry.clone(),
+        ),
+        config.rpc.chunk_gpa,
+    );
+    #[cfg(feature = "gpa-cache")]
+    let backend = config.rpc.cache.wrap(backend);
+    backend
+}
+
+/// Single snapshots are uploaded and recorded under their output file name,
+/// minus the extensions.
+fn collection_name(output: &Path) -> String {
+    output
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .unwrap_or("holders")
+        .to_string()
+}
+
+/// The source given on the command line, else the one in the config.
+fn snapshot_source(config: &mut Config, source: &SourceOpt) -> Result<Source> {
+    if source.update_authority.is_some() || source.creator.is_some() {
+        return Source::from_args(
+            &source.update_authority,
+            &source.creator,
+            source.position,
+            source.v2,
+        );
+    }
+    config.snapshot.source.take().ok_or(anyhow!(
+        "Specify a creator or update authority, or a source in the config"
+    ))
+}
+
+#[derive(Serialize)]
+struct HoldersReport {
+    holders: usize,
+    destinations: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listings: Option<ListingStats>,
+    /// Items held by exchange custodial wallets, per country.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    custodial: BTreeMap<String, usize>,
+    #[serde(flatten)]
+    result: SnapshotResult,
+}
+
+/// What `snapshot convert` wrote.
+#[derive(Serialize)]
+struct ConvertReport {
+    holders: usize,
+    output: String,
+    bytes: u64,
+    snapshot_bytes: u64,
+}
+
+/// The verdict of `snapshot replay`, with the snapshot taken.
+#[derive(Serialize)]
+struct ReplayReport {
+    matches_manifest: bool,
+    recorded_sha256: String,
+    replayed_sha256: String,
+    recorded_slot_range: Option<(u64, u64)>,
+    /// Mints whose holders differ, when compared against the recorded
+    /// snapshot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    differences: Option<Vec<ReplayDifference>>,
+    snapshot: HoldersReport,
+}
+
+/// Prints `value` as the command's only output, for `--json`.
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    serde_json::to_writer_pretty(std::io::stdout(), value)?;
+    println!();
+    Ok(())
+}
+
+/// Fails a snapshot whose output was written with items or collections
+/// missing, so the exit code tells it apart from a complete one.
+fn check_complete(missing_items: usize, failed_collections: usize) -> Result<()> {
+    if missing_items > 0 || failed_collections > 0 {
+        return Err(SnapshotError::PartialResults {
+            missing_items,
+            failed_collections,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Prints the outcome of a snapshot of several collections and fails it if
+/// any are incomplete.
+fn print_collections(report: &CombinedReport, json: bool) -> Result<()> {
+    let missing_items = report.collections.iter().map(|c| c.missing).sum();
+    let failed = report.collections.iter().filter(|c| c.error.is_some()).count();
+    if json {
+        print_json(report)?;
+        return check_complete(missing_items, failed);
+    }
+
+    for collection in &report.collections {
+        match &collection.error {
+            Some(err) => println!("{}: failed: {}", collection.name, err),
+            None => println!(
+                "{}: {} holders, {} unique owners",
+                collection.name, collection.holders, collection.unique_owners
+            ),
+        }
+    }
+    println!(
+        "{} unique owners, {} in more than one collection",
+        report.unique_owners, report.owners_in_multiple_collections
+    );
+    check_complete(missing_items, failed)
+}
+
+/// Writes a report listing wallets to `path` as JSON, encrypted like the
+/// snapshots are, with `.age` appended, if `output.encrypt_to` is set.
+fn write_wallet_report<T>(path: &str, output: &OutputConfig, report: &T) -> Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    let path = sealed_path(Path::new(path), &output.encrypt_to);
+    write_sealed_json(&path, output, report)
+}
+
+/// Files written alongside a holders snapshot, none by default. Those
+/// listing wallets are encrypted with the snapshot, so their paths end in
+/// `.age` then, see `SideOutputs::new`.
+#[derive(Default)]
+struct SideOutputs {
+    /// Empty token accounts, for `wallet close-empty`.
+    empty_accounts: Option<PathBuf>,
+    manifest: Option<String>,
+    /// Items that failed all retries, for `--retry-failed`.
+    dead_letter: Option<PathBuf>,
+}
+
+impl SideOutputs {
+    fn new(
+        output: &OutputConfig,
+        empty_accounts: Option<String>,
+        manifest: Option<String>,
+        dead_letter: Option<String>,
+    ) -> Self {
+        let sealed = |path: Option<String>| {
+            path.map(|path| sealed_path(Path::new(&path), &output.encrypt_to))
+        };
+        SideOutputs {
+            empty_accounts: sealed(empty_accounts),
+            manifest,
+            dead_letter: sealed(dead_letter),
+        }
+    }
+}
+
+/// Takes a holders snapshot of `source` into `output`, for `snapshot holders`
+/// and `snapshot replay`, and writes the `side` outputs asked for. Nothing
+/// is printed; see `print_holders`.
+fn take_holders(
+    rpc: BatchingRpc,
+    config: &Config,
+    source: &Source,
+    output: &Path,
+    side: &SideOutputs,
+) -> Result<(Vec<Holder>, HoldersReport)> {
+    let client = snapshot_backend(rpc, config);
+    let mut holders = Vec::new();
+    let result = stream_nftholders(&client, source, &config.snapshot.pipeline, |holder| {
+        holders.push(holder);
+        Ok(())
+    })?;
+    if let Some(path) = &side.empty_accounts {
+        write_sealed_json(path, &config.output, &result.empty_accounts)?;
+    }
+    if let Some(path) = &side.dead_letter {
+        write_sealed_json(path, &config.output, &result.failed)?;
+    }
+    let mut holders = FilterChain::from_config(&config.filters).apply(holders)?;
+    enrich(&client, &config.enrich, &mut holders)?;
+
+    let cluster = match side.manifest.is_some() || config.output.cluster_in_name {
+        true => Some(detect_cluster(&client)?),
+        false => None,
+    };
+    let output = match &cluster {
+        Some(cluster) if config.output.cluster_in_name => cluster_path(output, cluster),
+        _ => output.to_path_buf(),
+    };
+    let name = collection_name(&output);
+    let destinations = write_to_sinks(config, &name, &output, SystemTime::now(), &holders)?;
+    if let Some(path) = &side.manifest {
+        let usage = client.metrics().map(|metrics| metrics.report(&config.rpc.costs));
+        let manifest = Manifest::new(config, cluster, source, &result, usage.as_ref(), &holders)?;
+        manifest.write(Path::new(path))?;
+    }
+
+    let report = HoldersReport {
+        holders: holders.len(),
+        destinations,
+        listings: listing_stats(&holders),
+        custodial: custodial_counts(&holders),
+        result,
+    };
+    Ok((holders, report))
+}
+
+/// Prints the outcome of `take_holders`, unless `json`, where the caller
+/// prints the report as part of its own output.
+fn print_holders(report: &HoldersReport, side: &SideOutputs) {
+    let result = &report.result;
+    let missing_items = result.errors.len();
+    if missing_items > 0 {
+        println!("{} items could not be looked up and are missing", missing_items);
+        if let Some(path) = &side.dead_letter {
+            println!(
+                "Wrote them to {}, pass it to --retry-failed to try again",
+                path.display()
+            );
+        }
+    }
+    if result.skipped_burned > 0 {
+        println!("Skipped {} burned items", result.skipped_burned);
+    }
+    if let Some(path) = &side.empty_accounts {
+        let written = result.empty_accounts.len();
+        println!("Wrote {} empty token accounts to {}", written, path.display());
+    }
+    if let Some(listings) = &report.listings {
+        println!(
+            "{} of {} items listed, by {} wallets:",
+            listings.listed,
+            listings.held,
+            listings.wallets.len()
+        );
+        for wallet in &listings.wallets {
+            println!("  {} {} of {}", wallet.wallet, wallet.listed, wallet.held);
+        }
+    }
+    if !report.custodial.is_empty() {
+        println!("{} items held by exchanges:", report.custodial.values().sum::<usize>());
+        for (country, count) in &report.custodial {
+            println!("  {} {}", country, count);
+        }
+    }
+    for destination in &report.destinations {
+        println!("Wrote {} holders to {}", report.holders, destination);
+    }
+}
+
+/// `take_holders` keeping the holders in a `HolderTable`, for snapshots
+/// too large for a `Vec<Holder>`. Filters are applied to each holder as it
+/// comes in, so `filters.min_holdings`, which counts over all of them, is
+/// refused. Holders are not enriched and only go to the snapshot files.
+fn take_bulk_holders(
+    rpc: BatchingRpc,
+    config: &Config,
+    source: &Source,
+    output: &Path,
+    side: SideOutputs,
+    json: bool,
+) -> Result<()> {
+    if config.filters.min_holdings > 1 {
+        return Err(anyhow!(
+            "filters.min_holdings needs every holder at once, unset it for --bulk!"
+        ));
+    }
+    let client = snapshot_backend(rpc, config);
+    let filters = FilterChain::from_config(&config.filters);
+    let mut table = HolderTable::new();
+    let mut custodial: BTreeMap<String, usize> = BTreeMap::new();
+    let result = stream_nftholders(&client, source, &config.snapshot.pipeline, |holder| {
+        let kept = filters.apply(vec![holder])?;
+        for (country, count) in custodial_counts(&kept) {
+            *custodial.entry(country).or_default() += count;
+        }
+        for holder in &kept {
+            table.push(holder)?;
+        }
+        Ok(())
+    })?;
+    if let Some(path) = &side.empty_accounts {
+        write_sealed_json(path, &config.output, &result.empty_accounts)?;
+    }
+    if let Some(path) = &side.dead_letter {
+        write_sealed_json(path, &config.output, &result.failed)?;
+    }
+
+    let output = match config.output.cluster_in_name {
+        true => cluster_path(output, &detect_cluster(&client)?),
+        false => output.to_path_buf(),
+    };
+    let destinations = write_table(&output, &config.output, &table)?;
+
+    let missing_items = result.errors.len();
+    if json {
+        print_json(&HoldersReport {
+            holders: table.len(),
+            destinations,
+            listings: None,
+            custodial,
+            result,
+        })?;
+        return check_complete(missing_items, 0);
+    }
+    if missing_items > 0 {
+        println!("{} items could not be looked up and are missing", missing_items);
+        if let Some(path) = &side.dead_letter {
+            println!(
+                "Wrote them to {}, pass it to --retry-failed to try again",
+                path.display()
+            );
+        }
+    }
+    if let Some(path) = side.empty_accounts {
+        let written = result.empty_accounts.len();
+        println!("Wrote {} empty token accounts to {}", written, path.display());
+    }
+    if !custodial.is_empty() {
+        println!("{} holders are exchanges:", custodial.values().sum::<usize>());
+        for (country, count) in &custodial {
+            println!("  {} {}", country, count);
+        }
+    }
+    for destination in destinations {
+        println!(
+            "Wrote {} holders, {} distinct addresses, to {}",
+            table.len(),
+            table.addresses(),
+            destination
+        );
+    }
+    check_complete(missing_items, 0)
+}
+
+/// Looks up the items in the dead-letter file `failed` again and merges the
+/// holders found into the snapshot written for `output`, replacing any it
+/// has for those mints, then filters the merged set again. Items failing
+/// again are written to `dead_letter`, or back to `failed`. Pseudonymized
+/// snapshots cannot be merged into, their mints no longer match.
+fn retry_holders(
+    rpc: BatchingRpc,
+    config: &Config,
+    failed: &str,
+    output: &Path,
+    dead_letter: Option<String>,
+    json: bool,
+) -> Result<()> {
+    if config.output.pseudonymize {
+        return Err(anyhow!(
+            "Cannot merge retried holders into a pseudonymized snapshot; retry with \
+             pseudonymize off!"
+        ));
+    }
+    let items: Vec<FailedItem> = serde_json::from_reader(open_reader(Path::new(failed))?)?;
+
+    let client = snapshot_backend(rpc, config);
+    let output = match config.output.cluster_in_name {
+        true => cluster_path(output, &detect_cluster(&client)?),
+        false => output.to_path_buf(),
+    };
+    let existing = read_holders(&written_path(&output, &config.output))?;
+    check_cluster(&existing, &detect_cluster(&client)?)?;
+    let pseudonymized = |holder: &Holder| Pubkey::from_str(&holder.owner_wallet).is_err();
+    if existing.iter().any(pseudonymized) {
+        return Err(anyhow!(
+            "Snapshot {} is pseudonymized, cannot merge retried holders into it!",
+            output.display()
+        ));
+    }
+
+    let mut found = Vec::new();
+    let result = retry_failed(&client, &items, &config.snapshot.pipeline, |holder| {
+        found.push(holder);
+        Ok(())
+    })?;
+    let retried: HashSet<&str> = items.iter().map(|item| item.mint_account.as_str()).collect();
+    let mut holders: Vec<Holder> = existing
+        .into_iter()
+        .filter(|holder| !retried.contains(holder.mint_account.as_str()))
+        .collect();
+    holders.append(&mut found);
+    // Minimum holdings count a wallet's items across the whole snapshot.
+    let holders = FilterChain::from_config(&config.filters).apply(holders)?;
+    let (mut found, mut holders): (Vec<Holder>, Vec<Holder>) = holders
+        .into_iter()
+        .partition(|holder| retried.contains(holder.mint_account.as_str()));
+    enrich(&client, &config.enrich, &mut found)?;
+    let recovered = found.len();
+    holders.append(&mut found);
+    let dead_letter = dead_letter.unwrap_or_else(|| failed.to_string());
+    let dead_letter = sealed_path(Path::new(&dead_letter), &config.output.encrypt_to);
+    write_sealed_json(&dead_letter, &config.output, &result.failed)?;
+
+    let name = collection_name(&output);
+    let destinations = write_to_sinks(config, &name, &output, SystemTime::now(), &holders)?;
+    let missing_items = result.failed.len();
+    if json {
+        print_json(&HoldersReport {
+            holders: holders.len(),
+            destinations,
+            listings: listing_stats(&holders),
+            custodial: custodial_counts(&holders),
+            result,
+        })?;
+        return check_complete(missing_items, 0);
+    }
+    println!(
+        "Found {} holders for {} of {} items",
+        recovered,
+        items.len() - missing_items,
+        items.len()
+    );
+    if missing_items > 0 {
+        let dead_letter = dead_letter.display();
+        println!("{} items failed again, wrote them to {}", missing_items, dead_letter);
+    }
+    for destination in destinations {
+        println!("Wrote {} holders to {}", holders.len(), destination);
+    }
+    check_complete(missing_items, 0)
+}
+
+pub fn process_snapshot(
+    rpc: BatchingRpc,
+    mut config: Config,
+    cmd: SnapshotSubcommands,
+    json: bool,
+) -> Result<()> {
+    // One salt per run, so a wallet gets the same pseudonym in every
+    // collection.
+    if config.output.pseudonymize && config.output.salt.is_none() {
+        config.output.salt = Some(random_salt());
+    }
+
+    match cmd {
+        SnapshotSubcommands::Holders {
+            source,
+            mint,
+            min_balance,
+            output,
+            format,
+            compress,
+            explorer_links,
+            empty_accounts,
+            manifest,
+            dead_letter,
+            retry_failed,
+            bulk,
+        } => {
+            let output = output.unwrap_or_else(|| config.output.path.clone());
+            if let Some(format) = format {
+                config.output.format = format;
+            }
+            if let Some(compress) = compress {
+                config.output.compress = compress;
+            }
+            if explorer_links.is_some() {
+                config.output.explorer_links = explorer_links;
+            }
+            if let Some(failed) = retry_failed {
+                return retry_holders(rpc, &config, &failed, Path::new(&output), dead_letter, json);
+            }
+            let source = match mint {
+                Some(mint) => Source::Fungible {
+                    mint,
+                    min_balance: min_balance.unwrap_or_default(),
+                },
+                None => snapshot_source(&mut config, &source)?,
+            };
+            let side = SideOutputs::new(&config.output, empty_accounts, manifest, dead_letter);
+            if bulk {
+                return take_bulk_holders(rpc, &config, &source, Path::new(&output), side, json);
+            }
+            let (_, report) = take_holders(rpc, &config, &source, Path::new(&output), &side)?;
+            match json {
+                true => print_json(&report)?,
+                false => print_holders(&report, &side),
+            }
+            check_complete(report.result.errors.len(), 0)?;
+        }
+        SnapshotSubcommands::Replay {
+            manifest,
+            against,
+            output,
+            differences,
+        } => {
+            let manifest = Manifest::load(Path::new(&manifest))?;
+            manifest.apply(&mut config);
+            let endpoint = rpc_endpoint(&config.rpc_url());
+            if endpoint != manifest.rpc_endpoint {
+                warn!("Replaying on {}, the snapshot used {}", endpoint, manifest.rpc_endpoint);
+            }
+            if let Some(recorded) = &manifest.cluster {
+                let cluster = detect_cluster(&rpc)?;
+                if cluster != *recorded {
+                    return Err(anyhow!(
+                        "The snapshot was taken on {}, the RPC endpoint serves {}; refusing to \
+                         compare snapshots of different clusters!",
+                        recorded,
+                        cluster
+                    ));
+                }
+            }
+            let output = output.unwrap_or_else(|| config.output.path.clone());
+            let side = SideOutputs::default();
+            let (holders, report) =
+                take_holders(rpc, &config, &manifest.source, Path::new(&output), &side)?;
+            let replayed_sha256 = holders_sha256(&holders)?;
+
+            let found = match &against {
+                Some(against) => {
+                    let recorded = read_holders(Path::new(against))?;
+                    check_same_cluster(vec![&recorded[..], &holders[..]])?;
+                    Some(replay_differences(&recorded, &holders))
+                }
+                None => None,
+            };
+            if let (Some(path), Some(found)) = (&differences, &found) {
+                write_wallet_report(path, &config.output, found)?;
+            }
+            let matches_manifest = replayed_sha256 == manifest.holders_sha256;
+            let missing_items = report.result.errors.len();
+            if json {
+                print_json(&ReplayReport {
+                    matches_manifest,
+                    recorded_sha256: manifest.holders_sha256,
+                    replayed_sha256,
+                    recorded_slot_range: manifest.slot_range,
+                    differences: found,
+                    snapshot: report,
+                })?;
+                return check_complete(missing_items, 0);
+            }
+            print_holders(&report, &side);
+            match matches_manifest {
+                true => println!("Holders match the manifest"),
+                false => println!(
+                    "Holders differ from the manifest, taken at slots {:?}",
+                    manifest.slot_range
+                ),
+            }
+            if let Some(found) = found {
+                for difference in &found {
+                    println!(
+                        "  {} {} -> {}",
+                        difference.mint_account,
+                        difference.recorded.join(","),
+                        difference.replayed.join(",")
+                    );
+                }
+                println!("{} mints have other holders", found.len());
+            }
+            check_complete(missing_items, 0)?;
+        }
+        SnapshotSubcommands::Collections {
+            output_dir,
+            format,
+            compress,
+            explorer_links,
+        } => {
+            if let Some(output_dir) = output_dir {
+                config.output.dir = output_dir;
+            }
+            if let Some(format) = format {
+                config.output.format = format;
+            }
+            if let Some(compress) = compress {
+                config.output.compress = compress;
+            }
+            if explorer_links.is_some() {
+                config.output.explorer_links = explorer_links;
+            }
+
+            let client = snapshot_backend(rpc, &config);
+            let filters = FilterChain::from_config(&config.filters);
+            let report = snapshot_collections(&client, &config, &filters)?;
+            print_collections(&report, json)?;
+        }
+        SnapshotSubcommands::Authority {
+            update_authority,
+            split_by,
+            output_dir,
+            format,
+            compress,
+        } => {
+            if let Some(output_dir) = output_dir {
+                config.output.dir = output_dir;
+            }
+            if let Some(format) = format {
+                config.output.format = format;
+            }
+            if let Some(compress) = compress {
+                config.output.compress = compress;
+            }
+
+            let client = snapshot_backend(rpc, &config);
+            let filters = FilterChain::from_config(&config.filters);
+            let report =
+                snapshot_update_authority(&client, &update_authority, split_by, &config, &filters)?;
+            print_collections(&report, json)?;
+        }
+        SnapshotSubcommands::Analyze {
+            snapshots,
+            min_cycles,
+            output,
+        } => {
+            let snapshots = snapshots
+                .iter()
+                .map(|path| read_holders(Path::new(path)))
+                .collect::<Result<Vec<_>>>()?;
+            check_same_cluster(snapshots.iter().map(Vec::as_slice))?;
+            let report = analyze(&snapshots, min_cycles);
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &report)?;
+            }
+            if json {
+                return print_json(&report);
+            }
+
+            for turnover in &report.turnover {
+                println!(
+                    "{} -> {}: {} of {} changed hands ({:.2}%)",
+                    turnover.from,
+                    turnover.to,
+                    turnover.changed_hands,
+                    turnover.supply,
+                    turnover.percent
+                );
+            }
+            for wallet in &report.cycling_wallets {
+                println!(
+                    "{} got a mint back {} times, via {}",
+                    wallet.wallet,
+                    wallet.cycles,
+                    wallet.counterparties.join(", ")
+                );
+            }
+        }
+        SnapshotSubcommands::Reconcile { source, output } => {
+            let source = snapshot_source(&mut config, &source)?;
+
+            let client = snapshot_backend(rpc, &config);
+            let pipeline = &config.snapshot.pipeline;
+            let report = reconcile(&client, &source, pipeline.workers, pipeline.resized_fallback)?;
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &report)?;
+            }
+            if json {
+                return print_json(&report);
+            }
+
+            println!(
+                "{} metadata accounts, {} unverified, {} mints, {} held",
+                report.metadata_accounts, report.unverified, report.mints, report.holders
+            );
+            if let Some(items_redeemed) = report.items_redeemed {
+                println!("{} items redeemed from the candy machine", items_redeemed);
+            }
+            let discrepancies = report.discrepancies();
+            if discrepancies.is_empty() {
+                println!("Every mint is accounted for");
+            }
+            for discrepancy in &discrepancies {
+                println!("Discrepancy: {}", discrepancy);
+            }
+        }
+        SnapshotSubcommands::Verify { source, output } => {
+            let source = snapshot_source(&mut config, &source)?;
+
+            let client = snapshot_backend(rpc, &config);
+            let resized = config.snapshot.pipeline.resized_fallback;
+            let accounts = get_source_accounts(&client, &source, resized)?;
+            let items: Vec<_> = verified_metadata(accounts).collect();
+            let flags = find_spoofs(&items);
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &flags)?;
+            }
+            if json {
+                return print_json(&flags);
+            }
+
+            for flag in &flags {
+                println!("{} ({}): {}", flag.mint_account, flag.name, flag.reasons.join(", "));
+            }
+            println!("{} of {} items flagged", flags.len(), items.len());
+        }
+        SnapshotSubcommands::MetadataVersions { source, output } => {
+            let source = snapshot_source(&mut config, &source)?;
+
+            let client = snapshot_backend(rpc, &config);
+            let resized = config.snapshot.pipeline.resized_fallback;
+            let versions = metadata_versions(&get_source_accounts(&client, &source, resized)?);
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &versions)?;
+            }
+            if json {
+                return print_json(&versions);
+            }
+
+            let mut counts: BTreeMap<MetadataVersion, usize> = BTreeMap::new();
+            let mut programmable = 0;
+            for account in &versions {
+                if let Some(version) = account.version {
+                    *counts.entry(version).or_default() += 1;
+                }
+                if account.programmable == Some(true) {
+                    programmable += 1;
+                }
+                if let Some(err) = &account.error {
+                    println!("{}: {}", account.metadata_account, err);
+                }
+            }
+            for (version, count) in counts {
+                println!("{:?}: {}", version, count);
+            }
+            println!("{} programmable NFTs", programmable);
+            let undecodable = versions.iter().filter(|account| account.error.is_some()).count();
+            println!("{} of {} accounts could not be decoded", undecodable, versions.len());
+        }
+        SnapshotSubcommands::CandyMachine {
+            candy_machine,
+            holders,
+            output,
+        } => {
+            let source = Source::CandyMachine {
+                address: candy_machine.clone(),
+                update_authority: None,
+            };
+            let holders = match holders {
+                Some(path) => read_holders(Path::new(&path))?,
+                None => Vec::new(),
+            };
+
+            let client = snapshot_backend(rpc, &config);
+            check_cluster(&holders, &detect_cluster(&client)?)?;
+            let lines = get_config_lines(&client, &Pubkey::from_str(&candy_machine)?)?;
+            let resized = config.snapshot.pipeline.resized_fallback;
+            let accounts = get_source_accounts(&client, &source, resized)?;
+            let items: Vec<_> = verified_metadata(accounts).collect();
+            let status = mint_status(lines, &items, &holders);
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &status)?;
+            }
+            if json {
+                return print_json(&status);
+            }
+
+            println!(
+                "{} of {} config lines minted, {} available",
+                status.minted.len(),
+                status.config_lines,
+                status.available.len()
+            );
+            if !status.unmatched.is_empty() {
+                println!("{} items match no config line", status.unmatched.len());
+            }
+        }
+        SnapshotSubcommands::Clusters {
+            snapshot,
+            max_shared_funder,
+            output,
+        } => {
+            if let Some(max_shared_funder) = max_shared_funder {
+                config.clustering.max_shared_funder = max_shared_funder;
+            }
+
+            let holders = read_holders(Path::new(&snapshot))?;
+            let client = snapshot_backend(rpc, &config);
+            let links = find_links(&client, &holders, &config.clustering)?;
+            let report = cluster_holders(&holders, &links, &config.clustering);
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &report)?;
+            }
+            if json {
+                return print_json(&report);
+            }
+
+            for cluster in report.clusters.iter().take(10) {
+                println!(
+                    "{} items across {} wallets, funded by {}",
+                    cluster.items,
+                    cluster.wallets.len(),
+                    cluster.funders.join(", ")
+                );
+            }
+            println!(
+                "{} holders, {} effective; largest holder {:.2}%, largest cluster {:.2}%",
+                report.holders,
+                report.effective_holders,
+                report.largest_holder_share,
+                report.largest_cluster_share
+            );
+        }
+        SnapshotSubcommands::Royalties {
+            snapshot,
+            limit,
+            output,
+            format,
+        } => {
+            let format = format.unwrap_or(config.output.format);
+            let output = output.unwrap_or_else(|| format!("royalties.{}", format.extension()));
+
+            let holders = read_holders(Path::new(&snapshot))?;
+            let mut mints = holders
+                .iter()
+                .map(|holder| Pubkey::from_str(&holder.mint_account))
+                .collect::<Result<Vec<_>, _>>()?;
+            mints.sort();
+            mints.dedup();
+            let client = snapshot_backend(rpc, &config);
+            let report = royalty_report(&client, &mints, limit)?;
+            let recipients = parse_recipients(&config.output.encrypt_to)?;
+            let output = sealed_path(Path::new(&output), &config.output.encrypt_to);
+            write_payouts(&output, format, &recipients, &report.payouts)?;
+            if json {
+                return print_json(&report);
+            }
+
+            for payout in &report.payouts {
+                println!(
+                    "{}: {} sales, {} SOL owed, {} SOL paid, {} SOL outstanding",
+                    payout.creator,
+                    payout.sales,
+                    lamports_to_sol(payout.owed_lamports),
+                    lamports_to_sol(payout.paid_lamports),
+                    lamports_to_sol(payout.outstanding_lamports)
+                );
+            }
+            println!(
+                "Found {} sales of {} items, wrote {} creators to {}",
+                report.sales.len(),
+                mints.len(),
+                report.payouts.len(),
+                output.display()
+            );
+        }
+        SnapshotSubcommands::Weights {
+            snapshot,
+            power_per_nft,
+            max_power,
+            output,
+            format,
+        } => {
+            if let Some(power_per_nft) = power_per_nft {
+                config.governance.power_per_nft = power_per_nft;
+            }
+            if max_power.is_some() {
+                config.governance.max_power = max_power;
+            }
+            let format = format.unwrap_or(config.output.format);
+            let output = output.unwrap_or_else(|| format!("weights.{}", format.extension()));
+
+            let holders = read_holders(Path::new(&snapshot))?;
+            let weights = voter_weights(&holders, &config.governance);
+            let recipients = parse_recipients(&config.output.encrypt_to)?;
+            let output = sealed_path(Path::new(&output), &config.output.encrypt_to);
+            write_weights(&output, format, &recipients, &weights)?;
+            if json {
+                return print_json(&weights);
+            }
+
+            let total: u64 = weights.iter().map(|weight| weight.voting_power).sum();
+            println!(
+                "Wrote {} voters with {} total voting power to {}",
+                weights.len(),
+                total,
+                output.display()
+            );
+        }
+        SnapshotSubcommands::Validate { snapshot, output } => {
+            let holders = read_holders(Path::new(&snapshot))?;
+            let problems = validate_holders(&holders);
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &problems)?;
+            }
+            if json {
+                print_json(&problems)?;
+            } else {
+                for problem in &problems {
+                    println!("Row {} ({}): {}", problem.row, problem.mint_account, problem.detail);
+                }
+                println!("{} holders checked, {} problems", holders.len(), problems.len());
+            }
+            if !problems.is_empty() {
+                return Err(anyhow!("Snapshot {} has {} problems!", snapshot, problems.len()));
+            }
+        }
+        SnapshotSubcommands::Convert { snapshot, output } => {
+            let holders = read_holders(Path::new(&snapshot))?;
+            let path = Path::new(&output);
+            let (format, compression) = (format_of(path)?, compression_of(path));
+            write_holders(path, format, compression, None, &holders)?;
+            let report = ConvertReport {
+                holders: holders.len(),
+                bytes: fs::metadata(path)?.len(),
+                snapshot_bytes: fs::metadata(&snapshot)?.len(),
+                output,
+            };
+            if json {
+                return print_json(&report);
+            }
+            println!(
+                "Converted {} holders to {}: {} bytes, from {}",
+                report.holders, report.output, report.bytes, report.snapshot_bytes
+            );
+        }
+        SnapshotSubcommands::Decrypt {
+            snapshot,
+            identity,
+            output,
+        } => {
+            let output = output.unwrap_or_else(|| snapshot.trim_end_matches(".age").to_string());
+            if output == snapshot {
+                return Err(anyhow!("Snapshot {} has no .age extension, pass --output!", snapshot));
+            }
+            let identity = read_identity(Path::new(&identity))?;
+            unseal_file(Path::new(&snapshot), &identity, Path::new(&output))?;
+            if json {
+                return print_json(&json!({ "snapshot": snapshot, "output": output }));
+            }
+            println!("Decrypted {} to {}", snapshot, output);
+        }
+        SnapshotSubcommands::Index { snapshot, output } => {
+            // Lookups read the index in place, which encryption would prevent.
+            if !config.output.encrypt_to.is_empty() {
+                return Err(anyhow!("Holder indexes cannot be encrypted, drop --encrypt-to!"));
+            }
+            let holders = read_holders(Path::new(&snapshot))?;
+            let indexed = write_index(Path::new(&output), &holders)?;
+            if json {
+                return print_json(&json!({
+                    "indexed": indexed,
+                    "holders": holders.len(),
+                    "output": output,
+                }));
+            }
+            println!("Indexed {} of {} holders into {}", indexed, holders.len(), output);
+        }
+        SnapshotSubcommands::Proofs {
+            snapshot,
+            collection,
+            output,
+        } => {
+            let client = snapshot_backend(rpc, &config);
+            let holders = match (snapshot, collection) {
+                (Some(snapshot), _) => {
+                    let holders = read_holders(Path::new(&snapshot))?;
+                    check_cluster(&holders, &detect_cluster(&client)?)?;
+                    holders
+                }
+                (None, Some(collection)) => {
+                    compressed_holders(&client, &Pubkey::from_str(&collection)?)?
+                }
+                (None, None) => return Err(anyhow!("Give a snapshot or a --collection!")),
+            };
+            let proofs = holder_proofs(&client, &holders)?;
+            let output = sealed_path(Path::new(&output), &config.output.encrypt_to);
+            write_proofs(&output, &config.output, &proofs)?;
+            let missing_items = holders.len() - proofs.len();
+            if json {
+                print_json(&proofs)?;
+                return check_complete(missing_items, 0);
+            }
+
+            let output = output.display();
+            println!("Wrote {} of {} asset proofs to {}", proofs.len(), holders.len(), output);
+            check_complete(missing_items, 0)?;
+        }
+        SnapshotSubcommands::Combine {
+            nft_snapshot,
+            token_snapshot,
+            nft_weight,
+            token_weight,
+            max_tokens,
+            output,
+            format,
+        } => {
+            if let Some(nft_weight) = nft_weight {
+                config.combined.nft_weight = nft_weight;
+            }
+            if let Some(token_weight) = token_weight {
+                config.combined.token_weight = token_weight;
+            }
+            if max_tokens.is_some() {
+                config.combined.max_tokens = max_tokens;
+            }
+            let format = format.unwrap_or(config.output.format);
+            let output = output.unwrap_or_else(|| format!("scores.{}", format.extension()));
+
+            let nft_holders = read_holders(Path::new(&nft_snapshot))?;
+            let token_holders = read_holders(Path::new(&token_snapshot))?;
+            check_same_cluster(vec![&nft_holders[..], &token_holders[..]])?;
+            let scores = combined_scores(&nft_holders, &token_holders, &config.combined)?;
+            let recipients = parse_recipients(&config.output.encrypt_to)?;
+            let output = sealed_path(Path::new(&output), &config.output.encrypt_to);
+            write_scores(&output, format, &recipients, &scores)?;
+            if json {
+                return print_json(&scores);
+            }
+
+            let output = output.display();
+            println!("Wrote {} scored wallets to {}", scores.len(), output);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn process_wallet(
+    rpc: BatchingRpc,
+    config: Config,
+    config_path: Option<&str>,
+    cmd: WalletSubcommands,
+    json: bool,
+) -> Result<()> {
+    match cmd {
+        // Needs a signer, so main runs it through `process_close_empty`.
+        WalletSubcommands::CloseEmpty { .. } => {
+            return Err(anyhow!("Closing empty accounts needs a signer, see process_close_empty!"))
+        }
+        WalletSubcommands::Watch => {
+            let client = snapshot_backend(rpc, &config);
+            watch(&client, &config.watch, config_path, |changes| {
+                for change in changes {
+                    if json {
+                        if let Ok(line) = serde_json::to_string(change) {
+                            println!("{}", line);
+                        }
+                        continue;
+                    }
+                    let verb = match change.kind {
+                        ChangeKind::Acquired => "acquired",
+                        ChangeKind::Disposed => "disposed of",
+                    };
+                    println!(
+                        "{} {} {} {} ({})",
+                        change.wallet, verb, change.mint_account, change.name, change.collection
+                    );
+                }
+            })?;
+        }
+        WalletSubcommands::Audit { wallet, output } => {
+            let wallet = Pubkey::from_str(&wallet)?;
+            let client = snapshot_backend(rpc, &config);
+            let audit = audit_wallet(&client, &wallet)?;
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &audit)?;
+            }
+            if json {
+                return print_json(&audit);
+            }
+
+            for group in &audit.collections {
+                match group.grouped_by {
+                    GroupedBy::Collection => println!("Collection {}:", group.key),
+                    GroupedBy::Creator => println!("Creator {}:", group.key),
+                    GroupedBy::Unverified => println!("Unverified:"),
+                }
+                for item in &group.items {
+                    println!("  {} {}", item.mint_account, item.name);
+                }
+            }
+            println!("{} NFTs in {} collections", audit.nfts, audit.collections.len());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn process_mint(
+    rpc: BatchingRpc,
+    config: Config,
+    cmd: MintSubcommands,
+    json: bool,
+) -> Result<()> {
+    match cmd {
+        MintSubcommands::History {
+            mint,
+            limit,
+            output,
+        } => {
+            let mint = Pubkey::from_str(&mint)?;
+            let client = snapshot_backend(rpc, &config);
+            let history = ownership_history(&client, &mint, limit)?;
+            if let Some(output) = output {
+                write_wallet_report(&output, &config.output, &history)?;
+            }
+            if json {
+                return print_json(&history);
+            }
+
+            for change in &history {
+                println!(
+                    "{} {} {} ({})",
+                    change.block_time.map_or("?".to_string(), |time| time.to_string()),
+                    change.owner.as_deref().unwrap_or("unknown owner"),
+                    change.token_account,
+                    change.signature
+                );
+            }
+            println!("{} ownership changes", history.len());
+        }
+        MintSubcommands::WhoHolds { mint, index } => {
+            let mint = Pubkey::from_str(&mint)?;
+            let holders = HolderIndex::open(Path::new(&index))?.lookup(&mint)?;
+            if json {
+                return print_json(&holders);
+            }
+            if holders.is_empty() {
+                println!("{} is not in {}", mint, index);
+            }
+            for holder in &holders {
+                println!("{} ({})", holder.owner_wallet, holder.associated_token_address);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Closes the signer's accounts in the `report`, several per transaction.
+pub fn process_close_empty(
+    client: &ProgramClient,
+    report: &str,
+    dry_run: bool,
+    confirm_above: Option<f64>,
+    json: bool,
+) -> Result<()> {
+    let empty: Vec<EmptyAccount> = serde_json::from_reader(open_reader(Path::new(report))?)?;
+    let owner = client.payer.pubkey();
+    let instructions = close_empty_instructions(&empty, &owner)?;
+
+    let owned: Vec<&EmptyAccount> = empty
+        .iter()
+        .filter(|account| account.owner_wallet == owner.to_string())
+        .collect();
+    let lamports: u64 = owned.iter().map(|account| account.lamports).sum();
+    if !json {
+        println!(
+            "{} of {} empty accounts belong to {}, holding {} SOL of rent",
+            owned.len(),
+            empty.len(),
+            owner,
+            lamports_to_sol(lamports)
+        );
+    }
+    let transactions: Vec<Vec<Instruction>> = instructions
+        .chunks(CLOSES_PER_TRANSACTION)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+    let estimate = client.estimate(&transactions, &[])?;
+    if dry_run {
+        confirm_cost(&estimate, None, json)?;
+        if json {
+            return print_json(&json!({ "estimate": estimate, "accounts": owned }));
+        }
+        for account in owned {
+            println!("  {} ({})", account.token_account, account.mint_account);
+        }
+        return Ok(());
+    }
+    confirm_cost(&estimate, confirm_above, json)?;
+
+    // Later transactions do not depend on earlier ones, so every one is sent.
+    let mut failed = 0;
+    let mut sent = Vec::with_capacity(transactions.len());
+    for chunk in transactions {
+        match client.send(&chunk, &[]) {
+            Ok(signature) => {
+                if !json {
+                    println!("Closed {} accounts: {}", chunk.len(), signature);
+                }
+                sent.push(SentTransaction {
+                    accounts: Some(chunk.len()),
+                    ..SentTransaction::new(signature)
+                });
+            }
+            Err(err) => {
+                // Logged so JSON output stays parseable.
+                error!("Failed to close {} accounts: {}", chunk.len(), err);
+                failed += chunk.len();
+            }
+        }
+    }
+    if json {
+        print_json(&SendReport {
+            estimate: Some(estimate),
+            sent,
+        })?;
+    }
+    if failed > 0 {
+        return Err(anyhow!("Failed to close {} of {} accounts!", failed, instructions.len()));
+    }
+
+    Ok(())
+}
+
+pub fn process_fixtures(
+    client: &ProgramClient,
+    cmd: FixturesSubcommands,
+    confirm_above: Option<f64>,
+    json: bool,
+) -> Result<()> {
+    match cmd {
+        FixturesSubcommands::MintCollection {
+            size,
+            name,
+            symbol,
+            uri,
+            seller_fee_basis_points,
+            collection_nft,
+            holders,
+            output,
+        } => {
+            if detect_cluster(&client.rpc)? == Cluster::Mainnet {
+                return Err(anyhow!("Fixtures are not minted on mainnet!"));
+            }
+            let fixture = CollectionFixture {
+                size,
+                name,
+                symbol,
+                uri,
+                seller_fee_basis_points,
+                collection_nft,
+                owners: (0..holders).map(|_| Keypair::new().pubkey()).collect(),
+            };
+            let plans = plan_collection(client, &fixture)?;
+            let transactions: Vec<Vec<Instruction>> =
+                plans.iter().map(|plan| plan.instructions.clone()).collect();
+            let sizes: Vec<usize> = plans.iter().flat_map(|_| account_sizes()).collect();
+            confirm_cost(&client.estimate(&transactions, &sizes)?, confirm_above, json)?;
+
+            let minted = mint_collection(client, &fixture, &plans)?;
+            if let Some(path) = output {
+                serde_json::to_writer_pretty(File::create(path)?, &minted)?;
+            }
+            if json {
+                return print_json(&minted);
+            }
+            if let Some(collection_mint) = &minted.collection_mint {
+                println!("Collection NFT {}", collection_mint);
+            }
+            println!(
+                "Minted {} items with update authority {}",
+                minted.items.len(),
+                minted.update_authority
+            );
+        }
+    }
+    Ok(())
+}
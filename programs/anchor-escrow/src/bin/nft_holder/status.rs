@@ -0,0 +1,66 @@
+use {
+    anchor_escrow::{
+        retry_policy::{classify, FailureClass},
+        snapshot::SnapshotError,
+    },
+    anyhow::Result,
+    serde::Serialize,
+    solana_client::client_error::ClientError,
+};
+
+/// Why a run ended, as its exit code. The codes are part of the CLI's
+/// interface: wrappers branch on them, so never renumber one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitCode {
+    Success = 0,
+    /// Anything not covered below, e.g. a bad config file or argument.
+    Failure = 1,
+    /// The RPC endpoint failed, after retries.
+    RpcFailure = 2,
+    /// The output was written, but items or collections are missing.
+    PartialResults = 3,
+    /// The source does not describe a collection, e.g. a creator that no
+    /// metadata lists at the given position.
+    InvalidCollection = 4,
+    /// The RPC provider kept rate limiting, after retries.
+    RateLimited = 5,
+}
+
+impl ExitCode {
+    pub fn of(result: &Result<()>) -> Self {
+        let err = match result {
+            Ok(()) => return ExitCode::Success,
+            Err(err) => err,
+        };
+        match err.downcast_ref::<SnapshotError>() {
+            Some(SnapshotError::PartialResults { .. }) => return ExitCode::PartialResults,
+            Some(_) => return ExitCode::InvalidCollection,
+            None => {}
+        }
+        match classify(err) {
+            FailureClass::RateLimited => ExitCode::RateLimited,
+            _ if err.downcast_ref::<ClientError>().is_some() => ExitCode::RpcFailure,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+/// The last line a run writes to stderr.
+#[derive(Debug, Serialize)]
+pub struct ExitStatus {
+    pub status: ExitCode,
+    pub exit_code: i32,
+    pub error: Option<String>,
+}
+
+impl ExitStatus {
+    pub fn new(result: &Result<()>) -> Self {
+        let status = ExitCode::of(result);
+        ExitStatus {
+            status,
+            exit_code: status as i32,
+            error: result.as_ref().err().map(|err| format!("{:#}", err)),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use {
+    anchor_escrow::{
+        metrics::{RpcMetrics, RpcStats},
+        progress::{Progress, ProgressCounts},
+    },
+    anyhow::{anyhow, Result},
+    crossterm::{
+        cursor, execute,
+        terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    gag::BufferRedirect,
+    log::{LevelFilter, Log, Metadata, Record},
+    ratatui::{
+        backend::{Backend, CrosstermBackend},
+        layout::{Constraint, Direction, Layout},
+        widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
+        Frame, Terminal,
+    },
+    std::{
+        collections::VecDeque,
+        io::{self, Read},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    },
+};
+
+const LOG_LINES: usize = 500;
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// The latest log lines, kept for the dashboard rather than written to
+/// stderr, which it draws on.
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+struct BufferLogger {
+    buffer: LogBuffer,
+    level: LevelFilter,
+}
+
+impl Log for BufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let mut lines = self.buffer.lines.lock().unwrap();
+            if lines.len() == LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(format!("{} {}", record.level(), record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl LogBuffer {
+    /// Installs a logger collecting records up to `level`.
+    pub fn install(level: LevelFilter) -> Result<Self> {
+        let buffer = LogBuffer::default();
+        log::set_boxed_logger(Box::new(BufferLogger {
+            buffer: buffer.clone(),
+            level,
+        }))?;
+        log::set_max_level(level);
+        Ok(buffer)
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Full screen progress display on stderr. Whatever is printed to stdout
+/// meanwhile is held back and printed once the dashboard closes.
+pub struct Dashboard {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<()>>,
+    stdout: BufferRedirect,
+    logs: LogBuffer,
+}
+
+impl Dashboard {
+    pub fn start(
+        progress: Arc<Progress>,
+        metrics: Arc<RpcMetrics>,
+        logs: LogBuffer,
+    ) -> Result<Self> {
+        let stdout = BufferRedirect::stdout()?;
+        let mut stderr = io::stderr();
+        execute!(stderr, EnterAlternateScreen, cursor::Hide)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stderr))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (stop, logs) = (stop.clone(), logs.clone());
+            thread::spawn(move || -> Result<()> {
+                let started = Instant::now();
+                let (mut sampled_at, mut sampled) = (started, metrics.totals());
+                let mut calls_per_sec = 0.0;
+                while !stop.load(Ordering::Relaxed) {
+                    let totals = metrics.totals();
+                    let elapsed = sampled_at.elapsed().as_secs_f64();
+                    if elapsed >= 1.0 {
+                        calls_per_sec = totals.since(&sampled).calls as f64 / elapsed;
+                        sampled_at = Instant::now();
+                        sampled = totals;
+                    }
+                    let status = Status {
+                        elapsed: started.elapsed(),
+                        counts: progress.counts(),
+                        totals,
+                        calls_per_sec,
+                    };
+                    let lines = logs.lines();
+                    terminal.draw(|f| draw(f, &status, &lines))?;
+                    thread::sleep(REFRESH);
+                }
+                Ok(())
+            })
+        };
+
+        Ok(Dashboard {
+            stop,
+            handle,
+            stdout,
+            logs,
+        })
+    }
+
+    /// Restores the terminal, then prints the held back output and logs.
+    pub fn stop(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        let drawn = self
+            .handle
+            .join()
+            .unwrap_or_else(|_| Err(anyhow!("Dashboard thread panicked!")));
+        execute!(io::stderr(), LeaveAlternateScreen, cursor::Show)?;
+
+        let mut output = String::new();
+        self.stdout.into_inner().read_to_string(&mut output)?;
+        for line in self.logs.lines() {
+            eprintln!("{}", line);
+        }
+        print!("{}", output);
+        drawn
+    }
+}
+
+struct Status {
+    elapsed: Duration,
+    counts: ProgressCounts,
+    totals: RpcStats,
+    calls_per_sec: f64,
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, status: &Status, lines: &[String]) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Length(3), Constraint::Min(3)].as_ref())
+        .split(f.size());
+
+    let counts = &status.counts;
+    let ratio = match counts.items_total {
+        0 => 0.0,
+        total => (counts.items_done as f64 / total as f64).min(1.0),
+    };
+    let gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title("Items"))
+        .ratio(ratio)
+        .label(format!("{} / {}", counts.items_done, counts.items_total));
+    f.render_widget(gauge, chunks[0]);
+
+    let stats = Paragraph::new(format!(
+        "{} holders   {} errors   {} RPC calls ({:.1}/s)   {:.2} MB   {}s",
+        counts.holders,
+        counts.errors,
+        status.totals.calls,
+        status.calls_per_sec,
+        status.totals.bytes as f64 / (1024.0 * 1024.0),
+        status.elapsed.as_secs()
+    ))
+    .block(Block::default().borders(Borders::ALL).title("Snapshot"));
+    f.render_widget(stats, chunks[1]);
+
+    let height = chunks[2].height.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = lines[lines.len().saturating_sub(height)..]
+        .iter()
+        .map(|line| ListItem::new(line.as_str()))
+        .collect();
+    let log = List::new(items).block(Block::default().borders(Borders::ALL).title("Log"));
+    f.render_widget(log, chunks[2]);
+}
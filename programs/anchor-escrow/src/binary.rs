@@ -0,0 +1,250 @@
+use {
+    crate::{
+        bulk::Interner,
+        enrich::OwnerKind,
+        snapshot::{Holder, ResolveMethod},
+    },
+    anyhow::{anyhow, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        borrow::Borrow,
+        io::{Read, Write},
+    },
+};
+
+/// Starts every binary snapshot, the format version last.
+const MAGIC: &[u8; 8] = b"NFTHBIN3";
+/// Binary snapshots encoded whole, from before they were streamed.
+const MAGIC_V2: &[u8; 8] = b"NFTHBIN2";
+/// Binary snapshots from before the cluster was recorded.
+const MAGIC_V1: &[u8; 8] = b"NFTHBIN1";
+/// The layout of `Row`, bumped whenever a field is added or changed. Rows of
+/// older layouts are then decoded with a struct of their own.
+const LAYOUT: u32 = 1;
+
+/// A `Holder` with every string an index into the snapshot's string table.
+#[derive(Serialize, Deserialize)]
+struct Row {
+    owner_wallet: u32,
+    associated_token_address: u32,
+    mint_account: u32,
+    metadata_account: u32,
+    frozen: Option<bool>,
+    owner_kind: Option<OwnerKind>,
+    owner_program: Option<u32>,
+    sol_domain: Option<u32>,
+    holding_since: Option<i64>,
+    holding_days: Option<u64>,
+    multisig_signers: Option<u32>,
+    multisig: Option<u32>,
+    name: Option<u32>,
+    master_edition: Option<u32>,
+    edition_number: Option<u64>,
+    amount: Option<u32>,
+    resolved_by: Option<ResolveMethod>,
+    listed: Option<bool>,
+    listed_with: Option<u32>,
+    image: Option<u32>,
+    metadata_sha256: Option<u32>,
+    metadata_changed: Option<bool>,
+    custodian: Option<u32>,
+    custodian_country: Option<u32>,
+    mint_url: Option<u32>,
+    owner_url: Option<u32>,
+    token_account_url: Option<u32>,
+}
+
+/// Follows the magic; records follow it up to `Record::End`, all bincode
+/// encoded.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// See `LAYOUT`.
+    layout: u32,
+    /// The cluster of every holder.
+    cluster: Option<String>,
+}
+
+/// The string table is written as it grows, each string ahead of the first
+/// row using it, so holders are written as they come.
+#[derive(Serialize, Deserialize)]
+enum Record {
+    /// The next string of the string table.
+    String(String),
+    Row(Row),
+    /// After the last row, so a truncated snapshot is not mistaken for a
+    /// smaller one.
+    End,
+}
+
+/// Every distinct string once, then the rows.
+#[derive(Deserialize)]
+struct BinarySnapshotV2 {
+    cluster: Option<String>,
+    strings: Vec<String>,
+    rows: Vec<Row>,
+}
+
+/// `BinarySnapshotV2` before the cluster was recorded.
+#[derive(Deserialize)]
+struct BinarySnapshotV1 {
+    strings: Vec<String>,
+    rows: Vec<Row>,
+}
+
+fn row(strings: &mut Interner<String>, holder: &Holder) -> Row {
+    let mut id = |value: &String| strings.intern(value);
+    Row {
+        owner_wallet: id(&holder.owner_wallet),
+        associated_token_address: id(&holder.associated_token_address),
+        mint_account: id(&holder.mint_account),
+        metadata_account: id(&holder.metadata_account),
+        frozen: holder.frozen,
+        owner_kind: holder.owner_kind,
+        owner_program: holder.owner_program.as_ref().map(&mut id),
+        sol_domain: holder.sol_domain.as_ref().map(&mut id),
+        holding_since: holder.holding_since,
+        holding_days: holder.holding_days,
+        multisig_signers: holder.multisig_signers.as_ref().map(&mut id),
+        multisig: holder.multisig.as_ref().map(&mut id),
+        name: holder.name.as_ref().map(&mut id),
+        master_edition: holder.master_edition.as_ref().map(&mut id),
+        edition_number: holder.edition_number,
+        amount: holder.amount.as_ref().map(&mut id),
+        resolved_by: holder.resolved_by,
+        listed: holder.listed,
+        listed_with: holder.listed_with.as_ref().map(&mut id),
+        image: holder.image.as_ref().map(&mut id),
+        metadata_sha256: holder.metadata_sha256.as_ref().map(&mut id),
+        metadata_changed: holder.metadata_changed,
+        custodian: holder.custodian.as_ref().map(&mut id),
+        custodian_country: holder.custodian_country.as_ref().map(&mut id),
+        mint_url: holder.mint_url.as_ref().map(&mut id),
+        owner_url: holder.owner_url.as_ref().map(&mut id),
+        token_account_url: holder.token_account_url.as_ref().map(&mut id),
+    }
+}
+
+/// Writes `holders` as a binary snapshot: owners, mints and anything else
+/// repeated are stored once, and rows decode without parsing text, so large
+/// snapshots read back many times, e.g. for diffs and stats, load quickly.
+/// Each holder is written as it comes. The cluster is stored once, so the
+/// holders must share it.
+pub fn write_binary<I, H>(w: &mut dyn Write, holders: I) -> Result<()>
+where
+    I: IntoIterator<Item = H>,
+    H: Borrow<Holder>,
+{
+    let mut holders = holders.into_iter().peekable();
+    let cluster = holders
+        .peek()
+        .and_then(|holder| holder.borrow().cluster.clone());
+    w.write_all(MAGIC)?;
+    let header = Header {
+        layout: LAYOUT,
+        cluster: cluster.clone(),
+    };
+    bincode::serialize_into(&mut *w, &header)?;
+
+    let mut strings = Interner::new();
+    for holder in holders {
+        let holder = holder.borrow();
+        if holder.cluster != cluster {
+            return Err(anyhow!(
+                "Holders of clusters {:?} and {:?} cannot be written to one snapshot!",
+                cluster,
+                holder.cluster
+            ));
+        }
+        let known = strings.values().len();
+        let encoded = row(&mut strings, holder);
+        for value in &strings.values()[known..] {
+            bincode::serialize_into(&mut *w, &Record::String(value.clone()))?;
+        }
+        bincode::serialize_into(&mut *w, &Record::Row(encoded))?;
+    }
+    bincode::serialize_into(&mut *w, &Record::End)?;
+    Ok(())
+}
+
+/// Reads a snapshot written by `write_binary`, or by earlier versions of it.
+pub fn read_binary(mut reader: impl Read) -> Result<Vec<Holder>> {
+    let mut magic = [0u8; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|_| anyhow!("Snapshot is too short for a binary snapshot!"))?;
+    let snapshot: BinarySnapshotV2 = match &magic {
+        MAGIC => return read_records(reader),
+        MAGIC_V2 => bincode::deserialize_from(reader)?,
+        MAGIC_V1 => {
+            let snapshot: BinarySnapshotV1 = bincode::deserialize_from(reader)?;
+            BinarySnapshotV2 {
+                cluster: None,
+                strings: snapshot.strings,
+                rows: snapshot.rows,
+            }
+        }
+        _ => return Err(anyhow!("Snapshot is not a binary snapshot!")),
+    };
+    snapshot
+        .rows
+        .into_iter()
+        .map(|row| holder(&snapshot.strings, &snapshot.cluster, row))
+        .collect()
+}
+
+/// The records after the magic of a streamed snapshot.
+fn read_records(mut reader: impl Read) -> Result<Vec<Holder>> {
+    let header: Header = bincode::deserialize_from(&mut reader)?;
+    if header.layout != LAYOUT {
+        return Err(anyhow!(
+            "Snapshot rows have layout {}, which this version cannot read!",
+            header.layout
+        ));
+    }
+    let (mut strings, mut holders) = (Vec::new(), Vec::new());
+    loop {
+        match bincode::deserialize_from(&mut reader)? {
+            Record::String(value) => strings.push(value),
+            Record::Row(row) => holders.push(holder(&strings, &header.cluster, row)?),
+            Record::End => return Ok(holders),
+        }
+    }
+}
+
+/// The holder `row` stands for, its strings looked up in `strings`.
+fn holder(strings: &[String], cluster: &Option<String>, row: Row) -> Result<Holder> {
+    let get = |id: u32| {
+        let value = strings.get(id as usize).cloned();
+        value.ok_or_else(|| anyhow!("String {} is not in the string table!", id))
+    };
+    Ok(Holder {
+        owner_wallet: get(row.owner_wallet)?,
+        associated_token_address: get(row.associated_token_address)?,
+        mint_account: get(row.mint_account)?,
+        metadata_account: get(row.metadata_account)?,
+        frozen: row.frozen,
+        owner_kind: row.owner_kind,
+        owner_program: row.owner_program.map(get).transpose()?,
+        sol_domain: row.sol_domain.map(get).transpose()?,
+        holding_since: row.holding_since,
+        holding_days: row.holding_days,
+        multisig_signers: row.multisig_signers.map(get).transpose()?,
+        multisig: row.multisig.map(get).transpose()?,
+        name: row.name.map(get).transpose()?,
+        master_edition: row.master_edition.map(get).transpose()?,
+        edition_number: row.edition_number,
+        amount: row.amount.map(get).transpose()?,
+        resolved_by: row.resolved_by,
+        listed: row.listed,
+        listed_with: row.listed_with.map(get).transpose()?,
+        image: row.image.map(get).transpose()?,
+        metadata_sha256: row.metadata_sha256.map(get).transpose()?,
+        metadata_changed: row.metadata_changed,
+        custodian: row.custodian.map(get).transpose()?,
+        custodian_country: row.custodian_country.map(get).transpose()?,
+        cluster: cluster.clone(),
+        mint_url: row.mint_url.map(get).transpose()?,
+        owner_url: row.owner_url.map(get).transpose()?,
+        token_account_url: row.token_account_url.map(get).transpose()?,
+    })
+}
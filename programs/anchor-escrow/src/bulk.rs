@@ -0,0 +1,219 @@
+use {
+    crate::{
+        config::OutputConfig,
+        encryption::{parse_recipients, sealed_path},
+        output::{write_holders_from, OutputFormat},
+        snapshot::Holder,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    std::{collections::HashMap, hash::Hash, path::Path, str::FromStr},
+};
+
+/// Rows per arena chunk. Chunks are allocated whole and never grown, so
+/// adding rows never copies the ones before or briefly needs twice their
+/// memory, as a growing `Vec` does.
+const CHUNK_ROWS: usize = 64 * 1024;
+
+/// Values stored once however many rows refer to them, by index.
+pub(crate) struct Interner<T> {
+    values: Vec<T>,
+    ids: HashMap<T, u32>,
+}
+
+impl<T: Clone + Eq + Hash> Interner<T> {
+    pub(crate) fn new() -> Self {
+        Interner {
+            values: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn intern(&mut self, value: &T) -> u32 {
+        if let Some(id) = self.ids.get(value) {
+            return *id;
+        }
+        let id = self.values.len() as u32;
+        self.values.push(value.clone());
+        self.ids.insert(value.clone(), id);
+        id
+    }
+
+    fn get(&self, id: u32) -> &T {
+        &self.values[id as usize]
+    }
+
+    /// The values, each at its index.
+    pub(crate) fn values(&self) -> &[T] {
+        &self.values
+    }
+}
+
+/// A holder in under 80 bytes rather than a `Holder`'s several hundred: the
+/// token account inline, everything repeated across rows as an index.
+struct Row {
+    token_account: Pubkey,
+    owner: u32,
+    mint: u32,
+    metadata: u32,
+    /// Start and length of the UI amount in `HolderTable::amounts`.
+    amount: Option<(u32, u16)>,
+    frozen: Option<bool>,
+    custodian: Option<u32>,
+    custodian_country: Option<u32>,
+}
+
+/// Holders packed for snapshots of hundreds of thousands of token accounts,
+/// where `Vec<Holder>` takes gigabytes: rows go into preallocated chunks,
+/// amounts into one string arena, and addresses are interned, as owner
+/// wallets come up many times. Only the fields a snapshot and the filters
+/// set are kept; enrichment fields are dropped.
+pub struct HolderTable {
+    chunks: Vec<Vec<Row>>,
+    addresses: Interner<Pubkey>,
+    /// Custodian names and countries.
+    labels: Interner<String>,
+    amounts: String,
+    /// Cluster of every holder, as they come from one snapshot.
+    cluster: Option<String>,
+    len: usize,
+}
+
+impl Default for HolderTable {
+    fn default() -> Self {
+        HolderTable::new()
+    }
+}
+
+fn address(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|_| anyhow!("Holder address {} is not a public key!", value))
+}
+
+impl HolderTable {
+    pub fn new() -> Self {
+        HolderTable {
+            chunks: vec![Vec::with_capacity(CHUNK_ROWS)],
+            addresses: Interner::new(),
+            labels: Interner::new(),
+            amounts: String::new(),
+            cluster: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Distinct addresses stored, owners, mints and metadata accounts alike.
+    pub fn addresses(&self) -> usize {
+        self.addresses.values.len()
+    }
+
+    /// Holders with addresses that are not public keys, e.g. pseudonymized
+    /// ones, or of another cluster than the first cannot be stored.
+    pub fn push(&mut self, holder: &Holder) -> Result<()> {
+        if self.len == 0 {
+            self.cluster = holder.cluster.clone();
+        } else if holder.cluster != self.cluster {
+            return Err(anyhow!(
+                "Holder of cluster {:?} cannot join holders of cluster {:?}!",
+                holder.cluster,
+                self.cluster
+            ));
+        }
+        let amount = match &holder.amount {
+            Some(amount) => {
+                let start = self.amounts.len() as u32;
+                self.amounts.push_str(amount);
+                Some((start, amount.len() as u16))
+            }
+            None => None,
+        };
+        let row = Row {
+            token_account: address(&holder.associated_token_address)?,
+            owner: self.addresses.intern(&address(&holder.owner_wallet)?),
+            mint: self.addresses.intern(&address(&holder.mint_account)?),
+            metadata: self.addresses.intern(&address(&holder.metadata_account)?),
+            amount,
+            frozen: holder.frozen,
+            custodian: holder.custodian.as_ref().map(|name| self.labels.intern(name)),
+            custodian_country: holder
+                .custodian_country
+                .as_ref()
+                .map(|country| self.labels.intern(country)),
+        };
+
+        if self.chunks.last().map_or(true, |chunk| chunk.len() == CHUNK_ROWS) {
+            self.chunks.push(Vec::with_capacity(CHUNK_ROWS));
+        }
+        self.chunks.last_mut().unwrap().push(row);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// The holders in the order they were pushed, each made as it is read.
+    pub fn iter(&self) -> impl Iterator<Item = Holder> + '_ {
+        self.chunks.iter().flatten().map(move |row| self.holder(row))
+    }
+
+    fn holder(&self, row: &Row) -> Holder {
+        let label = |id: Option<u32>| id.map(|id| self.labels.get(id).clone());
+        Holder {
+            owner_wallet: self.addresses.get(row.owner).to_string(),
+            associated_token_address: row.token_account.to_string(),
+            mint_account: self.addresses.get(row.mint).to_string(),
+            metadata_account: self.addresses.get(row.metadata).to_string(),
+            frozen: row.frozen,
+            amount: row.amount.map(|(start, len)| {
+                self.amounts[start as usize..start as usize + len as usize].to_string()
+            }),
+            custodian: label(row.custodian),
+            custodian_country: label(row.custodian_country),
+            cluster: self.cluster.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Writes `table` in `output.format` and each of `output.extra_formats`,
+/// named as `sink::open_sinks` names them, and returns the files written.
+/// Rows are streamed from the table, so pseudonymized and grouped output,
+/// which need every holder at once, are refused.
+pub fn write_table(
+    path: &Path,
+    output: &OutputConfig,
+    table: &HolderTable,
+) -> Result<Vec<String>> {
+    if output.pseudonymize || output.group_by_owner || output.group_by_edition {
+        return Err(anyhow!(
+            "Bulk snapshots cannot be pseudonymized or grouped, unset output.pseudonymize, \
+             output.group_by_owner and output.group_by_edition!"
+        ));
+    }
+
+    let mut formats: Vec<OutputFormat> = vec![output.format];
+    for format in &output.extra_formats {
+        if !formats.contains(format) {
+            formats.push(*format);
+        }
+    }
+
+    let recipients = parse_recipients(&output.encrypt_to)?;
+    let mut written = Vec::with_capacity(formats.len());
+    for (index, format) in formats.into_iter().enumerate() {
+        let path = match index {
+            0 => path.to_path_buf(),
+            _ => path.with_extension(format.extension()),
+        };
+        let path = sealed_path(&output.compress.apply_extension(&path), &output.encrypt_to);
+        let (compress, links) = (output.compress, output.explorer_links);
+        write_holders_from(&path, format, compress, &recipients, links, table.iter())?;
+        written.push(path.display().to_string());
+    }
+    Ok(written)
+}
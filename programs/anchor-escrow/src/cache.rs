@@ -0,0 +1,272 @@
+use {
+    crate::{
+        das::{AssetPage, AssetProof},
+        metrics::RpcMetrics,
+        rpc::RpcBackend,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    log::{debug, warn},
+    serde::Deserialize,
+    mpl_token_metadata::ID as TOKEN_METADATA_PROGRAM_ID,
+    solana_account_decoder::{UiAccount, UiAccountEncoding},
+    solana_client::{
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_response::{RpcConfirmedTransactionStatusWithSignature, RpcKeyedAccount},
+    },
+    solana_sdk::{
+        account::Account,
+        clock::DEFAULT_MS_PER_SLOT,
+        hash::{hashv, Hash},
+        signature::Signature,
+    },
+    solana_transaction_status::EncodedConfirmedTransaction,
+    std::{
+        fs::{self, File},
+        path::PathBuf,
+        str::FromStr,
+    },
+};
+
+/// `[rpc.cache]`: where and how long gPA responses are kept.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GpaCacheConfig {
+    /// Cache directory; nothing is cached when unset.
+    pub dir: Option<String>,
+    /// Width of a cache bucket, see `CachedRpc`.
+    pub ttl_secs: u64,
+}
+
+impl Default for GpaCacheConfig {
+    fn default() -> Self {
+        GpaCacheConfig {
+            dir: None,
+            ttl_secs: 10 * 60,
+        }
+    }
+}
+
+impl GpaCacheConfig {
+    /// `inner` behind the cache, which passes every call through when no
+    /// directory is configured.
+    pub fn wrap<B: RpcBackend>(&self, inner: B) -> CachedRpc<B> {
+        let dir = self.dir.clone().unwrap_or_default();
+        let mut cached = CachedRpc::new(inner, PathBuf::from(dir), self.ttl_secs);
+        if self.dir.is_none() {
+            cached.programs.clear();
+        }
+        cached
+    }
+}
+
+/// Caches getProgramAccounts responses on disk, keyed by program, filter set
+/// and slot bucket. Buckets are `ttl_secs` wide, so a run repeated within the
+/// TTL reuses the response, though a run straddling a bucket boundary misses.
+///
+/// Only programs listed in `programs` are cached; by default that is token
+/// metadata, whose creator scans are the most expensive call of a snapshot
+/// and change slowly. Token account lookups always go to the backend.
+pub struct CachedRpc<B> {
+    pub inner: B,
+    pub dir: PathBuf,
+    pub ttl_secs: u64,
+    pub programs: Vec<Pubkey>,
+}
+
+impl<B: RpcBackend> CachedRpc<B> {
+    pub fn new(inner: B, dir: PathBuf, ttl_secs: u64) -> Self {
+        CachedRpc {
+            inner,
+            dir,
+            ttl_secs,
+            programs: vec![TOKEN_METADATA_PROGRAM_ID],
+        }
+    }
+
+    fn bucket_slots(&self) -> u64 {
+        (self.ttl_secs * 1000 / DEFAULT_MS_PER_SLOT).max(1)
+    }
+
+    fn cache_path(
+        &self,
+        program_id: &Pubkey,
+        config: &RpcProgramAccountsConfig,
+        slot: u64,
+    ) -> Result<PathBuf> {
+        let bucket = slot / self.bucket_slots();
+        let filters = serde_json::to_string(&config.filters)?;
+        let data_slice = serde_json::to_string(&config.account_config.data_slice)?;
+        let key = hashv(&[
+            program_id.as_ref(),
+            filters.as_bytes(),
+            data_slice.as_bytes(),
+            &bucket.to_le_bytes(),
+        ]);
+        Ok(self.dir.join(format!("{}.json", key)))
+    }
+
+    fn read(&self, path: &PathBuf) -> Result<Vec<(Pubkey, Account)>> {
+        let keyed: Vec<RpcKeyedAccount> = serde_json::from_reader(File::open(path)?)?;
+        keyed
+            .into_iter()
+            .map(|RpcKeyedAccount { pubkey, account }| {
+                let decoded = account
+                    .decode()
+                    .ok_or(anyhow!("Cached account {} could not be decoded!", pubkey))?;
+                Ok((Pubkey::from_str(&pubkey)?, decoded))
+            })
+            .collect()
+    }
+
+    fn write(&self, path: &PathBuf, accounts: &[(Pubkey, Account)]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let keyed: Vec<RpcKeyedAccount> = accounts
+            .iter()
+            .map(|(pubkey, account)| RpcKeyedAccount {
+                pubkey: pubkey.to_string(),
+                account: UiAccount::encode(pubkey, account, UiAccountEncoding::Base64, None, None),
+            })
+            .collect();
+        serde_json::to_writer(File::create(path)?, &keyed)?;
+        Ok(())
+    }
+}
+
+impl<B: RpcBackend> RpcBackend for CachedRpc<B> {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        if !self.programs.contains(program_id) {
+            return self.inner.get_program_accounts_with_config(program_id, config);
+        }
+
+        let slot = self.inner.get_slot()?;
+        let path = self.cache_path(program_id, &config, slot)?;
+        if path.exists() {
+            match self.read(&path) {
+                Ok(accounts) => {
+                    debug!("gPA cache hit {}", path.display());
+                    return Ok(accounts);
+                }
+                Err(err) => warn!("Ignoring unreadable cache file {}: {}", path.display(), err),
+            }
+        }
+
+        let accounts = self.inner.get_program_accounts_with_config(program_id, config)?;
+        if let Err(err) = self.write(&path, &accounts) {
+            warn!("Failed to write cache file {}: {}", path.display(), err);
+        }
+
+        Ok(accounts)
+    }
+
+    // Cached programs are looked up one query at a time, as each has its own
+    // cache file.
+    fn get_program_accounts_batch(
+        &self,
+        program_id: &Pubkey,
+        configs: Vec<RpcProgramAccountsConfig>,
+    ) -> Result<Vec<Result<Vec<(Pubkey, Account)>>>> {
+        if !self.programs.contains(program_id) {
+            return self.inner.get_program_accounts_batch(program_id, configs);
+        }
+        Ok(configs
+            .into_iter()
+            .map(|config| self.get_program_accounts_with_config(program_id, config))
+            .collect())
+    }
+
+    fn batch_size(&self) -> usize {
+        self.inner.batch_size()
+    }
+
+    fn metrics(&self) -> Option<&RpcMetrics> {
+        self.inner.metrics()
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        self.inner.get_multiple_accounts(pubkeys)
+    }
+
+    fn get_slot(&self) -> Result<u64> {
+        self.inner.get_slot()
+    }
+
+    fn get_genesis_hash(&self) -> Result<Hash> {
+        self.inner.get_genesis_hash()
+    }
+
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.inner.get_signatures_for_address(address, before, limit)
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction> {
+        self.inner.get_transaction(signature)
+    }
+
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof> {
+        self.inner.get_asset_proof(asset_id)
+    }
+
+    fn get_assets_by_collection(&self, collection: &Pubkey, page: usize) -> Result<AssetPage> {
+        self.inner.get_assets_by_collection(collection, page)
+    }
+
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+        self.inner.get_token_largest_accounts(mint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::rpc::MockRpc, std::process};
+
+    fn mock() -> MockRpc {
+        let mut rpc = MockRpc::new();
+        let account = Account {
+            lamports: 1,
+            data: vec![4; 8],
+            owner: TOKEN_METADATA_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        rpc.add_account(Pubkey::new_unique(), account);
+        rpc
+    }
+
+    fn scan(rpc: &dyn RpcBackend) -> usize {
+        let config = RpcProgramAccountsConfig::default();
+        let accounts = rpc.get_program_accounts_with_config(&TOKEN_METADATA_PROGRAM_ID, config);
+        accounts.unwrap().len()
+    }
+
+    #[test]
+    fn repeated_scans_are_answered_from_the_cache() {
+        let dir = std::env::temp_dir().join(format!("gpa-cache-{}", process::id()));
+        let config = GpaCacheConfig {
+            dir: Some(dir.display().to_string()),
+            ..GpaCacheConfig::default()
+        };
+        let mut cached = config.wrap(mock());
+        assert_eq!(scan(&cached), 1);
+        cached.inner.accounts.clear();
+        assert_eq!(scan(&cached), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_cached_without_a_dir() {
+        let mut cached = GpaCacheConfig::default().wrap(mock());
+        assert_eq!(scan(&cached), 1);
+        cached.inner.accounts.clear();
+        assert_eq!(scan(&cached), 0);
+    }
+}
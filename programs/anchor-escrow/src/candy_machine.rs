@@ -0,0 +1,175 @@
+use {
+    crate::{rpc::RpcBackend, snapshot::Holder},
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    mpl_token_metadata::state::{
+        Metadata, MAX_CREATOR_LEN, MAX_CREATOR_LIMIT, MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH,
+        MAX_URI_LENGTH,
+    },
+    serde::Serialize,
+    std::{collections::HashMap, convert::TryInto},
+};
+
+/// Where the config lines of a candy machine v2 account start. The fields
+/// before them are serialized at their maximum size.
+const CONFIG_ARRAY_START: usize = 8 + // discriminator
+    32 + // authority
+    32 + // wallet
+    33 + // token mint
+    4 + 6 + // uuid
+    8 + // price
+    8 + // items available
+    9 + // go live date
+    10 + // end settings
+    4 + MAX_SYMBOL_LENGTH + // symbol
+    2 + // seller fee basis points
+    4 + MAX_CREATOR_LIMIT * MAX_CREATOR_LEN + // creators
+    8 + // max supply
+    1 + // is mutable
+    1 + // retain authority
+    1 + // hidden settings option
+    4 + MAX_NAME_LENGTH + // hidden settings name
+    4 + MAX_URI_LENGTH + // hidden settings uri
+    32 + // hidden settings hash
+    4 + // max number of lines
+    8 + // items redeemed
+    1 + // whitelist option
+    1 + // whitelist mint mode
+    1 + // presale
+    9 + // discount price
+    32 + // whitelist mint
+    1 + 32 + 1; // gatekeeper
+
+const CONFIG_LINE_SIZE: usize = 4 + MAX_NAME_LENGTH + 4 + MAX_URI_LENGTH;
+
+/// An item loaded into a candy machine, minted or not.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigLine {
+    pub index: usize,
+    pub name: String,
+    pub uri: String,
+}
+
+/// A config line some item was minted from.
+#[derive(Debug, Serialize)]
+pub struct MintedItem {
+    pub index: usize,
+    pub name: String,
+    pub uri: String,
+    pub mint_account: String,
+    /// Current holder, from the snapshot joined against; `None` when the
+    /// item is not in it, e.g. burned.
+    pub owner_wallet: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MintStatus {
+    pub config_lines: usize,
+    pub minted: Vec<MintedItem>,
+    pub available: Vec<ConfigLine>,
+    /// Items of the collection matching no config line, e.g. minted before
+    /// the lines were replaced.
+    pub unmatched: Vec<String>,
+}
+
+fn trimmed(s: &str) -> String {
+    s.trim_matches(char::from(0)).trim().to_string()
+}
+
+/// A string padded to `max` bytes after its length prefix.
+fn read_padded(data: &[u8], max: usize) -> Option<String> {
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let bytes = data.get(4..4 + len.min(max))?;
+    Some(trimmed(&String::from_utf8_lossy(bytes)))
+}
+
+/// Decodes the config lines of a candy machine v2 account. Lines that were
+/// never loaded are left out.
+pub fn parse_config_lines(data: &[u8]) -> Result<Vec<ConfigLine>> {
+    let count = data
+        .get(CONFIG_ARRAY_START..CONFIG_ARRAY_START + 4)
+        .ok_or(anyhow!("Account is too short for a candy machine!"))?;
+    let count = u32::from_le_bytes(count.try_into()?) as usize;
+
+    let mut lines = Vec::with_capacity(count);
+    for index in 0..count {
+        let start = CONFIG_ARRAY_START + 4 + index * CONFIG_LINE_SIZE;
+        let line = data
+            .get(start..start + CONFIG_LINE_SIZE)
+            .ok_or(anyhow!("Config line {} is cut off!", index))?;
+        let name = read_padded(line, MAX_NAME_LENGTH).unwrap_or_default();
+        let uri = read_padded(&line[4 + MAX_NAME_LENGTH..], MAX_URI_LENGTH).unwrap_or_default();
+        if !name.is_empty() || !uri.is_empty() {
+            lines.push(ConfigLine { index, name, uri });
+        }
+    }
+    Ok(lines)
+}
+
+/// The config lines of `candy_machine`. Candy machines using hidden
+/// settings have none, as every item gets the same placeholder.
+pub fn get_config_lines(
+    client: &dyn RpcBackend,
+    candy_machine: &Pubkey,
+) -> Result<Vec<ConfigLine>> {
+    let account = client
+        .get_multiple_accounts(&[*candy_machine])?
+        .pop()
+        .flatten()
+        .ok_or(anyhow!("Candy machine {} does not exist!", candy_machine))?;
+    let lines = parse_config_lines(&account.data)?;
+    if lines.is_empty() {
+        return Err(anyhow!(
+            "Candy machine {} has no config lines, it may use hidden settings!",
+            candy_machine
+        ));
+    }
+    Ok(lines)
+}
+
+/// Splits `lines` into minted and available ones by matching the name and
+/// URI of the collection's `items`, which the candy machine copies into the
+/// metadata of every item it mints. Minted items get their owner from
+/// `holders`, so only actually minted items are airdropped to.
+pub fn mint_status(
+    lines: Vec<ConfigLine>,
+    items: &[(Pubkey, Metadata)],
+    holders: &[Holder],
+) -> MintStatus {
+    let owners: HashMap<&str, &str> = holders
+        .iter()
+        .map(|holder| (holder.mint_account.as_str(), holder.owner_wallet.as_str()))
+        .collect();
+    let mut mints: HashMap<(String, String), Pubkey> = items
+        .iter()
+        .map(|(_, metadata)| {
+            let key = (trimmed(&metadata.data.name), trimmed(&metadata.data.uri));
+            (key, metadata.mint)
+        })
+        .collect();
+
+    let config_lines = lines.len();
+    let (mut minted, mut available) = (Vec::new(), Vec::new());
+    for line in lines {
+        match mints.remove(&(line.name.clone(), line.uri.clone())) {
+            Some(mint) => {
+                let mint_account = mint.to_string();
+                minted.push(MintedItem {
+                    owner_wallet: owners.get(mint_account.as_str()).map(|o| o.to_string()),
+                    index: line.index,
+                    name: line.name,
+                    uri: line.uri,
+                    mint_account,
+                });
+            }
+            None => available.push(line),
+        }
+    }
+
+    MintStatus {
+        config_lines,
+        minted,
+        available,
+        unmatched: mints.values().map(|mint| mint.to_string()).collect(),
+    }
+}
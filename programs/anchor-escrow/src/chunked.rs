@@ -0,0 +1,262 @@
+use {
+    crate::{
+        das::{AssetPage, AssetProof},
+        http_sender::http_status,
+        metrics::RpcMetrics,
+        rpc::RpcBackend,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    log::{debug, warn},
+    mpl_token_metadata::ID as TOKEN_METADATA_PROGRAM_ID,
+    serde::{Deserialize, Serialize},
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_request::RpcError,
+        rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+        rpc_response::RpcConfirmedTransactionStatusWithSignature,
+    },
+    solana_sdk::{account::Account, hash::Hash, signature::Signature},
+    solana_transaction_status::EncodedConfirmedTransaction,
+    spl_token::ID as TOKEN_PROGRAM_ID,
+    std::collections::HashMap,
+};
+
+/// Metadata: key, update authority, then the mint.
+const METADATA_MINT_OFFSET: usize = 1 + 32;
+/// Token account: mint, then the owner.
+const TOKEN_ACCOUNT_OWNER_OFFSET: usize = 32;
+/// Each level multiplies the number of queries by 256.
+const MAX_SPLIT_DEPTH: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkMode {
+    Never,
+    /// Split only queries the endpoint refused as too large.
+    OnError,
+    Always,
+}
+
+impl Default for ChunkMode {
+    fn default() -> Self {
+        ChunkMode::OnError
+    }
+}
+
+/// The validator's error for scans whose results exceed its byte limit.
+const JSON_RPC_SCAN_ERROR: i64 = -32012;
+/// Payload Too Large, which providers send for oversized responses.
+const HTTP_PAYLOAD_TOO_LARGE: u16 = 413;
+
+/// Only the response size codes count; other errors, rate limiting above
+/// all, must not be split on, as splitting multiplies requests.
+fn is_response_too_large(err: &anyhow::Error) -> bool {
+    let client_error = match err.downcast_ref::<ClientError>() {
+        Some(client_error) => client_error,
+        None => return false,
+    };
+    match client_error.kind() {
+        ClientErrorKind::Reqwest(e) => {
+            e.status().map(|status| status.as_u16()) == Some(HTTP_PAYLOAD_TOO_LARGE)
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if *code == JSON_RPC_SCAN_ERROR =>
+        {
+            true
+        }
+        ClientErrorKind::RpcError(e) => http_status(e) == Some(HTTP_PAYLOAD_TOO_LARGE),
+        _ => false,
+    }
+}
+
+/// Splits getProgramAccounts queries that hit response size limits. Each
+/// split adds a memcmp on the next byte of a random-looking pubkey field,
+/// the mint for metadata and the owner for token accounts, so the 256
+/// sub-queries partition the original results.
+///
+/// Wrap the retrying, rate limited backend with this, so every sub-query is
+/// limited and retried on its own.
+pub struct ChunkedRpc<B> {
+    pub inner: B,
+    pub mode: ChunkMode,
+    /// Offset of the field to split on, per program.
+    pub split_offsets: HashMap<Pubkey, usize>,
+}
+
+impl<B: RpcBackend> ChunkedRpc<B> {
+    pub fn new(inner: B, mode: ChunkMode) -> Self {
+        let mut split_offsets = HashMap::new();
+        split_offsets.insert(TOKEN_METADATA_PROGRAM_ID, METADATA_MINT_OFFSET);
+        split_offsets.insert(TOKEN_PROGRAM_ID, TOKEN_ACCOUNT_OWNER_OFFSET);
+        ChunkedRpc {
+            inner,
+            mode,
+            split_offsets,
+        }
+    }
+
+    fn split(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+        offset: usize,
+        prefix: &[u8],
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let mut accounts = Vec::new();
+        for byte in 0..=u8::MAX {
+            let mut prefix = prefix.to_vec();
+            prefix.push(byte);
+
+            let mut chunk = config.clone();
+            let mut filters = chunk.filters.take().unwrap_or_default();
+            filters.push(RpcFilterType::Memcmp(Memcmp {
+                offset,
+                bytes: MemcmpEncodedBytes::Base58(bs58::encode(&prefix).into_string()),
+                encoding: None,
+            }));
+            chunk.filters = Some(filters);
+
+            match self.inner.get_program_accounts_with_config(program_id, chunk) {
+                Ok(found) => accounts.extend(found),
+                Err(err) if is_response_too_large(&err) && prefix.len() < MAX_SPLIT_DEPTH => {
+                    debug!("Splitting gPA chunk {:?} of {} further", prefix, program_id);
+                    accounts.extend(self.split(program_id, config.clone(), offset, &prefix)?);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(accounts)
+    }
+}
+
+impl<B: RpcBackend> RpcBackend for ChunkedRpc<B> {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let offset = match (self.mode, self.split_offsets.get(program_id)) {
+            (ChunkMode::Never, _) | (_, None) => {
+                return self.inner.get_program_accounts_with_config(program_id, config)
+            }
+            (_, Some(offset)) => *offset,
+        };
+
+        if self.mode == ChunkMode::OnError {
+            match self
+                .inner
+                .get_program_accounts_with_config(program_id, config.clone())
+            {
+                Err(err) if is_response_too_large(&err) => {
+                    warn!("gPA for {} too large, splitting it: {}", program_id, err)
+                }
+                result => return result,
+            }
+        }
+
+        self.split(program_id, config, offset, &[])
+            .map_err(|err| anyhow!("Chunked gPA for {} failed: {}", program_id, err))
+    }
+
+    // Batched queries are small per-mint lookups; only split them when asked
+    // to split everything.
+    fn get_program_accounts_batch(
+        &self,
+        program_id: &Pubkey,
+        configs: Vec<RpcProgramAccountsConfig>,
+    ) -> Result<Vec<Result<Vec<(Pubkey, Account)>>>> {
+        if self.mode != ChunkMode::Always || !self.split_offsets.contains_key(program_id) {
+            return self.inner.get_program_accounts_batch(program_id, configs);
+        }
+        Ok(configs
+            .into_iter()
+            .map(|config| self.get_program_accounts_with_config(program_id, config))
+            .collect())
+    }
+
+    fn batch_size(&self) -> usize {
+        self.inner.batch_size()
+    }
+
+    fn metrics(&self) -> Option<&RpcMetrics> {
+        self.inner.metrics()
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        self.inner.get_multiple_accounts(pubkeys)
+    }
+
+    fn get_slot(&self) -> Result<u64> {
+        self.inner.get_slot()
+    }
+
+    fn get_genesis_hash(&self) -> Result<Hash> {
+        self.inner.get_genesis_hash()
+    }
+
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.inner.get_signatures_for_address(address, before, limit)
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction> {
+        self.inner.get_transaction(signature)
+    }
+
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof> {
+        self.inner.get_asset_proof(asset_id)
+    }
+
+    fn get_assets_by_collection(&self, collection: &Pubkey, page: usize) -> Result<AssetPage> {
+        self.inner.get_assets_by_collection(collection, page)
+    }
+
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+        self.inner.get_token_largest_accounts(mint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_client::rpc_request::RpcResponseErrorData};
+
+    fn response_error(code: i64, message: &str) -> anyhow::Error {
+        ClientError::from(RpcError::RpcResponseError {
+            code,
+            message: message.to_string(),
+            data: RpcResponseErrorData::Empty,
+        })
+        .into()
+    }
+
+    #[test]
+    fn splits_on_response_size_errors() {
+        assert!(is_response_too_large(&response_error(
+            JSON_RPC_SCAN_ERROR,
+            "Scan aggregate size limit exceeded"
+        )));
+        assert!(is_response_too_large(&response_error(
+            -32600,
+            "Response is too big (HTTP 413)"
+        )));
+    }
+
+    #[test]
+    fn does_not_split_on_other_errors() {
+        assert!(!is_response_too_large(&response_error(
+            -32429,
+            "Rate limit exceeded (HTTP 429)"
+        )));
+        assert!(!is_response_too_large(&response_error(
+            -32002,
+            "Compute budget exceeded"
+        )));
+        assert!(!is_response_too_large(&anyhow!("Response too large!")));
+    }
+}
@@ -0,0 +1,628 @@
+use {
+    crate::{retry_policy::RetryPolicy, tx_error::describe_error},
+    anchor_lang::{
+        prelude::{AnchorSerialize, Pubkey},
+        solana_program::{
+            hash::hash,
+            instruction::{AccountMeta, Instruction},
+            system_instruction, system_program, sysvar,
+        },
+    },
+    anyhow::{anyhow, Result},
+    log::debug,
+    solana_client::rpc_client::RpcClient,
+    serde::Serialize,
+    solana_sdk::{
+        account_utils::StateMut,
+        hash::Hash,
+        message::Message,
+        nonce::state::{State, Versions},
+        rent::Rent,
+        signature::{Keypair, Signature, Signer},
+        transaction::Transaction,
+    },
+    std::{
+        cell::Cell,
+        fs::{File, OpenOptions},
+        io::{BufRead, BufReader, Write},
+        path::Path,
+    },
+};
+
+/// Seed of the global `state` PDA holding the spin item list.
+pub const STATE_SEED: &[u8] = b"sw_game_seeds";
+
+/// state PDA and its bump
+pub fn find_state_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STATE_SEED], program_id)
+}
+
+/// Reward token vault PDA for a spin item. `rand` is any unique key chosen
+/// by the admin when the item is set.
+pub fn find_token_vault_address(program_id: &Pubkey, rand: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[rand.as_ref()], program_id)
+}
+
+// Anchor dispatches on the first 8 bytes of sha256("global:<ix name>"),
+// followed by the borsh encoded arguments.
+fn instruction_data<T: AnchorSerialize>(name: &str, args: &T) -> Result<Vec<u8>> {
+    let preimage = format!("global:{}", name);
+    let mut data = hash(preimage.as_bytes()).to_bytes()[..8].to_vec();
+    args.serialize(&mut data)
+        .map_err(|err| anyhow!("Failed to serialize the arguments of {}: {}!", name, err))?;
+    Ok(data)
+}
+
+#[derive(AnchorSerialize)]
+struct InitializeArgs {
+    pool_bump: u8,
+}
+
+#[derive(AnchorSerialize)]
+struct SetItemArgs {
+    token_vault_bump: u8,
+    item_index: u8,
+    ratio: u8,
+    amount: u64,
+}
+
+#[derive(AnchorSerialize)]
+struct TransferRewardsArgs {
+    spin_index: u8,
+}
+
+pub fn initialize(program_id: &Pubkey, initializer: &Pubkey) -> Result<Instruction> {
+    let (state, pool_bump) = find_state_address(program_id);
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data: instruction_data("initialize", &InitializeArgs { pool_bump })?,
+    })
+}
+
+/// Register the spin item at `item_index`, moving `amount` reward tokens from
+/// `reward_account` into a freshly derived token vault.
+#[allow(clippy::too_many_arguments)]
+pub fn set_item(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    token_mint: &Pubkey,
+    reward_account: &Pubkey,
+    rand: &Pubkey,
+    item_index: u8,
+    ratio: u8,
+    amount: u64,
+) -> Result<Instruction> {
+    let (state, _) = find_state_address(program_id);
+    let (token_vault, token_vault_bump) = find_token_vault_address(program_id, rand);
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new(token_vault, false),
+            AccountMeta::new_readonly(*rand, false),
+            AccountMeta::new(*reward_account, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(sysvar::rent::ID, false),
+        ],
+        data: instruction_data(
+            "set_item",
+            &SetItemArgs {
+                token_vault_bump,
+                item_index,
+                ratio,
+                amount,
+            },
+        )?,
+    })
+}
+
+pub fn spin_wheel(program_id: &Pubkey) -> Result<Instruction> {
+    let (state, _) = find_state_address(program_id);
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new(state, false)],
+        data: instruction_data("spin_wheel", &())?,
+    })
+}
+
+/// Pay out the item picked by the last spin from its vault to `dest_account`.
+pub fn transfer_rewards(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    token_mint: &Pubkey,
+    token_vault: &Pubkey,
+    dest_account: &Pubkey,
+    spin_index: u8,
+) -> Result<Instruction> {
+    let (state, _) = find_state_address(program_id);
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new(*token_vault, false),
+            AccountMeta::new(*dest_account, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: instruction_data("transfer_rewards", &TransferRewardsArgs { spin_index })?,
+    })
+}
+
+/// Simulates a signed transaction before sending it, so a failing one costs
+/// no fee and is reported with its failing instruction and decoded error.
+/// Program logs of failed simulations are logged at debug level.
+pub fn submit(rpc: &RpcClient, tx: &Transaction) -> Result<Signature> {
+    let missing = missing_signers(tx);
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "Transaction is not signed by {}, sign it with --sign-only and pass their \
+             signatures with --signer!",
+            missing.join(", ")
+        ));
+    }
+    let simulation = rpc.simulate_transaction(tx)?.value;
+    if let Some(err) = simulation.err {
+        for line in simulation.logs.unwrap_or_default() {
+            debug!("{}", line);
+        }
+        return Err(anyhow!("Simulation failed: {}", describe_error(tx, &err)));
+    }
+
+    match rpc.send_and_confirm_transaction(tx) {
+        Ok(signature) => Ok(signature),
+        Err(err) => match err.get_transaction_error() {
+            Some(tx_err) => Err(anyhow!("{}", describe_error(tx, &tx_err))),
+            None => Err(err.into()),
+        },
+    }
+}
+
+/// Transactions written by a `sign_only` client, bincode and base58 encoded,
+/// one per line, in the order they were signed.
+pub fn read_signed(path: &Path) -> Result<Vec<Transaction>> {
+    let f = BufReader::new(File::open(path)?);
+    let mut transactions = Vec::new();
+    for (index, line) in f.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bytes = bs58::decode(line.trim())
+            .into_vec()
+            .map_err(|e| anyhow!("Line {} is not base58: {}", index + 1, e))?;
+        transactions.push(bincode::deserialize(&bytes)?);
+    }
+    Ok(transactions)
+}
+
+/// Fee per signature assumed by estimates made without the RPC, mainnet's.
+const OFFLINE_LAMPORTS_PER_SIGNATURE: u64 = 5000;
+
+/// Signers whose signature `tx` still lacks, e.g. those signed for by a
+/// `NullSigner` with `sign_only`.
+fn missing_signers(tx: &Transaction) -> Vec<String> {
+    let signers = tx.message.account_keys.iter();
+    signers
+        .zip(&tx.signatures)
+        .filter(|(_, signature)| **signature == Signature::default())
+        .map(|(pubkey, _)| pubkey.to_string())
+        .collect()
+}
+
+/// The blockhash stored in a durable nonce account.
+fn nonce_blockhash(rpc: &RpcClient, nonce_account: &Pubkey) -> Result<Hash> {
+    let account = rpc.get_account(nonce_account)?;
+    let versions: Versions = account.state()?;
+    match versions.convert_to_current() {
+        State::Initialized(data) => Ok(data.blockhash),
+        State::Uninitialized => Err(anyhow!("Nonce account {} is not initialized!", nonce_account)),
+    }
+}
+
+/// What a batch of program transactions costs the payer. The client sets no
+/// priority fees, so only base fees are counted.
+#[derive(Debug, Default, Serialize)]
+pub struct CostEstimate {
+    pub transactions: usize,
+    pub signatures: u64,
+    pub fee_lamports: u64,
+    /// Rent of the accounts the transactions create.
+    pub rent_lamports: u64,
+}
+
+impl CostEstimate {
+    pub fn total_lamports(&self) -> u64 {
+        self.fee_lamports + self.rent_lamports
+    }
+}
+
+/// Signs and sends program instructions, paying fees from `payer`. Signers
+/// can be keypair files, Ledger devices or seed phrases alike.
+pub struct ProgramClient {
+    pub rpc: RpcClient,
+    pub payer: Box<dyn Signer>,
+    pub program_id: Pubkey,
+    /// Applied to reads; sends are never retried blindly.
+    pub retry_policy: RetryPolicy,
+    /// Durable nonce accounts to sign with instead of a recent blockhash, so
+    /// signed transactions do not expire. Every transaction signed with
+    /// `sign_only` advances its nonce when sent, so each uses up the next
+    /// account; sent right away, the first one is reused.
+    pub nonce_accounts: Vec<Pubkey>,
+    /// Authority of the nonce accounts, the payer when `None`.
+    pub nonce_authority: Option<Box<dyn Signer>>,
+    /// Append signed transactions to this file instead of sending them, for
+    /// `read_signed` and `submit` later. Signers given as a pubkey, e.g. a
+    /// `NullSigner`, leave their signature empty for another party to add,
+    /// and `Presigner`s add theirs from an earlier offline signing.
+    pub sign_only: Option<String>,
+    /// Blockhashes to sign with instead of reading them from the RPC, so
+    /// `sign_only` needs no connection: the blockhash stored in each nonce
+    /// account, in the same order, or else one recent blockhash for all.
+    pub blockhashes: Vec<Hash>,
+    nonces_used: Cell<usize>,
+}
+
+impl ProgramClient {
+    pub fn new(rpc: RpcClient, payer: Box<dyn Signer>) -> Self {
+        ProgramClient {
+            rpc,
+            payer,
+            program_id: crate::ID,
+            retry_policy: RetryPolicy::default(),
+            nonce_accounts: Vec::new(),
+            nonce_authority: None,
+            sign_only: None,
+            blockhashes: Vec::new(),
+            nonces_used: Cell::new(0),
+        }
+    }
+
+    /// The nonce account to sign the next transaction with and its index,
+    /// if any.
+    fn next_nonce(&self) -> Result<Option<(usize, Pubkey)>> {
+        if self.nonce_accounts.is_empty() {
+            return Ok(None);
+        }
+        if self.sign_only.is_none() {
+            return Ok(Some((0, self.nonce_accounts[0])));
+        }
+        let used = self.nonces_used.get();
+        let nonce_account = self.nonce_accounts.get(used).ok_or(anyhow!(
+            "All {} nonce accounts are used up, pass one per transaction!",
+            self.nonce_accounts.len()
+        ))?;
+        self.nonces_used.set(used + 1);
+        Ok(Some((used, *nonce_account)))
+    }
+
+    /// Whether blockhashes were given, so the RPC is not asked for any.
+    fn offline(&self) -> bool {
+        !self.blockhashes.is_empty()
+    }
+
+    /// Fees of `transactions` at the current rate, including the nonce
+    /// advance of each when signing with nonces, plus the rent of accounts
+    /// of `new_account_sizes` bytes they create.
+    pub fn estimate(
+        &self,
+        transactions: &[Vec<Instruction>],
+        new_account_sizes: &[usize],
+    ) -> Result<CostEstimate> {
+        let lamports_per_signature = match self.offline() {
+            true => OFFLINE_LAMPORTS_PER_SIGNATURE,
+            false => {
+                let (_, fee_calculator) = self
+                    .retry_policy
+                    .call(|| Ok(self.rpc.get_recent_blockhash()?))?;
+                fee_calculator.lamports_per_signature
+            }
+        };
+        let payer = self.payer.pubkey();
+        let authority = self.nonce_authority.as_ref().unwrap_or(&self.payer).pubkey();
+
+        let mut estimate = CostEstimate {
+            transactions: transactions.len(),
+            ..CostEstimate::default()
+        };
+        for instructions in transactions {
+            let mut instructions = instructions.clone();
+            if let Some(nonce_account) = self.nonce_accounts.first() {
+                let advance = system_instruction::advance_nonce_account(nonce_account, &authority);
+                instructions.insert(0, advance);
+            }
+            let message = Message::new(&instructions, Some(&payer));
+            estimate.signatures += message.header.num_required_signatures as u64;
+        }
+        estimate.fee_lamports = estimate.signatures * lamports_per_signature;
+        for size in new_account_sizes {
+            estimate.rent_lamports += match self.offline() {
+                true => Rent::default().minimum_balance(*size),
+                false => self
+                    .retry_policy
+                    .call(|| Ok(self.rpc.get_minimum_balance_for_rent_exemption(*size)?))?,
+            };
+        }
+        Ok(estimate)
+    }
+
+    /// Signs the instructions with the payer and `signers`, advancing the
+    /// next nonce account first if any. Signatures of signers that cannot
+    /// sign here, see `sign_only`, are left empty.
+    pub fn sign(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&dyn Signer],
+    ) -> Result<Transaction> {
+        let mut all_signers: Vec<&dyn Signer> = vec![self.payer.as_ref()];
+        all_signers.extend(signers.iter().filter(|s| s.pubkey() != self.payer.pubkey()));
+
+        let (instructions, recent_blockhash) = match self.next_nonce()? {
+            Some((index, nonce_account)) => {
+                let authority = self.nonce_authority.as_deref().unwrap_or(self.payer.as_ref());
+                if all_signers.iter().all(|s| s.pubkey() != authority.pubkey()) {
+                    all_signers.push(authority);
+                }
+                // The nonce is advanced by the first instruction.
+                let mut with_advance = vec![system_instruction::advance_nonce_account(
+                    &nonce_account,
+                    &authority.pubkey(),
+                )];
+                with_advance.extend_from_slice(instructions);
+                let blockhash = match self.blockhashes.get(index) {
+                    Some(blockhash) => *blockhash,
+                    None if self.offline() => {
+                        return Err(anyhow!(
+                            "No blockhash given for nonce account {}!",
+                            nonce_account
+                        ))
+                    }
+                    None => self
+                        .retry_policy
+                        .call(|| nonce_blockhash(&self.rpc, &nonce_account))?,
+                };
+                (with_advance, blockhash)
+            }
+            None => {
+                let blockhash = match self.blockhashes.first() {
+                    Some(blockhash) => *blockhash,
+                    None => {
+                        self.retry_policy
+                            .call(|| Ok(self.rpc.get_recent_blockhash()?))?
+                            .0
+                    }
+                };
+                (instructions.to_vec(), blockhash)
+            }
+        };
+
+        let message = Message::new(&instructions, Some(&self.payer.pubkey()));
+        let mut tx = Transaction::new_unsigned(message);
+        tx.try_partial_sign(&all_signers, recent_blockhash)?;
+        Ok(tx)
+    }
+
+    /// Signs and submits the instructions, or with `sign_only` appends the
+    /// signed transaction to that file; the signature is what it will be
+    /// sent under.
+    pub fn send(&self, instructions: &[Instruction], signers: &[&dyn Signer]) -> Result<Signature> {
+        let tx = self.sign(instructions, signers)?;
+        match &self.sign_only {
+            Some(path) => {
+                let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(f, "{}", bs58::encode(bincode::serialize(&tx)?).into_string())?;
+                Ok(tx.signatures[0])
+            }
+            None => submit(&self.rpc, &tx),
+        }
+    }
+
+    pub fn initialize(&self, initializer: &dyn Signer) -> Result<Signature> {
+        let ix = initialize(&self.program_id, &initializer.pubkey())?;
+        self.send(&[ix], &[initializer])
+    }
+
+    pub fn set_item(
+        &self,
+        owner: &dyn Signer,
+        token_mint: &Pubkey,
+        reward_account: &Pubkey,
+        item_index: u8,
+        ratio: u8,
+        amount: u64,
+    ) -> Result<(Pubkey, Signature)> {
+        let rand = Keypair::new().pubkey();
+        let (token_vault, _) = find_token_vault_address(&self.program_id, &rand);
+        let ix = set_item(
+            &self.program_id,
+            &owner.pubkey(),
+            token_mint,
+            reward_account,
+            &rand,
+            item_index,
+            ratio,
+            amount,
+        )?;
+        let signature = self.send(&[ix], &[owner])?;
+
+        Ok((token_vault, signature))
+    }
+
+    pub fn spin_wheel(&self) -> Result<Signature> {
+        let ix = spin_wheel(&self.program_id)?;
+        self.send(&[ix], &[])
+    }
+
+    pub fn transfer_rewards(
+        &self,
+        owner: &dyn Signer,
+        token_mint: &Pubkey,
+        token_vault: &Pubkey,
+        dest_account: &Pubkey,
+        spin_index: u8,
+    ) -> Result<Signature> {
+        let ix = transfer_rewards(
+            &self.program_id,
+            &owner.pubkey(),
+            token_mint,
+            token_vault,
+            dest_account,
+            spin_index,
+        )?;
+        self.send(&[ix], &[owner])
+    }
+
+    /// Plain SPL transfer from `source` into an item vault to top up rewards.
+    pub fn fund_token_vault(
+        &self,
+        owner: &dyn Signer,
+        source: &Pubkey,
+        token_vault: &Pubkey,
+        amount: u64,
+    ) -> Result<Signature> {
+        let ix = spl_token::instruction::transfer(
+            &spl_token::ID,
+            source,
+            token_vault,
+            &owner.pubkey(),
+            &[],
+            amount,
+        )?;
+        self.send(&[ix], &[owner])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pubkey, signer and writable flags of every account, in order.
+    fn metas(ix: &Instruction) -> Vec<(Pubkey, bool, bool)> {
+        let metas = ix.accounts.iter();
+        metas.map(|m| (m.pubkey, m.is_signer, m.is_writable)).collect()
+    }
+
+    fn sighash(name: &str) -> Vec<u8> {
+        hash(format!("global:{}", name).as_bytes()).to_bytes()[..8].to_vec()
+    }
+
+    // Account orders as in tests/anchor-escrow.ts.
+    #[test]
+    fn instructions_list_accounts_in_the_order_of_the_program() {
+        let program_id = crate::ID;
+        let (state, pool_bump) = find_state_address(&program_id);
+        let owner = Pubkey::new_unique();
+
+        let ix = initialize(&program_id, &owner).unwrap();
+        let expected = [
+            (owner, true, true),
+            (state, false, true),
+            (system_program::ID, false, false),
+        ];
+        assert_eq!(metas(&ix), expected);
+        assert_eq!(ix.data, [sighash("initialize"), vec![pool_bump]].concat());
+
+        let (mint, reward) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let rand = Pubkey::new_unique();
+        let (vault, vault_bump) = find_token_vault_address(&program_id, &rand);
+        let ix = set_item(&program_id, &owner, &mint, &reward, &rand, 3, 5, 2).unwrap();
+        let expected = [
+            (owner, true, true),
+            (state, false, true),
+            (mint, false, false),
+            (vault, false, true),
+            (rand, false, false),
+            (reward, false, true),
+            (spl_token::ID, false, false),
+            (system_program::ID, false, false),
+            (sysvar::rent::ID, false, false),
+        ];
+        assert_eq!(metas(&ix), expected);
+        let args = [vec![vault_bump, 3, 5], 2u64.to_le_bytes().to_vec()].concat();
+        assert_eq!(ix.data, [sighash("set_item"), args].concat());
+
+        let ix = spin_wheel(&program_id).unwrap();
+        assert_eq!(metas(&ix), [(state, false, true)]);
+        assert_eq!(ix.data, sighash("spin_wheel"));
+
+        let dest = Pubkey::new_unique();
+        let ix = transfer_rewards(&program_id, &owner, &mint, &vault, &dest, 7).unwrap();
+        let expected = [
+            (owner, true, true),
+            (state, false, true),
+            (mint, false, false),
+            (vault, false, true),
+            (dest, false, true),
+            (system_program::ID, false, false),
+            (spl_token::ID, false, false),
+        ];
+        assert_eq!(metas(&ix), expected);
+        assert_eq!(ix.data, [sighash("transfer_rewards"), vec![7]].concat());
+    }
+
+    /// A client that never reaches its RPC, signing into `sign_only`.
+    fn offline_client(name: &str, blockhashes: Vec<Hash>) -> (ProgramClient, String) {
+        let path = std::env::temp_dir().join(format!("{}-{}.txs", name, std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let rpc = RpcClient::new("http://127.0.0.1:1".to_string());
+        let mut client = ProgramClient::new(rpc, Box::new(Keypair::new()));
+        client.sign_only = Some(path.clone());
+        client.blockhashes = blockhashes;
+        (client, path)
+    }
+
+    #[test]
+    fn sign_only_appends_transactions_for_later() {
+        let blockhash = Hash::new_unique();
+        let (client, path) = offline_client("sign-only", vec![blockhash]);
+        let ix = spin_wheel(&client.program_id).unwrap();
+        let signatures = [
+            client.send(&[ix.clone()], &[]).unwrap(),
+            client.send(&[ix.clone()], &[]).unwrap(),
+        ];
+
+        let signed = read_signed(Path::new(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(signed.len(), 2);
+        for (tx, signature) in signed.iter().zip(&signatures) {
+            assert_eq!(tx.signatures[0], *signature);
+            assert_eq!(tx.message.recent_blockhash, blockhash);
+            assert!(missing_signers(tx).is_empty());
+            tx.verify().unwrap();
+        }
+
+        let estimate = client.estimate(&[vec![ix]], &[165]).unwrap();
+        assert_eq!(estimate.signatures, 1);
+        assert_eq!(estimate.fee_lamports, OFFLINE_LAMPORTS_PER_SIGNATURE);
+        assert_eq!(estimate.rent_lamports, Rent::default().minimum_balance(165));
+    }
+
+    #[test]
+    fn sign_only_uses_up_one_nonce_account_per_transaction() {
+        let blockhashes = vec![Hash::new_unique(), Hash::new_unique()];
+        let (mut client, path) = offline_client("nonces", blockhashes.clone());
+        client.nonce_accounts = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let ix = spin_wheel(&client.program_id).unwrap();
+        client.send(&[ix.clone()], &[]).unwrap();
+        client.send(&[ix.clone()], &[]).unwrap();
+        assert!(client.send(&[ix], &[]).is_err());
+
+        let signed = read_signed(Path::new(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let used: Vec<Hash> = signed.iter().map(|tx| tx.message.recent_blockhash).collect();
+        assert_eq!(used, blockhashes);
+        // Each transaction advances its nonce first.
+        let advance = &signed[1].message.instructions[0];
+        assert_eq!(*advance.program_id(&signed[1].message.account_keys), system_program::ID);
+    }
+}
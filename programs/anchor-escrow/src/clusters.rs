@@ -0,0 +1,284 @@
+use {
+    crate::{rpc::RpcBackend, snapshot::Holder},
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    crossbeam::thread,
+    log::warn,
+    serde::{Deserialize, Serialize},
+    solana_sdk::signature::Signature,
+    std::{
+        collections::{BTreeSet, HashMap},
+        str::FromStr,
+    },
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// Signatures fetched per address; the oldest of them is taken as the
+    /// funding transaction, so long histories may hide the real funder.
+    pub history_limit: usize,
+    /// A funder shared by more wallets than this is assumed to be an exchange
+    /// or faucet and does not link them.
+    pub max_shared_funder: usize,
+    pub workers: usize,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        ClusterConfig {
+            history_limit: 1000,
+            max_shared_funder: 25,
+            workers: 4,
+        }
+    }
+}
+
+/// Funding sources found for one wallet.
+#[derive(Debug, Clone)]
+pub struct WalletLinks {
+    pub wallet: String,
+    /// Fee payer of the wallet's oldest transaction.
+    pub funded_by: Option<String>,
+    /// Fee payers that created the wallet's token accounts.
+    pub ata_funders: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Cluster {
+    pub wallets: Vec<String>,
+    pub items: usize,
+    /// Funders linking the wallets together.
+    pub funders: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterReport {
+    pub supply: usize,
+    pub holders: usize,
+    /// Holders once each cluster is counted as one.
+    pub effective_holders: usize,
+    /// Share of supply held by the largest single wallet.
+    pub largest_holder_share: f64,
+    /// Share of supply held by the largest cluster.
+    pub largest_cluster_share: f64,
+    /// Clusters of two or more wallets, largest first.
+    pub clusters: Vec<Cluster>,
+}
+
+/// The fee payer of the oldest successful transaction touching `address`,
+/// unless that is `address` itself.
+fn first_fee_payer(
+    client: &dyn RpcBackend,
+    address: &Pubkey,
+    limit: usize,
+) -> Result<Option<String>> {
+    let signatures = client.get_signatures_for_address(address, None, limit)?;
+    let oldest = match signatures.iter().rev().find(|status| status.err.is_none()) {
+        Some(oldest) => oldest,
+        None => return Ok(None),
+    };
+
+    let confirmed = client.get_transaction(&Signature::from_str(&oldest.signature)?)?;
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or(anyhow!("Transaction {} could not be decoded!", oldest.signature))?;
+
+    Ok(transaction
+        .message
+        .account_keys
+        .first()
+        .filter(|payer| *payer != address)
+        .map(|payer| payer.to_string()))
+}
+
+fn wallet_links(
+    client: &dyn RpcBackend,
+    wallet: &str,
+    token_accounts: &[&str],
+    config: &ClusterConfig,
+) -> Result<WalletLinks> {
+    let funded_by = first_fee_payer(client, &Pubkey::from_str(wallet)?, config.history_limit)?;
+
+    let mut ata_funders = Vec::new();
+    for token_account in token_accounts {
+        let token_account = Pubkey::from_str(token_account)?;
+        if let Some(payer) = first_fee_payer(client, &token_account, config.history_limit)? {
+            if payer != wallet && !ata_funders.contains(&payer) {
+                ata_funders.push(payer);
+            }
+        }
+    }
+
+    Ok(WalletLinks {
+        wallet: wallet.to_string(),
+        funded_by,
+        ata_funders,
+    })
+}
+
+/// Looks up the funding sources of every holder wallet. Wallets whose
+/// history cannot be read are logged and left unlinked.
+pub fn find_links(
+    client: &dyn RpcBackend,
+    holders: &[Holder],
+    config: &ClusterConfig,
+) -> Result<Vec<WalletLinks>> {
+    let mut token_accounts: HashMap<&str, Vec<&str>> = HashMap::new();
+    for holder in holders {
+        token_accounts
+            .entry(holder.owner_wallet.as_str())
+            .or_default()
+            .push(holder.associated_token_address.as_str());
+    }
+    let wallets: Vec<(&str, Vec<&str>)> = token_accounts.into_iter().collect();
+    let chunk_size = (wallets.len() / config.workers.max(1)).max(1);
+
+    thread::scope(|scope| -> Result<Vec<WalletLinks>> {
+        let handles: Vec<_> = wallets
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move |_| {
+                    chunk
+                        .iter()
+                        .filter_map(|(wallet, accounts)| {
+                            match wallet_links(client, wallet, accounts, config) {
+                                Ok(links) => Some(links),
+                                Err(err) => {
+                                    warn!("Wallet {} has no readable history: {}", wallet, err);
+                                    None
+                                }
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut links = Vec::new();
+        for handle in handles {
+            let found = handle
+                .join()
+                .map_err(|_| anyhow!("Cluster worker panicked!"))?;
+            links.extend(found);
+        }
+        Ok(links)
+    })
+    .map_err(|_| anyhow!("Cluster thread panicked!"))?
+}
+
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(size: usize) -> Self {
+        DisjointSet {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, index: usize) -> usize {
+        let parent = self.parents[index];
+        if parent == index {
+            return index;
+        }
+        let root = self.find(parent);
+        self.parents[index] = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a] = b;
+    }
+}
+
+/// Groups wallets sharing a funder, or funded by another holder, and reports
+/// holder concentration with each group counted as one holder.
+pub fn cluster_holders(
+    holders: &[Holder],
+    links: &[WalletLinks],
+    config: &ClusterConfig,
+) -> ClusterReport {
+    let mut items: HashMap<&str, usize> = HashMap::new();
+    for holder in holders {
+        *items.entry(holder.owner_wallet.as_str()).or_default() += 1;
+    }
+    let mut wallets: Vec<&str> = items.keys().copied().collect();
+    wallets.sort_unstable();
+    let index: HashMap<&str, usize> = wallets.iter().enumerate().map(|(i, w)| (*w, i)).collect();
+
+    let mut funded: HashMap<&str, BTreeSet<usize>> = HashMap::new();
+    for link in links {
+        let wallet = match index.get(link.wallet.as_str()) {
+            Some(wallet) => *wallet,
+            None => continue,
+        };
+        for funder in link.funded_by.iter().chain(&link.ata_funders) {
+            funded.entry(funder.as_str()).or_default().insert(wallet);
+        }
+    }
+
+    let mut sets = DisjointSet::new(wallets.len());
+    let mut cluster_funders: HashMap<usize, BTreeSet<&str>> = HashMap::new();
+    for (funder, funded_wallets) in &funded {
+        if funded_wallets.len() > config.max_shared_funder {
+            continue;
+        }
+        let mut members: Vec<usize> = funded_wallets.iter().copied().collect();
+        if let Some(funder_wallet) = index.get(funder) {
+            members.push(*funder_wallet);
+        }
+        if members.len() < 2 {
+            continue;
+        }
+        for member in &members[1..] {
+            sets.union(members[0], *member);
+        }
+        cluster_funders.entry(members[0]).or_default().insert(*funder);
+    }
+
+    let mut grouped: HashMap<usize, Vec<usize>> = HashMap::new();
+    for wallet in 0..wallets.len() {
+        let root = sets.find(wallet);
+        grouped.entry(root).or_default().push(wallet);
+    }
+    let mut funders_by_root: HashMap<usize, BTreeSet<&str>> = HashMap::new();
+    for (member, funders) in cluster_funders {
+        let root = sets.find(member);
+        funders_by_root.entry(root).or_default().extend(funders);
+    }
+
+    let supply = holders.len();
+    let share = |count: usize| {
+        if supply == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / supply as f64
+        }
+    };
+
+    let mut clusters: Vec<Cluster> = grouped
+        .iter()
+        .map(|(root, members)| Cluster {
+            wallets: members.iter().map(|m| wallets[*m].to_string()).collect(),
+            items: members.iter().map(|m| items[wallets[*m]]).sum(),
+            funders: funders_by_root
+                .get(root)
+                .map(|funders| funders.iter().map(|f| f.to_string()).collect())
+                .unwrap_or_default(),
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.items.cmp(&a.items));
+
+    ClusterReport {
+        supply,
+        holders: wallets.len(),
+        effective_holders: clusters.len(),
+        largest_holder_share: share(items.values().copied().max().unwrap_or_default()),
+        largest_cluster_share: share(clusters.first().map(|c| c.items).unwrap_or_default()),
+        clusters: clusters.into_iter().filter(|c| c.wallets.len() > 1).collect(),
+    }
+}
@@ -0,0 +1,404 @@
+use {
+    crate::{
+        config::Config,
+        enrich::enrich,
+        filters::{FilterChain, HolderFilter},
+        metadata::parse_metadata,
+        output::cluster_path,
+        rpc::{detect_cluster, Cluster, RpcBackend},
+        sink::write_to_sinks,
+        snapshot::{
+            get_update_authority_accounts, stream_metadata_accounts, stream_nftholders, Holder,
+            Source,
+        },
+    },
+    anyhow::{anyhow, Result},
+    crossbeam::thread,
+    log::{error, info, warn},
+    mpl_token_metadata::state::Metadata,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        fs::{self, File},
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CollectionConfig {
+    /// Used for the output file name and in the report.
+    pub name: String,
+    pub source: Source,
+    #[serde(default)]
+    pub workers: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionSummary {
+    pub name: String,
+    pub output: Option<PathBuf>,
+    pub holders: usize,
+    pub unique_owners: usize,
+    /// Items that could not be looked up and are missing from the output.
+    pub missing: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CombinedReport {
+    pub collections: Vec<CollectionSummary>,
+    pub unique_owners: usize,
+    /// Wallets holding items from more than one of the collections.
+    pub owners_in_multiple_collections: usize,
+}
+
+fn snapshot_collection(
+    client: &dyn RpcBackend,
+    collection: &CollectionConfig,
+    config: &Config,
+    filters: &FilterChain,
+    captured_at: SystemTime,
+    cluster: Option<&Cluster>,
+) -> Result<(PathBuf, Vec<Holder>, usize)> {
+    let mut pipeline = config.snapshot.pipeline.clone();
+    if let Some(workers) = collection.workers {
+        pipeline.workers = workers;
+    }
+
+    let mut holders: Vec<Holder> = Vec::new();
+    let result = stream_nftholders(client, &collection.source, &pipeline, |holder| {
+        holders.push(holder);
+        Ok(())
+    })?;
+    if !result.errors.is_empty() {
+        warn!(
+            "{}: {} items could not be looked up and are missing",
+            collection.name,
+            result.errors.len()
+        );
+    }
+    let (output, holders) = write_collection(
+        client,
+        &collection.name,
+        holders,
+        config,
+        filters,
+        captured_at,
+        cluster,
+    )?;
+    Ok((output, holders, result.errors.len()))
+}
+
+/// Filters, enriches and writes the holders of collection `name`, returning
+/// the output path and the holders written.
+fn write_collection(
+    client: &dyn RpcBackend,
+    name: &str,
+    holders: Vec<Holder>,
+    config: &Config,
+    filters: &FilterChain,
+    captured_at: SystemTime,
+    cluster: Option<&Cluster>,
+) -> Result<(PathBuf, Vec<Holder>)> {
+    let mut holders = filters.apply(holders)?;
+    enrich(client, &config.enrich, &mut holders)?;
+
+    let output = Path::new(&config.output.dir).join(format!(
+        "{}_holders.{}",
+        name,
+        config.output.format.extension()
+    ));
+    let output = match cluster {
+        Some(cluster) => cluster_path(&output, cluster),
+        None => output,
+    };
+    for destination in write_to_sinks(config, name, &output, captured_at, &holders)? {
+        info!("{}: wrote to {}", name, destination);
+    }
+
+    let output = config.output.compress.apply_extension(&output);
+    Ok((output, holders))
+}
+
+/// What `snapshot_update_authority` splits an update authority's items by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitBy {
+    /// The verified collection NFT, else the symbol for items minted before
+    /// collections.
+    Collection,
+    Symbol,
+}
+
+impl Default for SplitBy {
+    fn default() -> Self {
+        SplitBy::Collection
+    }
+}
+
+impl std::str::FromStr for SplitBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "collection" => Ok(SplitBy::Collection),
+            "symbol" => Ok(SplitBy::Symbol),
+            _ => Err(format!("Unknown split {}, expected collection or symbol", s)),
+        }
+    }
+}
+
+/// The group of `metadata` under `split`, usable in a file name. Items
+/// without a symbol go under `no_symbol`.
+fn split_key(metadata: &Metadata, split: SplitBy) -> String {
+    if split == SplitBy::Collection {
+        if let Some(collection) = metadata.collection.as_ref().filter(|c| c.verified) {
+            return collection.key.to_string();
+        }
+    }
+    let symbol: String = metadata
+        .data
+        .symbol
+        .trim_matches(char::from(0))
+        .trim()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    match symbol.is_empty() {
+        true => "no_symbol".to_string(),
+        false => symbol,
+    }
+}
+
+/// Snapshots every collection concurrently against the same backend, so a
+/// rate limiting backend is shared between them. A failing collection is
+/// recorded in the report and does not stop the others. `filters` run on
+/// each collection's holders before enrichment.
+pub fn snapshot_collections(
+    client: &dyn RpcBackend,
+    config: &Config,
+    filters: &FilterChain,
+) -> Result<CombinedReport> {
+    let names: HashSet<&String> = config.collections.iter().map(|c| &c.name).collect();
+    if names.len() != config.collections.len() {
+        return Err(anyhow!("Collection names must be unique!"));
+    }
+    if config.collections.is_empty() {
+        return Err(anyhow!("No collections configured!"));
+    }
+    fs::create_dir_all(&config.output.dir)?;
+    let cluster = match config.output.cluster_in_name {
+        true => Some(detect_cluster(client)?),
+        false => None,
+    };
+
+    let captured_at = SystemTime::now();
+    let results = thread::scope(|scope| {
+        let handles: Vec<_> = config
+            .collections
+            .iter()
+            .map(|collection| {
+                let cluster = cluster.as_ref();
+                scope.spawn(move |_| {
+                    snapshot_collection(client, collection, config, filters, captured_at, cluster)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("Snapshot thread panicked!")))
+            })
+            .collect::<Vec<_>>()
+    })
+    .map_err(|_| anyhow!("Snapshot thread panicked!"))?;
+
+    let names = config.collections.iter().map(|collection| collection.name.clone());
+    write_report(config, names.zip(results).collect())
+}
+
+/// Snapshots every item of `update_authority` with one scan and writes the
+/// holders of each collection, or symbol, as `split` says, to its own file,
+/// for studios sharing an update authority across collections. Collections
+/// are named by their collection mint or symbol in the outputs and report.
+pub fn snapshot_update_authority(
+    client: &dyn RpcBackend,
+    update_authority: &str,
+    split: SplitBy,
+    config: &Config,
+    filters: &FilterChain,
+) -> Result<CombinedReport> {
+    fs::create_dir_all(&config.output.dir)?;
+    let cluster = match config.output.cluster_in_name {
+        true => Some(detect_cluster(client)?),
+        false => None,
+    };
+
+    let captured_at = SystemTime::now();
+    let accounts = get_update_authority_accounts(client, &update_authority.to_string())?;
+    let keys: HashMap<String, String> = accounts
+        .iter()
+        .filter_map(|(pubkey, account)| {
+            let metadata = parse_metadata(&account.data).ok()?;
+            Some((pubkey.to_string(), split_key(&metadata, split)))
+        })
+        .collect();
+
+    let mut groups: BTreeMap<String, (Vec<Holder>, usize)> = BTreeMap::new();
+    let result = stream_metadata_accounts(client, accounts, &config.snapshot.pipeline, |holder| {
+        let key = keys.get(&holder.metadata_account).ok_or_else(|| {
+            anyhow!("Holder of {} has no group to go in!", holder.metadata_account)
+        })?;
+        groups.entry(key.clone()).or_default().0.push(holder);
+        Ok(())
+    })?;
+    for item in &result.failed {
+        if let Some(key) = keys.get(&item.metadata_account) {
+            groups.entry(key.clone()).or_default().1 += 1;
+        }
+    }
+    if !result.errors.is_empty() {
+        warn!(
+            "{}: {} items could not be looked up and are missing",
+            update_authority,
+            result.errors.len()
+        );
+    }
+    if groups.is_empty() {
+        return Err(anyhow!("Update authority {} has no holders!", update_authority));
+    }
+
+    let results = groups
+        .into_iter()
+        .map(|(key, (holders, missing))| {
+            let written = write_collection(
+                client,
+                &key,
+                holders,
+                config,
+                filters,
+                captured_at,
+                cluster.as_ref(),
+            );
+            let result = written.map(|(output, holders)| (output, holders, missing));
+            (key, result)
+        })
+        .collect();
+    write_report(config, results)
+}
+
+/// Sums up the snapshot of each named collection into `report.json` in the
+/// output directory.
+fn write_report(
+    config: &Config,
+    results: Vec<(String, Result<(PathBuf, Vec<Holder>, usize)>)>,
+) -> Result<CombinedReport> {
+    let mut owner_collections: HashMap<String, HashSet<usize>> = HashMap::new();
+    let mut summaries = Vec::new();
+    for (index, (name, result)) in results.into_iter().enumerate() {
+        let summary = match result {
+            Ok((output, holders, missing)) => {
+                let owners: HashSet<&String> = holders.iter().map(|h| &h.owner_wallet).collect();
+                for owner in &owners {
+                    owner_collections
+                        .entry(owner.to_string())
+                        .or_default()
+                        .insert(index);
+                }
+                info!("{}: {} holders", name, holders.len());
+                CollectionSummary {
+                    name,
+                    output: Some(output),
+                    holders: holders.len(),
+                    unique_owners: owners.len(),
+                    missing,
+                    error: None,
+                }
+            }
+            Err(err) => {
+                error!("{}: snapshot failed: {}", name, err);
+                CollectionSummary {
+                    name,
+                    output: None,
+                    holders: 0,
+                    unique_owners: 0,
+                    missing: 0,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+        summaries.push(summary);
+    }
+
+    let report = CombinedReport {
+        collections: summaries,
+        unique_owners: owner_collections.len(),
+        owners_in_multiple_collections: owner_collections
+            .values()
+            .filter(|collections| collections.len() > 1)
+            .count(),
+    };
+
+    let mut f = File::create(Path::new(&config.output.dir).join("report.json"))?;
+    serde_json::to_writer_pretty(&mut f, &report)?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        anchor_lang::prelude::Pubkey,
+        mpl_token_metadata::state::{Collection, Data, Key},
+    };
+
+    fn metadata(symbol: &str, collection: Option<Collection>) -> Metadata {
+        Metadata {
+            key: Key::MetadataV1,
+            update_authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            data: Data {
+                name: "Item #1".to_string(),
+                symbol: symbol.to_string(),
+                uri: String::new(),
+                seller_fee_basis_points: 0,
+                creators: None,
+            },
+            primary_sale_happened: false,
+            is_mutable: true,
+            edition_nonce: None,
+            token_standard: None,
+            collection,
+            uses: None,
+        }
+    }
+
+    #[test]
+    fn splits_by_verified_collection_else_symbol() {
+        let key = Pubkey::new_unique();
+        let verified = metadata("ITEM", Some(Collection { verified: true, key }));
+        assert_eq!(split_key(&verified, SplitBy::Collection), key.to_string());
+        assert_eq!(split_key(&verified, SplitBy::Symbol), "ITEM");
+
+        let unverified = metadata("ITEM", Some(Collection { verified: false, key }));
+        assert_eq!(split_key(&unverified, SplitBy::Collection), "ITEM");
+    }
+
+    #[test]
+    fn symbols_are_safe_file_names() {
+        // Symbols are stored zero padded to their maximum length.
+        let padded = metadata("ITEM\0\0\0\0", None);
+        assert_eq!(split_key(&padded, SplitBy::Symbol), "ITEM");
+        let spaced = metadata(" A/B.c-d_e ", None);
+        assert_eq!(split_key(&spaced, SplitBy::Symbol), "A_B_c-d_e");
+        let empty = metadata("\0\0\0", None);
+        assert_eq!(split_key(&empty, SplitBy::Collection), "no_symbol");
+    }
+}
@@ -0,0 +1,156 @@
+use {
+    crate::{
+        output::{write_records, Compression, OutputFormat, Record},
+        snapshot::Holder,
+    },
+    age::x25519::Recipient,
+    anyhow::{anyhow, Result},
+    serde::{Deserialize, Serialize},
+    std::{collections::HashMap, path::Path},
+};
+
+/// Weights of a combined NFT and token holder score:
+/// `nft_weight * nfts + token_weight * min(tokens, max_tokens)`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CombinedConfig {
+    pub nft_weight: f64,
+    /// Per token in UI units.
+    pub token_weight: f64,
+    /// Tokens counted per wallet at most, so large balances cannot outweigh
+    /// holding NFTs.
+    pub max_tokens: Option<f64>,
+    /// Wallets scoring less are left out.
+    pub min_score: f64,
+}
+
+impl Default for CombinedConfig {
+    fn default() -> Self {
+        CombinedConfig {
+            nft_weight: 1.0,
+            token_weight: 1.0,
+            max_tokens: None,
+            min_score: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CombinedScore {
+    pub wallet: String,
+    pub nfts: u64,
+    /// Token balance in UI units, over all of the wallet's token accounts.
+    pub tokens: f64,
+    pub score: f64,
+}
+
+/// Joins an NFT holder snapshot with a fungible token snapshot per wallet
+/// and scores every wallet in either, highest score first.
+pub fn combined_scores(
+    nft_holders: &[Holder],
+    token_holders: &[Holder],
+    config: &CombinedConfig,
+) -> Result<Vec<CombinedScore>> {
+    let mut wallets: HashMap<&str, (u64, f64)> = HashMap::new();
+    for holder in nft_holders {
+        wallets.entry(holder.owner_wallet.as_str()).or_default().0 += 1;
+    }
+    for holder in token_holders {
+        let amount: f64 = holder
+            .amount
+            .as_deref()
+            .ok_or(anyhow!(
+                "Account {} has no amount, is this a fungible token snapshot?",
+                holder.associated_token_address
+            ))?
+            .parse()?;
+        wallets.entry(holder.owner_wallet.as_str()).or_default().1 += amount;
+    }
+
+    let mut scores: Vec<CombinedScore> = wallets
+        .into_iter()
+        .map(|(wallet, (nfts, tokens))| {
+            let counted = config.max_tokens.map_or(tokens, |max| tokens.min(max));
+            CombinedScore {
+                wallet: wallet.to_string(),
+                nfts,
+                tokens,
+                score: config.nft_weight * nfts as f64 + config.token_weight * counted,
+            }
+        })
+        .filter(|score| score.score >= config.min_score)
+        .collect();
+    scores.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.wallet.cmp(&b.wallet))
+    });
+    Ok(scores)
+}
+
+/// Writes one row per scored wallet, encrypted to `recipients` if any.
+pub fn write_scores(
+    path: &Path,
+    format: OutputFormat,
+    recipients: &[Recipient],
+    scores: &[CombinedScore],
+) -> Result<()> {
+    write_records(path, format, Compression::None, recipients, scores)
+}
+
+impl Record for CombinedScore {}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::snapshot::test_holder, serde_json::json};
+
+    fn nfts(owners: &[&str]) -> Vec<Holder> {
+        owners.iter().map(|owner| test_holder(json!({"owner_wallet": owner}))).collect()
+    }
+
+    fn tokens(balances: &[(&str, &str)]) -> Vec<Holder> {
+        balances
+            .iter()
+            .map(|(owner, amount)| test_holder(json!({"owner_wallet": owner, "amount": amount})))
+            .collect()
+    }
+
+    fn scores(scores: &[CombinedScore]) -> Vec<(&str, f64)> {
+        scores.iter().map(|s| (s.wallet.as_str(), s.score)).collect()
+    }
+
+    #[test]
+    fn weighs_nfts_and_tokens_of_every_wallet() {
+        let config = CombinedConfig {
+            nft_weight: 10.0,
+            token_weight: 0.5,
+            ..CombinedConfig::default()
+        };
+        let nft_holders = nfts(&["both", "both", "nfts"]);
+        let token_holders = tokens(&[("both", "4"), ("both", "2.5"), ("tokens", "30")]);
+        let scored = combined_scores(&nft_holders, &token_holders, &config).unwrap();
+        assert_eq!(scores(&scored), [("both", 23.25), ("tokens", 15.0), ("nfts", 10.0)]);
+        assert_eq!(scored[0].nfts, 2);
+        assert_eq!(scored[0].tokens, 6.5);
+    }
+
+    #[test]
+    fn caps_tokens_and_keeps_scores_at_the_minimum() {
+        let config = CombinedConfig {
+            max_tokens: Some(5.0),
+            min_score: 5.0,
+            ..CombinedConfig::default()
+        };
+        let token_holders = tokens(&[("at-cap", "5"), ("over-cap", "500"), ("under", "4.9")]);
+        let scored = combined_scores(&[], &token_holders, &config).unwrap();
+        assert_eq!(scores(&scored), [("at-cap", 5.0), ("over-cap", 5.0)]);
+        assert_eq!(scored[1].tokens, 500.0);
+    }
+
+    #[test]
+    fn token_snapshots_need_amounts() {
+        let config = CombinedConfig::default();
+        assert!(combined_scores(&[], &nfts(&["owner"]), &config).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use {
+    crate::{
+        chunked::ChunkMode,
+        clusters::ClusterConfig,
+        collections::CollectionConfig,
+        combined::CombinedConfig,
+        enrich::EnrichConfig,
+        governance::GovernanceConfig,
+        http_sender::ConnectionSettings,
+        metrics::CostModel,
+        output::{Compression, Explorer, OutputFormat},
+        retry_policy::RetryPolicy,
+        snapshot::{PipelineConfig, Source},
+        watch::WatchConfig,
+    },
+    anyhow::{anyhow, Result},
+    serde::{Deserialize, Serialize},
+    solana_cli_config::{Config as CliConfig, CONFIG_FILE},
+    solana_sdk::commitment_config::CommitmentLevel,
+    std::{env, fs, path::Path},
+};
+
+#[cfg(feature = "gpa-cache")]
+use crate::cache::GpaCacheConfig;
+#[cfg(feature = "history")]
+use crate::history::HistoryConfig;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteConfig;
+#[cfg(feature = "upload")]
+use crate::upload::UploadConfig;
+
+/// Snapshot settings loaded from a TOML, YAML or JSON file. Every section is
+/// optional; command line flags are applied on top by the binary.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub rpc: RpcConfig,
+    pub snapshot: SnapshotConfig,
+    pub output: OutputConfig,
+    pub filters: FilterConfig,
+    pub enrich: EnrichConfig,
+    pub collections: Vec<CollectionConfig>,
+    /// Wallet linkage heuristics for `snapshot clusters`.
+    pub clustering: ClusterConfig,
+    /// Voting power for `snapshot weights`.
+    pub governance: GovernanceConfig,
+    /// Scoring for `snapshot combine`.
+    pub combined: CombinedConfig,
+    /// Wallets monitored by `wallet watch`.
+    pub watch: WatchConfig,
+    #[cfg(feature = "history")]
+    pub history: HistoryConfig,
+    #[cfg(feature = "sqlite")]
+    pub sqlite: SqliteConfig,
+    #[cfg(feature = "upload")]
+    pub upload: UploadConfig,
+    /// Signer for program administration: keypair file, `usb://ledger` or
+    /// `prompt://`.
+    pub keypair: Option<String>,
+    pub admin: AdminConfig,
+}
+
+pub const DEFAULT_RPC_URL: &str = "https://api.devnet.solana.com";
+pub const DEFAULT_KEYPAIR: &str = "~/.config/solana/id.json";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RpcConfig {
+    pub url: Option<String>,
+    /// Commitment of reads and transaction confirmations.
+    pub commitment: CommitmentLevel,
+    /// Requests per second.
+    pub rate_limit: u32,
+    /// Per-mint token account lookups sent per JSON-RPC batch request; 1
+    /// sends them one by one, for endpoints that refuse batches.
+    pub batch_size: usize,
+    /// When to split getProgramAccounts queries: never, on_error or always.
+    pub chunk_gpa: ChunkMode,
+    #[serde(flatten)]
+    pub connection: ConnectionSettings,
+    pub retry: RetryPolicy,
+    /// Provider pricing for the usage report printed after each run.
+    pub costs: CostModel,
+    #[cfg(feature = "gpa-cache")]
+    pub cache: GpaCacheConfig,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            url: None,
+            commitment: CommitmentLevel::Confirmed,
+            rate_limit: 10,
+            batch_size: 1,
+            chunk_gpa: ChunkMode::OnError,
+            connection: ConnectionSettings::default(),
+            retry: RetryPolicy::default(),
+            costs: CostModel::default(),
+            #[cfg(feature = "gpa-cache")]
+            cache: GpaCacheConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    pub source: Option<Source>,
+    #[serde(flatten)]
+    pub pipeline: PipelineConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Output file of a single collection snapshot.
+    pub path: String,
+    /// Directory for per-collection outputs and the combined report.
+    pub dir: String,
+    pub format: OutputFormat,
+    /// Also write the snapshot in these formats, next to the main output.
+    pub extra_formats: Vec<OutputFormat>,
+    pub compress: Compression,
+    /// Explorer to add link columns for in CSV output.
+    pub explorer_links: Option<Explorer>,
+    /// Replace addresses with salted hashes; the mapping back is written to
+    /// `<output>.mapping.json`, encrypted like the snapshot, and must not be
+    /// published.
+    pub pseudonymize: bool,
+    /// Reusing a salt keeps pseudonyms stable across snapshots. A random one
+    /// is used when unset.
+    pub salt: Option<String>,
+    /// One entry per owner with its items nested, instead of one per item.
+    pub group_by_owner: bool,
+    /// One entry per master edition with its prints and their holders
+    /// nested; turns on `enrich.editions` when loaded from a file.
+    pub group_by_edition: bool,
+    /// Name outputs after the cluster snapshotted too, e.g.
+    /// `holders.devnet.json`, see `output::cluster_path`.
+    pub cluster_in_name: bool,
+    /// age public keys (`age1...`) to encrypt snapshot files to, adding
+    /// `.age` to their names, along with every other file listing wallets,
+    /// e.g. dead letters, weights and reports; any one of the matching
+    /// identities decrypts them. Files are written in plain text when empty.
+    pub encrypt_to: Vec<String>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            path: "holders.json".to_string(),
+            dir: ".".to_string(),
+            format: OutputFormat::Json,
+            extra_formats: Vec::new(),
+            compress: Compression::None,
+            explorer_links: None,
+            pseudonymize: false,
+            salt: None,
+            group_by_owner: false,
+            group_by_edition: false,
+            cluster_in_name: false,
+            encrypt_to: Vec::new(),
+        }
+    }
+}
+
+/// What to do with holders whose token account is frozen, e.g. by a
+/// sanctions freeze or a staking program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrozenPolicy {
+    Include,
+    Exclude,
+    /// Include them with a `frozen` field on every holder.
+    Tag,
+}
+
+impl Default for FrozenPolicy {
+    fn default() -> Self {
+        FrozenPolicy::Include
+    }
+}
+
+impl std::str::FromStr for FrozenPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(FrozenPolicy::Include),
+            "exclude" => Ok(FrozenPolicy::Exclude),
+            "tag" => Ok(FrozenPolicy::Tag),
+            _ => Err(format!("Unknown frozen policy {}, expected include, exclude or tag", s)),
+        }
+    }
+}
+
+/// What to do with holders that are custodial wallets of an exchange,
+/// which hold items for their users so should not get wallet airdrops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustodialPolicy {
+    Include,
+    Exclude,
+    /// Include them with `custodian` and `custodian_country` set.
+    Tag,
+}
+
+impl Default for CustodialPolicy {
+    fn default() -> Self {
+        CustodialPolicy::Include
+    }
+}
+
+impl std::str::FromStr for CustodialPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(CustodialPolicy::Include),
+            "exclude" => Ok(CustodialPolicy::Exclude),
+            "tag" => Ok(CustodialPolicy::Tag),
+            _ => Err(format!("Unknown custodial policy {}, expected include, exclude or tag", s)),
+        }
+    }
+}
+
+/// An exchange and the wallets it keeps its users' items in.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Custodian {
+    pub name: String,
+    /// Where the exchange is based, e.g. an ISO 3166 code.
+    #[serde(default)]
+    pub country: Option<String>,
+    pub wallets: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    /// Owners left out of the output, e.g. team or marketplace wallets.
+    pub exclude_wallets: Vec<String>,
+    pub frozen: FrozenPolicy,
+    pub custodial: CustodialPolicy,
+    /// Known exchange custodial wallets, for `custodial`.
+    pub custodians: Vec<Custodian>,
+    /// Owners holding fewer items than this are left out; 0 or 1 keeps all.
+    pub min_holdings: usize,
+}
+
+/// Program administration settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Batch operations estimated to cost more SOL than this, fees and rent,
+    /// only run with `--yes`.
+    pub confirm_above_sol: f64,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        AdminConfig {
+            confirm_above_sol: 0.1,
+        }
+    }
+}
+
+fn solana_cli_config() -> Option<CliConfig> {
+    let path = CONFIG_FILE.as_ref()?;
+    CliConfig::load(path).ok()
+}
+
+impl Config {
+    /// The endpoint set by flag or config file, else `SOLANA_RPC_URL`, else
+    /// the Solana CLI config, else devnet.
+    pub fn rpc_url(&self) -> String {
+        self.rpc
+            .url
+            .clone()
+            .or_else(|| env::var("SOLANA_RPC_URL").ok())
+            .or_else(|| solana_cli_config().map(|c| c.json_rpc_url))
+            .unwrap_or_else(|| DEFAULT_RPC_URL.to_string())
+    }
+
+    /// The keypair set by flag or config file, else the Solana CLI config,
+    /// else the CLI default location.
+    pub fn keypair_path(&self) -> String {
+        self.keypair
+            .clone()
+            .or_else(|| solana_cli_config().map(|c| c.keypair_path))
+            .unwrap_or_else(|| DEFAULT_KEYPAIR.to_string())
+    }
+
+    /// The format is picked from the file extension.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+
+        let mut config: Config = match extension {
+            "toml" => toml::from_str(&contents)?,
+            "yaml" | "yml" => serde_yaml::from_str(&contents)?,
+            "json" => serde_json::from_str(&contents)?,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown config extension for {}, expected .toml, .yaml or .json",
+                    path
+                ))
+            }
+        };
+        if config.output.group_by_edition {
+            config.enrich.editions = true;
+        }
+
+        Ok(config)
+    }
+}
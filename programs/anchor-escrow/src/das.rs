@@ -0,0 +1,204 @@
+use {
+    crate::{
+        config::OutputConfig, output::write_sealed_json, rpc::RpcBackend, snapshot::Holder,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    log::error,
+    serde::{Deserialize, Serialize},
+    std::{convert::TryFrom, path::Path, str::FromStr},
+};
+
+/// A DAS `getAssetProof` response: the path from a compressed asset's leaf
+/// to its tree's root.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AssetProof {
+    pub root: String,
+    /// Sibling hashes, leaf first.
+    pub proof: Vec<String>,
+    pub node_index: u64,
+    pub leaf: String,
+    pub tree_id: String,
+}
+
+impl AssetProof {
+    /// Position of the leaf in the tree; nodes are numbered from the root,
+    /// so the leaves of a tree of depth `proof.len()` start at 2^depth. A
+    /// node index outside the leaves means the proof is malformed.
+    pub fn leaf_index(&self) -> Result<u64> {
+        let depth = self.proof.len();
+        // A tree of depth d has 2^d leaves, numbered from 2^d.
+        let leaves = u32::try_from(depth)
+            .ok()
+            .and_then(|depth| 1u64.checked_shl(depth));
+        match leaves.and_then(|leaves| Some((self.node_index.checked_sub(leaves)?, leaves))) {
+            Some((index, leaves)) if index < leaves => Ok(index),
+            _ => Err(anyhow!(
+                "Node {} is not a leaf of a tree of depth {}!",
+                self.node_index,
+                depth
+            )),
+        }
+    }
+}
+
+/// Assets per `getAssetsByGroup` page, the most providers return.
+pub const ASSET_PAGE_LIMIT: usize = 1000;
+
+/// One page of a DAS `getAssetsByGroup` response.
+#[derive(Debug, Deserialize)]
+pub struct AssetPage {
+    pub items: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub id: String,
+    pub ownership: AssetOwnership,
+    pub compression: AssetCompression,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssetOwnership {
+    pub owner: String,
+    #[serde(default)]
+    pub frozen: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssetCompression {
+    pub compressed: bool,
+    #[serde(default)]
+    pub tree: String,
+}
+
+/// The holder of a compressed asset. It has neither a token account nor a
+/// metadata account, both live in the leaf, so its tree stands in for them.
+fn compressed_holder(asset: Asset) -> Holder {
+    Holder {
+        owner_wallet: asset.ownership.owner,
+        associated_token_address: asset.compression.tree.clone(),
+        mint_account: asset.id,
+        metadata_account: asset.compression.tree,
+        frozen: Some(asset.ownership.frozen),
+        ..Default::default()
+    }
+}
+
+/// Holders of the compressed assets of `collection`, from the DAS index,
+/// with the asset id as `mint_account`. Uncompressed items of the
+/// collection are left to `snapshot holders`.
+pub fn compressed_holders(client: &dyn RpcBackend, collection: &Pubkey) -> Result<Vec<Holder>> {
+    let mut holders = Vec::new();
+    for page in 1.. {
+        let assets = client.get_assets_by_collection(collection, page)?.items;
+        let last = assets.len() < ASSET_PAGE_LIMIT;
+        holders.extend(
+            assets
+                .into_iter()
+                .filter(|asset| asset.compression.compressed)
+                .map(compressed_holder),
+        );
+        if last {
+            break;
+        }
+    }
+    Ok(holders)
+}
+
+/// A holder with the proof needed to transfer or burn its compressed asset,
+/// for claim flows.
+#[derive(Debug, Serialize)]
+pub struct HolderProof {
+    #[serde(flatten)]
+    pub holder: Holder,
+    pub tree_id: String,
+    pub leaf_index: u64,
+    pub root: String,
+    pub proof: Vec<String>,
+}
+
+/// Looks up the asset proof of every holder of a compressed collection,
+/// whose `mint_account` is the asset id. Proofs change with every update of
+/// the tree, so export them right before they are used. Holders whose proof
+/// cannot be fetched are logged and left out.
+pub fn holder_proofs(client: &dyn RpcBackend, holders: &[Holder]) -> Result<Vec<HolderProof>> {
+    let mut proofs = Vec::with_capacity(holders.len());
+    for holder in holders {
+        let asset_id = Pubkey::from_str(&holder.mint_account)?;
+        let found = client
+            .get_asset_proof(&asset_id)
+            .and_then(|proof| Ok((proof.leaf_index()?, proof)));
+        match found {
+            Ok((leaf_index, proof)) => proofs.push(HolderProof {
+                holder: holder.clone(),
+                tree_id: proof.tree_id,
+                leaf_index,
+                root: proof.root,
+                proof: proof.proof,
+            }),
+            Err(err) => error!("Asset {} has no proof: {}", asset_id, err),
+        }
+    }
+    if proofs.is_empty() && !holders.is_empty() {
+        return Err(anyhow!("No asset proofs found, does the RPC support the DAS API?"));
+    }
+    Ok(proofs)
+}
+
+/// Writes `proofs`, which carry every holder's wallet, encrypted like the
+/// snapshots are if `output` has recipients; name `path` with `sealed_path`.
+pub fn write_proofs(path: &Path, output: &OutputConfig, proofs: &[HolderProof]) -> Result<()> {
+    write_sealed_json(path, output, proofs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proof(node_index: u64, depth: usize) -> AssetProof {
+        AssetProof {
+            root: String::new(),
+            proof: vec![String::new(); depth],
+            node_index,
+            leaf: String::new(),
+            tree_id: String::new(),
+        }
+    }
+
+    #[test]
+    fn leaf_index_counts_from_first_leaf() {
+        assert_eq!(proof(8, 3).leaf_index().unwrap(), 0);
+        assert_eq!(proof(15, 3).leaf_index().unwrap(), 7);
+        assert_eq!(proof(1, 0).leaf_index().unwrap(), 0);
+    }
+
+    #[test]
+    fn leaf_index_rejects_inner_nodes() {
+        assert!(proof(7, 3).leaf_index().is_err());
+        assert!(proof(16, 3).leaf_index().is_err());
+        assert!(proof(0, 0).leaf_index().is_err());
+    }
+
+    #[test]
+    fn leaf_index_rejects_overdeep_proofs() {
+        assert!(proof(u64::MAX, 64).leaf_index().is_err());
+        assert_eq!(proof(u64::MAX, 63).leaf_index().unwrap(), (1u64 << 63) - 1);
+    }
+
+    #[test]
+    fn compressed_holder_takes_the_tree_for_accounts() {
+        let asset: Asset = serde_json::from_value(serde_json::json!({
+            "id": "asset",
+            "ownership": { "owner": "wallet", "frozen": false },
+            "compression": { "compressed": true, "tree": "tree" },
+        }))
+        .unwrap();
+        let holder = compressed_holder(asset);
+        assert_eq!(holder.mint_account, "asset");
+        assert_eq!(holder.owner_wallet, "wallet");
+        assert_eq!(holder.associated_token_address, "tree");
+        assert_eq!(holder.metadata_account, "tree");
+        assert_eq!(holder.frozen, Some(false));
+    }
+}
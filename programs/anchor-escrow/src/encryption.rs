@@ -0,0 +1,76 @@
+use {
+    age::{
+        stream::StreamWriter,
+        x25519::{Identity, Recipient},
+    },
+    anyhow::{anyhow, Result},
+    std::{
+        fs::{self, File},
+        io::{self, BufReader, BufWriter, Write},
+        iter,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+};
+
+/// Parses age recipients, the `age1...` public keys of `age-keygen`.
+pub fn parse_recipients(keys: &[String]) -> Result<Vec<Recipient>> {
+    keys.iter()
+        .map(|key| {
+            Recipient::from_str(key.trim())
+                .map_err(|err| anyhow!("Recipient {} is not an age public key: {}!", key, err))
+        })
+        .collect()
+}
+
+/// `path` with `.age` appended if it is encrypted to `recipients`.
+pub fn sealed_path(path: &Path, recipients: &[String]) -> PathBuf {
+    if recipients.is_empty() {
+        return path.to_path_buf();
+    }
+    let mut path = path.as_os_str().to_owned();
+    path.push(".age");
+    PathBuf::from(path)
+}
+
+/// Wraps `w` so what is written to it is encrypted to each of
+/// `recipients`; any one of their identities decrypts it. The stream is
+/// only complete once `StreamWriter::finish` is called.
+pub fn seal<W: Write>(w: W, recipients: &[Recipient]) -> Result<StreamWriter<W>> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| Box::new(recipient.clone()) as Box<dyn age::Recipient>)
+        .collect();
+    Ok(age::Encryptor::with_recipients(recipients).wrap_output(w)?)
+}
+
+/// The first secret key in the identity file at `path`, as written by
+/// `age-keygen`.
+pub fn read_identity(path: &Path) -> Result<Identity> {
+    let contents = fs::read_to_string(path)?;
+    let key = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+        .ok_or(anyhow!("{} has no age secret key!", path.display()))?;
+    Identity::from_str(key).map_err(|err| anyhow!("{} has an invalid key: {}!", path.display(), err))
+}
+
+/// Decrypts the age file at `input` into `output`, which is left as it was
+/// written before encryption, compressed or not.
+pub fn unseal_file(input: &Path, identity: &Identity, output: &Path) -> Result<()> {
+    let decryptor = match age::Decryptor::new(BufReader::new(File::open(input)?))? {
+        age::Decryptor::Recipients(decryptor) => decryptor,
+        _ => {
+            return Err(anyhow!(
+                "{} is encrypted with a passphrase, not to recipients!",
+                input.display()
+            ))
+        }
+    };
+    let mut reader = decryptor.decrypt(iter::once(identity as &dyn age::Identity))?;
+    let mut w = BufWriter::new(File::create(output)?);
+    io::copy(&mut reader, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,714 @@
+use {
+    crate::{
+        fetch::{FetchConfig, UriFetcher},
+        metadata::parse_metadata,
+        output::{check_same_cluster, read_holders},
+        provenance::received_at,
+        rpc::RpcBackend,
+        snapshot::Holder,
+    },
+    anchor_lang::{prelude::Pubkey, solana_program::borsh::try_from_slice_unchecked},
+    anyhow::Result,
+    curve25519_dalek::edwards::CompressedEdwardsY,
+    log::warn,
+    mpl_token_metadata::{
+        state::{Edition, Key},
+        ID as TOKEN_METADATA_PROGRAM_ID,
+    },
+    serde::{Deserialize, Serialize},
+    solana_sdk::{hash::hashv, program_pack::Pack, system_program},
+    spl_token::state::{Account as TokenAccount, Multisig},
+    std::{
+        collections::{BTreeMap, HashMap},
+        convert::TryInto,
+        path::Path,
+        str::FromStr,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// getMultipleAccounts accepts at most 100 keys.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+pub const NAME_SERVICE_PROGRAM_ID: &str = "namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX";
+/// Bonfida's offers program, which stores each wallet's favourite domain.
+pub const NAME_OFFERS_PROGRAM_ID: &str = "85iDfUvr3HJyLM2zcq5BXSiDvUWfw6cSE1FfNBo8Ap29";
+pub const REVERSE_LOOKUP_CLASS: &str = "33m47vH6Eav6jr5Ry86XjhRft2jRBLDnDgPSHoquXi2Z";
+const NAME_HASH_PREFIX: &str = "SPL Name Service";
+/// Parent, owner and class keys precede the data of a name registry account.
+const NAME_REGISTRY_HEADER_LEN: usize = 96;
+
+/// Extra per-holder lookups, each costing RPC calls, so all are off by
+/// default.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EnrichConfig {
+    /// Tag each holder with `owner_kind` and `owner_program`.
+    pub owners: bool,
+    /// Add the owner's favourite .sol domain as `sol_domain`.
+    pub sol_domains: bool,
+    /// Add `holding_since` and `holding_days`, replaying each token
+    /// account's history.
+    pub hold_duration: bool,
+    /// How to report items held by SPL multisig authorities.
+    pub multisig: MultisigPolicy,
+    /// Add each item's on-chain `name`.
+    pub names: bool,
+    /// Add `master_edition` and `edition_number` for editioned collections.
+    pub editions: bool,
+    /// Add `listed`, and `listed_with` for listed items, from the token
+    /// account's delegate.
+    pub listings: bool,
+    /// Delegates that count as a marketplace listing; any delegate when
+    /// empty.
+    pub marketplace_delegates: Vec<String>,
+    /// Add each item's `image` from its off-chain JSON.
+    pub images: bool,
+    /// Add each item's `metadata_sha256`, a hash of its off-chain JSON.
+    pub metadata_hashes: bool,
+    /// An earlier snapshot taken with `metadata_hashes`; items whose hash
+    /// differs from it get `metadata_changed`.
+    pub metadata_baseline: Option<String>,
+    /// How off-chain JSON is fetched.
+    pub fetch: FetchConfig,
+}
+
+/// Treatment of token accounts whose owner is an SPL token multisig.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultisigPolicy {
+    /// Do not look owners up; multisigs count as any other owner.
+    Ignore,
+    /// Keep the multisig as owner and list its signers in
+    /// `multisig_signers`.
+    Attribute,
+    /// Replace the holder with one per signer, each with the multisig in
+    /// `multisig`. An item then counts once per signer.
+    Expand,
+}
+
+impl Default for MultisigPolicy {
+    fn default() -> Self {
+        MultisigPolicy::Ignore
+    }
+}
+
+impl FromStr for MultisigPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(MultisigPolicy::Ignore),
+            "attribute" => Ok(MultisigPolicy::Attribute),
+            "expand" => Ok(MultisigPolicy::Expand),
+            _ => Err(format!(
+                "Unknown multisig policy {}, expected ignore, attribute or expand",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnerKind {
+    /// A key on the ed25519 curve, so one somebody holds the private key for.
+    Wallet,
+    /// A program derived address, i.e. the NFT is held by a contract.
+    Pda,
+}
+
+fn owner_kind(owner: &Pubkey) -> OwnerKind {
+    match CompressedEdwardsY::from_slice(owner.as_ref()).decompress() {
+        Some(_) => OwnerKind::Wallet,
+        None => OwnerKind::Pda,
+    }
+}
+
+/// Sets `owner_kind` and `owner_program` on every holder. Owners without an
+/// account are reported as owned by the system program, as the runtime does.
+pub fn enrich_owners(client: &dyn RpcBackend, holders: &mut [Holder]) -> Result<()> {
+    let mut owners: Vec<Pubkey> = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.owner_wallet))
+        .collect::<std::result::Result<_, _>>()?;
+    owners.sort_unstable();
+    owners.dedup();
+
+    let mut programs: HashMap<String, (OwnerKind, String)> = HashMap::new();
+    for chunk in owners.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = client.get_multiple_accounts(chunk)?;
+        for (owner, account) in chunk.iter().zip(accounts) {
+            let program = account
+                .map(|account| account.owner)
+                .unwrap_or_else(system_program::id);
+            programs.insert(owner.to_string(), (owner_kind(owner), program.to_string()));
+        }
+    }
+
+    for holder in holders.iter_mut() {
+        if let Some((kind, program)) = programs.get(&holder.owner_wallet) {
+            holder.owner_kind = Some(*kind);
+            holder.owner_program = Some(program.clone());
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn get_multiple_accounts_chunked(
+    client: &dyn RpcBackend,
+    pubkeys: &[Pubkey],
+) -> Result<Vec<Option<Vec<u8>>>> {
+    let mut data = Vec::with_capacity(pubkeys.len());
+    for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = client.get_multiple_accounts(chunk)?;
+        data.extend(accounts.into_iter().map(|account| account.map(|a| a.data)));
+    }
+    Ok(data)
+}
+
+pub fn find_favourite_domain_address(owner: &Pubkey) -> Pubkey {
+    let program_id = Pubkey::from_str(NAME_OFFERS_PROGRAM_ID).unwrap();
+    let (address, _) =
+        Pubkey::find_program_address(&[b"favourite_domain", owner.as_ref()], &program_id);
+    address
+}
+
+/// The reverse lookup account of a domain, holding its name.
+pub fn find_reverse_lookup_address(domain: &Pubkey) -> Pubkey {
+    let program_id = Pubkey::from_str(NAME_SERVICE_PROGRAM_ID).unwrap();
+    let class = Pubkey::from_str(REVERSE_LOOKUP_CLASS).unwrap();
+    let hashed_name = hashv(&[NAME_HASH_PREFIX.as_bytes(), domain.to_string().as_bytes()]);
+    let (address, _) = Pubkey::find_program_address(
+        &[hashed_name.as_ref(), class.as_ref(), Pubkey::default().as_ref()],
+        &program_id,
+    );
+    address
+}
+
+/// Favourite domain account: a tag byte, then the domain's name account.
+fn parse_favourite_domain(data: &[u8]) -> Option<Pubkey> {
+    let bytes: [u8; 32] = data.get(1..33)?.try_into().ok()?;
+    Some(Pubkey::new_from_array(bytes))
+}
+
+/// Reverse lookup account: the registry header, then a u32 length prefixed
+/// name.
+fn parse_reverse_lookup(data: &[u8]) -> Option<String> {
+    let data = data.get(NAME_REGISTRY_HEADER_LEN..)?;
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let name = std::str::from_utf8(data.get(4..4 + len)?).ok()?;
+    Some(format!("{}.sol", name.trim_start_matches('\0')))
+}
+
+/// Sets `sol_domain` on every holder to the favourite domain its owner set
+/// with Bonfida, or an empty string when there is none. Wallets owning
+/// domains without picking a favourite are not resolved.
+pub fn enrich_sol_domains(client: &dyn RpcBackend, holders: &mut [Holder]) -> Result<()> {
+    let mut owners: Vec<Pubkey> = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.owner_wallet))
+        .collect::<std::result::Result<_, _>>()?;
+    owners.sort_unstable();
+    owners.dedup();
+
+    let favourites: Vec<Pubkey> = owners.iter().map(find_favourite_domain_address).collect();
+    let mut with_domain: Vec<(Pubkey, Pubkey)> = Vec::new();
+    let favourite_data = get_multiple_accounts_chunked(client, &favourites)?;
+    for (owner, data) in owners.iter().zip(favourite_data) {
+        if let Some(domain) = data.as_deref().and_then(parse_favourite_domain) {
+            with_domain.push((*owner, find_reverse_lookup_address(&domain)));
+        }
+    }
+
+    let reverse: Vec<Pubkey> = with_domain.iter().map(|(_, reverse)| *reverse).collect();
+    let mut names: HashMap<String, String> = HashMap::new();
+    let reverse_data = get_multiple_accounts_chunked(client, &reverse)?;
+    for ((owner, _), data) in with_domain.iter().zip(reverse_data) {
+        if let Some(name) = data.as_deref().and_then(parse_reverse_lookup) {
+            names.insert(owner.to_string(), name);
+        }
+    }
+
+    for holder in holders.iter_mut() {
+        holder.sol_domain = Some(names.get(&holder.owner_wallet).cloned().unwrap_or_default());
+    }
+
+    Ok(())
+}
+
+/// Signers of every owner that is an initialized SPL token multisig.
+fn find_multisigs(
+    client: &dyn RpcBackend,
+    holders: &[Holder],
+) -> Result<HashMap<String, Vec<String>>> {
+    let mut owners: Vec<Pubkey> = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.owner_wallet))
+        .collect::<std::result::Result<_, _>>()?;
+    owners.sort_unstable();
+    owners.dedup();
+
+    let mut multisigs = HashMap::new();
+    for chunk in owners.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = client.get_multiple_accounts(chunk)?;
+        for (owner, account) in chunk.iter().zip(accounts) {
+            let account = match account {
+                Some(account) if account.owner == spl_token::ID => account,
+                _ => continue,
+            };
+            if let Ok(multisig) = Multisig::unpack(&account.data) {
+                let signers = multisig.signers[..multisig.n as usize]
+                    .iter()
+                    .map(|signer| signer.to_string())
+                    .collect();
+                multisigs.insert(owner.to_string(), signers);
+            }
+        }
+    }
+    Ok(multisigs)
+}
+
+/// Applies `policy` to holders whose owner is an SPL token multisig.
+pub fn enrich_multisig(
+    client: &dyn RpcBackend,
+    holders: &mut Vec<Holder>,
+    policy: MultisigPolicy,
+) -> Result<()> {
+    if policy == MultisigPolicy::Ignore {
+        return Ok(());
+    }
+    let multisigs = find_multisigs(client, holders)?;
+    if multisigs.is_empty() {
+        return Ok(());
+    }
+
+    let mut enriched = Vec::with_capacity(holders.len());
+    for mut holder in holders.drain(..) {
+        let signers = match multisigs.get(&holder.owner_wallet) {
+            Some(signers) => signers,
+            None => {
+                enriched.push(holder);
+                continue;
+            }
+        };
+        match policy {
+            MultisigPolicy::Ignore => unreachable!(),
+            MultisigPolicy::Attribute => {
+                holder.multisig_signers = Some(signers.join(" "));
+                enriched.push(holder);
+            }
+            MultisigPolicy::Expand => {
+                for signer in signers {
+                    enriched.push(Holder {
+                        owner_wallet: signer.clone(),
+                        multisig: Some(holder.owner_wallet.clone()),
+                        ..holder.clone()
+                    });
+                }
+            }
+        }
+    }
+    *holders = enriched;
+    Ok(())
+}
+
+/// Sets `name` on every holder from the item's metadata account. Metadata
+/// that is gone or cannot be decoded is logged and left unset.
+pub fn enrich_names(client: &dyn RpcBackend, holders: &mut [Holder]) -> Result<()> {
+    let mut accounts: Vec<Pubkey> = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.metadata_account))
+        .collect::<std::result::Result<_, _>>()?;
+    accounts.sort_unstable();
+    accounts.dedup();
+
+    let mut names: HashMap<String, String> = HashMap::new();
+    for (account, data) in accounts.iter().zip(get_multiple_accounts_chunked(client, &accounts)?) {
+        match data.map(|data| parse_metadata(&data)) {
+            Some(Ok(metadata)) => {
+                let name = metadata.data.name.trim_matches(char::from(0)).trim();
+                names.insert(account.to_string(), name.to_string());
+            }
+            _ => warn!("Account {} has no metadata", account),
+        }
+    }
+
+    for holder in holders.iter_mut() {
+        holder.name = names.get(&holder.metadata_account).cloned();
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct OffChainJson {
+    #[serde(default)]
+    image: String,
+}
+
+/// Off-chain JSON of a set of holders' items.
+struct OffChain {
+    /// Metadata account to URI.
+    uris: HashMap<String, String>,
+    /// URI to the JSON it served; URIs that could not be fetched are absent.
+    bodies: HashMap<String, Vec<u8>>,
+}
+
+impl OffChain {
+    fn body(&self, holder: &Holder) -> Option<&Vec<u8>> {
+        self.bodies.get(self.uris.get(&holder.metadata_account)?)
+    }
+}
+
+/// Reads the URI from every holder's metadata account and fetches each URI
+/// once, as `fetch` says, bypassing the cache if `fresh`. Failures are
+/// logged and left out.
+fn fetch_off_chain(
+    client: &dyn RpcBackend,
+    fetch: &FetchConfig,
+    holders: &[Holder],
+    fresh: bool,
+) -> Result<OffChain> {
+    let mut accounts: Vec<Pubkey> = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.metadata_account))
+        .collect::<std::result::Result<_, _>>()?;
+    accounts.sort_unstable();
+    accounts.dedup();
+
+    let mut uris: HashMap<String, String> = HashMap::new();
+    for (account, data) in accounts.iter().zip(get_multiple_accounts_chunked(client, &accounts)?) {
+        match data.map(|data| parse_metadata(&data)) {
+            Some(Ok(metadata)) => {
+                let uri = metadata.data.uri.trim_matches(char::from(0)).trim();
+                uris.insert(account.to_string(), uri.to_string());
+            }
+            _ => warn!("Account {} has no metadata", account),
+        }
+    }
+
+    // Editions and semi-fungibles share their JSON.
+    let mut unique: Vec<String> = uris.values().cloned().collect();
+    unique.sort_unstable();
+    unique.dedup();
+    let fetcher = UriFetcher::new(fetch)?;
+    let mut bodies: HashMap<String, Vec<u8>> = HashMap::new();
+    let fetched = fetcher.fetch_all(&unique, fresh)?;
+    for (uri, body) in unique.into_iter().zip(fetched) {
+        match body {
+            Ok(body) => {
+                bodies.insert(uri, body);
+            }
+            Err(err) => warn!("{} cannot be fetched: {}", uri, err),
+        }
+    }
+    Ok(OffChain { uris, bodies })
+}
+
+fn set_images(off_chain: &OffChain, holders: &mut [Holder]) {
+    let mut images: HashMap<&str, Option<String>> = HashMap::new();
+    for (uri, body) in &off_chain.bodies {
+        let image = match serde_json::from_slice::<OffChainJson>(body) {
+            Ok(json) if !json.image.is_empty() => Some(json.image),
+            Ok(_) => {
+                warn!("{} has no image", uri);
+                None
+            }
+            Err(err) => {
+                warn!("{} is not metadata JSON: {}", uri, err);
+                None
+            }
+        };
+        images.insert(uri, image);
+    }
+
+    for holder in holders.iter_mut() {
+        let uri = off_chain.uris.get(&holder.metadata_account);
+        holder.image = uri.and_then(|uri| images.get(uri.as_str()).cloned().flatten());
+    }
+}
+
+/// Sets `image` on every holder from the item's off-chain JSON, fetched
+/// as `fetch` says. Items whose metadata or JSON cannot be read are logged
+/// and left unset.
+pub fn enrich_images(
+    client: &dyn RpcBackend,
+    fetch: &FetchConfig,
+    holders: &mut [Holder],
+) -> Result<()> {
+    set_images(&fetch_off_chain(client, fetch, holders, false)?, holders);
+    Ok(())
+}
+
+/// sha256 of off-chain JSON, parsed and written out again so that key order
+/// and whitespace do not count. Bodies that are not JSON are hashed as is.
+pub fn metadata_sha256(body: &[u8]) -> String {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(json) => hashv(&[json.to_string().as_bytes()]).to_string(),
+        Err(_) => hashv(&[body]).to_string(),
+    }
+}
+
+fn set_metadata_hashes(
+    off_chain: &OffChain,
+    baseline: Option<&[Holder]>,
+    holders: &mut [Holder],
+) {
+    let expected: HashMap<&str, &str> = baseline
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|holder| {
+            let hash = holder.metadata_sha256.as_deref()?;
+            Some((holder.mint_account.as_str(), hash))
+        })
+        .collect();
+
+    for holder in holders.iter_mut() {
+        holder.metadata_sha256 = off_chain.body(holder).map(|body| metadata_sha256(body));
+        holder.metadata_changed = match (&holder.metadata_sha256, baseline) {
+            (Some(hash), Some(_)) => {
+                expected.get(holder.mint_account.as_str()).map(|expected| expected != hash)
+            }
+            _ => None,
+        };
+    }
+}
+
+/// Sets `metadata_sha256` on every holder from the item's off-chain JSON,
+/// and `metadata_changed` where `baseline`, an earlier snapshot taken with
+/// hashes, has the item's hash. Items whose JSON cannot be fetched are
+/// logged and left unset. The JSON is always fetched again, as a cached
+/// copy could never show a change; the cache is updated with it.
+pub fn enrich_metadata_hashes(
+    client: &dyn RpcBackend,
+    fetch: &FetchConfig,
+    baseline: Option<&[Holder]>,
+    holders: &mut [Holder],
+) -> Result<()> {
+    let off_chain = fetch_off_chain(client, fetch, holders, true)?;
+    set_metadata_hashes(&off_chain, baseline, holders);
+    Ok(())
+}
+
+pub(crate) fn edition_address(mint: &Pubkey) -> Pubkey {
+    let seeds = &[
+        b"metadata".as_ref(),
+        TOKEN_METADATA_PROGRAM_ID.as_ref(),
+        mint.as_ref(),
+        b"edition".as_ref(),
+    ];
+    Pubkey::find_program_address(seeds, &TOKEN_METADATA_PROGRAM_ID).0
+}
+
+/// Sets `master_edition` and `edition_number` on every holder from the
+/// item's edition account. Items without one are not editioned and left
+/// unset.
+pub fn enrich_editions(client: &dyn RpcBackend, holders: &mut [Holder]) -> Result<()> {
+    let mut mints: Vec<Pubkey> = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.mint_account))
+        .collect::<std::result::Result<_, _>>()?;
+    mints.sort_unstable();
+    mints.dedup();
+    let addresses: Vec<Pubkey> = mints.iter().map(edition_address).collect();
+
+    let mut editions: HashMap<String, (String, Option<u64>)> = HashMap::new();
+    for ((mint, address), data) in mints
+        .iter()
+        .zip(&addresses)
+        .zip(get_multiple_accounts_chunked(client, &addresses)?)
+    {
+        let data = match data {
+            Some(data) if !data.is_empty() => data,
+            _ => continue,
+        };
+        if data[0] == Key::EditionV1 as u8 {
+            match try_from_slice_unchecked::<Edition>(&data) {
+                Ok(edition) => {
+                    let master = edition.parent.to_string();
+                    editions.insert(mint.to_string(), (master, Some(edition.edition)));
+                }
+                Err(_) => warn!("Account {} has no edition", address),
+            }
+        } else {
+            editions.insert(mint.to_string(), (address.to_string(), None));
+        }
+    }
+
+    for holder in holders.iter_mut() {
+        if let Some((master, number)) = editions.get(&holder.mint_account) {
+            holder.master_edition = Some(master.clone());
+            holder.edition_number = *number;
+        }
+    }
+    Ok(())
+}
+
+/// Sets `listed` on every holder, and `listed_with` to the delegate of those
+/// listed. Marketplaces listing without escrow, e.g. Magic Eden v2 and
+/// Tensor, leave the item in the seller's token account and take a
+/// delegation over it; escrow listings move the item away, so the seller no
+/// longer shows up as holder at all. Staking programs delegating the same
+/// way count as listings unless `delegates` names the marketplaces.
+pub fn enrich_listings(
+    client: &dyn RpcBackend,
+    holders: &mut [Holder],
+    delegates: &[String],
+) -> Result<()> {
+    let accounts: Vec<Pubkey> = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.associated_token_address))
+        .collect::<std::result::Result<_, _>>()?;
+
+    let mut listings: HashMap<Pubkey, Option<Pubkey>> = HashMap::new();
+    for (account, data) in accounts.iter().zip(get_multiple_accounts_chunked(client, &accounts)?) {
+        // Token-2022 accounts share the layout up to the extensions.
+        let token_account = data
+            .as_ref()
+            .and_then(|data| data.get(..TokenAccount::LEN))
+            .map(TokenAccount::unpack_from_slice);
+        match token_account {
+            Some(Ok(token_account)) => {
+                let delegate = Option::from(token_account.delegate)
+                    .filter(|_| token_account.delegated_amount > 0)
+                    .filter(|delegate: &Pubkey| {
+                        delegates.is_empty() || delegates.contains(&delegate.to_string())
+                    });
+                listings.insert(*account, delegate);
+            }
+            _ => warn!("Account {} is not a token account", account),
+        }
+    }
+
+    for (holder, account) in holders.iter_mut().zip(&accounts) {
+        if let Some(delegate) = listings.get(account) {
+            holder.listed = Some(delegate.is_some());
+            holder.listed_with = delegate.map(|delegate| delegate.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Items of a wallet, and how many of them are listed.
+#[derive(Debug, Serialize)]
+pub struct WalletListings {
+    pub wallet: String,
+    pub held: usize,
+    pub listed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListingStats {
+    pub held: usize,
+    pub listed: usize,
+    /// Wallets with at least one item listed, most listed first.
+    pub wallets: Vec<WalletListings>,
+}
+
+/// Listed and held items per wallet, from holders enriched with
+/// `enrich_listings`; `None` for holders that were not.
+pub fn listing_stats(holders: &[Holder]) -> Option<ListingStats> {
+    if holders.iter().all(|holder| holder.listed.is_none()) {
+        return None;
+    }
+    let mut wallets: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for holder in holders {
+        let counts = wallets.entry(holder.owner_wallet.as_str()).or_default();
+        counts.0 += 1;
+        if holder.listed == Some(true) {
+            counts.1 += 1;
+        }
+    }
+
+    let mut listing: Vec<WalletListings> = wallets
+        .into_iter()
+        .filter(|(_, (_, listed))| *listed > 0)
+        .map(|(wallet, (held, listed))| WalletListings {
+            wallet: wallet.to_string(),
+            held,
+            listed,
+        })
+        .collect();
+    listing.sort_by(|a, b| b.listed.cmp(&a.listed));
+    Some(ListingStats {
+        held: holders.len(),
+        listed: listing.iter().map(|wallet| wallet.listed).sum(),
+        wallets: listing,
+    })
+}
+
+/// Token account transactions read to find the last transfer in. Token
+/// accounts see little traffic besides transfers, so this goes a long way.
+const HOLD_HISTORY_LIMIT: usize = 100;
+
+/// Sets `holding_since` and `holding_days` on every holder from the last
+/// transfer of the item into its token account. Holders whose history cannot
+/// be read, or goes back further than the node keeps, are logged and left
+/// unset.
+pub fn enrich_hold_duration(client: &dyn RpcBackend, holders: &mut [Holder]) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    for holder in holders.iter_mut() {
+        let token_account = Pubkey::from_str(&holder.associated_token_address)?;
+        let mint = Pubkey::from_str(&holder.mint_account)?;
+        match received_at(client, &token_account, &mint, HOLD_HISTORY_LIMIT) {
+            Ok(Some(since)) => {
+                holder.holding_since = Some(since);
+                holder.holding_days = Some((now - since).max(0) as u64 / SECONDS_PER_DAY);
+            }
+            Ok(None) => warn!("Account {} has no inbound transfer", token_account),
+            Err(err) => warn!("Account {} has no readable history: {}", token_account, err),
+        }
+    }
+    Ok(())
+}
+
+/// Runs the lookups enabled in `config`.
+pub fn enrich(
+    client: &dyn RpcBackend,
+    config: &EnrichConfig,
+    holders: &mut Vec<Holder>,
+) -> Result<()> {
+    // First, so expanded signers get the other lookups too.
+    enrich_multisig(client, holders, config.multisig)?;
+    if config.owners {
+        enrich_owners(client, holders)?;
+    }
+    if config.sol_domains {
+        enrich_sol_domains(client, holders)?;
+    }
+    if config.hold_duration {
+        enrich_hold_duration(client, holders)?;
+    }
+    if config.names {
+        enrich_names(client, holders)?;
+    }
+    if config.editions {
+        enrich_editions(client, holders)?;
+    }
+    if config.listings {
+        enrich_listings(client, holders, &config.marketplace_delegates)?;
+    }
+    // Both read the same JSON, which is fetched once, bypassing the cache
+    // when hashing, see `enrich_metadata_hashes`.
+    if config.images || config.metadata_hashes {
+        let fresh = config.metadata_hashes;
+        let off_chain = fetch_off_chain(client, &config.fetch, holders, fresh)?;
+        if config.images {
+            set_images(&off_chain, holders);
+        }
+        if config.metadata_hashes {
+            let baseline = match &config.metadata_baseline {
+                Some(path) => Some(read_holders(Path::new(path))?),
+                None => None,
+            };
+            if let Some(baseline) = &baseline {
+                check_same_cluster(vec![&baseline[..], &holders[..]])?;
+            }
+            set_metadata_hashes(&off_chain, baseline.as_deref(), holders);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use {
+    crate::limiter::RateLimiter,
+    anyhow::{anyhow, Result},
+    crossbeam::thread,
+    log::warn,
+    reqwest::{blocking::Client, Url},
+    serde::{Deserialize, Serialize},
+    solana_sdk::hash::hashv,
+    std::{
+        collections::HashMap,
+        fs,
+        io::Read,
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// How off-chain metadata is fetched.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FetchConfig {
+    /// Tried in order for IPFS content, after the URI's own host; each ends
+    /// where the CID goes.
+    pub ipfs_gateways: Vec<String>,
+    /// Likewise for Arweave content, ending where the transaction id goes.
+    pub arweave_gateways: Vec<String>,
+    /// Requests per second to any one host.
+    pub host_rate_limit: u32,
+    /// Responses larger than this are dropped.
+    pub max_bytes: u64,
+    pub timeout_secs: u64,
+    pub workers: usize,
+    /// Responses are kept here by URI hash; nothing is cached when unset.
+    pub cache_dir: Option<String>,
+    /// Cached responses older than this are fetched again.
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            ipfs_gateways: vec![
+                "https://ipfs.io/ipfs/".to_string(),
+                "https://cloudflare-ipfs.com/ipfs/".to_string(),
+                "https://nftstorage.link/ipfs/".to_string(),
+            ],
+            arweave_gateways: vec!["https://arweave.net/".to_string()],
+            host_rate_limit: 5,
+            max_bytes: 1024 * 1024,
+            timeout_secs: 30,
+            workers: 8,
+            cache_dir: None,
+            cache_ttl_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Fetches metadata URIs with per-host rate limits, falling back to other
+/// gateways for IPFS and Arweave content.
+pub struct UriFetcher {
+    config: FetchConfig,
+    client: Client,
+    limiters: Mutex<HashMap<String, RateLimiter>>,
+}
+
+impl UriFetcher {
+    pub fn new(config: &FetchConfig) -> Result<Self> {
+        if let Some(dir) = &config.cache_dir {
+            fs::create_dir_all(dir)?;
+        }
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+        Ok(UriFetcher {
+            config: config.clone(),
+            client,
+            limiters: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// URLs serving `uri`, in the order to try them: the URI itself if it is
+    /// HTTP, then every gateway for IPFS and Arweave content.
+    pub fn candidates(&self, uri: &str) -> Vec<String> {
+        let uri = uri.trim();
+        if let Some(path) = uri.strip_prefix("ipfs://") {
+            let path = path.trim_start_matches("ipfs/");
+            return self.config.ipfs_gateways.iter().map(|g| format!("{}{}", g, path)).collect();
+        }
+        if let Some(path) = uri.strip_prefix("ar://") {
+            let gateways = self.config.arweave_gateways.iter();
+            return gateways.map(|g| format!("{}{}", g, path)).collect();
+        }
+
+        let mut candidates = vec![uri.to_string()];
+        let url = match Url::parse(uri) {
+            Ok(url) => url,
+            Err(_) => return candidates,
+        };
+        let gateways = match url.path().find("/ipfs/") {
+            Some(start) => Some((&self.config.ipfs_gateways, &url.path()[start + 6..])),
+            None if url.host_str().map_or(false, |host| host.contains("arweave")) => {
+                Some((&self.config.arweave_gateways, &url.path()[1..]))
+            }
+            None => None,
+        };
+        if let Some((gateways, path)) = gateways {
+            let path = match url.query() {
+                Some(query) => format!("{}?{}", path, query),
+                None => path.to_string(),
+            };
+            for gateway in gateways {
+                let candidate = format!("{}{}", gateway, path);
+                if !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        candidates
+    }
+
+    fn cache_path(&self, uri: &str) -> Option<PathBuf> {
+        let dir = self.config.cache_dir.as_ref()?;
+        Some(PathBuf::from(dir).join(hashv(&[uri.as_bytes()]).to_string()))
+    }
+
+    /// The cached body at `path`, unless it is older than `cache_ttl_secs`.
+    fn cached(&self, path: &Path) -> Option<Vec<u8>> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        if age > Duration::from_secs(self.config.cache_ttl_secs) {
+            return None;
+        }
+        fs::read(path).ok()
+    }
+
+    fn limiter(&self, url: &str) -> Option<RateLimiter> {
+        let host = Url::parse(url).ok()?.host_str()?.to_string();
+        let mut limiters = self.limiters.lock().unwrap();
+        let limiter = limiters
+            .entry(host)
+            .or_insert_with(|| RateLimiter::new(self.config.host_rate_limit));
+        Some(limiter.clone())
+    }
+
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        if let Some(limiter) = self.limiter(url) {
+            limiter.wait();
+        }
+        let response = self.client.get(url).send()?.error_for_status()?;
+        if response.content_length().unwrap_or_default() > self.config.max_bytes {
+            return Err(anyhow!("{} is larger than {} bytes", url, self.config.max_bytes));
+        }
+        // Content-Length may be missing or wrong, so the read is capped too.
+        let mut body = Vec::new();
+        response.take(self.config.max_bytes + 1).read_to_end(&mut body)?;
+        if body.len() as u64 > self.config.max_bytes {
+            return Err(anyhow!("{} is larger than {} bytes", url, self.config.max_bytes));
+        }
+        // Gateways answer some failures with an HTML page and a 200.
+        if serde_json::from_slice::<serde_json::Value>(&body).is_err() {
+            return Err(anyhow!("{} did not serve JSON", url));
+        }
+        Ok(body)
+    }
+
+    /// The JSON at `uri`, from the cache or the first candidate URL that
+    /// serves it.
+    pub fn fetch(&self, uri: &str) -> Result<Vec<u8>> {
+        let cache_path = self.cache_path(uri);
+        if let Some(body) = cache_path.as_ref().and_then(|path| self.cached(path)) {
+            return Ok(body);
+        }
+        self.fetch_fresh(uri, cache_path.as_deref())
+    }
+
+    /// `fetch` skipping the cache, which is updated with the response.
+    pub fn fetch_fresh(&self, uri: &str, cache_path: Option<&Path>) -> Result<Vec<u8>> {
+
+        let mut last_err = anyhow!("{} is not a URL", uri);
+        for url in self.candidates(uri) {
+            match self.get(&url) {
+                Ok(body) => {
+                    if let Some(path) = cache_path {
+                        if let Err(err) = fs::write(path, &body) {
+                            warn!("Cache file {} cannot be written: {}", path.display(), err);
+                        }
+                    }
+                    return Ok(body);
+                }
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Fetches `uris` on `workers` threads, in order; `fresh` skips the
+    /// cache, e.g. to see whether content changed.
+    pub fn fetch_all(&self, uris: &[String], fresh: bool) -> Result<Vec<Result<Vec<u8>>>> {
+        let chunk_size = uris.len().div_ceil(self.config.workers.max(1)).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = uris
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move |_| {
+                        let fetch = |uri: &String| match fresh {
+                            true => self.fetch_fresh(uri, self.cache_path(uri).as_deref()),
+                            false => self.fetch(uri),
+                        };
+                        chunk.iter().map(fetch).collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<std::thread::Result<Vec<_>>>()
+        })
+        .and_then(|bodies| bodies)
+        .map(|bodies| bodies.into_iter().flatten().collect())
+        .map_err(|_| anyhow!("Fetch thread panicked!"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetcher() -> UriFetcher {
+        UriFetcher::new(&FetchConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn candidates_of_ipfs_uris_are_every_gateway() {
+        assert_eq!(
+            fetcher().candidates("ipfs://ipfs/bafy/1.json"),
+            vec![
+                "https://ipfs.io/ipfs/bafy/1.json",
+                "https://cloudflare-ipfs.com/ipfs/bafy/1.json",
+                "https://nftstorage.link/ipfs/bafy/1.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn candidates_of_gateway_urls_start_with_the_url() {
+        let uri = "https://gateway.pinata.cloud/ipfs/bafy/1.json?ext=json";
+        let candidates = fetcher().candidates(uri);
+        assert_eq!(candidates[0], "https://gateway.pinata.cloud/ipfs/bafy/1.json?ext=json");
+        assert_eq!(candidates[1], "https://ipfs.io/ipfs/bafy/1.json?ext=json");
+        assert_eq!(candidates.len(), 4);
+    }
+
+    #[test]
+    fn candidates_of_arweave_urls_are_not_repeated() {
+        assert_eq!(
+            fetcher().candidates("https://arweave.net/tx"),
+            vec!["https://arweave.net/tx"]
+        );
+        assert_eq!(fetcher().candidates("ar://tx"), vec!["https://arweave.net/tx"]);
+    }
+
+    #[test]
+    fn candidates_of_other_uris_are_the_uri_alone() {
+        assert_eq!(
+            fetcher().candidates(" https://example.com/1.json "),
+            vec!["https://example.com/1.json"]
+        );
+        assert_eq!(fetcher().candidates("not a url"), vec!["not a url"]);
+    }
+}
@@ -0,0 +1,176 @@
+use {
+    crate::{
+        config::{CustodialPolicy, Custodian, FilterConfig, FrozenPolicy},
+        snapshot::Holder,
+    },
+    anyhow::Result,
+    std::collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// A business rule applied to a collection's holders before enrichment and
+/// output. Filters see the whole collection, so rules over several holders,
+/// such as minimum holdings, fit as well as per-holder ones.
+///
+/// Any `Fn(&Holder) -> bool` is a filter keeping the holders it accepts.
+pub trait HolderFilter: Sync {
+    fn apply(&self, holders: Vec<Holder>) -> Result<Vec<Holder>>;
+}
+
+impl<F> HolderFilter for F
+where
+    F: Fn(&Holder) -> bool + Sync,
+{
+    fn apply(&self, holders: Vec<Holder>) -> Result<Vec<Holder>> {
+        Ok(holders.into_iter().filter(|holder| self(holder)).collect())
+    }
+}
+
+/// Owners left out of the output, e.g. team or marketplace wallets.
+pub struct ExcludeWallets {
+    pub wallets: HashSet<String>,
+}
+
+impl HolderFilter for ExcludeWallets {
+    fn apply(&self, holders: Vec<Holder>) -> Result<Vec<Holder>> {
+        Ok(holders
+            .into_iter()
+            .filter(|holder| !self.wallets.contains(&holder.owner_wallet))
+            .collect())
+    }
+}
+
+/// Drops or untags holders with a frozen token account per `FrozenPolicy`.
+pub struct FrozenFilter {
+    pub policy: FrozenPolicy,
+}
+
+impl HolderFilter for FrozenFilter {
+    fn apply(&self, holders: Vec<Holder>) -> Result<Vec<Holder>> {
+        Ok(holders
+            .into_iter()
+            .filter_map(|mut holder| {
+                match self.policy {
+                    FrozenPolicy::Include => holder.frozen = None,
+                    FrozenPolicy::Exclude if holder.frozen == Some(true) => return None,
+                    FrozenPolicy::Exclude => holder.frozen = None,
+                    FrozenPolicy::Tag => {}
+                }
+                Some(holder)
+            })
+            .collect())
+    }
+}
+
+/// Drops or tags holders that are exchange custodial wallets per
+/// `CustodialPolicy`.
+pub struct CustodialFilter {
+    pub policy: CustodialPolicy,
+    /// Wallet to its custodian.
+    pub wallets: HashMap<String, Custodian>,
+}
+
+impl CustodialFilter {
+    pub fn new(policy: CustodialPolicy, custodians: &[Custodian]) -> Self {
+        let mut wallets = HashMap::new();
+        for custodian in custodians {
+            for wallet in &custodian.wallets {
+                wallets.insert(wallet.clone(), custodian.clone());
+            }
+        }
+        CustodialFilter { policy, wallets }
+    }
+}
+
+impl HolderFilter for CustodialFilter {
+    fn apply(&self, holders: Vec<Holder>) -> Result<Vec<Holder>> {
+        Ok(holders
+            .into_iter()
+            .filter_map(|mut holder| {
+                let custodian = self.wallets.get(&holder.owner_wallet);
+                match (self.policy, custodian) {
+                    (CustodialPolicy::Exclude, Some(_)) => return None,
+                    (CustodialPolicy::Tag, Some(custodian)) => {
+                        holder.custodian = Some(custodian.name.clone());
+                        holder.custodian_country = custodian.country.clone();
+                    }
+                    _ => {}
+                }
+                Some(holder)
+            })
+            .collect())
+    }
+}
+
+/// Items held per custodian country, from holders tagged by
+/// `CustodialFilter`; custodians without a country count as "unknown".
+pub fn custodial_counts(holders: &[Holder]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for holder in holders.iter().filter(|holder| holder.custodian.is_some()) {
+        let country = holder.custodian_country.as_deref().unwrap_or("unknown");
+        *counts.entry(country.to_string()).or_default() += 1;
+    }
+    counts
+}
+
+/// Leaves out owners holding fewer than `min` items, counted after the
+/// filters before it in the chain.
+pub struct MinHoldings {
+    pub min: usize,
+}
+
+impl HolderFilter for MinHoldings {
+    fn apply(&self, holders: Vec<Holder>) -> Result<Vec<Holder>> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for holder in &holders {
+            *counts.entry(holder.owner_wallet.clone()).or_default() += 1;
+        }
+        Ok(holders
+            .into_iter()
+            .filter(|holder| counts[&holder.owner_wallet] >= self.min)
+            .collect())
+    }
+}
+
+/// Filters applied in order, each to the output of the one before.
+#[derive(Default)]
+pub struct FilterChain {
+    pub filters: Vec<Box<dyn HolderFilter>>,
+}
+
+impl FilterChain {
+    /// The built-in filters `config` enables: excluded wallets, the frozen
+    /// and custodial policies, then minimum holdings.
+    pub fn from_config(config: &FilterConfig) -> Self {
+        let mut chain = FilterChain::default();
+        if !config.exclude_wallets.is_empty() {
+            chain.push(ExcludeWallets {
+                wallets: config.exclude_wallets.iter().cloned().collect(),
+            });
+        }
+        chain.push(FrozenFilter {
+            policy: config.frozen,
+        });
+        if config.custodial != CustodialPolicy::Include && !config.custodians.is_empty() {
+            chain.push(CustodialFilter::new(config.custodial, &config.custodians));
+        }
+        if config.min_holdings > 1 {
+            chain.push(MinHoldings {
+                min: config.min_holdings,
+            });
+        }
+        chain
+    }
+
+    pub fn push<F: HolderFilter + 'static>(&mut self, filter: F) {
+        self.filters.push(Box::new(filter));
+    }
+}
+
+impl HolderFilter for FilterChain {
+    fn apply(&self, mut holders: Vec<Holder>) -> Result<Vec<Holder>> {
+        for filter in &self.filters {
+            holders = filter.apply(holders)?;
+        }
+        Ok(holders)
+    }
+}
@@ -0,0 +1,252 @@
+use {
+    crate::{client::ProgramClient, enrich::edition_address, wallet::metadata_address},
+    anchor_lang::{
+        prelude::Pubkey,
+        solana_program::{instruction::Instruction, program_pack::Pack, system_instruction},
+    },
+    anyhow::Result,
+    mpl_token_metadata::{
+        instruction::{create_master_edition_v3, create_metadata_accounts_v2, verify_collection},
+        state::{Collection, Creator, MAX_MASTER_EDITION_LEN, MAX_METADATA_LEN},
+        ID as TOKEN_METADATA_PROGRAM_ID,
+    },
+    serde::Serialize,
+    solana_sdk::signature::{Keypair, Signer},
+    spl_token::{
+        state::{Account as TokenAccount, Mint},
+        ID as TOKEN_PROGRAM_ID,
+    },
+};
+
+/// A test collection for `fixtures mint-collection`. The payer is update
+/// authority, mint authority and sole, verified creator of every item.
+#[derive(Debug, Clone)]
+pub struct CollectionFixture {
+    pub size: usize,
+    pub name: String,
+    pub symbol: String,
+    /// "{}" is replaced with the item number.
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    /// Mint a collection NFT first and verify every item in it.
+    pub collection_nft: bool,
+    /// Items go to these wallets in turn, to the payer when empty.
+    pub owners: Vec<Pubkey>,
+}
+
+/// One NFT to mint, with the keypairs of the accounts it creates.
+pub struct PlannedMint {
+    pub name: String,
+    pub mint: Keypair,
+    pub token_account: Keypair,
+    pub owner: Pubkey,
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MintedItem {
+    pub name: String,
+    pub mint_account: String,
+    pub owner_wallet: String,
+    pub token_account: String,
+    pub signature: String,
+}
+
+/// What `mint_collection` created, to point snapshots at.
+#[derive(Debug, Serialize)]
+pub struct MintedCollection {
+    pub update_authority: String,
+    pub collection_mint: Option<String>,
+    pub items: Vec<MintedItem>,
+}
+
+/// Sizes of the accounts one NFT creates, for `ProgramClient::estimate`.
+pub fn account_sizes() -> Vec<usize> {
+    vec![Mint::LEN, TokenAccount::LEN, MAX_METADATA_LEN, MAX_MASTER_EDITION_LEN]
+}
+
+/// Rent of a new mint and token account.
+struct Rents {
+    mint: u64,
+    token_account: u64,
+}
+
+/// Instructions minting one master edition into a plain token account of
+/// `owner`, which need not sign. With `collection`, the item is verified
+/// in it, the payer being the collection's update authority too.
+#[allow(clippy::too_many_arguments)]
+fn mint_instructions(
+    payer: &Pubkey,
+    mint: &Pubkey,
+    token_account: &Pubkey,
+    owner: &Pubkey,
+    rents: &Rents,
+    fixture: &CollectionFixture,
+    name: &str,
+    uri: &str,
+    collection: Option<&Pubkey>,
+) -> Result<Vec<Instruction>> {
+    let metadata = metadata_address(mint);
+    let edition = edition_address(mint);
+    let creators = vec![Creator {
+        address: *payer,
+        verified: true,
+        share: 100,
+    }];
+
+    let mut instructions = vec![
+        system_instruction::create_account(
+            payer,
+            mint,
+            rents.mint,
+            Mint::LEN as u64,
+            &TOKEN_PROGRAM_ID,
+        ),
+        spl_token::instruction::initialize_mint(&TOKEN_PROGRAM_ID, mint, payer, Some(payer), 0)?,
+        system_instruction::create_account(
+            payer,
+            token_account,
+            rents.token_account,
+            TokenAccount::LEN as u64,
+            &TOKEN_PROGRAM_ID,
+        ),
+        spl_token::instruction::initialize_account(&TOKEN_PROGRAM_ID, token_account, mint, owner)?,
+        spl_token::instruction::mint_to(&TOKEN_PROGRAM_ID, mint, token_account, payer, &[], 1)?,
+        create_metadata_accounts_v2(
+            TOKEN_METADATA_PROGRAM_ID,
+            metadata,
+            *mint,
+            *payer,
+            *payer,
+            *payer,
+            name.to_string(),
+            fixture.symbol.clone(),
+            uri.to_string(),
+            Some(creators),
+            fixture.seller_fee_basis_points,
+            true,
+            true,
+            collection.map(|key| Collection {
+                verified: false,
+                key: *key,
+            }),
+            None,
+        ),
+        create_master_edition_v3(
+            TOKEN_METADATA_PROGRAM_ID,
+            edition,
+            *mint,
+            *payer,
+            *payer,
+            metadata,
+            *payer,
+            Some(0),
+        ),
+    ];
+    if let Some(collection_mint) = collection {
+        instructions.push(verify_collection(
+            TOKEN_METADATA_PROGRAM_ID,
+            metadata,
+            *payer,
+            *payer,
+            *collection_mint,
+            metadata_address(collection_mint),
+            edition_address(collection_mint),
+            None,
+        ));
+    }
+    Ok(instructions)
+}
+
+/// A mint with fresh keys, so a fixture can be minted any number of times.
+fn plan_mint(
+    payer: &Pubkey,
+    rents: &Rents,
+    fixture: &CollectionFixture,
+    name: String,
+    uri: &str,
+    owner: Pubkey,
+    collection: Option<&Pubkey>,
+) -> Result<PlannedMint> {
+    let (mint, token_account) = (Keypair::new(), Keypair::new());
+    let instructions = mint_instructions(
+        payer,
+        &mint.pubkey(),
+        &token_account.pubkey(),
+        &owner,
+        rents,
+        fixture,
+        &name,
+        uri,
+        collection,
+    )?;
+    Ok(PlannedMint {
+        name,
+        mint,
+        token_account,
+        owner,
+        instructions,
+    })
+}
+
+/// The collection NFT, if any, then every item, one transaction each.
+pub fn plan_collection(
+    client: &ProgramClient,
+    fixture: &CollectionFixture,
+) -> Result<Vec<PlannedMint>> {
+    let payer = client.payer.pubkey();
+    let rents = Rents {
+        mint: client.rpc.get_minimum_balance_for_rent_exemption(Mint::LEN)?,
+        token_account: client.rpc.get_minimum_balance_for_rent_exemption(TokenAccount::LEN)?,
+    };
+
+    let mut plans = Vec::with_capacity(fixture.size + 1);
+    let mut collection_mint = None;
+    if fixture.collection_nft {
+        let uri = fixture.uri.replace("{}", "collection");
+        let plan = plan_mint(&payer, &rents, fixture, fixture.name.clone(), &uri, payer, None)?;
+        collection_mint = Some(plan.mint.pubkey());
+        plans.push(plan);
+    }
+    for number in 1..=fixture.size {
+        let owner = match fixture.owners.is_empty() {
+            true => payer,
+            false => fixture.owners[(number - 1) % fixture.owners.len()],
+        };
+        let name = format!("{} #{}", fixture.name, number);
+        let uri = fixture.uri.replace("{}", &number.to_string());
+        let collection = collection_mint.as_ref();
+        plans.push(plan_mint(&payer, &rents, fixture, name, &uri, owner, collection)?);
+    }
+    Ok(plans)
+}
+
+/// Sends the planned mints in order. The collection NFT has to land before
+/// its items can be verified, so a failure stops the run; items sent until
+/// then stay minted.
+pub fn mint_collection(
+    client: &ProgramClient,
+    fixture: &CollectionFixture,
+    plans: &[PlannedMint],
+) -> Result<MintedCollection> {
+    let mut minted = MintedCollection {
+        update_authority: client.payer.pubkey().to_string(),
+        collection_mint: None,
+        items: Vec::with_capacity(fixture.size),
+    };
+    for (index, plan) in plans.iter().enumerate() {
+        let signature = client.send(&plan.instructions, &[&plan.mint, &plan.token_account])?;
+        if fixture.collection_nft && index == 0 {
+            minted.collection_mint = Some(plan.mint.pubkey().to_string());
+            continue;
+        }
+        minted.items.push(MintedItem {
+            name: plan.name.clone(),
+            mint_account: plan.mint.pubkey().to_string(),
+            owner_wallet: plan.owner.to_string(),
+            token_account: plan.token_account.pubkey().to_string(),
+            signature: signature.to_string(),
+        });
+    }
+    Ok(minted)
+}
@@ -0,0 +1,119 @@
+use {
+    crate::{
+        output::{write_records, Compression, OutputFormat, Record},
+        snapshot::Holder,
+    },
+    age::x25519::Recipient,
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    std::{collections::HashMap, path::Path},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GovernanceConfig {
+    pub power_per_nft: u64,
+    /// Upper bound on any one wallet's voting power, so whales cannot
+    /// decide a vote alone.
+    pub max_power: Option<u64>,
+}
+
+impl Default for GovernanceConfig {
+    fn default() -> Self {
+        GovernanceConfig {
+            power_per_nft: 1,
+            max_power: None,
+        }
+    }
+}
+
+/// One row of a Realms or Squads style voter weight import.
+#[derive(Debug, Serialize)]
+pub struct VoterWeight {
+    pub wallet: String,
+    pub voting_power: u64,
+}
+
+/// Voting power per owner, strongest first.
+pub fn voter_weights(holders: &[Holder], config: &GovernanceConfig) -> Vec<VoterWeight> {
+    let mut held: HashMap<&str, u64> = HashMap::new();
+    for holder in holders {
+        *held.entry(holder.owner_wallet.as_str()).or_default() += 1;
+    }
+
+    let mut weights: Vec<VoterWeight> = held
+        .into_iter()
+        .map(|(wallet, count)| {
+            let power = count.saturating_mul(config.power_per_nft);
+            VoterWeight {
+                wallet: wallet.to_string(),
+                voting_power: config.max_power.map_or(power, |max| power.min(max)),
+            }
+        })
+        .collect();
+    weights.sort_by(|a, b| {
+        b.voting_power
+            .cmp(&a.voting_power)
+            .then_with(|| a.wallet.cmp(&b.wallet))
+    });
+    weights
+}
+
+/// Writes one row per voter, encrypted to `recipients` if any.
+pub fn write_weights(
+    path: &Path,
+    format: OutputFormat,
+    recipients: &[Recipient],
+    weights: &[VoterWeight],
+) -> Result<()> {
+    write_records(path, format, Compression::None, recipients, weights)
+}
+
+impl Record for VoterWeight {}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::snapshot::test_holder, serde_json::json};
+
+    /// One holder per `(owner, mint)`.
+    fn holders(items: &[(&str, &str)]) -> Vec<Holder> {
+        items
+            .iter()
+            .map(|(owner, mint)| test_holder(json!({"owner_wallet": owner, "mint_account": mint})))
+            .collect()
+    }
+
+    fn powers(weights: &[VoterWeight]) -> Vec<(&str, u64)> {
+        weights.iter().map(|w| (w.wallet.as_str(), w.voting_power)).collect()
+    }
+
+    #[test]
+    fn caps_power_at_the_maximum_and_no_lower() {
+        let holders = holders(&[
+            ("under", "a1"),
+            ("at", "b1"),
+            ("at", "b2"),
+            ("over", "c1"),
+            ("over", "c2"),
+            ("over", "c3"),
+        ]);
+        let config = GovernanceConfig {
+            power_per_nft: 5,
+            max_power: Some(10),
+        };
+        let weights = voter_weights(&holders, &config);
+        assert_eq!(powers(&weights), [("at", 10), ("over", 10), ("under", 5)]);
+
+        let uncapped = voter_weights(&holders, &GovernanceConfig::default());
+        assert_eq!(powers(&uncapped), [("over", 3), ("at", 2), ("under", 1)]);
+    }
+
+    #[test]
+    fn counts_items_of_every_collection_held() {
+        // Two collections' snapshots, one after the other.
+        let first = holders(&[("both", "first-1"), ("first", "first-2")]);
+        let second = holders(&[("both", "second-1"), ("second", "second-2")]);
+        let weights = voter_weights(&[first, second].concat(), &GovernanceConfig::default());
+        assert_eq!(powers(&weights), [("both", 2), ("first", 1), ("second", 1)]);
+    }
+}
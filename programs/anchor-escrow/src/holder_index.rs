@@ -0,0 +1,181 @@
+use {
+    crate::snapshot::Holder,
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    serde::Serialize,
+    std::{
+        convert::TryInto,
+        fs::File,
+        io::{BufWriter, Read, Seek, SeekFrom, Write},
+        path::Path,
+        str::FromStr,
+    },
+};
+
+const MAGIC: &[u8; 8] = b"NFTHIDX2";
+/// Linearly probed tables by mint, which fungible snapshots, all one mint,
+/// turned into a single run of probes.
+const MAGIC_V1: &[u8; 8] = b"NFTHIDX1";
+const HEADER_SIZE: u64 = 16;
+/// Where a bucket's entries start.
+const OFFSET_SIZE: u64 = 8;
+/// Mint, owner and token account.
+const ENTRY_SIZE: usize = 96;
+
+/// A holder found in a `HolderIndex`.
+#[derive(Debug, Serialize)]
+pub struct IndexedHolder {
+    pub mint_account: String,
+    pub owner_wallet: String,
+    pub associated_token_address: String,
+}
+
+/// Mints are uniformly distributed already, so their first bytes serve as
+/// the hash.
+fn bucket_of(mint: &Pubkey, buckets: u64) -> u64 {
+    u64::from_le_bytes(mint.as_ref()[..8].try_into().unwrap()) % buckets
+}
+
+/// Writes an on-disk hash table of `holders` by mint, chained: as many
+/// buckets as holders, each the offset of its entries, which are stored
+/// together, so a lookup reads an offset pair and then its bucket in one go.
+/// Mints with several holders, e.g. every holder of a fungible snapshot,
+/// share their bucket. Returns the holders indexed; those without
+/// parseable addresses, e.g. from pseudonymized snapshots, are left out.
+pub fn write_index(path: &Path, holders: &[Holder]) -> Result<usize> {
+    let mut entries = Vec::with_capacity(holders.len());
+    for holder in holders {
+        let parsed = (
+            Pubkey::from_str(&holder.mint_account),
+            Pubkey::from_str(&holder.owner_wallet),
+            Pubkey::from_str(&holder.associated_token_address),
+        );
+        if let (Ok(mint), Ok(owner), Ok(token_account)) = parsed {
+            entries.push((mint, owner, token_account));
+        }
+    }
+
+    let buckets = (entries.len() as u64).max(1);
+    entries.sort_by_key(|(mint, _, _)| bucket_of(mint, buckets));
+    // Bucket `b` holds the entries from `offsets[b]` up to `offsets[b + 1]`.
+    let mut offsets = vec![0u64; buckets as usize + 1];
+    for (mint, _, _) in &entries {
+        offsets[bucket_of(mint, buckets) as usize + 1] += 1;
+    }
+    for bucket in 1..offsets.len() {
+        offsets[bucket] += offsets[bucket - 1];
+    }
+
+    let mut f = BufWriter::new(File::create(path)?);
+    f.write_all(MAGIC)?;
+    f.write_all(&buckets.to_le_bytes())?;
+    for offset in &offsets {
+        f.write_all(&offset.to_le_bytes())?;
+    }
+    for (mint, owner, token_account) in &entries {
+        f.write_all(mint.as_ref())?;
+        f.write_all(owner.as_ref())?;
+        f.write_all(token_account.as_ref())?;
+    }
+    f.flush()?;
+    Ok(entries.len())
+}
+
+/// An index written by `write_index`, looked up in place.
+pub struct HolderIndex {
+    file: File,
+    buckets: u64,
+}
+
+impl HolderIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| anyhow!("{} is not a holder index!", path.display()))?;
+        match &header[..8] {
+            magic if magic == MAGIC => {}
+            magic if magic == MAGIC_V1 => {
+                return Err(anyhow!(
+                    "{} is a holder index of an older layout, write it again!",
+                    path.display()
+                ))
+            }
+            _ => return Err(anyhow!("{} is not a holder index!", path.display())),
+        }
+        let buckets = u64::from_le_bytes(header[8..].try_into()?);
+        Ok(HolderIndex { file, buckets })
+    }
+
+    /// The holders of `mint`, none when it is not in the index.
+    pub fn lookup(&self, mint: &Pubkey) -> Result<Vec<IndexedHolder>> {
+        let bucket = bucket_of(mint, self.buckets);
+        let mut file = &self.file;
+        let mut range = [0u8; 2 * OFFSET_SIZE as usize];
+        file.seek(SeekFrom::Start(HEADER_SIZE + bucket * OFFSET_SIZE))?;
+        file.read_exact(&mut range)?;
+        let start = u64::from_le_bytes(range[..8].try_into()?);
+        let end = u64::from_le_bytes(range[8..].try_into()?);
+
+        let entries_start = HEADER_SIZE + (self.buckets + 1) * OFFSET_SIZE;
+        let mut entries = vec![0u8; (end - start) as usize * ENTRY_SIZE];
+        file.seek(SeekFrom::Start(entries_start + start * ENTRY_SIZE as u64))?;
+        file.read_exact(&mut entries)?;
+        Ok(entries
+            .chunks(ENTRY_SIZE)
+            .filter(|entry| entry[..32] == mint.as_ref()[..])
+            .map(|entry| IndexedHolder {
+                mint_account: mint.to_string(),
+                owner_wallet: Pubkey::new(&entry[32..64]).to_string(),
+                associated_token_address: Pubkey::new(&entry[64..96]).to_string(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::snapshot::test_holder, serde_json::json};
+
+    fn holder(mint: &Pubkey) -> Holder {
+        test_holder(json!({
+            "owner_wallet": Pubkey::new_unique().to_string(),
+            "associated_token_address": Pubkey::new_unique().to_string(),
+            "mint_account": mint.to_string(),
+            "metadata_account": Pubkey::new_unique().to_string(),
+        }))
+    }
+
+    #[test]
+    fn finds_every_holder_written() {
+        // A fungible token held by many, next to NFTs held by one each.
+        let fungible = Pubkey::new_unique();
+        let mut holders: Vec<Holder> = (0..50).map(|_| holder(&fungible)).collect();
+        let nfts: Vec<Pubkey> = (0..20).map(|_| Pubkey::new_unique()).collect();
+        holders.extend(nfts.iter().map(holder));
+        // Placeholder addresses, like those of a pseudonymized snapshot.
+        holders.push(test_holder(json!({})));
+
+        let path = std::env::temp_dir().join(format!("index-{}.idx", std::process::id()));
+        assert_eq!(write_index(&path, &holders).unwrap(), 70);
+        let index = HolderIndex::open(&path).unwrap();
+
+        let found = index.lookup(&fungible).unwrap();
+        assert!(found.iter().all(|h| h.mint_account == fungible.to_string()));
+        let mut owners: Vec<&str> = found.iter().map(|h| h.owner_wallet.as_str()).collect();
+        let mut expected: Vec<&str> =
+            holders[..50].iter().map(|h| h.owner_wallet.as_str()).collect();
+        owners.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(owners, expected);
+
+        for (mint, holder) in nfts.iter().zip(&holders[50..]) {
+            let found = index.lookup(mint).unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].owner_wallet, holder.owner_wallet);
+            assert_eq!(found[0].associated_token_address, holder.associated_token_address);
+        }
+        assert!(index.lookup(&Pubkey::new_unique()).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use {
+    crate::{limiter::RateLimiter, metrics::RpcMetrics},
+    reqwest::{blocking::Client, header::CONTENT_TYPE, StatusCode},
+    serde::Deserialize,
+    serde_json::Value,
+    solana_client::{
+        client_error::Result,
+        rpc_client::{RpcClient, RpcClientConfig},
+        rpc_custom_error::{
+            NodeUnhealthyErrorData, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+            JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE,
+        },
+        rpc_request::{RpcError, RpcRequest, RpcResponseErrorData},
+        rpc_response::RpcSimulateTransactionResult,
+        rpc_sender::RpcSender,
+    },
+    solana_sdk::commitment_config::CommitmentConfig,
+    std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+};
+
+/// HTTP settings for RPC connections. The stock sender only exposes an
+/// overall timeout, which does not help when a connection never completes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    /// Upper bound for a whole request, including reading a large gPA body.
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    /// TCP keep-alive interval, `None` to leave it off.
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Idle connections kept open per host for reuse; should cover the
+    /// number of workers when HTTP/2 is off.
+    pub pool_max_idle_per_host: usize,
+    /// Offer HTTP/2 during the TLS handshake, so concurrent requests share
+    /// one multiplexed connection where the endpoint supports it.
+    pub http2: bool,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        ConnectionSettings {
+            timeout_secs: 120,
+            connect_timeout_secs: 10,
+            tcp_keepalive_secs: Some(60),
+            pool_max_idle_per_host: 32,
+            http2: true,
+        }
+    }
+}
+
+pub struct ConfiguredHttpSender {
+    client: Client,
+    url: String,
+    request_id: AtomicU64,
+    metrics: Arc<RpcMetrics>,
+    limiter: RateLimiter,
+}
+
+/// Build one per endpoint and share it: clones use the same connection
+/// pool, so TLS handshakes are not repeated per sender.
+pub fn http_client(settings: &ConnectionSettings) -> Client {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(settings.timeout_secs))
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+        .tcp_keepalive(settings.tcp_keepalive_secs.map(Duration::from_secs))
+        .pool_max_idle_per_host(settings.pool_max_idle_per_host);
+    if !settings.http2 {
+        builder = builder.http1_only();
+    }
+    builder.build().expect("Failed to build http client!")
+}
+
+impl ConfiguredHttpSender {
+    /// `limiter` is told about every response, see `RateLimiter::observe`.
+    pub fn new(
+        client: Client,
+        url: String,
+        metrics: Arc<RpcMetrics>,
+        limiter: RateLimiter,
+    ) -> Self {
+        ConfiguredHttpSender {
+            client,
+            url,
+            request_id: AtomicU64::new(0),
+            metrics,
+            limiter,
+        }
+    }
+}
+
+/// The JSON-RPC error in `body`, if it has one, with its data decoded as
+/// the stock sender does. Providers send these with error statuses too,
+/// e.g. oversized gPA responses, so the status is appended to the message
+/// when it is not a success, see `http_status`.
+pub(crate) fn response_error(status: StatusCode, body: &str) -> Option<RpcError> {
+    let json: Value = serde_json::from_str(body).ok()?;
+    let err = json.get("error")?;
+    let code = err["code"].as_i64().unwrap_or_default();
+    let data = match code {
+        JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE => {
+            serde_json::from_value::<RpcSimulateTransactionResult>(err["data"].clone())
+                .map(RpcResponseErrorData::SendTransactionPreflightFailure)
+                .unwrap_or(RpcResponseErrorData::Empty)
+        }
+        JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => {
+            serde_json::from_value::<NodeUnhealthyErrorData>(err["data"].clone())
+                .map(|data| RpcResponseErrorData::NodeUnhealthy {
+                    num_slots_behind: data.num_slots_behind,
+                })
+                .unwrap_or(RpcResponseErrorData::Empty)
+        }
+        _ => RpcResponseErrorData::Empty,
+    };
+    let mut message = err["message"].as_str().unwrap_or_default().to_string();
+    if !status.is_success() {
+        message = format!("{} (HTTP {})", message, status.as_u16());
+    }
+    Some(RpcError::RpcResponseError {
+        code,
+        message,
+        data,
+    })
+}
+
+/// The HTTP status a JSON-RPC error from `ConfiguredHttpSender` came with,
+/// when it was not a success.
+pub fn http_status(err: &RpcError) -> Option<u16> {
+    let message = match err {
+        RpcError::RpcResponseError { message, .. } => message,
+        _ => return None,
+    };
+    let status = message.strip_suffix(')')?.rsplit_once("(HTTP ")?.1;
+    status.parse().ok()
+}
+
+impl RpcSender for ConfiguredHttpSender {
+    fn send(&self, request: RpcRequest, params: Value) -> Result<Value> {
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let request_json = request.build_request_json(request_id, params).to_string();
+        let method = request.to_string();
+
+        let response = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request_json)
+            .send();
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                // Providers bill calls that time out too.
+                self.metrics.record(&method, 1, 0);
+                return Err(err.into());
+            }
+        };
+        let status = response.status();
+        self.limiter.observe(status, response.headers());
+        let status_error = response.error_for_status_ref().err();
+
+        let body = response.text()?;
+        self.metrics.record(&method, 1, body.len() as u64);
+        if let Some(err) = response_error(status, &body) {
+            return Err(err.into());
+        }
+        if let Some(err) = status_error {
+            return Err(err.into());
+        }
+        let mut json: Value = serde_json::from_str(&body)?;
+        Ok(json["result"].take())
+    }
+}
+
+pub fn rpc_client(
+    client: Client,
+    url: String,
+    metrics: Arc<RpcMetrics>,
+    limiter: RateLimiter,
+    commitment: CommitmentConfig,
+) -> RpcClient {
+    RpcClient::new_sender(
+        ConfiguredHttpSender::new(client, url, metrics, limiter),
+        RpcClientConfig::with_commitment(commitment),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_bodies_keep_data_and_status() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,
+            "message":"Node is behind by 42 slots","data":{"numSlotsBehind":42}}}"#;
+        let err = response_error(StatusCode::SERVICE_UNAVAILABLE, body).unwrap();
+        assert_eq!(http_status(&err), Some(503));
+        match err {
+            RpcError::RpcResponseError { code, data, .. } => {
+                assert_eq!(code, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY);
+                assert!(matches!(
+                    data,
+                    RpcResponseErrorData::NodeUnhealthy {
+                        num_slots_behind: Some(42)
+                    }
+                ));
+            }
+            err => panic!("unexpected {:?}", err),
+        }
+    }
+
+    #[test]
+    fn successful_error_responses_have_no_status() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"Invalid param"}}"#;
+        let err = response_error(StatusCode::OK, body).unwrap();
+        assert_eq!(http_status(&err), None);
+        match err {
+            RpcError::RpcResponseError { code, message, .. } => {
+                assert_eq!(code, -32602);
+                assert_eq!(message, "Invalid param");
+            }
+            err => panic!("unexpected {:?}", err),
+        }
+    }
+
+    #[test]
+    fn results_and_plain_bodies_are_not_errors() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"result":7}"#;
+        assert!(response_error(StatusCode::OK, body).is_none());
+        assert!(response_error(StatusCode::TOO_MANY_REQUESTS, "Too many requests").is_none());
+    }
+}
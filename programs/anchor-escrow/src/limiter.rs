@@ -0,0 +1,260 @@
+use {
+    crate::{
+        das::{AssetPage, AssetProof},
+        metrics::RpcMetrics,
+        rpc::RpcBackend,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::Result,
+    reqwest::{header::HeaderMap, StatusCode},
+    solana_client::{
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_response::RpcConfirmedTransactionStatusWithSignature,
+    },
+    solana_sdk::{account::Account, hash::Hash, signature::Signature},
+    solana_transaction_status::EncodedConfirmedTransaction,
+    std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Slowest the limiter backs off to after repeated 429s without a hint.
+const MAX_INTERVAL: Duration = Duration::from_secs(5);
+/// Reset values above this are unix timestamps rather than seconds.
+const MIN_TIMESTAMP: u64 = 1_000_000_000;
+
+fn header_u64(headers: &HeaderMap, names: &[&str]) -> Option<u64> {
+    names
+        .iter()
+        .filter_map(|name| headers.get(*name))
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| value.trim().parse().ok())
+}
+
+/// `Retry-After`, either seconds or an HTTP date to wait until.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get("retry-after")?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let until = httpdate::parse_http_date(value).ok()?;
+    Some(until.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// How long a provider asked clients to hold off, from `Retry-After` or an
+/// exhausted `x-ratelimit-*` / `ratelimit-*` quota. Reset times may be
+/// seconds from now or a unix timestamp; a quota without one is assumed to
+/// reset within a second.
+pub fn requested_delay(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(delay) = retry_after(headers) {
+        return Some(delay);
+    }
+
+    let remaining = header_u64(headers, &["x-ratelimit-remaining", "ratelimit-remaining"])?;
+    if remaining > 0 {
+        return None;
+    }
+    let delay = match header_u64(headers, &["x-ratelimit-reset", "ratelimit-reset"]) {
+        Some(reset) if reset >= MIN_TIMESTAMP => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Duration::from_secs(reset).checked_sub(now).unwrap_or_default()
+        }
+        Some(secs) => Duration::from_secs(secs),
+        None => Duration::from_secs(1),
+    };
+    Some(delay)
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    next: Instant,
+    interval: Duration,
+}
+
+/// Spaces requests evenly at `requests_per_second`. Cloning shares the limit.
+///
+/// Senders report every response through `observe`, so the limiter adapts
+/// to the provider: it pauses for as long as asked, halves the rate on a 429
+/// without a hint, and creeps back to the configured rate on success.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    base_interval: Duration,
+    state: Arc<Mutex<LimiterState>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> Self {
+        let interval = Duration::from_secs(1) / requests_per_second.max(1);
+        RateLimiter {
+            base_interval: interval,
+            state: Arc::new(Mutex::new(LimiterState {
+                next: Instant::now(),
+                interval,
+            })),
+        }
+    }
+
+    /// Blocks until the caller may send its next request.
+    pub fn wait(&self) {
+        let wait_until = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let slot = if state.next > now { state.next } else { now };
+            state.next = slot + state.interval;
+            slot
+        };
+
+        let now = Instant::now();
+        if wait_until > now {
+            thread::sleep(wait_until - now);
+        }
+    }
+
+    /// Holds every caller back until `delay` from now.
+    pub fn pause(&self, delay: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let until = Instant::now() + delay;
+        if until > state.next {
+            state.next = until;
+        }
+    }
+
+    pub fn slow_down(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.interval = (state.interval * 2).min(MAX_INTERVAL.max(self.base_interval));
+    }
+
+    pub fn recover(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.interval > self.base_interval {
+            state.interval = (state.interval - state.interval / 16).max(self.base_interval);
+        }
+    }
+
+    /// Adapts to a response from the provider.
+    pub fn observe(&self, status: StatusCode, headers: &HeaderMap) {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            self.slow_down();
+        } else if status.is_success() {
+            self.recover();
+        }
+        if let Some(delay) = requested_delay(headers) {
+            self.pause(delay);
+        }
+    }
+}
+
+/// Applies a `RateLimiter` to every call of the wrapped backend.
+pub struct RateLimitedRpc<B> {
+    pub inner: B,
+    pub limiter: RateLimiter,
+}
+
+impl<B: RpcBackend> RateLimitedRpc<B> {
+    pub fn new(inner: B, limiter: RateLimiter) -> Self {
+        RateLimitedRpc { inner, limiter }
+    }
+}
+
+impl<B: RpcBackend> RpcBackend for RateLimitedRpc<B> {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        self.limiter.wait();
+        self.inner.get_program_accounts_with_config(program_id, config)
+    }
+
+    // Providers count every call in a batch against the limit.
+    fn get_program_accounts_batch(
+        &self,
+        program_id: &Pubkey,
+        configs: Vec<RpcProgramAccountsConfig>,
+    ) -> Result<Vec<Result<Vec<(Pubkey, Account)>>>> {
+        for _ in &configs {
+            self.limiter.wait();
+        }
+        self.inner.get_program_accounts_batch(program_id, configs)
+    }
+
+    fn batch_size(&self) -> usize {
+        self.inner.batch_size()
+    }
+
+    fn metrics(&self) -> Option<&RpcMetrics> {
+        self.inner.metrics()
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        self.limiter.wait();
+        self.inner.get_multiple_accounts(pubkeys)
+    }
+
+    fn get_slot(&self) -> Result<u64> {
+        self.limiter.wait();
+        self.inner.get_slot()
+    }
+
+    fn get_genesis_hash(&self) -> Result<Hash> {
+        self.limiter.wait();
+        self.inner.get_genesis_hash()
+    }
+
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.limiter.wait();
+        self.inner.get_signatures_for_address(address, before, limit)
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction> {
+        self.limiter.wait();
+        self.inner.get_transaction(signature)
+    }
+
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof> {
+        self.limiter.wait();
+        self.inner.get_asset_proof(asset_id)
+    }
+
+    fn get_assets_by_collection(&self, collection: &Pubkey, page: usize) -> Result<AssetPage> {
+        self.limiter.wait();
+        self.inner.get_assets_by_collection(collection, page)
+    }
+
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+        self.limiter.wait();
+        self.inner.get_token_largest_accounts(mint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, reqwest::header::HeaderValue};
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_is_seconds_or_a_date() {
+        let delay = requested_delay(&headers("retry-after", "7")).unwrap();
+        assert_eq!(delay, Duration::from_secs(7));
+
+        let at = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(30));
+        let delay = requested_delay(&headers("retry-after", &at)).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+
+        let past = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(requested_delay(&headers("retry-after", past)), Some(Duration::default()));
+        assert_eq!(requested_delay(&headers("retry-after", "soon")), None);
+    }
+}
@@ -0,0 +1,201 @@
+use {
+    crate::{
+        chunked::ChunkMode,
+        config::{Config, FilterConfig},
+        enrich::EnrichConfig,
+        metrics::UsageReport,
+        rpc::Cluster,
+        snapshot::{Holder, HolderMode, ResolveMethod, SnapshotResult, Source},
+    },
+    anyhow::Result,
+    reqwest::Url,
+    serde::{Deserialize, Serialize},
+    solana_sdk::{commitment_config::CommitmentLevel, hash::hashv},
+    std::{
+        collections::{BTreeMap, HashMap},
+        fs::File,
+        path::Path,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Everything a holders snapshot was taken with, so a disputed list can be
+/// audited and taken again with `snapshot replay`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub tool_version: String,
+    /// Commit the tool was built from, "unknown" outside a git checkout.
+    pub git_hash: String,
+    /// Unix time.
+    pub captured_at: u64,
+    /// Scheme and host only, as provider URLs often carry API keys.
+    pub rpc_endpoint: String,
+    /// Unset in manifests from before clusters were recorded, or when the
+    /// endpoint did not report its genesis hash.
+    #[serde(default)]
+    pub cluster: Option<Cluster>,
+    pub commitment: CommitmentLevel,
+    pub source: Source,
+    pub mode: HolderMode,
+    pub resolve: ResolveMethod,
+    pub history_limit: usize,
+    pub quarantine_spoofs: bool,
+    /// Unset in manifests from before it was recorded, i.e. off.
+    #[serde(default)]
+    pub resized_fallback: bool,
+    /// Splitting queries changes the accounts an endpoint with response
+    /// limits returns. Unset in older manifests, i.e. the default.
+    #[serde(default)]
+    pub chunk_gpa: ChunkMode,
+    pub filters: FilterConfig,
+    pub enrich: EnrichConfig,
+    /// Holders reflect some slot in between.
+    pub slot_range: Option<(u64, u64)>,
+    /// Calls per RPC method over the run.
+    pub rpc_calls: BTreeMap<String, u64>,
+    pub holders: usize,
+    /// sha256 of the holder rows, see `holders_sha256`.
+    pub holders_sha256: String,
+}
+
+/// Scheme and host of `url`.
+pub fn rpc_endpoint(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default()),
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+/// The part of a holder a replay must reproduce.
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct CoreRow<'a> {
+    mint_account: &'a str,
+    associated_token_address: &'a str,
+    owner_wallet: &'a str,
+    metadata_account: &'a str,
+    amount: Option<&'a str>,
+}
+
+/// sha256 of the holders' mints, token accounts, owners, metadata accounts
+/// and amounts as JSON, sorted, whatever the output format. Enrichment,
+/// e.g. hold durations and listings, changes over time and from run to
+/// run, so it is left out, as is the order workers found the holders in.
+pub fn holders_sha256(holders: &[Holder]) -> Result<String> {
+    let mut rows: Vec<CoreRow> = holders
+        .iter()
+        .map(|holder| CoreRow {
+            mint_account: &holder.mint_account,
+            associated_token_address: &holder.associated_token_address,
+            owner_wallet: &holder.owner_wallet,
+            metadata_account: &holder.metadata_account,
+            amount: holder.amount.as_deref(),
+        })
+        .collect();
+    rows.sort();
+    Ok(hashv(&[&serde_json::to_vec(&rows)?]).to_string())
+}
+
+impl Manifest {
+    pub fn new(
+        config: &Config,
+        cluster: Option<Cluster>,
+        source: &Source,
+        result: &SnapshotResult,
+        usage: Option<&UsageReport>,
+        holders: &[Holder],
+    ) -> Result<Self> {
+        let pipeline = &config.snapshot.pipeline;
+        Ok(Manifest {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: env!("GIT_HASH").to_string(),
+            captured_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            rpc_endpoint: rpc_endpoint(&config.rpc_url()),
+            cluster,
+            commitment: config.rpc.commitment,
+            source: source.clone(),
+            mode: pipeline.mode,
+            resolve: pipeline.resolve,
+            history_limit: pipeline.history_limit,
+            quarantine_spoofs: pipeline.quarantine_spoofs,
+            resized_fallback: pipeline.resized_fallback,
+            chunk_gpa: config.rpc.chunk_gpa,
+            filters: config.filters.clone(),
+            enrich: config.enrich.clone(),
+            slot_range: result.slot_range,
+            rpc_calls: usage
+                .map(|usage| {
+                    let calls = usage.methods.iter();
+                    calls.map(|(method, usage)| (method.clone(), usage.calls)).collect()
+                })
+                .unwrap_or_default(),
+            holders: holders.len(),
+            holders_sha256: holders_sha256(holders)?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+
+    /// Applies the recorded settings to `config`, for a replay. The RPC
+    /// endpoint is left as configured, its URL not being recorded in full.
+    pub fn apply(&self, config: &mut Config) {
+        config.rpc.commitment = self.commitment;
+        config.rpc.chunk_gpa = self.chunk_gpa;
+        let pipeline = &mut config.snapshot.pipeline;
+        pipeline.mode = self.mode;
+        pipeline.resolve = self.resolve;
+        pipeline.history_limit = self.history_limit;
+        pipeline.quarantine_spoofs = self.quarantine_spoofs;
+        pipeline.resized_fallback = self.resized_fallback;
+        config.filters = self.filters.clone();
+        config.enrich = self.enrich.clone();
+    }
+}
+
+/// A mint whose holders differ between a snapshot and its replay.
+#[derive(Debug, Serialize)]
+pub struct ReplayDifference {
+    pub mint_account: String,
+    pub recorded: Vec<String>,
+    pub replayed: Vec<String>,
+}
+
+/// Mints whose owners differ between the `recorded` snapshot and its
+/// `replayed` one, e.g. as items changed hands in the meantime.
+pub fn replay_differences(recorded: &[Holder], replayed: &[Holder]) -> Vec<ReplayDifference> {
+    let owners = |holders: &[Holder]| {
+        let mut owners: HashMap<String, Vec<String>> = HashMap::new();
+        for holder in holders {
+            let mint_owners = owners.entry(holder.mint_account.clone()).or_default();
+            mint_owners.push(holder.owner_wallet.clone());
+            mint_owners.sort();
+        }
+        owners
+    };
+    let (recorded, mut replayed) = (owners(recorded), owners(replayed));
+
+    let mut differences: Vec<ReplayDifference> = Vec::new();
+    for (mint, recorded) in recorded {
+        let replayed = replayed.remove(&mint).unwrap_or_default();
+        if recorded != replayed {
+            differences.push(ReplayDifference {
+                mint_account: mint,
+                recorded,
+                replayed,
+            });
+        }
+    }
+    differences.extend(replayed.into_iter().map(|(mint, replayed)| ReplayDifference {
+        mint_account: mint,
+        recorded: Vec::new(),
+        replayed,
+    }));
+    differences.sort_by(|a, b| a.mint_account.cmp(&b.mint_account));
+    differences
+}
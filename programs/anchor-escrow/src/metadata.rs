@@ -0,0 +1,284 @@
+use {
+    anchor_lang::{prelude::Pubkey, AnchorDeserialize},
+    anyhow::{anyhow, Result},
+    mpl_token_metadata::state::{Collection, Data, Key, Metadata, TokenStandard, Uses},
+    serde::Serialize,
+    solana_sdk::account::Account,
+};
+
+/// Key, update authority, mint, the shortest possible `Data` (empty strings,
+/// no creators) and the two flags every version has.
+const MIN_METADATA_LEN: usize = 1 + 32 + 32 + (4 + 4 + 4 + 2 + 1) + 2;
+
+/// Token metadata layouts, named after the program version that appended
+/// fields to the account. Accounts keep the layout they were written with
+/// until the item is updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataVersion {
+    /// Ends at `is_mutable`.
+    V1_0,
+    /// Adds `edition_nonce`.
+    V1_1,
+    /// Adds `token_standard`, `collection` and `uses`.
+    V1_2,
+    /// Adds `collection_details`, for sized collections.
+    V1_3,
+    /// Adds `programmable_config`, for programmable NFTs.
+    Programmable,
+}
+
+#[derive(AnchorDeserialize)]
+enum CollectionDetails {
+    V1 { size: u64 },
+}
+
+#[derive(AnchorDeserialize)]
+enum ProgrammableConfig {
+    V1 { rule_set: Option<Pubkey> },
+}
+
+/// A metadata account with the fields newer than `Metadata` knows.
+#[derive(Debug, Clone)]
+pub struct DecodedMetadata {
+    pub metadata: Metadata,
+    /// The oldest layout holding every field that is set. Unset trailing
+    /// fields read the same in every layout, so older items updated since
+    /// can report an older version than they are stored in.
+    pub version: MetadataVersion,
+    /// Token standard as stored, including those `TokenStandard` does not
+    /// have, e.g. 4 for programmable NFTs; `metadata.token_standard` is
+    /// unset for these.
+    pub token_standard: Option<u8>,
+    /// Items in the collection, set on a sized collection's own metadata.
+    pub collection_size: Option<u64>,
+    /// Whether the item is a programmable NFT.
+    pub programmable: bool,
+    /// The programmable NFT's rule set, if it has one.
+    pub rule_set: Option<Pubkey>,
+}
+
+/// A trailing field, `None` where the account ends before it, as accounts
+/// written by older versions or trimmed by Metaplex's resize do.
+fn read_trailing<T: AnchorDeserialize>(buf: &mut &[u8]) -> Result<Option<T>> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    Ok(Option::<T>::deserialize(buf)?)
+}
+
+/// A trailing field newer than any layout `mpl_token_metadata` knows, `None`
+/// where it does not decode, e.g. a variant added since. The fields after it
+/// cannot be located then, so the rest of `buf` is dropped.
+fn read_newer<T: AnchorDeserialize>(buf: &mut &[u8]) -> Option<T> {
+    let mut rest = *buf;
+    match read_trailing::<T>(&mut rest) {
+        Ok(value) => {
+            *buf = rest;
+            value
+        }
+        Err(_) => {
+            *buf = &[];
+            None
+        }
+    }
+}
+
+fn token_standard(code: u8) -> Option<TokenStandard> {
+    match code {
+        0 => Some(TokenStandard::NonFungible),
+        1 => Some(TokenStandard::FungibleAsset),
+        2 => Some(TokenStandard::Fungible),
+        3 => Some(TokenStandard::NonFungibleEdition),
+        _ => None,
+    }
+}
+
+/// Decodes a metadata account of any layout, checking its key and that no
+/// field runs past the end of the data. Unlike `try_from_slice_unchecked`,
+/// this reads accounts that end early and programmable NFTs, whose token
+/// standard the `mpl_token_metadata` version here predates.
+pub fn decode_metadata(data: &[u8]) -> Result<DecodedMetadata> {
+    if data.len() < MIN_METADATA_LEN {
+        return Err(anyhow!("{} bytes are too short for metadata!", data.len()));
+    }
+    if data[0] != Key::MetadataV1 as u8 {
+        return Err(anyhow!("Key {} is not a metadata key!", data[0]));
+    }
+
+    let mut buf = &data[1..];
+    let update_authority = Pubkey::deserialize(&mut buf)?;
+    let mint = Pubkey::deserialize(&mut buf)?;
+    let metadata_data = Data::deserialize(&mut buf)?;
+    let primary_sale_happened = bool::deserialize(&mut buf)?;
+    let is_mutable = bool::deserialize(&mut buf)?;
+
+    let edition_nonce = read_trailing::<u8>(&mut buf)?;
+    let token_standard_code = read_trailing::<u8>(&mut buf)?;
+    let collection = read_trailing::<Collection>(&mut buf)?;
+    let uses = read_trailing::<Uses>(&mut buf)?;
+    let collection_details = read_newer::<CollectionDetails>(&mut buf);
+    let programmable_config = read_newer::<ProgrammableConfig>(&mut buf);
+
+    let version = if programmable_config.is_some() {
+        MetadataVersion::Programmable
+    } else if collection_details.is_some() {
+        MetadataVersion::V1_3
+    } else if token_standard_code.is_some() || collection.is_some() || uses.is_some() {
+        MetadataVersion::V1_2
+    } else if edition_nonce.is_some() {
+        MetadataVersion::V1_1
+    } else {
+        MetadataVersion::V1_0
+    };
+
+    Ok(DecodedMetadata {
+        metadata: Metadata {
+            key: Key::MetadataV1,
+            update_authority,
+            mint,
+            data: metadata_data,
+            primary_sale_happened,
+            is_mutable,
+            edition_nonce,
+            token_standard: token_standard_code.and_then(token_standard),
+            collection,
+            uses,
+        },
+        version,
+        token_standard: token_standard_code,
+        collection_size: collection_details.map(|CollectionDetails::V1 { size }| size),
+        programmable: programmable_config.is_some() || matches!(token_standard_code, Some(4 | 5)),
+        rule_set: programmable_config.and_then(|ProgrammableConfig::V1 { rule_set }| rule_set),
+    })
+}
+
+/// The `Metadata` of a metadata account, see `decode_metadata`.
+pub fn parse_metadata(data: &[u8]) -> Result<Metadata> {
+    Ok(decode_metadata(data)?.metadata)
+}
+
+/// The layout of a metadata account, for `snapshot metadata-versions`.
+#[derive(Debug, Serialize)]
+pub struct AccountVersion {
+    pub metadata_account: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mint_account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<MetadataVersion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub programmable: Option<bool>,
+    /// Why the account could not be decoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Decodes every account in `accounts` and reports its layout.
+pub fn metadata_versions(accounts: &[(Pubkey, Account)]) -> Vec<AccountVersion> {
+    accounts
+        .iter()
+        .map(|(pubkey, account)| match decode_metadata(&account.data) {
+            Ok(decoded) => AccountVersion {
+                metadata_account: pubkey.to_string(),
+                mint_account: Some(decoded.metadata.mint.to_string()),
+                version: Some(decoded.version),
+                programmable: Some(decoded.programmable),
+                error: None,
+            },
+            Err(err) => AccountVersion {
+                metadata_account: pubkey.to_string(),
+                mint_account: None,
+                version: None,
+                programmable: None,
+                error: Some(err.to_string()),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anchor_lang::AnchorSerialize};
+
+    /// A V1.0 metadata account for `mint`, followed by `trailing`.
+    fn account(mint: &Pubkey, trailing: &[u8]) -> Vec<u8> {
+        let mut data = vec![Key::MetadataV1 as u8];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(mint.as_ref());
+        let metadata_data = Data {
+            name: "Item #1".to_string(),
+            symbol: "ITEM".to_string(),
+            uri: "https://example.com/1.json".to_string(),
+            seller_fee_basis_points: 500,
+            creators: None,
+        };
+        data.extend(metadata_data.try_to_vec().unwrap());
+        data.extend_from_slice(&[1, 1]);
+        data.extend_from_slice(trailing);
+        data
+    }
+
+    #[test]
+    fn decodes_accounts_ending_early() {
+        let mint = Pubkey::new_unique();
+        let decoded = decode_metadata(&account(&mint, &[])).unwrap();
+        assert_eq!(decoded.metadata.mint, mint);
+        assert_eq!(decoded.metadata.data.name, "Item #1");
+        assert_eq!(decoded.version, MetadataVersion::V1_0);
+
+        let decoded = decode_metadata(&account(&mint, &[1, 255])).unwrap();
+        assert_eq!(decoded.metadata.edition_nonce, Some(255));
+        assert_eq!(decoded.version, MetadataVersion::V1_1);
+    }
+
+    #[test]
+    fn zero_padding_reads_as_unset() {
+        let decoded = decode_metadata(&account(&Pubkey::new_unique(), &[0; 64])).unwrap();
+        assert_eq!(decoded.version, MetadataVersion::V1_0);
+        assert_eq!(decoded.collection_size, None);
+    }
+
+    #[test]
+    fn decodes_programmable_token_standard() {
+        // Edition nonce, then token standard 4, which TokenStandard lacks.
+        let decoded = decode_metadata(&account(&Pubkey::new_unique(), &[0, 1, 4])).unwrap();
+        assert_eq!(decoded.token_standard, Some(4));
+        assert!(decoded.metadata.token_standard.is_none());
+        assert!(decoded.programmable);
+        assert_eq!(decoded.version, MetadataVersion::V1_2);
+    }
+
+    #[test]
+    fn decodes_collection_details_and_rule_set() {
+        let rule_set = Pubkey::new_unique();
+        // No nonce, standard, collection or uses.
+        let mut trailing = vec![0, 0, 0, 0];
+        trailing.extend_from_slice(&[1, 0]);
+        trailing.extend_from_slice(&42u64.to_le_bytes());
+        trailing.extend_from_slice(&[1, 0, 1]);
+        trailing.extend_from_slice(rule_set.as_ref());
+        let decoded = decode_metadata(&account(&Pubkey::new_unique(), &trailing)).unwrap();
+        assert_eq!(decoded.collection_size, Some(42));
+        assert_eq!(decoded.rule_set, Some(rule_set));
+        assert!(decoded.programmable);
+        assert_eq!(decoded.version, MetadataVersion::Programmable);
+    }
+
+    #[test]
+    fn unknown_newer_fields_read_as_unset() {
+        // Token standard 0, then a collection details variant added since.
+        let trailing = [0, 1, 0, 0, 0, 1, 7, 1, 2, 3];
+        let decoded = decode_metadata(&account(&Pubkey::new_unique(), &trailing)).unwrap();
+        assert_eq!(decoded.collection_size, None);
+        assert_eq!(decoded.rule_set, None);
+        assert_eq!(decoded.version, MetadataVersion::V1_2);
+    }
+
+    #[test]
+    fn rejects_short_and_foreign_accounts() {
+        assert!(decode_metadata(&[Key::MetadataV1 as u8; 10]).is_err());
+        let mut data = account(&Pubkey::new_unique(), &[]);
+        data[0] = Key::EditionV1 as u8;
+        assert!(decode_metadata(&data).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use {
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap},
+        sync::Mutex,
+    },
+};
+
+/// Provider pricing, in whatever credits or compute units the provider
+/// bills. Methods missing from `credits` cost `default_credits` per call.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CostModel {
+    pub default_credits: f64,
+    /// Per-call cost by JSON-RPC method name, e.g. `getProgramAccounts`.
+    pub credits: HashMap<String, f64>,
+    /// For providers billing bandwidth on top of calls.
+    pub credits_per_mb: f64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            default_credits: 1.0,
+            credits: HashMap::new(),
+            credits_per_mb: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MethodUsage {
+    pub calls: u64,
+    /// Response bodies as received, before decoding.
+    pub bytes: u64,
+    pub credits: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub methods: BTreeMap<String, MethodUsage>,
+    pub calls: u64,
+    pub bytes: u64,
+    pub credits: f64,
+}
+
+/// Calls and response bytes over some stretch of a run, see
+/// `RpcMetrics::totals`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RpcStats {
+    pub calls: u64,
+    pub bytes: u64,
+}
+
+impl RpcStats {
+    /// Traffic between `earlier` and these totals.
+    pub fn since(&self, earlier: &RpcStats) -> RpcStats {
+        RpcStats {
+            calls: self.calls.saturating_sub(earlier.calls),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+        }
+    }
+}
+
+/// Tallies RPC calls and response bytes per method. Shared by every sender
+/// of a run, so the totals cover all traffic, retries included.
+#[derive(Debug, Default)]
+pub struct RpcMetrics {
+    methods: Mutex<BTreeMap<String, MethodUsage>>,
+}
+
+impl RpcMetrics {
+    pub fn new() -> Self {
+        RpcMetrics::default()
+    }
+
+    /// `calls` is above 1 for batch requests, which bill per call.
+    pub fn record(&self, method: &str, calls: u64, bytes: u64) {
+        let mut methods = self.methods.lock().unwrap();
+        let usage = methods.entry(method.to_string()).or_default();
+        usage.calls += calls;
+        usage.bytes += bytes;
+    }
+
+    pub fn totals(&self) -> RpcStats {
+        let methods = self.methods.lock().unwrap();
+        RpcStats {
+            calls: methods.values().map(|usage| usage.calls).sum(),
+            bytes: methods.values().map(|usage| usage.bytes).sum(),
+        }
+    }
+
+    pub fn report(&self, costs: &CostModel) -> UsageReport {
+        let mut methods = self.methods.lock().unwrap().clone();
+        for (method, usage) in methods.iter_mut() {
+            let per_call = costs
+                .credits
+                .get(method)
+                .copied()
+                .unwrap_or(costs.default_credits);
+            let mb = usage.bytes as f64 / (1024.0 * 1024.0);
+            usage.credits = usage.calls as f64 * per_call + mb * costs.credits_per_mb;
+        }
+
+        UsageReport {
+            calls: methods.values().map(|usage| usage.calls).sum(),
+            bytes: methods.values().map(|usage| usage.bytes).sum(),
+            credits: methods.values().map(|usage| usage.credits).sum(),
+            methods,
+        }
+    }
+}
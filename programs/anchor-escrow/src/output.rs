@@ -0,0 +1,1042 @@
+use {
+    crate::{
+        anonymize::{pseudonymize, random_salt, write_mapping},
+        binary::{read_binary, write_binary},
+        config::OutputConfig,
+        encryption::{parse_recipients, seal},
+        enrich::OwnerKind,
+        rpc::Cluster,
+        snapshot::Holder,
+        wallet::metadata_address,
+    },
+    anchor_lang::prelude::Pubkey,
+    age::x25519::Recipient,
+    anyhow::{anyhow, Result},
+    flate2::{read::GzDecoder, write::GzEncoder, Compression as GzLevel},
+    serde::{Deserialize, Serialize, Serializer as _},
+    std::{
+        borrow::Borrow,
+        collections::{BTreeMap, HashMap, HashSet},
+        fs::File,
+        io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    /// One JSON object per line, for appending and streaming consumers.
+    Jsonl,
+    /// Holders with a string table, see `binary::write_binary`; snapshots
+    /// only.
+    Bin,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Json
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "bin" => Ok(OutputFormat::Bin),
+            _ => Err(format!("Unknown output format {}, expected json, csv, jsonl or bin", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Bin => "bin",
+        }
+    }
+}
+
+/// Block explorer linked to from CSV exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Explorer {
+    Solscan,
+    SolanaExplorer,
+    SolanaFm,
+}
+
+impl std::str::FromStr for Explorer {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "solscan" => Ok(Explorer::Solscan),
+            "solana_explorer" => Ok(Explorer::SolanaExplorer),
+            "solana_fm" => Ok(Explorer::SolanaFm),
+            _ => Err(format!(
+                "Unknown explorer {}, expected solscan, solana_explorer or solana_fm",
+                s
+            )),
+        }
+    }
+}
+
+impl Explorer {
+    pub fn account_url(&self, address: &str) -> String {
+        match self {
+            Explorer::Solscan => format!("https://solscan.io/account/{}", address),
+            Explorer::SolanaExplorer => format!("https://explorer.solana.com/address/{}", address),
+            Explorer::SolanaFm => format!("https://solana.fm/address/{}", address),
+        }
+    }
+
+    pub fn token_url(&self, mint: &str) -> String {
+        match self {
+            Explorer::Solscan => format!("https://solscan.io/token/{}", mint),
+            _ => self.account_url(mint),
+        }
+    }
+
+    fn link(&self, holder: &Holder) -> Holder {
+        let mut holder = holder.clone();
+        holder.mint_url = Some(self.token_url(&holder.mint_account));
+        holder.owner_url = Some(self.account_url(&holder.owner_wallet));
+        holder.token_account_url = Some(self.account_url(&holder.associated_token_address));
+        holder
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("Unknown compression {}, expected gzip or zstd", s)),
+        }
+    }
+}
+
+impl Compression {
+    /// `path` with the suffix for this compression appended, if any.
+    pub fn apply_extension(&self, path: &Path) -> PathBuf {
+        let suffix = match self {
+            Compression::None => return path.to_path_buf(),
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        };
+        let mut path = path.as_os_str().to_owned();
+        path.push(".");
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+}
+
+/// Runs `write` against the file at `path`, compressing on the way out.
+pub fn with_writer<F>(path: &Path, compression: Compression, write: F) -> Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    with_sealed_writer(path, compression, &[], write)
+}
+
+/// `with_writer` encrypting the compressed output to `recipients`, unless
+/// there are none.
+pub fn with_sealed_writer<F>(
+    path: &Path,
+    compression: Compression,
+    recipients: &[Recipient],
+    write: F,
+) -> Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    let f = BufWriter::new(File::create(path)?);
+    if recipients.is_empty() {
+        return Ok(compress_into(f, compression, write)?.flush()?);
+    }
+    let sealed = compress_into(seal(f, recipients)?, compression, write)?;
+    sealed.finish()?.flush()?;
+    Ok(())
+}
+
+/// Writes `value` as pretty JSON to `path`, encrypted to the recipients of
+/// `output` if it has any, for the files listing wallets beside a snapshot.
+/// Name `path` with `sealed_path`, so encrypted files end in `.age`.
+pub fn write_sealed_json<T>(path: &Path, output: &OutputConfig, value: &T) -> Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    let recipients = parse_recipients(&output.encrypt_to)?;
+    with_sealed_writer(path, Compression::None, &recipients, |w| {
+        Ok(serde_json::to_writer_pretty(w, value)?)
+    })
+}
+
+/// Runs `write` against `w` through `compression` and hands `w` back once
+/// the compressed stream is complete.
+fn compress_into<W, F>(w: W, compression: Compression, write: F) -> Result<W>
+where
+    W: Write,
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    match compression {
+        Compression::None => {
+            let mut w = w;
+            write(&mut w)?;
+            Ok(w)
+        }
+        Compression::Gzip => {
+            let mut w = GzEncoder::new(w, GzLevel::default());
+            write(&mut w)?;
+            Ok(w.finish()?)
+        }
+        Compression::Zstd => {
+            let mut w = zstd::Encoder::new(w, 0)?;
+            write(&mut w)?;
+            Ok(w.finish()?)
+        }
+    }
+}
+
+/// Opens `path` for reading, decompressing gzip and zstd files based on
+/// their magic bytes so callers never need to know how a file was written.
+/// Encrypted files have to be decrypted first, see `snapshot decrypt`.
+pub fn open_reader(path: &Path) -> Result<Box<dyn Read>> {
+    let mut f = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let read = f.read(&mut magic)?;
+    let f = BufReader::new(File::open(path)?);
+
+    match &magic[..read] {
+        [0x1f, 0x8b, ..] => Ok(Box::new(GzDecoder::new(f))),
+        [0x28, 0xb5, 0x2f, 0xfd] => Ok(Box::new(zstd::Decoder::with_buffer(f)?)),
+        b"age-" => Err(anyhow!(
+            "{} is encrypted, decrypt it with `snapshot decrypt` first!",
+            path.display()
+        )),
+        _ => Ok(Box::new(f)),
+    }
+}
+
+/// Every column of a holder in CSV output, in `Holder` field order. Rows
+/// all have every column, empty where a field is unset, as enrichment sets
+/// some fields on only some rows and CSV needs the same fields throughout.
+const CSV_COLUMNS: &[&str] = &[
+    "owner_wallet",
+    "associated_token_address",
+    "mint_account",
+    "metadata_account",
+    "frozen",
+    "owner_kind",
+    "owner_program",
+    "sol_domain",
+    "holding_since",
+    "holding_days",
+    "multisig_signers",
+    "multisig",
+    "name",
+    "master_edition",
+    "edition_number",
+    "amount",
+    "resolved_by",
+    "listed",
+    "listed_with",
+    "image",
+    "metadata_sha256",
+    "metadata_changed",
+    "custodian",
+    "custodian_country",
+    "cluster",
+];
+
+/// Follow `CSV_COLUMNS` when explorer links are asked for.
+const LINK_COLUMNS: &[&str] = &["mint_url", "owner_url", "token_account_url"];
+
+/// `holder`'s value for each of `columns`, empty where unset.
+fn csv_record(holder: &Holder, columns: &[&str]) -> Result<Vec<String>> {
+    let value = serde_json::to_value(holder)?;
+    let record = columns
+        .iter()
+        .map(|column| match value.get(*column) {
+            Some(serde_json::Value::String(value)) => value.clone(),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        })
+        .collect();
+    Ok(record)
+}
+
+/// `explorer` adds mint, owner and token account link columns to CSV
+/// output; JSON output is left as is.
+pub fn write_holders(
+    path: &Path,
+    format: OutputFormat,
+    compression: Compression,
+    explorer: Option<Explorer>,
+    holders: &[Holder],
+) -> Result<()> {
+    write_holders_from(path, format, compression, &[], explorer, holders)
+}
+
+/// `write_holders` for holders made one at a time, e.g. by a `HolderTable`,
+/// so they never need to be in memory all at once, encrypted to
+/// `recipients` if any. JSON arrays are streamed too and come out the same
+/// as from `write_holders`.
+pub fn write_holders_from<I, H>(
+    path: &Path,
+    format: OutputFormat,
+    compression: Compression,
+    recipients: &[Recipient],
+    explorer: Option<Explorer>,
+    holders: I,
+) -> Result<()>
+where
+    I: IntoIterator<Item = H>,
+    H: Borrow<Holder> + Serialize,
+{
+    with_sealed_writer(path, compression, recipients, |w| {
+        match format {
+            OutputFormat::Json => {
+                let mut serializer = serde_json::Serializer::pretty(w);
+                serializer.collect_seq(holders)?;
+            }
+            OutputFormat::Csv => {
+                let mut columns = CSV_COLUMNS.to_vec();
+                if explorer.is_some() {
+                    columns.extend_from_slice(LINK_COLUMNS);
+                }
+                let mut writer = csv::Writer::from_writer(w);
+                writer.write_record(&columns)?;
+                for holder in holders {
+                    let record = match explorer {
+                        Some(explorer) => csv_record(&explorer.link(holder.borrow()), &columns)?,
+                        None => csv_record(holder.borrow(), &columns)?,
+                    };
+                    writer.write_record(&record)?;
+                }
+                writer.flush()?;
+            }
+            OutputFormat::Jsonl => {
+                for holder in holders {
+                    serde_json::to_writer(&mut *w, holder.borrow())?;
+                    writeln!(w)?;
+                }
+            }
+            OutputFormat::Bin => write_binary(w, holders)?,
+        }
+        Ok(())
+    })
+}
+
+/// One item of an owner in grouped output.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeldItem {
+    pub mint_account: String,
+    pub associated_token_address: String,
+    pub metadata_account: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frozen: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub holding_since: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub holding_days: Option<u64>,
+}
+
+/// An owner with every item it holds, for `OutputConfig::group_by_owner`.
+/// Fields about the owner are taken from its first holder.
+#[derive(Debug, Clone, Serialize)]
+pub struct OwnerHoldings {
+    pub owner_wallet: String,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_kind: Option<OwnerKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_program: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sol_domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multisig_signers: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<String>,
+    pub items: Vec<HeldItem>,
+}
+
+/// CSV cannot nest, so grouped CSV output has the mints of each owner
+/// space separated in one column.
+#[derive(Serialize)]
+struct OwnerRow<'a> {
+    owner_wallet: &'a str,
+    count: usize,
+    mint_accounts: String,
+}
+
+/// `holders` grouped by owner, largest holdings first, items in their
+/// original order.
+pub fn group_by_owner(holders: &[Holder]) -> Vec<OwnerHoldings> {
+    let mut owners: Vec<OwnerHoldings> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for holder in holders {
+        let i = *index.entry(holder.owner_wallet.as_str()).or_insert_with(|| {
+            owners.push(OwnerHoldings {
+                owner_wallet: holder.owner_wallet.clone(),
+                count: 0,
+                owner_kind: holder.owner_kind,
+                owner_program: holder.owner_program.clone(),
+                sol_domain: holder.sol_domain.clone(),
+                multisig_signers: holder.multisig_signers.clone(),
+                multisig: holder.multisig.clone(),
+                items: Vec::new(),
+            });
+            owners.len() - 1
+        });
+        owners[i].count += 1;
+        owners[i].items.push(HeldItem {
+            mint_account: holder.mint_account.clone(),
+            associated_token_address: holder.associated_token_address.clone(),
+            metadata_account: holder.metadata_account.clone(),
+            name: holder.name.clone(),
+            frozen: holder.frozen,
+            holding_since: holder.holding_since,
+            holding_days: holder.holding_days,
+        });
+    }
+    // Stable, so ties keep the order owners were first seen in.
+    owners.sort_by(|a, b| b.count.cmp(&a.count));
+    owners
+}
+
+/// A record of report output, see `write_records`. CSV cannot nest, so
+/// records that do write rows of their own.
+pub trait Record: Serialize {
+    /// Writes this record as CSV, a row of its own by default.
+    fn write_csv(&self, writer: &mut csv::Writer<&mut dyn Write>) -> Result<()> {
+        writer.serialize(self)?;
+        Ok(())
+    }
+}
+
+/// Writes `records` as a JSON array, one JSON object per line or CSV rows,
+/// encrypted to `recipients` if any. Binary output is for snapshots only.
+pub fn write_records<R: Record>(
+    path: &Path,
+    format: OutputFormat,
+    compression: Compression,
+    recipients: &[Recipient],
+    records: &[R],
+) -> Result<()> {
+    with_sealed_writer(path, compression, recipients, |w| {
+        match format {
+            OutputFormat::Json => serde_json::to_writer_pretty(w, records)?,
+            OutputFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(w);
+                for record in records {
+                    record.write_csv(&mut writer)?;
+                }
+                writer.flush()?;
+            }
+            OutputFormat::Jsonl => {
+                for record in records {
+                    serde_json::to_writer(&mut *w, record)?;
+                    writeln!(w)?;
+                }
+            }
+            OutputFormat::Bin => return Err(anyhow!("Binary output is for snapshots only!")),
+        }
+        Ok(())
+    })
+}
+
+impl Record for OwnerHoldings {
+    /// One row per owner.
+    fn write_csv(&self, writer: &mut csv::Writer<&mut dyn Write>) -> Result<()> {
+        let mints: Vec<&str> = self.items.iter().map(|item| item.mint_account.as_str()).collect();
+        writer.serialize(OwnerRow {
+            owner_wallet: &self.owner_wallet,
+            count: self.count,
+            mint_accounts: mints.join(" "),
+        })?;
+        Ok(())
+    }
+}
+
+/// Writes `owners` as a JSON array, one JSON object per line, or one CSV
+/// row per owner.
+pub fn write_grouped(
+    path: &Path,
+    format: OutputFormat,
+    compression: Compression,
+    recipients: &[Recipient],
+    owners: &[OwnerHoldings],
+) -> Result<()> {
+    write_records(path, format, compression, recipients, owners)
+}
+
+/// An owner of a print in `OutputConfig::group_by_edition` output.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrintHolder {
+    pub owner_wallet: String,
+    pub associated_token_address: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Print {
+    /// Unset for the master edition itself.
+    pub edition_number: Option<u64>,
+    pub mint_account: String,
+    pub holders: Vec<PrintHolder>,
+}
+
+/// A master edition with its prints, for `OutputConfig::group_by_edition`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EditionGroup {
+    /// `None` groups the items that are not editioned.
+    pub master_edition: Option<String>,
+    pub unique_owners: usize,
+    pub prints: Vec<Print>,
+}
+
+/// One CSV row per holder of a print in grouped output.
+#[derive(Serialize)]
+struct PrintRow<'a> {
+    master_edition: Option<&'a str>,
+    edition_number: Option<u64>,
+    mint_account: &'a str,
+    owner_wallet: &'a str,
+}
+
+/// `holders` grouped by `master_edition`, then by print, the master first
+/// and prints in edition order. Needs `enrich::enrich_editions`.
+pub fn group_by_edition(holders: &[Holder]) -> Vec<EditionGroup> {
+    let mut masters: BTreeMap<Option<&str>, BTreeMap<(Option<u64>, &str), Vec<PrintHolder>>> =
+        BTreeMap::new();
+    for holder in holders {
+        masters
+            .entry(holder.master_edition.as_deref())
+            .or_default()
+            .entry((holder.edition_number, holder.mint_account.as_str()))
+            .or_default()
+            .push(PrintHolder {
+                owner_wallet: holder.owner_wallet.clone(),
+                associated_token_address: holder.associated_token_address.clone(),
+            });
+    }
+
+    masters
+        .into_iter()
+        .map(|(master_edition, prints)| {
+            let prints: Vec<Print> = prints
+                .into_iter()
+                .map(|((edition_number, mint_account), holders)| Print {
+                    edition_number,
+                    mint_account: mint_account.to_string(),
+                    holders,
+                })
+                .collect();
+            let owners: HashSet<&str> = prints
+                .iter()
+                .flat_map(|print| print.holders.iter().map(|h| h.owner_wallet.as_str()))
+                .collect();
+            EditionGroup {
+                master_edition: master_edition.map(|m| m.to_string()),
+                unique_owners: owners.len(),
+                prints,
+            }
+        })
+        .collect()
+}
+
+/// Writes `groups` as a JSON array, one JSON object per line, or one CSV
+/// row per holder of each print.
+pub fn write_edition_groups(
+    path: &Path,
+    format: OutputFormat,
+    compression: Compression,
+    recipients: &[Recipient],
+    groups: &[EditionGroup],
+) -> Result<()> {
+    write_records(path, format, compression, recipients, groups)
+}
+
+impl Record for EditionGroup {
+    /// One row per holder of each print.
+    fn write_csv(&self, writer: &mut csv::Writer<&mut dyn Write>) -> Result<()> {
+        for print in &self.prints {
+            for holder in &print.holders {
+                writer.serialize(PrintRow {
+                    master_edition: self.master_edition.as_deref(),
+                    edition_number: print.edition_number,
+                    mint_account: &print.mint_account,
+                    owner_wallet: &holder.owner_wallet,
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_output(
+    path: &Path,
+    output: &OutputConfig,
+    explorer: Option<Explorer>,
+    holders: &[Holder],
+) -> Result<()> {
+    let (format, compress) = (output.format, output.compress);
+    let to = parse_recipients(&output.encrypt_to)?;
+    match (output.group_by_owner, output.group_by_edition) {
+        (true, true) => Err(anyhow!("Output can be grouped by owner or by edition, not both!")),
+        (true, false) => write_grouped(path, format, compress, &to, &group_by_owner(holders)),
+        (false, true) => {
+            // Without edition enrichment every holder would land in one group.
+            if !holders.is_empty() && holders.iter().all(|h| h.master_edition.is_none()) {
+                return Err(anyhow!(
+                    "No holder has a master edition to group by, set enrich.editions!"
+                ));
+            }
+            write_edition_groups(path, format, compress, &to, &group_by_edition(holders))
+        }
+        (false, false) => write_holders_from(path, format, compress, &to, explorer, holders),
+    }
+}
+
+/// Writes holders with the format, compression, links and recipients of
+/// `output`, pseudonymized first and grouped by owner if configured. Explorer links
+/// are left out of pseudonymized and grouped output, as they would point at
+/// the pseudonyms or have no row of their own.
+pub fn write_snapshot(path: &Path, output: &OutputConfig, holders: &[Holder]) -> Result<()> {
+    if !output.pseudonymize {
+        return write_output(path, output, output.explorer_links, holders);
+    }
+
+    let salt = output.salt.clone().unwrap_or_else(random_salt);
+    let (holders, mapping) = pseudonymize(&salt, holders);
+    write_output(path, output, None, &holders)?;
+    // The mapping is named after the plain output.
+    let plain = match output.encrypt_to.is_empty() {
+        true => path.to_path_buf(),
+        false => path.with_extension(""),
+    };
+    write_mapping(&plain, output, &mapping)?;
+    Ok(())
+}
+
+/// `path` with `cluster` before its extensions, e.g. `holders.devnet.json`
+/// for `holders.json`.
+pub fn cluster_path(path: &Path, cluster: &Cluster) -> PathBuf {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("holders");
+    let name = match name.split_once('.') {
+        Some((stem, extensions)) => format!("{}.{}.{}", stem, cluster, extensions),
+        None => format!("{}.{}", name, cluster),
+    };
+    path.with_file_name(name)
+}
+
+/// Fails unless the holders of all `snapshots` were taken on the same
+/// cluster. Holders without one, from before clusters were recorded, go
+/// with any.
+pub fn check_same_cluster<'a, I>(snapshots: I) -> Result<()>
+where
+    I: IntoIterator<Item = &'a [Holder]>,
+{
+    let mut seen: Option<&str> = None;
+    for holder in snapshots.into_iter().flatten() {
+        match (seen, holder.cluster.as_deref()) {
+            (Some(seen), Some(cluster)) if seen != cluster => {
+                return Err(anyhow!(
+                    "Snapshots of clusters {} and {} cannot be compared!",
+                    seen,
+                    cluster
+                ))
+            }
+            (None, cluster) => seen = cluster,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `check_same_cluster` for a snapshot and the cluster it is checked
+/// against, e.g. the one the RPC serves.
+pub fn check_cluster(holders: &[Holder], cluster: &Cluster) -> Result<()> {
+    let id = cluster.id();
+    match holders.iter().find_map(|holder| holder.cluster.as_deref()) {
+        Some(recorded) if recorded != id => Err(anyhow!(
+            "Snapshot was taken on cluster {}, not {}!",
+            recorded,
+            id
+        )),
+        _ => check_same_cluster(Some(holders)),
+    }
+}
+
+/// Reads a snapshot written by `write_holders`; grouped output cannot be
+/// read back. The format is taken from the
+/// extension once any `.gz`/`.zst` suffix is stripped.
+pub fn read_holders(path: &Path) -> Result<Vec<Holder>> {
+    iter_holders(path)?.collect()
+}
+
+pub type HolderIter = Box<dyn Iterator<Item = Result<Holder>>>;
+
+/// Like `read_holders`, but yields the holders as they are decoded, so CSV
+/// and JSON lines snapshots are never held in memory whole. JSON arrays and
+/// binary snapshots are still read at once.
+pub fn iter_holders(path: &Path) -> Result<HolderIter> {
+    let format = format_of(path)?;
+    let reader = open_reader(path)?;
+    match format {
+        OutputFormat::Csv => Ok(Box::new(
+            csv::Reader::from_reader(reader)
+                .into_deserialize()
+                .map(|holder| Ok(holder?)),
+        )),
+        OutputFormat::Json => {
+            let holders: Vec<Holder> = serde_json::from_reader(reader)?;
+            Ok(Box::new(holders.into_iter().map(Ok)))
+        }
+        OutputFormat::Jsonl => Ok(Box::new(
+            BufReader::new(reader)
+                .lines()
+                .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+                .map(|line| Ok(serde_json::from_str(&line?)?)),
+        )),
+        OutputFormat::Bin => Ok(Box::new(read_binary(reader)?.into_iter().map(Ok))),
+    }
+}
+
+/// How the snapshot at `path` is compressed, by its last extension.
+pub fn compression_of(path: &Path) -> Compression {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Compression::Gzip,
+        Some("zst") => Compression::Zstd,
+        _ => Compression::None,
+    }
+}
+
+/// The format of the snapshot at `path`, by its extension before any
+/// compression and encryption extensions.
+pub fn format_of(path: &Path) -> Result<OutputFormat> {
+    let mut inner = path.to_path_buf();
+    if inner.extension().map_or(false, |ext| ext == "age") {
+        inner.set_extension("");
+    }
+    if compression_of(&inner) != Compression::None {
+        inner.set_extension("");
+    }
+    let format = inner.extension().and_then(|ext| ext.to_str()?.parse().ok());
+    format.ok_or_else(|| {
+        anyhow!(
+            "Cannot tell the format of {}, expected .json, .csv, .jsonl or .bin",
+            path.display()
+        )
+    })
+}
+
+/// A page of a snapshot's holders, see `holders_page`.
+#[derive(Debug, Serialize)]
+pub struct HolderPage {
+    pub holders: Vec<Holder>,
+    /// Pass to `holders_page` for the next page; `None` after the last one.
+    pub next_cursor: Option<String>,
+}
+
+impl HolderPage {
+    /// The page of `holders` read from a store ordered by mint, one more
+    /// than `limit` to tell whether there is a next page. Its cursor is the
+    /// last mint on this page.
+    pub(crate) fn by_mint(mut holders: Vec<Holder>, limit: usize) -> HolderPage {
+        let next_cursor = match holders.len() > limit {
+            true => {
+                holders.truncate(limit);
+                holders.last().map(|holder| holder.mint_account.clone())
+            }
+            false => None,
+        };
+        HolderPage {
+            holders,
+            next_cursor,
+        }
+    }
+}
+
+/// A holder as the history stores keep it: who holds which mint through
+/// which token account, on which cluster.
+pub(crate) fn stored_holder(
+    owner_wallet: String,
+    token_account: String,
+    mint: String,
+    cluster: Option<String>,
+) -> Result<Holder> {
+    let metadata_account = metadata_address(&Pubkey::from_str(&mint)?).to_string();
+    Ok(Holder {
+        owner_wallet,
+        associated_token_address: token_account,
+        mint_account: mint,
+        metadata_account,
+        cluster,
+        ..Default::default()
+    })
+}
+
+/// Pages of no holders would never get to the next one.
+pub(crate) fn check_page_limit(limit: usize) -> Result<()> {
+    match limit {
+        0 => Err(anyhow!("Pages must hold at least one holder!")),
+        _ => Ok(()),
+    }
+}
+
+/// Up to `limit` holders of a snapshot, starting at `cursor`, the
+/// `next_cursor` of the previous page, or at the first holder when `None`.
+/// Cursors are byte offsets into the file, so each page is read from where
+/// the previous one ended, and only good for as long as the file is not
+/// rewritten. Only uncompressed CSV and JSON lines snapshots can be read
+/// from the middle.
+pub fn holders_page(path: &Path, cursor: Option<&str>, limit: usize) -> Result<HolderPage> {
+    check_page_limit(limit)?;
+    let offset: u64 = match cursor {
+        Some(cursor) => cursor
+            .parse()
+            .map_err(|_| anyhow!("Invalid cursor {}!", cursor))?,
+        None => 0,
+    };
+
+    // The offset of the holder after the page, if any, is the next cursor.
+    let mut holders = Vec::with_capacity(limit);
+    let mut next_offset = None;
+    match path.extension().and_then(|ext| ext.to_str()?.parse().ok()) {
+        Some(OutputFormat::Csv) => {
+            let mut reader = csv::Reader::from_path(path)?;
+            let headers = reader.headers()?.clone();
+            if offset > 0 {
+                let mut position = csv::Position::new();
+                position.set_byte(offset);
+                reader.seek(position)?;
+            }
+            let mut record = csv::StringRecord::new();
+            while reader.read_record(&mut record)? {
+                if holders.len() == limit {
+                    next_offset = record.position().map(csv::Position::byte);
+                    break;
+                }
+                holders.push(record.deserialize(Some(&headers))?);
+            }
+        }
+        Some(OutputFormat::Jsonl) => {
+            let mut reader = BufReader::new(File::open(path)?);
+            reader.seek(SeekFrom::Start(offset))?;
+            let (mut position, mut line) = (offset, String::new());
+            loop {
+                line.clear();
+                let read = reader.read_line(&mut line)?;
+                if read == 0 {
+                    break;
+                }
+                if !line.trim().is_empty() {
+                    if holders.len() == limit {
+                        next_offset = Some(position);
+                        break;
+                    }
+                    holders.push(serde_json::from_str(&line)?);
+                }
+                position += read as u64;
+            }
+        }
+        _ => {
+            return Err(anyhow!(
+                "Cannot page through {}, expected an uncompressed .csv or .jsonl snapshot!",
+                path.display()
+            ))
+        }
+    }
+    Ok(HolderPage {
+        holders,
+        next_cursor: next_offset.map(|offset| offset.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::snapshot::test_holder as holder, serde_json::json};
+
+    /// CSV of `holders` written and read back.
+    fn csv_roundtrip(name: &str, holders: &[Holder]) -> Vec<Holder> {
+        let path = std::env::temp_dir().join(format!("{}-{}.csv", name, std::process::id()));
+        write_holders(&path, OutputFormat::Csv, Compression::None, None, holders).unwrap();
+        let read = read_holders(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        read
+    }
+
+    #[test]
+    fn csv_rows_have_every_column_whichever_fields_are_set() {
+        // Rows without an optional field once lost its column, shifting the
+        // rest, when other rows of the same snapshot had it.
+        let cases = [
+            (
+                "hold-duration",
+                vec![json!({"holding_since": 1_650_000_000, "holding_days": 3}), json!({})],
+            ),
+            (
+                "multisig",
+                vec![
+                    json!({"multisig_signers": "signer1 signer2"}),
+                    json!({"owner_wallet": "signer1", "multisig": "owner"}),
+                    json!({}),
+                ],
+            ),
+            ("names", vec![json!({}), json!({"name": "Item #1"})]),
+            (
+                "editions",
+                vec![
+                    json!({"master_edition": "master", "edition_number": 7}),
+                    json!({"master_edition": "master"}),
+                    json!({}),
+                ],
+            ),
+            (
+                "listings",
+                vec![
+                    json!({"listed": true, "listed_with": "marketplace"}),
+                    json!({"listed": false}),
+                ],
+            ),
+            ("images", vec![json!({"image": "https://example.com/1.png"}), json!({})]),
+            (
+                "metadata-hashes",
+                vec![
+                    json!({"metadata_sha256": "hash", "metadata_changed": true}),
+                    json!({"metadata_sha256": "hash"}),
+                    json!({}),
+                ],
+            ),
+            (
+                "custodial",
+                vec![
+                    json!({}),
+                    json!({"custodian": "Exchange", "custodian_country": "US"}),
+                    json!({"custodian": "Other exchange"}),
+                ],
+            ),
+        ];
+        for (name, fields) in &cases {
+            let holders: Vec<Holder> = fields.iter().cloned().map(holder).collect();
+            let read = csv_roundtrip(name, &holders);
+            let as_json = |holders: &[Holder]| serde_json::to_value(holders).unwrap();
+            assert_eq!(as_json(&read), as_json(&holders), "{}", name);
+        }
+    }
+
+    #[test]
+    fn csv_records_leave_unset_columns_empty() {
+        let record = csv_record(&holder(json!({"frozen": true})), CSV_COLUMNS).unwrap();
+        assert_eq!(record.len(), CSV_COLUMNS.len());
+        assert_eq!(record[4], "true");
+        assert_eq!(record[5], "");
+    }
+
+    #[test]
+    fn snapshots_of_other_clusters_are_not_compared() {
+        let mainnet = [holder(json!({"cluster": "mainnet"}))];
+        let devnet = [holder(json!({"cluster": "devnet"}))];
+        let unrecorded = [holder(json!({}))];
+
+        check_same_cluster(vec![&mainnet[..], &unrecorded[..], &mainnet[..]]).unwrap();
+        assert!(check_same_cluster(vec![&mainnet[..], &devnet[..]]).is_err());
+        assert!(check_same_cluster(vec![&unrecorded[..], &mainnet[..], &devnet[..]]).is_err());
+
+        check_cluster(&mainnet, &Cluster::Mainnet).unwrap();
+        check_cluster(&unrecorded, &Cluster::Devnet).unwrap();
+        assert!(check_cluster(&mainnet, &Cluster::Devnet).is_err());
+    }
+
+    #[test]
+    fn csv_and_binary_snapshots_keep_the_cluster() {
+        let holders = [holder(json!({"cluster": "devnet"}))];
+        assert_eq!(csv_roundtrip("cluster", &holders)[0].cluster.as_deref(), Some("devnet"));
+
+        let mut bin = Vec::new();
+        write_binary(&mut bin, &holders).unwrap();
+        let read = read_binary(bin.as_slice()).unwrap();
+        assert_eq!(read[0].cluster.as_deref(), Some("devnet"));
+
+        let mixed = [holders[0].clone(), holder(json!({"cluster": "mainnet"}))];
+        assert!(write_binary(&mut Vec::<u8>::new(), &mixed).is_err());
+    }
+
+    #[test]
+    fn binary_snapshots_are_read_back_whole_or_not_at_all() {
+        let holders = [
+            holder(json!({"name": "One"})),
+            holder(json!({"owner_wallet": "other", "name": "One"})),
+            holder(json!({})),
+        ];
+        let mut bin = Vec::new();
+        write_binary(&mut bin, &holders).unwrap();
+        let read = read_binary(bin.as_slice()).unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[1].owner_wallet, "other");
+        assert_eq!(read[1].name.as_deref(), Some("One"));
+        assert_eq!(read[2].name, None);
+
+        bin.truncate(bin.len() - 1);
+        assert!(read_binary(bin.as_slice()).is_err());
+    }
+
+    #[test]
+    fn pages_continue_where_the_previous_one_ended() {
+        let holders: Vec<Holder> = (0..5)
+            .map(|index| holder(json!({"mint_account": format!("mint-{}", index)})))
+            .collect();
+        for (format, ext) in [(OutputFormat::Csv, "csv"), (OutputFormat::Jsonl, "jsonl")] {
+            let name = format!("pages-{}.{}", std::process::id(), ext);
+            let path = std::env::temp_dir().join(name);
+            write_holders(&path, format, Compression::None, None, &holders).unwrap();
+
+            let (mut paged, mut cursor) = (Vec::new(), None);
+            loop {
+                let page = holders_page(&path, cursor.as_deref(), 2).unwrap();
+                assert!(page.holders.len() <= 2);
+                paged.extend(page.holders.into_iter().map(|holder| holder.mint_account));
+                cursor = page.next_cursor;
+                if cursor.is_none() {
+                    break;
+                }
+            }
+            assert!(holders_page(&path, None, 0).is_err());
+            std::fs::remove_file(&path).unwrap();
+
+            let mints: Vec<String> = holders.iter().map(|h| h.mint_account.clone()).collect();
+            assert_eq!(paged, mints);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use {
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    mpl_token_metadata::state::Creator,
+    solana_account_decoder::parse_account_data::{
+        parse_account_data, AccountAdditionalData, ParsedAccount,
+    },
+    solana_sdk::program_pack::Pack,
+    spl_token::{
+        state::{Account as TokenAccount, Mint},
+        ID as TOKEN_PROGRAM_ID,
+    },
+    std::{convert::TryInto, str::FromStr},
+};
+
+pub fn first_creator_is_verified(creators_opt: &Option<Vec<Creator>>) -> bool {
+    // Only add mints with a verified creator.
+    if let Some(creators) = creators_opt {
+        if creators[0].verified {
+            return true;
+        }
+    }
+    false
+}
+
+pub fn parse_token_amount(data: &ParsedAccount) -> Result<u64> {
+    let amount = data
+        .parsed
+        .get("info")
+        .ok_or(anyhow!("Invalid data account!"))?
+        .get("tokenAmount")
+        .ok_or(anyhow!("Invalid token amount!"))?
+        .get("amount")
+        .ok_or(anyhow!("Invalid token amount!"))?
+        .as_str()
+        .ok_or(anyhow!("Invalid token amount!"))?
+        .parse()?;
+    Ok(amount)
+}
+
+pub fn parse_state(data: &ParsedAccount) -> Result<String> {
+    let state = data
+        .parsed
+        .get("info")
+        .ok_or(anyhow!("Invalid data account!"))?
+        .get("state")
+        .ok_or(anyhow!("Invalid account state!"))?
+        .as_str()
+        .ok_or(anyhow!("Invalid account state!"))?
+        .to_string();
+    Ok(state)
+}
+
+pub fn parse_owner(data: &ParsedAccount) -> Result<String> {
+    let owner = data
+        .parsed
+        .get("info")
+        .ok_or(anyhow!("Invalid owner account!"))?
+        .get("owner")
+        .ok_or(anyhow!("Invalid owner account!"))?
+        .as_str()
+        .ok_or(anyhow!("Invalid owner amount!"))?
+        .to_string();
+    Ok(owner)
+}
+
+/// The fields of a token account a snapshot reads.
+pub struct TokenAccountFields {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub frozen: bool,
+}
+
+/// Token-2022 marks accounts with extensions by this account type, stored
+/// right after the SPL token layout.
+const TOKEN_2022_ACCOUNT_TYPE: u8 = 2;
+
+/// Reads a token account straight from its bytes: owner at 32..64, amount
+/// at 64..72 and state at 108. `None` unless `data` is an initialized
+/// account in the SPL token layout, or a Token-2022 one extending it.
+pub fn read_token_account(data: &[u8]) -> Option<TokenAccountFields> {
+    match data.len() {
+        TokenAccount::LEN => {}
+        len if len > TokenAccount::LEN && data[TokenAccount::LEN] == TOKEN_2022_ACCOUNT_TYPE => {}
+        _ => return None,
+    }
+    let frozen = match data[108] {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    Some(TokenAccountFields {
+        owner: Pubkey::new(&data[32..64]),
+        amount: u64::from_le_bytes(data[64..72].try_into().ok()?),
+        frozen,
+    })
+}
+
+/// Reads the supply, at 36..44, of an initialized mint in the SPL token
+/// layout, which Token-2022 mints extend.
+pub fn read_mint_supply(data: &[u8]) -> Option<u64> {
+    if data.len() < Mint::LEN || data[45] != 1 {
+        return None;
+    }
+    Some(u64::from_le_bytes(data[36..44].try_into().ok()?))
+}
+
+/// `read_token_account`, falling back to `parse_account_data` and its JSON
+/// for accounts it cannot read. Scans of millions of accounts spend most of
+/// their CPU building that JSON, so it is kept off the common path.
+pub fn token_account_fields(
+    mint: &Pubkey,
+    decimals: u8,
+    data: &[u8],
+) -> Result<TokenAccountFields> {
+    if let Some(fields) = read_token_account(data) {
+        return Ok(fields);
+    }
+    let parsed = parse_account_data(
+        mint,
+        &TOKEN_PROGRAM_ID,
+        data,
+        Some(AccountAdditionalData {
+            spl_token_decimals: Some(decimals),
+        }),
+    )?;
+    Ok(TokenAccountFields {
+        owner: Pubkey::from_str(&parse_owner(&parsed)?)?,
+        amount: parse_token_amount(&parsed)?,
+        frozen: parse_state(&parsed)? == "frozen",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::program_option::COption,
+        spl_token::state::AccountState,
+    };
+
+    fn token_account(owner: Pubkey, amount: u64, state: AccountState) -> Vec<u8> {
+        let account = TokenAccount {
+            mint: Pubkey::new_unique(),
+            owner,
+            amount,
+            state,
+            ..TokenAccount::default()
+        };
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack_into_slice(&account, &mut data);
+        data
+    }
+
+    #[test]
+    fn reads_spl_token_accounts() {
+        let owner = Pubkey::new_unique();
+        let fields = read_token_account(&token_account(owner, 7, AccountState::Initialized))
+            .expect("initialized account");
+        assert_eq!(fields.owner, owner);
+        assert_eq!(fields.amount, 7);
+        assert!(!fields.frozen);
+
+        let frozen = read_token_account(&token_account(owner, 1, AccountState::Frozen));
+        assert!(frozen.expect("frozen account").frozen);
+    }
+
+    #[test]
+    fn reads_token_2022_accounts_with_extensions() {
+        let owner = Pubkey::new_unique();
+        let mut data = token_account(owner, 1, AccountState::Initialized);
+        // Account type, then an immutable owner extension: type 7, length 0.
+        data.extend_from_slice(&[TOKEN_2022_ACCOUNT_TYPE, 7, 0, 0, 0]);
+        let fields = read_token_account(&data).expect("Token-2022 account");
+        assert_eq!(fields.owner, owner);
+        assert_eq!(fields.amount, 1);
+
+        data[TokenAccount::LEN] = 1;
+        assert!(read_token_account(&data).is_none());
+    }
+
+    #[test]
+    fn rejects_uninitialized_accounts_and_other_lengths() {
+        let owner = Pubkey::new_unique();
+        let uninitialized = token_account(owner, 1, AccountState::Uninitialized);
+        assert!(read_token_account(&uninitialized).is_none());
+        let data = token_account(owner, 1, AccountState::Initialized);
+        assert!(read_token_account(&data[..TokenAccount::LEN - 1]).is_none());
+        assert!(read_token_account(&[]).is_none());
+    }
+
+    #[test]
+    fn reads_the_supply_of_initialized_mints() {
+        let mint = Mint {
+            mint_authority: COption::None,
+            supply: 1,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack_into_slice(&mint, &mut data);
+        assert_eq!(read_mint_supply(&data), Some(1));
+        assert_eq!(read_mint_supply(&data[..Mint::LEN - 1]), None);
+
+        let burned = Mint { supply: 0, ..mint };
+        Mint::pack_into_slice(&burned, &mut data);
+        assert_eq!(read_mint_supply(&data), Some(0));
+
+        assert_eq!(read_mint_supply(&[0; Mint::LEN]), None);
+    }
+}
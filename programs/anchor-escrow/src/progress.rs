@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Live counters of running snapshots, for progress displays. Share one
+/// through `PipelineConfig::progress`; concurrent collections add up.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// Items expected: source accounts while they are being decoded, then
+    /// the items actually queued.
+    pub items_total: AtomicUsize,
+    pub items_done: AtomicUsize,
+    pub holders: AtomicUsize,
+    pub errors: AtomicUsize,
+}
+
+/// A consistent enough reading of `Progress`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProgressCounts {
+    pub items_total: usize,
+    pub items_done: usize,
+    pub holders: usize,
+    pub errors: usize,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress::default()
+    }
+
+    pub fn counts(&self) -> ProgressCounts {
+        ProgressCounts {
+            items_total: self.items_total.load(Ordering::Relaxed),
+            items_done: self.items_done.load(Ordering::Relaxed),
+            holders: self.holders.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
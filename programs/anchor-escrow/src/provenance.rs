@@ -0,0 +1,203 @@
+use {
+    crate::rpc::RpcBackend,
+    anchor_lang::prelude::Pubkey,
+    anchor_spl::associated_token::get_associated_token_address,
+    anyhow::{anyhow, Result},
+    log::warn,
+    serde::Serialize,
+    solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature,
+    solana_sdk::signature::Signature,
+    solana_transaction_status::UiTransactionTokenBalance,
+    std::str::FromStr,
+};
+
+/// Most signatures getSignaturesForAddress returns per call.
+const SIGNATURE_PAGE: usize = 1000;
+
+/// A transaction after which a new token account held the mint.
+#[derive(Debug, Serialize)]
+pub struct OwnershipChange {
+    /// Wallet owning `token_account`; unknown when that is not the
+    /// associated token account of any address in the transaction.
+    pub owner: Option<String>,
+    pub token_account: String,
+    /// Signer paying for the transaction, e.g. the minter or buyer.
+    pub fee_payer: String,
+    pub signature: String,
+    pub slot: u64,
+    /// Unix timestamp, when the node still has it.
+    pub block_time: Option<i64>,
+}
+
+fn holds_one(balances: &[UiTransactionTokenBalance], mint: &str, index: u8) -> bool {
+    balances
+        .iter()
+        .any(|b| b.account_index == index && b.mint == mint && b.ui_token_amount.amount == "1")
+}
+
+/// Index of the token account holding `mint` after a transaction but not
+/// before it, if the transaction moved the mint.
+pub(crate) fn receiver_index(
+    pre: &[UiTransactionTokenBalance],
+    post: &[UiTransactionTokenBalance],
+    mint: &str,
+) -> Option<u8> {
+    post.iter()
+        .map(|balance| balance.account_index)
+        .find(|index| holds_one(post, mint, *index) && !holds_one(pre, mint, *index))
+}
+
+/// The change of holder `signature` made, if any.
+fn ownership_change(
+    client: &dyn RpcBackend,
+    mint: &Pubkey,
+    status: &RpcConfirmedTransactionStatusWithSignature,
+) -> Result<Option<OwnershipChange>> {
+    let confirmed = client.get_transaction(&Signature::from_str(&status.signature)?)?;
+    let meta = match &confirmed.transaction.meta {
+        Some(meta) => meta,
+        None => {
+            warn!("Transaction {} has no status meta", status.signature);
+            return Ok(None);
+        }
+    };
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or(anyhow!("Transaction {} could not be decoded!", status.signature))?;
+    let account_keys = &transaction.message.account_keys;
+
+    let mint_str = mint.to_string();
+    let pre = meta.pre_token_balances.as_deref().unwrap_or_default();
+    let post = meta.post_token_balances.as_deref().unwrap_or_default();
+    let receiver = receiver_index(pre, post, &mint_str);
+    let token_account = match receiver.and_then(|index| account_keys.get(index as usize)) {
+        Some(token_account) => token_account,
+        None => return Ok(None),
+    };
+
+    let owner = account_keys
+        .iter()
+        .find(|key| get_associated_token_address(key, mint) == *token_account)
+        .map(|owner| owner.to_string());
+    Ok(Some(OwnershipChange {
+        owner,
+        token_account: token_account.to_string(),
+        fee_payer: account_keys
+            .first()
+            .map(|payer| payer.to_string())
+            .unwrap_or_default(),
+        signature: status.signature.clone(),
+        slot: status.slot,
+        block_time: status.block_time,
+    }))
+}
+
+/// Replays the successful transactions touching `mint`, oldest first, and
+/// returns every change of the token account holding it. Only the newest
+/// `limit` transactions are read. Transfers that do not mention the mint,
+/// such as plain `Transfer` instructions between existing accounts, are not
+/// seen; the current holder in a snapshot is authoritative.
+pub fn ownership_history(
+    client: &dyn RpcBackend,
+    mint: &Pubkey,
+    limit: usize,
+) -> Result<Vec<OwnershipChange>> {
+    let signatures = client.get_signatures_for_address(mint, None, limit)?;
+
+    let mut changes = Vec::new();
+    for status in signatures.iter().rev().filter(|status| status.err.is_none()) {
+        changes.extend(ownership_change(client, mint, status)?);
+    }
+    Ok(changes)
+}
+
+/// Every signature of `address`, newest first, paging back with `before`
+/// until the oldest.
+pub fn all_signatures(
+    client: &dyn RpcBackend,
+    address: &Pubkey,
+) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+    let mut signatures: Vec<RpcConfirmedTransactionStatusWithSignature> = Vec::new();
+    loop {
+        let before = match signatures.last() {
+            Some(status) => Some(Signature::from_str(&status.signature)?),
+            None => None,
+        };
+        let page = client.get_signatures_for_address(address, before, SIGNATURE_PAGE)?;
+        let last_page = page.len() < SIGNATURE_PAGE;
+        signatures.extend(page);
+        if last_page {
+            return Ok(signatures);
+        }
+    }
+}
+
+/// The first holder of `mint`, i.e. its minter, replaying at most its
+/// oldest `limit` transactions. Signatures are paged back to the oldest
+/// first, however long the item's history.
+pub fn first_holder(
+    client: &dyn RpcBackend,
+    mint: &Pubkey,
+    limit: usize,
+) -> Result<Option<OwnershipChange>> {
+    let signatures = all_signatures(client, mint)?;
+    let oldest = signatures.iter().rev().filter(|status| status.err.is_none());
+    for status in oldest.take(limit) {
+        if let Some(change) = ownership_change(client, mint, status)? {
+            return Ok(Some(change));
+        }
+    }
+    Ok(None)
+}
+
+/// When `token_account` last received `mint`, as a unix timestamp, reading
+/// at most `limit` of its transactions. `None` when that is further back or
+/// the node no longer has block times that old.
+pub fn received_at(
+    client: &dyn RpcBackend,
+    token_account: &Pubkey,
+    mint: &Pubkey,
+    limit: usize,
+) -> Result<Option<i64>> {
+    let signatures = client.get_signatures_for_address(token_account, None, limit)?;
+    for status in signatures.iter().filter(|status| status.err.is_none()) {
+        if let Some(change) = ownership_change(client, mint, status)? {
+            if change.token_account == token_account.to_string() {
+                return Ok(change.block_time);
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::rpc::MockRpc};
+
+    fn status(slot: u64) -> RpcConfirmedTransactionStatusWithSignature {
+        let mut signature = [0; 64];
+        signature[..8].copy_from_slice(&slot.to_le_bytes());
+        RpcConfirmedTransactionStatusWithSignature {
+            signature: Signature::new(&signature).to_string(),
+            slot,
+            err: None,
+            memo: None,
+            block_time: None,
+            confirmation_status: None,
+        }
+    }
+
+    #[test]
+    fn pages_back_to_the_oldest_signature() {
+        let address = Pubkey::new_unique();
+        let mut rpc = MockRpc::new();
+        let newest_first = (0..2 * SIGNATURE_PAGE as u64 + 7).rev().map(status).collect();
+        rpc.signatures.insert(address, newest_first);
+
+        let signatures = all_signatures(&rpc, &address).unwrap();
+        assert_eq!(signatures.len(), 2 * SIGNATURE_PAGE + 7);
+        assert_eq!(signatures.last().unwrap().slot, 0);
+    }
+}
@@ -0,0 +1,217 @@
+use {
+    crate::{
+        metadata::parse_metadata,
+        parse::{first_creator_is_verified, read_mint_supply},
+        rpc::RpcBackend,
+        snapshot::{get_source_accounts, resolve_holders_batch, Source},
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    crossbeam::thread,
+    log::warn,
+    serde::Serialize,
+    std::{convert::TryInto, str::FromStr},
+};
+
+/// Counts from each stage of a snapshot, and the mints lost between them.
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    /// Metadata accounts returned by the source scan.
+    pub metadata_accounts: usize,
+    /// Accounts that failed to decode as metadata.
+    pub decode_failures: Vec<String>,
+    /// Metadata whose first creator is not verified, e.g. fakes.
+    pub unverified: usize,
+    /// Mints left after the checks above.
+    pub mints: usize,
+    /// `items_redeemed` of the candy machine, for candy machine sources.
+    pub items_redeemed: Option<u64>,
+    /// Mints with a current holder.
+    pub holders: usize,
+    /// Mints whose supply is 0, or that were closed.
+    pub burned: Vec<String>,
+    /// Mints with supply but no token account holding it.
+    pub missing_token_accounts: Vec<String>,
+    /// Mints whose token accounts or mint account could not be fetched, so
+    /// are none of the above.
+    pub lookup_failures: Vec<String>,
+}
+
+impl ReconcileReport {
+    /// Human readable discrepancies; empty when every mint is accounted for.
+    pub fn discrepancies(&self) -> Vec<String> {
+        let mut discrepancies = Vec::new();
+        if !self.decode_failures.is_empty() {
+            discrepancies.push(format!(
+                "{} metadata accounts failed to decode",
+                self.decode_failures.len()
+            ));
+        }
+        if let Some(items_redeemed) = self.items_redeemed {
+            if items_redeemed != self.mints as u64 {
+                discrepancies.push(format!(
+                    "candy machine redeemed {} items but the scan found {} mints",
+                    items_redeemed, self.mints
+                ));
+            }
+        }
+        if !self.burned.is_empty() {
+            discrepancies.push(format!("{} mints were burned", self.burned.len()));
+        }
+        if !self.missing_token_accounts.is_empty() {
+            discrepancies.push(format!(
+                "{} mints have no token account holding them",
+                self.missing_token_accounts.len()
+            ));
+        }
+        if !self.lookup_failures.is_empty() {
+            discrepancies.push(format!(
+                "{} mints could not be looked up, so their holders are unknown",
+                self.lookup_failures.len()
+            ));
+        }
+        discrepancies
+    }
+}
+
+/// Reads `items_redeemed` from a candy machine v2 account: discriminator,
+/// authority, wallet, then an optional token mint.
+fn parse_items_redeemed(data: &[u8]) -> Result<u64> {
+    let mut offset = 8 + 32 + 32;
+    match data.get(offset) {
+        Some(0) => offset += 1,
+        Some(1) => offset += 1 + 32,
+        _ => return Err(anyhow!("Candy machine account is malformed!")),
+    }
+    let bytes = data
+        .get(offset..offset + 8)
+        .ok_or(anyhow!("Candy machine account is malformed!"))?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+fn get_items_redeemed(client: &dyn RpcBackend, candy_machine: &str) -> Result<u64> {
+    let candy_machine = Pubkey::from_str(candy_machine)?;
+    let account = client
+        .get_multiple_accounts(&[candy_machine])?
+        .pop()
+        .flatten()
+        .ok_or(anyhow!("Account {} has no candy machine", candy_machine))?;
+    parse_items_redeemed(&account.data)
+}
+
+enum MintStatus {
+    Held,
+    Burned,
+    Missing,
+    /// Either lookup failed, so the mint could be any of the above.
+    LookupFailed(anyhow::Error),
+}
+
+/// The status of every `(metadata, mint)` pair, with holders looked up the
+/// way snapshots do, so items in Token-2022 accounts count as held.
+fn mint_statuses(client: &dyn RpcBackend, items: &[(Pubkey, Pubkey)]) -> Vec<MintStatus> {
+    let resolved = match resolve_holders_batch(client, items) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            let failed = |_| MintStatus::LookupFailed(anyhow!("{:#}", err));
+            return items.iter().map(failed).collect();
+        }
+    };
+    items
+        .iter()
+        .zip(resolved)
+        .map(|((_, mint), holders)| match holders {
+            Ok((holders, _)) if !holders.is_empty() => MintStatus::Held,
+            Ok(_) => unheld_status(client, mint),
+            Err(err) => MintStatus::LookupFailed(err),
+        })
+        .collect()
+}
+
+/// Whether `mint`, which has no holder, was burned.
+fn unheld_status(client: &dyn RpcBackend, mint: &Pubkey) -> MintStatus {
+    match client.get_multiple_accounts(&[*mint]) {
+        // A closed mint is burned as much as one without supply.
+        Ok(mut accounts) => match accounts.pop().flatten() {
+            Some(account) if read_mint_supply(&account.data) != Some(0) => MintStatus::Missing,
+            _ => MintStatus::Burned,
+        },
+        Err(err) => MintStatus::LookupFailed(err),
+    }
+}
+
+/// Walks every stage of a snapshot of `source` and records where mints drop
+/// out, so a snapshot's completeness can be checked rather than assumed.
+pub fn reconcile(
+    client: &dyn RpcBackend,
+    source: &Source,
+    workers: usize,
+    resized_fallback: bool,
+) -> Result<ReconcileReport> {
+    let accounts = get_source_accounts(client, source, resized_fallback)?;
+    let mut report = ReconcileReport {
+        metadata_accounts: accounts.len(),
+        ..ReconcileReport::default()
+    };
+
+    let mut mints = Vec::new();
+    for (metadata_pubkey, account) in accounts {
+        let metadata = match parse_metadata(&account.data) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                report.decode_failures.push(metadata_pubkey.to_string());
+                continue;
+            }
+        };
+        if !first_creator_is_verified(&metadata.data.creators) {
+            report.unverified += 1;
+            continue;
+        }
+        mints.push((metadata_pubkey, metadata.mint));
+    }
+    report.mints = mints.len();
+
+    if let Source::CandyMachine { address, .. } = source {
+        report.items_redeemed = Some(get_items_redeemed(client, address)?);
+    }
+
+    // At most `workers` threads, each looking its mints up a batch at a time.
+    let chunk_size = mints.len().div_ceil(workers.max(1)).max(1);
+    let batch_size = client.batch_size().max(1);
+    let statuses = thread::scope(|scope| {
+        let handles: Vec<_> = mints
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move |_| {
+                    chunk
+                        .chunks(batch_size)
+                        .flat_map(|batch| {
+                            let statuses = mint_statuses(client, batch);
+                            batch.iter().map(|(_, mint)| *mint).zip(statuses)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join())
+            .collect::<std::thread::Result<Vec<_>>>()
+    })
+    .and_then(|statuses| statuses)
+    .map_err(|_| anyhow!("Reconcile thread panicked!"))?;
+
+    for (mint, status) in statuses.into_iter().flatten() {
+        match status {
+            MintStatus::Held => report.holders += 1,
+            MintStatus::Burned => report.burned.push(mint.to_string()),
+            MintStatus::Missing => report.missing_token_accounts.push(mint.to_string()),
+            MintStatus::LookupFailed(err) => {
+                warn!("Mint {} could not be looked up: {}", mint, err);
+                report.lookup_failures.push(mint.to_string());
+            }
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,364 @@
+use {
+    crate::{
+        das::{AssetPage, AssetProof},
+        http_sender::http_status,
+        metrics::RpcMetrics,
+        rpc::RpcBackend,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    retry::{delay::Exponential, retry, OperationResult},
+    serde::Deserialize,
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_request::{RpcError, RpcResponseErrorData},
+        rpc_response::RpcConfirmedTransactionStatusWithSignature,
+    },
+    solana_sdk::{account::Account, hash::Hash, signature::Signature},
+    solana_transaction_status::EncodedConfirmedTransaction,
+    std::{thread, time::Duration},
+};
+
+/// Why an RPC call failed, as far as retrying is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    /// HTTP 429 from the provider.
+    RateLimited,
+    /// HTTP 5xx from the provider, or a node reporting itself unhealthy.
+    ServerError,
+    /// Connection errors and timeouts before any status was received.
+    Transport,
+    /// The response arrived but could not be decoded.
+    Deserialization,
+    /// Anything else, e.g. an RPC error the node returned on purpose.
+    Other,
+}
+
+pub fn classify(err: &anyhow::Error) -> FailureClass {
+    let client_error = match err.downcast_ref::<ClientError>() {
+        Some(client_error) => client_error,
+        None => return FailureClass::Other,
+    };
+
+    match client_error.kind() {
+        ClientErrorKind::Reqwest(e) => match e.status() {
+            Some(status) if status.as_u16() == 429 => FailureClass::RateLimited,
+            Some(status) if status.is_server_error() => FailureClass::ServerError,
+            Some(_) => FailureClass::Other,
+            None => FailureClass::Transport,
+        },
+        // JSON-RPC errors sent with an error status, see `http_status`.
+        ClientErrorKind::RpcError(e) => match (http_status(e), e) {
+            (Some(429), _) => FailureClass::RateLimited,
+            (Some(status), _) if status >= 500 => FailureClass::ServerError,
+            (
+                _,
+                RpcError::RpcResponseError {
+                    data: RpcResponseErrorData::NodeUnhealthy { .. },
+                    ..
+                },
+            ) => FailureClass::ServerError,
+            _ => FailureClass::Other,
+        },
+        ClientErrorKind::Io(_) => FailureClass::Transport,
+        ClientErrorKind::SerdeJson(_) => FailureClass::Deserialization,
+        _ => FailureClass::Other,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryOn {
+    pub rate_limited: bool,
+    pub server_error: bool,
+    pub transport: bool,
+    pub deserialization: bool,
+}
+
+impl Default for RetryOn {
+    fn default() -> Self {
+        RetryOn {
+            rate_limited: true,
+            server_error: true,
+            transport: true,
+            deserialization: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total tries including the first one.
+    pub max_attempts: usize,
+    pub base_delay_ms: u64,
+    /// Multiplier applied to the delay after every failed try.
+    pub factor: f64,
+    /// Fraction of each delay that is randomized, 0.0 to 1.0.
+    pub jitter: f64,
+    pub retry_on: RetryOn,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay_ms: 250,
+            factor: 2.0,
+            jitter: 0.1,
+            retry_on: RetryOn::default(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    pub fn should_retry(&self, class: FailureClass) -> bool {
+        match class {
+            FailureClass::RateLimited => self.retry_on.rate_limited,
+            FailureClass::ServerError => self.retry_on.server_error,
+            FailureClass::Transport => self.retry_on.transport,
+            FailureClass::Deserialization => self.retry_on.deserialization,
+            FailureClass::Other => false,
+        }
+    }
+
+    fn delays(&self) -> impl Iterator<Item = Duration> {
+        let jitter = self.jitter.max(0.0).min(1.0);
+        Exponential::from_millis_with_factor(self.base_delay_ms, self.factor)
+            .map(move |delay| delay.mul_f64(1.0 - jitter * rand::random::<f64>()))
+            .take(self.max_attempts.saturating_sub(1))
+    }
+
+    /// Runs `op` until it succeeds, fails with a class this policy does not
+    /// retry, or runs out of attempts.
+    pub fn call<T, F>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let result = retry(self.delays(), || match op() {
+            Ok(value) => OperationResult::Ok(value),
+            Err(err) if self.should_retry(classify(&err)) => OperationResult::Retry(err),
+            Err(err) => OperationResult::Err(err),
+        });
+
+        result.map_err(|e| match e {
+            retry::Error::Operation { error, .. } => error,
+            retry::Error::Internal(msg) => anyhow!(msg),
+        })
+    }
+}
+
+/// Applies a `RetryPolicy` to every call of the wrapped backend.
+pub struct RetryingRpc<B> {
+    pub inner: B,
+    pub policy: RetryPolicy,
+}
+
+impl<B: RpcBackend> RetryingRpc<B> {
+    pub fn new(inner: B, policy: RetryPolicy) -> Self {
+        RetryingRpc { inner, policy }
+    }
+}
+
+impl<B: RpcBackend> RpcBackend for RetryingRpc<B> {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        self.policy.call(|| {
+            self.inner
+                .get_program_accounts_with_config(program_id, config.clone())
+        })
+    }
+
+    fn get_program_accounts_batch(
+        &self,
+        program_id: &Pubkey,
+        configs: Vec<RpcProgramAccountsConfig>,
+    ) -> Result<Vec<Result<Vec<(Pubkey, Account)>>>> {
+        let mut results = self.policy.call(|| {
+            self.inner
+                .get_program_accounts_batch(program_id, configs.clone())
+        })?;
+        // Only the calls that failed in a way worth retrying are sent again.
+        for delay in self.policy.delays() {
+            let pending: Vec<usize> = (0..results.len())
+                .filter(|index| match &results[*index] {
+                    Err(err) => self.policy.should_retry(classify(err)),
+                    Ok(_) => false,
+                })
+                .collect();
+            if pending.is_empty() {
+                break;
+            }
+            thread::sleep(delay);
+            let retried: Vec<RpcProgramAccountsConfig> =
+                pending.iter().map(|index| configs[*index].clone()).collect();
+            let answers = self.policy.call(|| {
+                self.inner
+                    .get_program_accounts_batch(program_id, retried.clone())
+            })?;
+            for (index, answer) in pending.into_iter().zip(answers) {
+                results[index] = answer;
+            }
+        }
+        Ok(results)
+    }
+
+    fn batch_size(&self) -> usize {
+        self.inner.batch_size()
+    }
+
+    fn metrics(&self) -> Option<&RpcMetrics> {
+        self.inner.metrics()
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        self.policy.call(|| self.inner.get_multiple_accounts(pubkeys))
+    }
+
+    fn get_slot(&self) -> Result<u64> {
+        self.policy.call(|| self.inner.get_slot())
+    }
+
+    fn get_genesis_hash(&self) -> Result<Hash> {
+        self.policy.call(|| self.inner.get_genesis_hash())
+    }
+
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.policy
+            .call(|| self.inner.get_signatures_for_address(address, before, limit))
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction> {
+        self.policy.call(|| self.inner.get_transaction(signature))
+    }
+
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof> {
+        self.policy.call(|| self.inner.get_asset_proof(asset_id))
+    }
+
+    fn get_assets_by_collection(&self, collection: &Pubkey, page: usize) -> Result<AssetPage> {
+        self.policy
+            .call(|| self.inner.get_assets_by_collection(collection, page))
+    }
+
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+        self.policy.call(|| self.inner.get_token_largest_accounts(mint))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{cell::Cell, io},
+    };
+
+    fn rpc_error(message: &str, data: RpcResponseErrorData) -> anyhow::Error {
+        ClientError::from(RpcError::RpcResponseError {
+            code: -32000,
+            message: message.to_string(),
+            data,
+        })
+        .into()
+    }
+
+    #[test]
+    fn classifies_by_status_and_error_kind() {
+        let limited = rpc_error("Too many requests (HTTP 429)", RpcResponseErrorData::Empty);
+        assert_eq!(classify(&limited), FailureClass::RateLimited);
+        let unavailable = rpc_error("Service unavailable (HTTP 503)", RpcResponseErrorData::Empty);
+        assert_eq!(classify(&unavailable), FailureClass::ServerError);
+        let unhealthy = RpcResponseErrorData::NodeUnhealthy {
+            num_slots_behind: Some(42),
+        };
+        assert_eq!(classify(&rpc_error("Node is behind", unhealthy)), FailureClass::ServerError);
+        let invalid = rpc_error("Invalid param", RpcResponseErrorData::Empty);
+        assert_eq!(classify(&invalid), FailureClass::Other);
+
+        let reset = ClientError::from(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+        assert_eq!(classify(&reset.into()), FailureClass::Transport);
+        let garbled = serde_json::from_str::<u64>("{").unwrap_err();
+        assert_eq!(classify(&ClientError::from(garbled).into()), FailureClass::Deserialization);
+        assert_eq!(classify(&anyhow!("Not an RPC error!")), FailureClass::Other);
+    }
+
+    #[test]
+    fn retries_only_what_it_is_told_to() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(FailureClass::RateLimited));
+        assert!(policy.should_retry(FailureClass::ServerError));
+        assert!(policy.should_retry(FailureClass::Transport));
+        assert!(!policy.should_retry(FailureClass::Deserialization));
+        assert!(!policy.should_retry(FailureClass::Other));
+    }
+
+    #[test]
+    fn backs_off_exponentially_within_the_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay_ms: 100,
+            factor: 2.0,
+            jitter: 0.1,
+            ..RetryPolicy::default()
+        };
+        let delays: Vec<Duration> = policy.delays().collect();
+        assert_eq!(delays.len(), 3);
+        for (delay, full) in delays.iter().zip(&[100, 200, 400]) {
+            let full = Duration::from_millis(*full);
+            assert!(*delay <= full && *delay >= full.mul_f64(0.9), "{:?}", delays);
+        }
+        assert_eq!(RetryPolicy::none().delays().count(), 0);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts_or_on_fatal_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 0,
+            ..RetryPolicy::default()
+        };
+
+        let tries = Cell::new(0);
+        let result: Result<()> = policy.call(|| {
+            tries.set(tries.get() + 1);
+            Err(rpc_error("Busy (HTTP 503)", RpcResponseErrorData::Empty))
+        });
+        assert!(result.is_err());
+        assert_eq!(tries.get(), 3);
+
+        tries.set(0);
+        let result: Result<()> = policy.call(|| {
+            tries.set(tries.get() + 1);
+            Err(rpc_error("Invalid param", RpcResponseErrorData::Empty))
+        });
+        assert!(result.is_err());
+        assert_eq!(tries.get(), 1);
+
+        tries.set(0);
+        let result = policy.call(|| {
+            tries.set(tries.get() + 1);
+            match tries.get() {
+                1 => Err(rpc_error("Busy (HTTP 503)", RpcResponseErrorData::Empty)),
+                _ => Ok(7),
+            }
+        });
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(tries.get(), 2);
+    }
+}
@@ -0,0 +1,521 @@
+use {
+    crate::{
+        enrich::get_multiple_accounts_chunked,
+        metadata::parse_metadata,
+        output::{write_records, Compression, OutputFormat, Record},
+        provenance::receiver_index,
+        rpc::RpcBackend,
+        wallet::metadata_address,
+    },
+    age::x25519::Recipient,
+    anchor_lang::{prelude::Pubkey, AnchorDeserialize},
+    anchor_spl::associated_token::get_associated_token_address,
+    anyhow::{anyhow, Result},
+    log::{error, warn},
+    mpl_token_metadata::{
+        instruction::MetadataInstruction, state::Creator, ID as TOKEN_METADATA_PROGRAM_ID,
+    },
+    serde::Serialize,
+    solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature,
+    solana_sdk::signature::Signature,
+    solana_transaction_status::{UiInstruction, UiTransactionStatusMeta},
+    std::{collections::BTreeMap, path::Path, str::FromStr},
+};
+
+/// A secondary sale of an item found in its transactions.
+#[derive(Debug, Serialize)]
+pub struct Sale {
+    pub mint_account: String,
+    pub signature: String,
+    /// Unix timestamp, when the node still has it.
+    pub block_time: Option<i64>,
+    pub buyer: String,
+    /// Lamports the buyer spent, less the fee and the rent of accounts the
+    /// sale created.
+    pub price_lamports: u64,
+    /// Royalty due under the item's seller fee, all creators together.
+    pub owed_lamports: u64,
+    /// Lamports the item's creators received in the sale.
+    pub paid_lamports: u64,
+}
+
+/// Royalties due to and received by one creator over the sales found.
+#[derive(Debug, Default, Serialize)]
+pub struct CreatorPayout {
+    pub creator: String,
+    pub sales: usize,
+    pub owed_lamports: u64,
+    pub paid_lamports: u64,
+    /// Owed less paid, i.e. what is left to pay the creator.
+    pub outstanding_lamports: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoyaltyReport {
+    /// Oldest first per item.
+    pub sales: Vec<Sale>,
+    /// Most outstanding first.
+    pub payouts: Vec<CreatorPayout>,
+}
+
+/// The part of `price` due to a creator with `share` percent of a
+/// `basis_points` seller fee.
+fn royalty_share(price: u64, basis_points: u16, share: u8) -> u64 {
+    (price as u128 * basis_points as u128 * share as u128 / 1_000_000) as u64
+}
+
+/// Seller fee and creators of an item from `slot` on.
+#[derive(Debug, Clone)]
+struct RoyaltyTerms {
+    slot: u64,
+    basis_points: u16,
+    creators: Vec<Creator>,
+}
+
+/// The seller fee and creators a token metadata instruction sets, if it
+/// creates the metadata or updates its data.
+fn terms_set_by(data: &[u8]) -> Option<(u16, Vec<Creator>)> {
+    let (basis_points, creators) = match MetadataInstruction::try_from_slice(data).ok()? {
+        MetadataInstruction::CreateMetadataAccount(args) => {
+            (args.data.seller_fee_basis_points, args.data.creators)
+        }
+        MetadataInstruction::CreateMetadataAccountV2(args) => {
+            (args.data.seller_fee_basis_points, args.data.creators)
+        }
+        MetadataInstruction::UpdateMetadataAccount(args) => {
+            let data = args.data?;
+            (data.seller_fee_basis_points, data.creators)
+        }
+        MetadataInstruction::UpdateMetadataAccountV2(args) => {
+            let data = args.data?;
+            (data.seller_fee_basis_points, data.creators)
+        }
+        _ => return None,
+    };
+    Some((basis_points, creators.unwrap_or_default()))
+}
+
+/// The royalty terms of `metadata` set in its newest `limit` transactions,
+/// oldest first. Candy machines create metadata through CPI, so inner
+/// instructions count too.
+fn terms_history(
+    client: &dyn RpcBackend,
+    metadata: &Pubkey,
+    limit: usize,
+) -> Result<Vec<RoyaltyTerms>> {
+    let signatures = client.get_signatures_for_address(metadata, None, limit)?;
+    let mut history = Vec::new();
+    for status in signatures.iter().rev().filter(|status| status.err.is_none()) {
+        let confirmed = client.get_transaction(&Signature::from_str(&status.signature)?)?;
+        let transaction = match confirmed.transaction.transaction.decode() {
+            Some(transaction) => transaction,
+            None => {
+                warn!("Transaction {} could not be decoded", status.signature);
+                continue;
+            }
+        };
+        let message = &transaction.message;
+        let mut instructions: Vec<(u8, Vec<u8>, Vec<u8>)> = message
+            .instructions
+            .iter()
+            .map(|ix| (ix.program_id_index, ix.accounts.clone(), ix.data.clone()))
+            .collect();
+        let inner = confirmed.transaction.meta.and_then(|meta| meta.inner_instructions);
+        for ix in inner.into_iter().flatten().flat_map(|inner| inner.instructions) {
+            if let UiInstruction::Compiled(ix) = ix {
+                let data = bs58::decode(&ix.data).into_vec().unwrap_or_default();
+                instructions.push((ix.program_id_index, ix.accounts, data));
+            }
+        }
+
+        let key = |index: &u8| message.account_keys.get(*index as usize);
+        for (program_id_index, accounts, data) in instructions {
+            // Metadata instructions take the metadata account first.
+            if key(&program_id_index) != Some(&TOKEN_METADATA_PROGRAM_ID)
+                || accounts.first().and_then(key) != Some(metadata)
+            {
+                continue;
+            }
+            if let Some((basis_points, creators)) = terms_set_by(&data) {
+                history.push(RoyaltyTerms {
+                    slot: status.slot,
+                    basis_points,
+                    creators,
+                });
+            }
+        }
+    }
+    Ok(history)
+}
+
+/// The terms in effect at `slot`: the last set at or before it, else
+/// `current` when the history does not reach back that far.
+fn terms_at<'a>(
+    history: &'a [RoyaltyTerms],
+    current: &'a RoyaltyTerms,
+    slot: u64,
+) -> &'a RoyaltyTerms {
+    history
+        .iter()
+        .rev()
+        .find(|terms| terms.slot <= slot)
+        .unwrap_or(current)
+}
+
+/// The sale `status` made of `mint`, if it was one, with what each of
+/// `creators` received in it. A sale moves the item from a previous holder
+/// to a wallet that paid for it; mints and plain transfers are not sales.
+fn sale_of(
+    client: &dyn RpcBackend,
+    mint: &Pubkey,
+    creators: &[Creator],
+    status: &RpcConfirmedTransactionStatusWithSignature,
+) -> Result<Option<(Sale, Vec<Option<u64>>)>> {
+    let confirmed = client.get_transaction(&Signature::from_str(&status.signature)?)?;
+    let meta = match &confirmed.transaction.meta {
+        Some(meta) => meta,
+        None => {
+            warn!("Transaction {} has no status meta", status.signature);
+            return Ok(None);
+        }
+    };
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or(anyhow!("Transaction {} could not be decoded!", status.signature))?;
+    Ok(sale_in(mint, creators, status, &transaction.message.account_keys, meta))
+}
+
+/// `sale_of` for a transaction already fetched. Creators who are the seller
+/// or the buyer get `None`: what they receive is the price, not royalties.
+fn sale_in(
+    mint: &Pubkey,
+    creators: &[Creator],
+    status: &RpcConfirmedTransactionStatusWithSignature,
+    account_keys: &[Pubkey],
+    meta: &UiTransactionStatusMeta,
+) -> Option<(Sale, Vec<Option<u64>>)> {
+    let mint_str = mint.to_string();
+    let pre = meta.pre_token_balances.as_deref().unwrap_or_default();
+    let post = meta.post_token_balances.as_deref().unwrap_or_default();
+    // A mint has no previous holder to buy from.
+    if !pre
+        .iter()
+        .any(|balance| balance.mint == mint_str && balance.ui_token_amount.amount == "1")
+    {
+        return None;
+    }
+    let token_account =
+        |index: Option<u8>| index.and_then(|index| account_keys.get(index as usize));
+    let owner_of = |token_account: &Pubkey| {
+        account_keys
+            .iter()
+            .position(|key| get_associated_token_address(key, mint) == *token_account)
+    };
+    let receiving = token_account(receiver_index(pre, post, &mint_str))?;
+    // The wallet owning the receiving token account, else the fee payer.
+    let buyer = owner_of(receiving).unwrap_or(0);
+    // The token account that held the item before but not after.
+    let seller = token_account(receiver_index(post, pre, &mint_str)).and_then(owner_of);
+
+    let balance = |balances: &[u64], index: usize| balances.get(index).copied().unwrap_or(0);
+    let received = |index: usize| {
+        balance(&meta.post_balances, index).saturating_sub(balance(&meta.pre_balances, index))
+    };
+    let creator_indexes: Vec<Option<usize>> = creators
+        .iter()
+        .map(|creator| account_keys.iter().position(|key| *key == creator.address))
+        .collect();
+
+    let spent = balance(&meta.pre_balances, buyer)
+        .saturating_sub(balance(&meta.post_balances, buyer));
+    let fee = match buyer {
+        0 => meta.fee,
+        _ => 0,
+    };
+    // Accounts created by the sale, e.g. the buyer's token account, hold the
+    // rent the buyer paid rather than any of the price. Creators and the
+    // seller receive their part of the price even into new accounts.
+    let rent: u64 = (0..account_keys.len())
+        .filter(|index| balance(&meta.pre_balances, *index) == 0)
+        .filter(|index| Some(*index) != seller && !creator_indexes.contains(&Some(*index)))
+        .map(|index| balance(&meta.post_balances, index))
+        .sum();
+    let price = spent.saturating_sub(fee).saturating_sub(rent);
+    if price == 0 {
+        return None;
+    }
+
+    let paid = creator_indexes
+        .into_iter()
+        .map(|index| match index {
+            Some(index) if index == buyer || Some(index) == seller => None,
+            Some(index) => Some(received(index)),
+            None => Some(0),
+        })
+        .collect();
+    let sale = Sale {
+        mint_account: mint_str,
+        signature: status.signature.clone(),
+        block_time: status.block_time,
+        buyer: account_keys[buyer].to_string(),
+        price_lamports: price,
+        owed_lamports: 0,
+        paid_lamports: 0,
+    };
+    Some((sale, paid))
+}
+
+/// Replays the newest `limit` transactions of each of `mints` and totals
+/// the royalties owed and paid per creator, by the seller fee and creator
+/// shares in effect at each sale, as set in the newest `limit` transactions
+/// of the item's metadata, else its current ones. Items without metadata
+/// are logged and skipped. Sales are recognized from balance changes alone,
+/// so a marketplace paying the seller from an escrow funded in an earlier
+/// transaction is missed.
+pub fn royalty_report(
+    client: &dyn RpcBackend,
+    mints: &[Pubkey],
+    limit: usize,
+) -> Result<RoyaltyReport> {
+    let addresses: Vec<Pubkey> = mints.iter().map(metadata_address).collect();
+    let accounts = get_multiple_accounts_chunked(client, &addresses)?;
+
+    let mut sales = Vec::new();
+    let mut payouts: BTreeMap<Pubkey, CreatorPayout> = BTreeMap::new();
+    for ((mint, address), data) in mints.iter().zip(&addresses).zip(accounts) {
+        let metadata = match data.map(|data| parse_metadata(&data)) {
+            Some(Ok(metadata)) => metadata,
+            _ => {
+                error!("Mint {} has no metadata", mint);
+                continue;
+            }
+        };
+        let current = RoyaltyTerms {
+            slot: 0,
+            basis_points: metadata.data.seller_fee_basis_points,
+            creators: metadata.data.creators.unwrap_or_default(),
+        };
+        let history = terms_history(client, address, limit)?;
+
+        let signatures = client.get_signatures_for_address(mint, None, limit)?;
+        for status in signatures.iter().rev().filter(|status| status.err.is_none()) {
+            let terms = terms_at(&history, &current, status.slot);
+            let (mut sale, paid) = match sale_of(client, mint, &terms.creators, status)? {
+                Some(sale) => sale,
+                None => continue,
+            };
+            for (creator, paid) in terms.creators.iter().zip(paid) {
+                // The creator is a party to the sale.
+                let paid = match paid {
+                    Some(paid) => paid,
+                    None => continue,
+                };
+                let owed = royalty_share(sale.price_lamports, terms.basis_points, creator.share);
+                let payout = payouts
+                    .entry(creator.address)
+                    .or_insert_with(|| CreatorPayout {
+                        creator: creator.address.to_string(),
+                        ..CreatorPayout::default()
+                    });
+                payout.sales += 1;
+                payout.owed_lamports += owed;
+                payout.paid_lamports += paid;
+                sale.owed_lamports += owed;
+                sale.paid_lamports += paid;
+            }
+            sales.push(sale);
+        }
+    }
+
+    let mut payouts: Vec<CreatorPayout> = payouts
+        .into_iter()
+        .map(|(_, mut payout)| {
+            payout.outstanding_lamports = payout.owed_lamports.saturating_sub(payout.paid_lamports);
+            payout
+        })
+        .collect();
+    payouts.sort_by(|a, b| {
+        b.outstanding_lamports
+            .cmp(&a.outstanding_lamports)
+            .then_with(|| a.creator.cmp(&b.creator))
+    });
+    Ok(RoyaltyReport { sales, payouts })
+}
+
+/// Writes one row per creator, e.g. as the recipients of a payout,
+/// encrypted to `recipients` if any.
+pub fn write_payouts(
+    path: &Path,
+    format: OutputFormat,
+    recipients: &[Recipient],
+    payouts: &[CreatorPayout],
+) -> Result<()> {
+    write_records(path, format, Compression::None, recipients, payouts)
+}
+
+impl Record for CreatorPayout {}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        anchor_lang::AnchorSerialize,
+        mpl_token_metadata::{instruction::UpdateMetadataAccountArgs, state::Data},
+        serde_json::{json, Value},
+    };
+
+    const ATA_RENT: u64 = 2_039_280;
+    const PRICE: u64 = 1_000_000_000;
+
+    fn creator(address: Pubkey, share: u8) -> Creator {
+        Creator {
+            address,
+            verified: true,
+            share,
+        }
+    }
+
+    fn status() -> RpcConfirmedTransactionStatusWithSignature {
+        RpcConfirmedTransactionStatusWithSignature {
+            signature: Signature::default().to_string(),
+            slot: 1,
+            err: None,
+            memo: None,
+            block_time: None,
+            confirmation_status: None,
+        }
+    }
+
+    fn token_balance(index: u8, mint: &Pubkey, amount: &str) -> Value {
+        json!({
+            "accountIndex": index,
+            "mint": mint.to_string(),
+            "uiTokenAmount": {
+                "uiAmount": null,
+                "decimals": 0,
+                "amount": amount,
+                "uiAmountString": amount,
+            },
+        })
+    }
+
+    /// A sale of `mint` from the wallet at index 1 to the fee payer at index
+    /// 0 for `PRICE`, the buyer funding its new token account, with keys
+    /// `[buyer, seller, creator, buyer token account, seller token account]`.
+    /// The creator gets 5%; its account is new.
+    fn sale(mint: &Pubkey, creator: &Pubkey) -> (Vec<Pubkey>, UiTransactionStatusMeta) {
+        let (buyer, seller) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let keys = vec![
+            buyer,
+            seller,
+            *creator,
+            get_associated_token_address(&buyer, mint),
+            get_associated_token_address(&seller, mint),
+        ];
+        let royalty = PRICE / 20;
+        let meta = serde_json::from_value(json!({
+            "err": null,
+            "status": {"Ok": null},
+            "fee": 5000,
+            "preBalances": [10 * PRICE, 1000, 0, 0, ATA_RENT],
+            "postBalances": [
+                10 * PRICE - PRICE - 5000 - ATA_RENT,
+                1000 + PRICE - royalty,
+                royalty,
+                ATA_RENT,
+                ATA_RENT,
+            ],
+            "preTokenBalances": [token_balance(4, mint, "1")],
+            "postTokenBalances": [token_balance(3, mint, "1"), token_balance(4, mint, "0")],
+        }))
+        .unwrap();
+        (keys, meta)
+    }
+
+    #[test]
+    fn splits_royalties_by_share() {
+        // 5% of 1 SOL, split 60/40.
+        assert_eq!(royalty_share(PRICE, 500, 60), 30_000_000);
+        assert_eq!(royalty_share(PRICE, 500, 40), 20_000_000);
+        assert_eq!(royalty_share(PRICE, 0, 100), 0);
+        assert_eq!(royalty_share(u64::MAX, 10_000, 100), u64::MAX);
+    }
+
+    #[test]
+    fn finds_price_and_paid_royalties() {
+        let (mint, address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (keys, meta) = sale(&mint, &address);
+        let creators = [creator(address, 100)];
+
+        let (sale, paid) = sale_in(&mint, &creators, &status(), &keys, &meta).unwrap();
+        // The buyer's token account is rent, the creator's new account is not.
+        assert_eq!(sale.price_lamports, PRICE);
+        assert_eq!(sale.buyer, keys[0].to_string());
+        assert_eq!(paid, vec![Some(PRICE / 20)]);
+    }
+
+    #[test]
+    fn leaves_parties_to_the_sale_out_of_paid() {
+        let (mint, address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (keys, meta) = sale(&mint, &address);
+        // The seller is a creator too, and receives the price.
+        let creators = [creator(address, 50), creator(keys[1], 50)];
+
+        let (_, paid) = sale_in(&mint, &creators, &status(), &keys, &meta).unwrap();
+        assert_eq!(paid, vec![Some(PRICE / 20), None]);
+    }
+
+    #[test]
+    fn ignores_transfers_without_a_price() {
+        let (mint, address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (keys, mut meta) = sale(&mint, &address);
+        meta.post_balances = vec![10 * PRICE - 5000 - ATA_RENT, 1000, 0, ATA_RENT, ATA_RENT];
+        assert!(sale_in(&mint, &[], &status(), &keys, &meta).is_none());
+    }
+
+    #[test]
+    fn reads_terms_set_by_updates() {
+        let address = Pubkey::new_unique();
+        let update = |data: Option<Data>| {
+            MetadataInstruction::UpdateMetadataAccount(UpdateMetadataAccountArgs {
+                data,
+                update_authority: None,
+                primary_sale_happened: None,
+            })
+            .try_to_vec()
+            .unwrap()
+        };
+        let data = Data {
+            name: String::new(),
+            symbol: String::new(),
+            uri: String::new(),
+            seller_fee_basis_points: 750,
+            creators: Some(vec![creator(address, 100)]),
+        };
+
+        let (basis_points, creators) = terms_set_by(&update(Some(data))).unwrap();
+        assert_eq!((basis_points, creators[0].address), (750, address));
+        // Updates of the update authority alone keep the terms.
+        assert!(terms_set_by(&update(None)).is_none());
+        assert!(terms_set_by(&[0xff]).is_none());
+    }
+
+    #[test]
+    fn uses_terms_in_effect_at_the_sale() {
+        let terms = |slot, basis_points| RoyaltyTerms {
+            slot,
+            basis_points,
+            creators: Vec::new(),
+        };
+        let history = [terms(10, 500), terms(20, 1000)];
+        let current = terms(0, 250);
+
+        assert_eq!(terms_at(&history, &current, 15).basis_points, 500);
+        assert_eq!(terms_at(&history, &current, 20).basis_points, 1000);
+        // Older than the history reaches: the current terms.
+        assert_eq!(terms_at(&history, &current, 5).basis_points, 250);
+        assert_eq!(terms_at(&[], &current, 30).basis_points, 250);
+    }
+}
@@ -0,0 +1,363 @@
+use {
+    crate::{
+        das::{AssetPage, AssetProof, ASSET_PAGE_LIMIT},
+        metrics::RpcMetrics,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    solana_client::{
+        rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_filter::RpcFilterType,
+        rpc_request::RpcRequest,
+        rpc_response::{RpcConfirmedTransactionStatusWithSignature, RpcKeyedAccount},
+    },
+    solana_sdk::{account::Account, hash::Hash, program_pack::Pack, signature::Signature},
+    solana_transaction_status::{EncodedConfirmedTransaction, UiTransactionEncoding},
+    spl_token::{state::Account as TokenAccount, ID as TOKEN_PROGRAM_ID},
+    std::{collections::HashMap, fs::File, str::FromStr},
+};
+
+/// The subset of RPC calls the snapshot code depends on. Backends are shared
+/// between the snapshot worker threads, hence `Sync`.
+pub trait RpcBackend: Sync {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>>;
+
+    /// Runs several gPA queries against `program_id`, answering in order.
+    /// Each query fails on its own; the outer error is for failures of the
+    /// whole batch. Backends that can send them as one JSON-RPC batch
+    /// override this.
+    fn get_program_accounts_batch(
+        &self,
+        program_id: &Pubkey,
+        configs: Vec<RpcProgramAccountsConfig>,
+    ) -> Result<Vec<Result<Vec<(Pubkey, Account)>>>> {
+        Ok(configs
+            .into_iter()
+            .map(|config| self.get_program_accounts_with_config(program_id, config))
+            .collect())
+    }
+
+    /// How many queries are worth handing to `get_program_accounts_batch`
+    /// at once.
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    /// Traffic counters, for backends sending through a metered sender.
+    fn metrics(&self) -> Option<&RpcMetrics> {
+        None
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+
+    fn get_slot(&self) -> Result<u64>;
+
+    /// Hash of the cluster's genesis block, which tells clusters apart.
+    fn get_genesis_hash(&self) -> Result<Hash>;
+
+    /// Newest first, at most `limit` of them, starting before `before` if
+    /// given; see `provenance::all_signatures` to page through them.
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>>;
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction>;
+
+    /// DAS `getAssetProof` of a compressed asset. Only RPC providers running
+    /// a DAS indexer support it.
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof>;
+
+    /// Page `page` (from 1) of the DAS `getAssetsByGroup` listing of the
+    /// assets in `collection`, compressed ones included.
+    fn get_assets_by_collection(&self, collection: &Pubkey, page: usize) -> Result<AssetPage>;
+
+    /// Addresses of the token accounts holding the most of `mint`, largest
+    /// first. Answered from an index, which may lag behind.
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>>;
+}
+
+impl RpcBackend for RpcClient {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        Ok(RpcClient::get_program_accounts_with_config(
+            self, program_id, config,
+        )?)
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(RpcClient::get_multiple_accounts(self, pubkeys)?)
+    }
+
+    fn get_slot(&self) -> Result<u64> {
+        Ok(RpcClient::get_slot(self)?)
+    }
+
+    fn get_genesis_hash(&self) -> Result<Hash> {
+        Ok(RpcClient::get_genesis_hash(self)?)
+    }
+
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before,
+            limit: Some(limit),
+            ..GetConfirmedSignaturesForAddress2Config::default()
+        };
+        Ok(RpcClient::get_signatures_for_address_with_config(
+            self, address, config,
+        )?)
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction> {
+        Ok(RpcClient::get_transaction(
+            self,
+            signature,
+            UiTransactionEncoding::Base64,
+        )?)
+    }
+
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof> {
+        let request = RpcRequest::Custom {
+            method: "getAssetProof",
+        };
+        Ok(self.send(request, json!({ "id": asset_id.to_string() }))?)
+    }
+
+    fn get_assets_by_collection(&self, collection: &Pubkey, page: usize) -> Result<AssetPage> {
+        let request = RpcRequest::Custom {
+            method: "getAssetsByGroup",
+        };
+        let params = json!({
+            "groupKey": "collection",
+            "groupValue": collection.to_string(),
+            "page": page,
+            "limit": ASSET_PAGE_LIMIT,
+        });
+        Ok(self.send(request, params)?)
+    }
+
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+        RpcClient::get_token_largest_accounts(self, mint)?
+            .iter()
+            .map(|balance| Ok(Pubkey::from_str(&balance.address)?))
+            .collect()
+    }
+}
+
+pub const MAINNET_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+pub const DEVNET_GENESIS_HASH: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+pub const TESTNET_GENESIS_HASH: &str = "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY";
+
+/// The cluster a backend serves, told apart by genesis hash since the same
+/// collection can be minted on several.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cluster {
+    Mainnet,
+    Devnet,
+    Testnet,
+    /// Any other cluster, e.g. a local validator, by its genesis hash.
+    Other(String),
+}
+
+impl Cluster {
+    pub fn from_genesis_hash(hash: &Hash) -> Self {
+        match hash.to_string().as_str() {
+            MAINNET_GENESIS_HASH => Cluster::Mainnet,
+            DEVNET_GENESIS_HASH => Cluster::Devnet,
+            TESTNET_GENESIS_HASH => Cluster::Testnet,
+            other => Cluster::Other(other.to_string()),
+        }
+    }
+
+    /// What snapshots record the cluster as: its name, or unlike `Display`,
+    /// the whole genesis hash of any other cluster, so two local validators
+    /// stay apart.
+    pub fn id(&self) -> String {
+        match self {
+            Cluster::Other(hash) => hash.clone(),
+            cluster => cluster.to_string(),
+        }
+    }
+}
+
+/// Short enough for file names: other clusters go by the start of their
+/// genesis hash.
+impl std::fmt::Display for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Cluster::Mainnet => write!(f, "mainnet"),
+            Cluster::Devnet => write!(f, "devnet"),
+            Cluster::Testnet => write!(f, "testnet"),
+            Cluster::Other(hash) => write!(f, "{}", &hash[..hash.len().min(8)]),
+        }
+    }
+}
+
+pub fn detect_cluster(client: &dyn RpcBackend) -> Result<Cluster> {
+    Ok(Cluster::from_genesis_hash(&client.get_genesis_hash()?))
+}
+
+/// In-memory backend answering from a fixed set of accounts. Filters are
+/// applied the same way the validator does, so gPA queries built for a real
+/// endpoint work unchanged.
+#[derive(Debug, Default)]
+pub struct MockRpc {
+    pub accounts: HashMap<Pubkey, Account>,
+    pub slot: u64,
+    pub genesis_hash: Hash,
+    /// Transaction history by address, newest first. Addresses without any
+    /// look like they were never used.
+    pub signatures: HashMap<Pubkey, Vec<RpcConfirmedTransactionStatusWithSignature>>,
+    /// Token accounts by mint, so the per-mint gPAs of large mocks do not
+    /// scan every account. Kept by `add_account`.
+    token_accounts: HashMap<Pubkey, Vec<Pubkey>>,
+}
+
+impl MockRpc {
+    pub fn new() -> Self {
+        MockRpc::default()
+    }
+
+    /// Loads a JSON array of keyed accounts, the same shape as a
+    /// getProgramAccounts response or `solana account --output json`.
+    pub fn from_fixture(path: &str) -> Result<Self> {
+        let f = File::open(path)?;
+        let keyed: Vec<RpcKeyedAccount> = serde_json::from_reader(f)?;
+
+        let mut mock = MockRpc::new();
+        for RpcKeyedAccount { pubkey, account } in keyed {
+            let decoded = account
+                .decode()
+                .ok_or(anyhow!("Fixture account {} could not be decoded!", pubkey))?;
+            mock.add_account(Pubkey::from_str(&pubkey)?, decoded);
+        }
+
+        Ok(mock)
+    }
+
+    pub fn add_account(&mut self, pubkey: Pubkey, account: Account) {
+        if account.owner == TOKEN_PROGRAM_ID && account.data.len() == TokenAccount::LEN {
+            let by_mint = self.token_accounts.entry(Pubkey::new(&account.data[..32]));
+            let token_accounts = by_mint.or_default();
+            if !token_accounts.contains(&pubkey) {
+                token_accounts.push(pubkey);
+            }
+        }
+        self.accounts.insert(pubkey, account);
+    }
+
+    /// Accounts a gPA can match: those of the mint when the query is for
+    /// token accounts by mint, otherwise all of them. Filters still apply, so
+    /// index entries of since replaced accounts do no harm.
+    fn candidates<'a>(
+        &'a self,
+        program_id: &Pubkey,
+        filters: &[RpcFilterType],
+    ) -> Box<dyn Iterator<Item = (&'a Pubkey, &'a Account)> + 'a> {
+        let mint = filters.iter().find_map(|filter| match filter {
+            RpcFilterType::Memcmp(memcmp) if memcmp.offset == 0 => memcmp.bytes(),
+            _ => None,
+        });
+        match mint {
+            Some(mint) if *program_id == TOKEN_PROGRAM_ID && mint.len() == 32 => {
+                let pubkeys = self.token_accounts.get(&Pubkey::new(&mint)).into_iter().flatten();
+                Box::new(pubkeys.filter_map(move |pubkey| self.accounts.get_key_value(pubkey)))
+            }
+            _ => Box::new(self.accounts.iter()),
+        }
+    }
+}
+
+fn filter_matches(filter: &RpcFilterType, data: &[u8]) -> bool {
+    match filter {
+        RpcFilterType::DataSize(size) => data.len() as u64 == *size,
+        RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+    }
+}
+
+impl RpcBackend for MockRpc {
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let filters = config.filters.unwrap_or_default();
+
+        let mut accounts: Vec<(Pubkey, Account)> = self
+            .candidates(program_id, &filters)
+            .filter(|(_, account)| account.owner == *program_id)
+            .filter(|(_, account)| filters.iter().all(|f| filter_matches(f, &account.data)))
+            .map(|(pubkey, account)| (*pubkey, account.clone()))
+            .collect();
+        // HashMap order is random; keep results deterministic for tests.
+        accounts.sort_by_key(|(pubkey, _)| *pubkey);
+
+        Ok(accounts)
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(pubkeys
+            .iter()
+            .map(|pubkey| self.accounts.get(pubkey).cloned())
+            .collect())
+    }
+
+    fn get_slot(&self) -> Result<u64> {
+        Ok(self.slot)
+    }
+
+    fn get_genesis_hash(&self) -> Result<Hash> {
+        Ok(self.genesis_hash)
+    }
+
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let signatures = self.signatures.get(address).map(Vec::as_slice).unwrap_or_default();
+        let start = match before {
+            Some(before) => signatures
+                .iter()
+                .position(|status| status.signature == before.to_string())
+                .map_or(signatures.len(), |index| index + 1),
+            None => 0,
+        };
+        Ok(signatures.iter().skip(start).take(limit).cloned().collect())
+    }
+
+    fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransaction> {
+        Err(anyhow!("MockRpc has no transaction {}!", signature))
+    }
+
+    fn get_asset_proof(&self, asset_id: &Pubkey) -> Result<AssetProof> {
+        Err(anyhow!("MockRpc has no asset proof for {}!", asset_id))
+    }
+
+    fn get_assets_by_collection(&self, collection: &Pubkey, _page: usize) -> Result<AssetPage> {
+        Err(anyhow!("MockRpc has no assets of {}!", collection))
+    }
+
+    fn get_token_largest_accounts(&self, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+        Err(anyhow!("MockRpc has no token index for {}!", mint))
+    }
+}
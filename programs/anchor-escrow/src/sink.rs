@@ -0,0 +1,154 @@
+use {
+    crate::{
+        config::{Config, OutputConfig},
+        encryption::sealed_path,
+        output::write_snapshot,
+        snapshot::Holder,
+    },
+    anyhow::Result,
+    std::{
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+/// A destination for the holders of one collection snapshot. Sinks get the
+/// filtered, enriched holders one at a time, then are finished once.
+pub trait OutputSink: Send {
+    fn write_holder(&mut self, holder: &Holder) -> Result<()>;
+
+    /// Flushes the output and returns where it went, for the summary.
+    fn finish(self: Box<Self>) -> Result<Vec<String>>;
+}
+
+/// Writes a snapshot file with the format, compression, links and
+/// pseudonymization of `output`. JSON arrays and pseudonym mappings need
+/// every holder, so holders are kept until `finish`.
+pub struct FileSink {
+    pub path: PathBuf,
+    output: OutputConfig,
+    holders: Vec<Holder>,
+}
+
+impl FileSink {
+    pub fn new(path: PathBuf, output: &OutputConfig) -> Self {
+        FileSink {
+            path,
+            output: output.clone(),
+            holders: Vec::new(),
+        }
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write_holder(&mut self, holder: &Holder) -> Result<()> {
+        self.holders.push(holder.clone());
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<String>> {
+        write_snapshot(&self.path, &self.output, &self.holders)?;
+        Ok(vec![self.path.display().to_string()])
+    }
+}
+
+/// Feeds every holder to each of `sinks`.
+pub struct MultiSink {
+    pub sinks: Vec<Box<dyn OutputSink>>,
+}
+
+impl OutputSink for MultiSink {
+    fn write_holder(&mut self, holder: &Holder) -> Result<()> {
+        for sink in self.sinks.iter_mut() {
+            sink.write_holder(holder)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<String>> {
+        let mut destinations = Vec::new();
+        for sink in self.sinks {
+            destinations.extend(sink.finish()?);
+        }
+        Ok(destinations)
+    }
+}
+
+/// Where the snapshot file for `path` (uncompressed name) is written
+/// under `output`: with the compression extension, then `.age` if sealed.
+pub fn written_path(path: &Path, output: &OutputConfig) -> PathBuf {
+    let path = output.compress.apply_extension(path);
+    sealed_path(&path, &output.encrypt_to)
+}
+
+fn file_sink(path: PathBuf, output: &OutputConfig) -> FileSink {
+    FileSink::new(written_path(&path, output), output)
+}
+
+/// Every sink `config` enables for `collection`: the snapshot file at
+/// `path` (uncompressed name) in `output.format`, one more per entry of
+/// `output.extra_formats` with the extension swapped, each uploaded if
+/// `[upload]` is set, plus the history databases configured. `captured_at` is shared by the
+/// collections of a run, so they are recorded as one snapshot.
+#[cfg_attr(not(any(feature = "history", feature = "sqlite")), allow(unused_variables))]
+pub fn open_sinks(
+    config: &Config,
+    collection: &str,
+    path: &Path,
+    captured_at: SystemTime,
+) -> Result<MultiSink> {
+    let mut formats = vec![config.output.format];
+    for format in &config.output.extra_formats {
+        if !formats.contains(format) {
+            formats.push(*format);
+        }
+    }
+
+    let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+    for (index, format) in formats.into_iter().enumerate() {
+        let output = OutputConfig {
+            format,
+            ..config.output.clone()
+        };
+        let file = match index {
+            0 => file_sink(path.to_path_buf(), &output),
+            _ => file_sink(path.with_extension(format.extension()), &output),
+        };
+        #[cfg(feature = "upload")]
+        if config.upload.url.is_some() {
+            sinks.push(Box::new(crate::upload::UploadSink::new(
+                file,
+                &config.upload,
+                collection,
+            )));
+            continue;
+        }
+        sinks.push(Box::new(file));
+    }
+
+    #[cfg(feature = "history")]
+    if let Some(history) = config.history.connect(captured_at)? {
+        sinks.push(Box::new(history.for_collection(collection)));
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(sqlite) = config.sqlite.open(captured_at)? {
+        sinks.push(Box::new(sqlite.for_collection(collection)));
+    }
+
+    Ok(MultiSink { sinks })
+}
+
+/// Writes `holders` to every sink `config` enables, see `open_sinks`.
+pub fn write_to_sinks(
+    config: &Config,
+    collection: &str,
+    path: &Path,
+    captured_at: SystemTime,
+    holders: &[Holder],
+) -> Result<Vec<String>> {
+    let mut sink = open_sinks(config, collection, path, captured_at)?;
+    for holder in holders {
+        sink.write_holder(holder)?;
+    }
+    Box::new(sink).finish()
+}
@@ -0,0 +1,1444 @@
+use {
+    crate::{
+        alt_metadata::{resolve_alt_metadata, TOKEN_2022_PROGRAM_ID},
+        enrich::{get_multiple_accounts_chunked, OwnerKind},
+        metadata::parse_metadata,
+        metrics::RpcStats,
+        progress::Progress,
+        provenance::first_holder,
+        parse::{first_creator_is_verified, read_mint_supply, token_account_fields},
+        rpc::{detect_cluster, RpcBackend},
+        verify::quarantine_spoofs,
+        wallet::metadata_address,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    crossbeam::{channel, thread},
+    log::{error, warn},
+    mpl_token_metadata::{
+        state::{
+            Metadata, MAX_CREATOR_LIMIT, MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH,
+        },
+        ID as TOKEN_METADATA_PROGRAM_ID,
+    },
+    serde::{Deserialize, Serialize},
+    solana_account_decoder::{
+        parse_token::token_amount_to_ui_amount, UiAccountEncoding, UiDataSliceConfig,
+    },
+    solana_client::{
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+    },
+    solana_sdk::{account::Account, program_pack::Pack},
+    spl_token::{state::Mint, ID as TOKEN_PROGRAM_ID},
+    std::{
+        collections::HashSet,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+pub const CANDY_MACHINE_V2_PROGRAM_ID: &str = "cndy3Z4yapfJBmL3ShUp5exZKqR3z33thTzeNMm2gRZ";
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Holder {
+    pub owner_wallet: String,
+    pub associated_token_address: String,
+    pub mint_account: String,
+    pub metadata_account: String,
+    /// Whether the token account is frozen; only set when frozen accounts are
+    /// tagged, see `FrozenPolicy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen: Option<bool>,
+    /// Set by `enrich::enrich_owners`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_kind: Option<OwnerKind>,
+    /// Program owning the holder's account, the system program for wallets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_program: Option<String>,
+    /// Set by `enrich::enrich_sol_domains`; empty when the owner has none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sol_domain: Option<String>,
+    /// Unix timestamp of the transfer to the current token account, set by
+    /// `enrich::enrich_hold_duration`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holding_since: Option<i64>,
+    /// Whole days since `holding_since`, as of the snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holding_days: Option<u64>,
+    /// Signers of the owner when it is an SPL token multisig, space
+    /// separated; see `enrich::MultisigPolicy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig_signers: Option<String>,
+    /// The multisig owning the token account, when the holder is one of its
+    /// signers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<String>,
+    /// The item's on-chain name, set by `enrich::enrich_names`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Master edition account of the print, or of the item itself when it is
+    /// a master edition, set by `enrich::enrich_editions`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub master_edition: Option<String>,
+    /// Print number; unset on master editions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition_number: Option<u64>,
+    /// Balance in UI units, i.e. respecting the mint's decimals; only set in
+    /// snapshots of a `Source::Fungible`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<String>,
+    /// How the holder was found; only set with `ResolveMethod::LargestAccounts`,
+    /// which falls back to the gPA path per mint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<ResolveMethod>,
+    /// Whether the item is listed on a marketplace, see `enrich_listings`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listed: Option<bool>,
+    /// Delegate of a listed item's token account, i.e. the marketplace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listed_with: Option<String>,
+    /// Image URL from the item's off-chain JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// sha256 of the item's off-chain JSON, see `enrich::metadata_sha256`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_sha256: Option<String>,
+    /// Whether `metadata_sha256` differs from the baseline snapshot's; unset
+    /// for items the baseline has no hash for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_changed: Option<bool>,
+    /// Exchange whose custodial wallet holds the item, see
+    /// `config::CustodialPolicy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custodian: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custodian_country: Option<String>,
+    /// Cluster the snapshot was taken on, see `Cluster::id`, so snapshots
+    /// are only compared with snapshots of the same cluster.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    /// Explorer links, set on CSV export, see `output::Explorer`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_account_url: Option<String>,
+}
+
+/// A holder of placeholder addresses with `fields` set over them, as read
+/// from a JSON snapshot, for tests.
+#[cfg(test)]
+pub(crate) fn test_holder(fields: serde_json::Value) -> Holder {
+    let mut value = serde_json::json!({
+        "owner_wallet": "owner",
+        "associated_token_address": "token",
+        "mint_account": "mint",
+        "metadata_account": "metadata",
+    });
+    for (key, field) in fields.as_object().expect("fields are an object") {
+        value[key] = field.clone();
+    }
+    serde_json::from_value(value).unwrap()
+}
+
+/// The outcome of a snapshot, with enough detail for automation to decide
+/// whether it is complete.
+#[derive(Debug, Serialize)]
+pub struct SnapshotResult {
+    /// Left empty by `stream_nftholders`, which hands holders to its sink.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub holders: Vec<Holder>,
+    /// Items whose holders could not be looked up, so are missing.
+    pub errors: Vec<String>,
+    /// The items behind `errors`, to write to a dead-letter file for
+    /// `retry_failed`.
+    #[serde(skip)]
+    pub failed: Vec<FailedItem>,
+    /// Items whose mint is closed or has no supply left. Items that still
+    /// have supply but no holder was found for are in `errors` instead.
+    pub skipped_burned: usize,
+    /// Token accounts of the items holding nothing, left behind by sales
+    /// and transfers. Their owners can close them to recover the rent.
+    pub empty_accounts: Vec<EmptyAccount>,
+    /// Slots before and after the snapshot; holders reflect some slot in
+    /// between. `None` when the backend could not report a slot.
+    pub slot_range: Option<(u64, u64)>,
+    pub duration: Duration,
+    /// Traffic of this snapshot, for backends keeping `RpcMetrics`. Other
+    /// snapshots sharing the backend at the same time are included.
+    pub rpc_stats: Option<RpcStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmptyAccount {
+    pub token_account: String,
+    pub mint_account: String,
+    pub owner_wallet: String,
+    /// Rent returned by closing the account.
+    pub lamports: u64,
+}
+
+/// An item whose holders could not be looked up once the backend gave up
+/// retrying.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedItem {
+    pub metadata_account: String,
+    pub mint_account: String,
+    pub error: String,
+}
+
+impl FailedItem {
+    fn message(&self) -> String {
+        format!("Account {} has no token accounts: {}", self.metadata_account, self.error)
+    }
+}
+
+/// Candy machine v2 signs as this PDA, so it is the first creator on every
+/// item it mints rather than the candy machine itself.
+pub fn derive_cmv2_pda(candy_machine: &Pubkey) -> Pubkey {
+    let cmv2_program_id = Pubkey::from_str(CANDY_MACHINE_V2_PROGRAM_ID).unwrap();
+    let (pda, _) =
+        Pubkey::find_program_address(&[b"candy_machine", candy_machine.as_ref()], &cmv2_program_id);
+    pda
+}
+
+/// Settings of the snapshot pipeline. The source's metadata accounts are
+/// fetched whole, as a getProgramAccounts response cannot be read in parts,
+/// so they take memory in proportion to the collection. Past the source,
+/// the channel capacities bound the mints and holders in flight, and full
+/// channels slow the upstream stage down instead of piling up RPC
+/// responses; what the sink keeps is up to the sink.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    /// Threads resolving token accounts for mints.
+    pub workers: usize,
+    /// Capacity of both the mint and holder channels.
+    pub channel_capacity: usize,
+    /// Leave out items `verify::find_spoofs` flags.
+    pub quarantine_spoofs: bool,
+    pub mode: HolderMode,
+    /// Oldest transactions replayed per item in `HolderMode::FirstMinter`.
+    pub history_limit: usize,
+    /// How current holders are looked up.
+    pub resolve: ResolveMethod,
+    /// Also look for items whose metadata was trimmed by Metaplex's resize,
+    /// which the creator filter misses; see `get_resized_creator_accounts`.
+    pub resized_fallback: bool,
+    /// Counters to report progress to, e.g. for a dashboard.
+    #[serde(skip)]
+    pub progress: Option<Arc<Progress>>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            workers: 8,
+            channel_capacity: 256,
+            quarantine_spoofs: false,
+            mode: HolderMode::default(),
+            history_limit: 1000,
+            resolve: ResolveMethod::default(),
+            resized_fallback: false,
+            progress: None,
+        }
+    }
+}
+
+/// Which owner of each item a snapshot reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HolderMode {
+    /// Whoever holds the item now.
+    Current,
+    /// Whoever received the item first, for rewarding original minters.
+    /// Replays each item's history, so costs several calls per item.
+    FirstMinter,
+}
+
+impl Default for HolderMode {
+    fn default() -> Self {
+        HolderMode::Current
+    }
+}
+
+impl FromStr for HolderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "current" => Ok(HolderMode::Current),
+            "first_minter" => Ok(HolderMode::FirstMinter),
+            _ => Err(format!("Unknown holder mode {}, expected current or first_minter", s)),
+        }
+    }
+}
+
+/// How the token accounts holding each item are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolveMethod {
+    /// A gPA per mint over the token program, always current.
+    Gpa,
+    /// getTokenLargestAccounts, cheaper on most providers but answered from
+    /// an index that can lag. Mints it finds no holder for are looked up
+    /// with a gPA; empty token accounts are not all reported.
+    LargestAccounts,
+}
+
+impl Default for ResolveMethod {
+    fn default() -> Self {
+        ResolveMethod::Gpa
+    }
+}
+
+impl FromStr for ResolveMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "gpa" => Ok(ResolveMethod::Gpa),
+            "largest_accounts" => Ok(ResolveMethod::LargestAccounts),
+            _ => Err(format!(
+                "Unknown resolve method {}, expected gpa or largest_accounts",
+                s
+            )),
+        }
+    }
+}
+
+/// Where the metadata accounts of a collection are found.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Source {
+    /// Items listing `address` as creator at `position`, and if set, whose
+    /// update authority is `update_authority`.
+    Creator {
+        address: String,
+        #[serde(default)]
+        position: usize,
+        #[serde(default)]
+        update_authority: Option<String>,
+    },
+    /// Items minted by a candy machine v2, which is first creator through its
+    /// PDA, optionally narrowed to an update authority like `Creator`.
+    CandyMachine {
+        address: String,
+        #[serde(default)]
+        update_authority: Option<String>,
+    },
+    /// Items whose update authority is `address`.
+    UpdateAuthority { address: String },
+    /// Items given by mint, e.g. of a Token-2022 or inscription collection
+    /// without a creator to search by. Their metadata account is the
+    /// Metaplex one, else the one a metadata pointer or inscription leads
+    /// to, see `resolve_alt_metadata`; mints with neither are left out.
+    Mints { mints: Vec<String> },
+    /// Accounts of the fungible SPL token `mint` holding at least
+    /// `min_balance`, in UI units. Any positive balance counts when unset.
+    Fungible {
+        mint: String,
+        #[serde(default)]
+        min_balance: f64,
+    },
+}
+
+impl Source {
+    /// Builds a source from the command line style arguments, where `v2`
+    /// means `creator` is a candy machine id. Giving both a creator and an
+    /// update authority matches items that have both.
+    pub fn from_args(
+        update_authority: &Option<String>,
+        creator: &Option<String>,
+        position: usize,
+        v2: bool,
+    ) -> Result<Self> {
+        let source = match (update_authority, creator) {
+            (_, Some(creator)) if v2 => Source::CandyMachine {
+                address: creator.to_string(),
+                update_authority: update_authority.clone(),
+            },
+            (_, Some(creator)) => Source::Creator {
+                address: creator.to_string(),
+                position,
+                update_authority: update_authority.clone(),
+            },
+            (Some(update_authority), None) => Source::UpdateAuthority {
+                address: update_authority.to_string(),
+            },
+            (None, None) => {
+                return Err(anyhow!(
+                    "Please specify a creator, an update authority, or both."
+                ))
+            }
+        };
+
+        Ok(source)
+    }
+}
+
+/// Metadata accounts of `source`, with `resized_fallback` as in
+/// `PipelineConfig`.
+pub fn get_source_accounts(
+    client: &dyn RpcBackend,
+    source: &Source,
+    resized_fallback: bool,
+) -> Result<Vec<(Pubkey, Account)>> {
+    let creator_accounts = |creator: &str, position: usize, update_authority: Option<&str>| {
+        match resized_fallback {
+            true => get_resized_creator_accounts(client, creator, position, update_authority),
+            false => get_creator_accounts(client, creator, position, update_authority),
+        }
+    };
+    match source {
+        Source::Creator {
+            address,
+            position,
+            update_authority,
+        } => creator_accounts(address, *position, update_authority.as_deref()),
+        Source::CandyMachine {
+            address,
+            update_authority,
+        } => {
+            let creator = derive_cmv2_pda(&Pubkey::from_str(address)?).to_string();
+            creator_accounts(&creator, 0, update_authority.as_deref())
+        }
+        Source::UpdateAuthority { address } => get_update_authority_accounts(client, address),
+        Source::Fungible { mint, .. } => Err(anyhow!(
+            "Fungible token {} has no metadata accounts per item!",
+            mint
+        )),
+        Source::Mints { .. } => Err(anyhow!(
+            "Items given by mint are not found by their metadata accounts!"
+        )),
+    }
+}
+
+/// The `(metadata, mint)` pairs of `mints`, with metadata accounts as in
+/// `Source::Mints`. Mints without either are logged and left out.
+pub fn mint_items(client: &dyn RpcBackend, mints: &[String]) -> Result<Vec<(Pubkey, Pubkey)>> {
+    let mints: Vec<Pubkey> = mints
+        .iter()
+        .map(|mint| Pubkey::from_str(mint))
+        .collect::<Result<_, _>>()?;
+    let mut addresses: Vec<Pubkey> = mints.iter().map(metadata_address).collect();
+    addresses.extend_from_slice(&mints);
+    let mut accounts = get_multiple_accounts_chunked(client, &addresses)?;
+    let mint_accounts = accounts.split_off(mints.len());
+
+    let mut items = Vec::with_capacity(mints.len());
+    let mut without_metadata = Vec::new();
+    for ((mint, metadata), mint_data) in mints.iter().zip(accounts).zip(mint_accounts) {
+        match (metadata, mint_data) {
+            (_, None) => error!("Mint {} does not exist", mint),
+            (Some(data), _) if parse_metadata(&data).is_ok() => {
+                items.push((metadata_address(mint), *mint))
+            }
+            (_, Some(data)) => without_metadata.push((*mint, data)),
+        }
+    }
+
+    let lookups: Vec<(Pubkey, &[u8])> = without_metadata
+        .iter()
+        .map(|(mint, data)| (*mint, data.as_slice()))
+        .collect();
+    for ((mint, _), metadata) in lookups.iter().zip(resolve_alt_metadata(client, &lookups)?) {
+        match metadata {
+            Some(metadata) => items.push((metadata.address, *mint)),
+            None => error!("Mint {} has no metadata", mint),
+        }
+    }
+    Ok(items)
+}
+
+/// Retries are left to the backend; wrap it in a `RetryingRpc` to apply a
+/// `RetryPolicy` to every call.
+pub fn get_nftholders(
+    client: &dyn RpcBackend,
+    update_authority: &Option<String>,
+    creator: &Option<String>,
+    position: usize,
+    v2: bool,
+) -> Result<SnapshotResult> {
+    let source = Source::from_args(update_authority, creator, position, v2)?;
+
+    let mut nft_holders: Vec<Holder> = Vec::new();
+    let result = stream_nftholders(client, &source, &PipelineConfig::default(), |holder| {
+        nft_holders.push(holder);
+        Ok(())
+    })?;
+
+    Ok(SnapshotResult {
+        holders: nft_holders,
+        ..result
+    })
+}
+
+/// Decodes metadata accounts, keeping those whose first creator is verified.
+/// Consuming the response lets each account be freed once decoded.
+pub fn verified_metadata(
+    accounts: Vec<(Pubkey, Account)>,
+) -> impl Iterator<Item = (Pubkey, Metadata)> {
+    accounts
+        .into_iter()
+        .filter_map(|(metadata_pubkey, account)| {
+            match parse_metadata(&account.data) {
+                Ok(metadata) => Some((metadata_pubkey, metadata)),
+                Err(_) => {
+                    error!("Account {} has no metadata", metadata_pubkey);
+                    None
+                }
+            }
+        })
+        // Check that first creator is verified
+        .filter(|(_, metadata)| first_creator_is_verified(&metadata.data.creators))
+}
+
+/// Runs the snapshot as metadata producer -> holder resolver workers -> sink.
+/// The producer holds the source's metadata accounts, see `PipelineConfig`.
+/// `sink` is called on the current thread for every holder found; returning
+/// an error from it stops the pipeline. Items whose lookup fails are
+/// reported in the result rather than failing the snapshot. Fungible
+/// sources are read in one call instead, see `stream_token_holders`.
+pub fn stream_nftholders<F>(
+    client: &dyn RpcBackend,
+    source: &Source,
+    config: &PipelineConfig,
+    sink: F,
+) -> Result<SnapshotResult>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let sink = stamped(client, sink)?;
+    measure(client, || match source {
+        Source::Fungible { mint, min_balance } => {
+            stream_token_holders(client, mint, *min_balance, config, sink)
+        }
+        Source::Mints { mints } => stream_mints(client, mints, config, sink),
+        _ => stream_items(client, source, config, sink),
+    })
+}
+
+/// `stream_nftholders` over metadata accounts already fetched, e.g. by
+/// `get_source_accounts`, for callers that need the accounts themselves too.
+pub fn stream_metadata_accounts<F>(
+    client: &dyn RpcBackend,
+    accounts: Vec<(Pubkey, Account)>,
+    config: &PipelineConfig,
+    sink: F,
+) -> Result<SnapshotResult>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let sink = stamped(client, sink)?;
+    measure(client, || stream_accounts(client, accounts, config, sink))
+}
+
+/// Looks the holders of `failed` items up again, e.g. from the dead-letter
+/// file of an earlier snapshot, with the pipeline of `stream_nftholders`.
+/// Items failing again are in the result's `failed`.
+pub fn retry_failed<F>(
+    client: &dyn RpcBackend,
+    failed: &[FailedItem],
+    config: &PipelineConfig,
+    sink: F,
+) -> Result<SnapshotResult>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let items = failed
+        .iter()
+        .map(|item| {
+            let metadata = Pubkey::from_str(&item.metadata_account)?;
+            Ok((metadata, Pubkey::from_str(&item.mint_account)?))
+        })
+        .collect::<Result<Vec<(Pubkey, Pubkey)>>>()?;
+    if let Some(progress) = config.progress.as_deref() {
+        progress.items_total.fetch_add(items.len(), Ordering::Relaxed);
+    }
+
+    let sink = stamped(client, sink)?;
+    measure(client, || {
+        resolve_items(
+            client,
+            config,
+            move |mint_tx| {
+                for item in items {
+                    if mint_tx.send(item).is_err() {
+                        break;
+                    }
+                }
+            },
+            sink,
+        )
+    })
+}
+
+/// `sink` with every holder stamped with the cluster `client` serves.
+fn stamped<F>(client: &dyn RpcBackend, mut sink: F) -> Result<impl FnMut(Holder) -> Result<()>>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let cluster = detect_cluster(client)?.id();
+    Ok(move |mut holder: Holder| {
+        holder.cluster = Some(cluster.clone());
+        sink(holder)
+    })
+}
+
+/// Runs `stream` with the slots and RPC traffic around it recorded.
+fn measure(
+    client: &dyn RpcBackend,
+    stream: impl FnOnce() -> Result<StreamOutcome>,
+) -> Result<SnapshotResult> {
+    let started = Instant::now();
+    let start_slot = client.get_slot().ok();
+    let start_stats = client.metrics().map(|metrics| metrics.totals());
+
+    let (failed, skipped_burned, empty_accounts) = stream()?;
+
+    let end_slot = client.get_slot().ok();
+    let rpc_stats = match (client.metrics(), start_stats) {
+        (Some(metrics), Some(start)) => Some(metrics.totals().since(&start)),
+        _ => None,
+    };
+    Ok(SnapshotResult {
+        holders: Vec::new(),
+        errors: failed.iter().map(FailedItem::message).collect(),
+        failed,
+        skipped_burned,
+        empty_accounts,
+        slot_range: start_slot.zip(end_slot),
+        duration: started.elapsed(),
+        rpc_stats,
+    })
+}
+
+/// Failed items, burned items and empty token accounts of a snapshot, the
+/// parts of `SnapshotResult` its sources report.
+type StreamOutcome = (Vec<FailedItem>, usize, Vec<EmptyAccount>);
+
+/// The pipeline behind `stream_nftholders` for collections of NFTs.
+fn stream_items<F>(
+    client: &dyn RpcBackend,
+    source: &Source,
+    config: &PipelineConfig,
+    sink: F,
+) -> Result<StreamOutcome>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let accounts = get_source_accounts(client, source, config.resized_fallback)?;
+    stream_accounts(client, accounts, config, sink)
+}
+
+fn stream_accounts<F>(
+    client: &dyn RpcBackend,
+    accounts: Vec<(Pubkey, Account)>,
+    config: &PipelineConfig,
+    sink: F,
+) -> Result<StreamOutcome>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let accounts_len = accounts.len();
+    let progress = config.progress.as_deref();
+    if let Some(progress) = progress {
+        progress.items_total.fetch_add(accounts_len, Ordering::Relaxed);
+    }
+
+    resolve_items(
+        client,
+        config,
+        move |mint_tx| {
+            // Quarantining compares items against each other, so it needs the
+            // whole collection decoded before anything is sent.
+            let items: Box<dyn Iterator<Item = (Pubkey, Metadata)>> = if config.quarantine_spoofs {
+                Box::new(quarantine_spoofs(verified_metadata(accounts).collect()).into_iter())
+            } else {
+                Box::new(verified_metadata(accounts))
+            };
+
+            let mut queued = 0;
+            for (metadata_pubkey, metadata) in items {
+                // The workers are gone once the sink fails.
+                if mint_tx.send((metadata_pubkey, metadata.mint)).is_err() {
+                    break;
+                }
+                queued += 1;
+            }
+            if let Some(progress) = progress {
+                // Unverified and quarantined items are never queued.
+                progress
+                    .items_total
+                    .fetch_sub(accounts_len - queued, Ordering::Relaxed);
+            }
+        },
+        sink,
+    )
+}
+
+/// The pipeline behind `stream_nftholders` for items given by mint.
+fn stream_mints<F>(
+    client: &dyn RpcBackend,
+    mints: &[String],
+    config: &PipelineConfig,
+    sink: F,
+) -> Result<StreamOutcome>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let items = mint_items(client, mints)?;
+    let progress = config.progress.as_deref();
+    if let Some(progress) = progress {
+        progress
+            .items_total
+            .fetch_add(items.len(), Ordering::Relaxed);
+    }
+
+    resolve_items(
+        client,
+        config,
+        move |mint_tx| {
+            for item in items {
+                // The workers are gone once the sink fails.
+                if mint_tx.send(item).is_err() {
+                    break;
+                }
+            }
+        },
+        sink,
+    )
+}
+
+/// The resolver workers and sink of the pipeline, fed `(metadata, mint)`
+/// pairs by `produce` on a thread of its own.
+fn resolve_items<P, F>(
+    client: &dyn RpcBackend,
+    config: &PipelineConfig,
+    produce: P,
+    mut sink: F,
+) -> Result<StreamOutcome>
+where
+    P: FnOnce(channel::Sender<(Pubkey, Pubkey)>) + Send,
+    F: FnMut(Holder) -> Result<()>,
+{
+    let progress = config.progress.as_deref();
+    let (mint_tx, mint_rx) = channel::bounded::<(Pubkey, Pubkey)>(config.channel_capacity);
+    let (holder_tx, holder_rx) = channel::bounded::<Holder>(config.channel_capacity);
+    let failed = Mutex::new(Vec::new());
+    let burned = AtomicUsize::new(0);
+    let empty_accounts = Mutex::new(Vec::new());
+
+    thread::scope(|scope| -> Result<()> {
+        scope.spawn(move |_| produce(mint_tx));
+
+        // History lookups are not batched.
+        let batch_size = match config.mode {
+            HolderMode::Current => client.batch_size().max(1),
+            HolderMode::FirstMinter => 1,
+        };
+        for _ in 0..config.workers.max(1) {
+            let mint_rx = mint_rx.clone();
+            let holder_tx = holder_tx.clone();
+            let (failed, burned, empty_accounts) = (&failed, &burned, &empty_accounts);
+            scope.spawn(move |_| {
+                // Take whatever else is queued, up to a batch, without
+                // waiting for it to fill up.
+                while let Ok(first) = mint_rx.recv() {
+                    let mut items = vec![first];
+                    items.extend(mint_rx.try_iter().take(batch_size - 1));
+
+                    let holders = match (config.mode, config.resolve) {
+                        (HolderMode::Current, ResolveMethod::Gpa) => {
+                            resolve_holders_batch(client, &items)
+                        }
+                        (HolderMode::Current, ResolveMethod::LargestAccounts) => {
+                            resolve_holders_largest(client, &items)
+                        }
+                        (HolderMode::FirstMinter, _) => {
+                            resolve_first_minters(client, &items, config.history_limit)
+                                .map(|resolved| resolved.into_iter().map(Ok).collect())
+                        }
+                    };
+                    if let Some(progress) = progress {
+                        progress.items_done.fetch_add(items.len(), Ordering::Relaxed);
+                    }
+                    let holders = match holders {
+                        Ok(holders) => holders,
+                        Err(err) => {
+                            record_failed(failed, progress, &items, &err.to_string());
+                            continue;
+                        }
+                    };
+                    // Calls of a batch fail on their own.
+                    let mut resolved = Vec::with_capacity(items.len());
+                    for (item, item_holders) in items.iter().zip(holders) {
+                        match item_holders {
+                            Ok(item_holders) => resolved.push((*item, item_holders)),
+                            Err(err) => record_failed(failed, progress, &[*item], &err.to_string()),
+                        }
+                    }
+
+                    // An item without a holder is only burned if its mint
+                    // says so; otherwise the lookup missed its holder.
+                    let unheld: Vec<(Pubkey, Pubkey)> = resolved
+                        .iter()
+                        .filter(|(_, (item_holders, _))| item_holders.is_empty())
+                        .map(|(item, _)| *item)
+                        .collect();
+                    if !unheld.is_empty() {
+                        let mints: Vec<Pubkey> = unheld.iter().map(|(_, mint)| *mint).collect();
+                        match burned_mints(client, &mints) {
+                            Ok(burned_items) => {
+                                let missed: Vec<(Pubkey, Pubkey)> = unheld
+                                    .into_iter()
+                                    .zip(burned_items)
+                                    .filter(|(_, is_burned)| !is_burned)
+                                    .map(|(item, _)| item)
+                                    .collect();
+                                burned.fetch_add(mints.len() - missed.len(), Ordering::Relaxed);
+                                let error = "Mint has supply left but no holder was found";
+                                record_failed(failed, progress, &missed, error);
+                            }
+                            Err(err) => record_failed(failed, progress, &unheld, &err.to_string()),
+                        }
+                    }
+
+                    for (_, (item_holders, empty)) in resolved {
+                        empty_accounts.lock().unwrap().extend(empty);
+                        for holder in item_holders {
+                            if holder_tx.send(holder).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+        // Only the workers hold senders now, so the loop below ends with them.
+        drop(holder_tx);
+        drop(mint_rx);
+
+        for holder in holder_rx {
+            sink(holder)?;
+            if let Some(progress) = progress {
+                progress.holders.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    })
+    .map_err(|_| anyhow!("Snapshot pipeline thread panicked!"))??;
+
+    Ok((
+        failed.into_inner().unwrap(),
+        burned.into_inner(),
+        empty_accounts.into_inner().unwrap(),
+    ))
+}
+
+/// Records `items` as failed with `error`, for `retry_failed`.
+fn record_failed(
+    failed: &Mutex<Vec<FailedItem>>,
+    progress: Option<&Progress>,
+    items: &[(Pubkey, Pubkey)],
+    error: &str,
+) {
+    if let Some(progress) = progress {
+        progress.errors.fetch_add(items.len(), Ordering::Relaxed);
+    }
+    let mut failed = failed.lock().unwrap();
+    for (metadata_pubkey, mint) in items {
+        let item = FailedItem {
+            metadata_account: metadata_pubkey.to_string(),
+            mint_account: mint.to_string(),
+            error: error.to_string(),
+        };
+        error!("{}", item.message());
+        failed.push(item);
+    }
+}
+
+/// Whether each of `mints` is burned, i.e. closed or without supply left.
+fn burned_mints(client: &dyn RpcBackend, mints: &[Pubkey]) -> Result<Vec<bool>> {
+    Ok(client
+        .get_multiple_accounts(mints)?
+        .into_iter()
+        .map(|account| match account {
+            Some(account) => read_mint_supply(&account.data) == Some(0),
+            None => true,
+        })
+        .collect())
+}
+
+/// Holders of the fungible token `mint` with at least `min_balance`, with
+/// `amount` in UI units. Every token account comes from one call, so there
+/// is nothing for the pipeline's workers to do.
+fn stream_token_holders<F>(
+    client: &dyn RpcBackend,
+    mint: &str,
+    min_balance: f64,
+    config: &PipelineConfig,
+    mut sink: F,
+) -> Result<StreamOutcome>
+where
+    F: FnMut(Holder) -> Result<()>,
+{
+    let mint_pubkey = Pubkey::from_str(mint)?;
+    let mint_account = client
+        .get_multiple_accounts(&[mint_pubkey])?
+        .pop()
+        .flatten()
+        .ok_or(anyhow!("Mint {} does not exist!", mint))?;
+    let decimals = Mint::unpack(&mint_account.data)?.decimals;
+    let min_amount = (min_balance.max(0.0) * 10f64.powi(decimals as i32)).ceil() as u64;
+    let metadata_account = metadata_address(&mint_pubkey).to_string();
+
+    let token_accounts = get_holder_token_accounts(client, mint.to_string())?;
+    let progress = config.progress.as_deref();
+    if let Some(progress) = progress {
+        progress.items_total.fetch_add(token_accounts.len(), Ordering::Relaxed);
+    }
+
+    let mut empty = Vec::new();
+    for (token_account, account) in token_accounts {
+        if let Some(progress) = progress {
+            progress.items_done.fetch_add(1, Ordering::Relaxed);
+        }
+        let fields = match token_account_fields(&mint_pubkey, decimals, &account.data) {
+            Ok(fields) => fields,
+            Err(err) => {
+                error!("Account {} has no token balance: {}", token_account, err);
+                continue;
+            }
+        };
+        let (amount, owner_wallet) = (fields.amount, fields.owner.to_string());
+
+        if amount == 0 {
+            empty.push(EmptyAccount {
+                token_account: token_account.to_string(),
+                mint_account: mint.to_string(),
+                owner_wallet,
+                lamports: account.lamports,
+            });
+            continue;
+        }
+        if amount < min_amount.max(1) {
+            continue;
+        }
+
+        sink(Holder {
+            owner_wallet,
+            associated_token_address: token_account.to_string(),
+            mint_account: mint.to_string(),
+            metadata_account: metadata_account.clone(),
+            frozen: Some(fields.frozen),
+            amount: Some(token_amount_to_ui_amount(amount, decimals).ui_amount_string),
+            ..Default::default()
+        })?;
+        if let Some(progress) = progress {
+            progress.holders.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    Ok((Vec::new(), 0, empty))
+}
+
+/// Current holders of several `(metadata, mint)` pairs at once, batching
+/// the token account lookups where the backend supports it; undecodable
+/// token accounts are logged and skipped. The empty token
+/// accounts of each mint come along with its holders, or the error of its
+/// lookup. Mints without a single SPL Token account are looked up under
+/// Token-2022 too, so closed items cost both lookups.
+pub(crate) fn resolve_holders_batch(
+    client: &dyn RpcBackend,
+    items: &[(Pubkey, Pubkey)],
+) -> Result<Vec<Result<(Vec<Holder>, Vec<EmptyAccount>)>>> {
+    let configs = items
+        .iter()
+        .map(|(_, mint)| holder_token_accounts_config(mint.to_string()))
+        .collect();
+    let mut token_accounts = client.get_program_accounts_batch(&TOKEN_PROGRAM_ID, configs)?;
+
+    let missing: Vec<usize> = (0..items.len())
+        .filter(|index| matches!(&token_accounts[*index], Ok(accounts) if accounts.is_empty()))
+        .collect();
+    if !missing.is_empty() {
+        let configs = missing
+            .iter()
+            .map(|index| token_2022_accounts_config(items[*index].1.to_string()))
+            .collect();
+        let token_2022 = Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?;
+        let found = client.get_program_accounts_batch(&token_2022, configs)?;
+        for (index, accounts) in missing.into_iter().zip(found) {
+            token_accounts[index] = accounts;
+        }
+    }
+
+    Ok(items
+        .iter()
+        .zip(token_accounts)
+        .map(|((metadata_pubkey, mint), accounts)| {
+            Ok(parse_holders(metadata_pubkey, mint, accounts?))
+        })
+        .collect())
+}
+
+/// The largest token accounts of `mint`, fetched.
+fn get_largest_token_accounts(
+    client: &dyn RpcBackend,
+    mint: &Pubkey,
+) -> Result<Vec<(Pubkey, Account)>> {
+    let largest = client.get_token_largest_accounts(mint)?;
+    let accounts = client.get_multiple_accounts(&largest)?;
+    Ok(largest
+        .into_iter()
+        .zip(accounts)
+        .filter_map(|(pubkey, account)| Some((pubkey, account?)))
+        .collect())
+}
+
+/// `resolve_holders_batch` through getTokenLargestAccounts. Mints for which
+/// it fails or finds no holder, e.g. as its index has not caught up with a
+/// transfer yet, are looked up again the gPA way; burned items therefore
+/// cost both lookups. Every holder records the method that found it.
+fn resolve_holders_largest(
+    client: &dyn RpcBackend,
+    items: &[(Pubkey, Pubkey)],
+) -> Result<Vec<Result<(Vec<Holder>, Vec<EmptyAccount>)>>> {
+    let mut resolved = Vec::with_capacity(items.len());
+    let mut fallback = Vec::new();
+    for (index, (metadata_pubkey, mint)) in items.iter().enumerate() {
+        let token_accounts = match get_largest_token_accounts(client, mint) {
+            Ok(token_accounts) => token_accounts,
+            Err(err) => {
+                warn!("Mint {} has no largest accounts: {}", mint, err);
+                Vec::new()
+            }
+        };
+        let (mut holders, empty) = parse_holders(metadata_pubkey, mint, token_accounts);
+        if holders.is_empty() {
+            fallback.push(index);
+        }
+        for holder in holders.iter_mut() {
+            holder.resolved_by = Some(ResolveMethod::LargestAccounts);
+        }
+        resolved.push(Ok((holders, empty)));
+    }
+
+    if !fallback.is_empty() {
+        let retry: Vec<(Pubkey, Pubkey)> = fallback.iter().map(|index| items[*index]).collect();
+        for (index, found) in fallback
+            .into_iter()
+            .zip(resolve_holders_batch(client, &retry)?)
+        {
+            resolved[index] = found.map(|(mut holders, empty)| {
+                for holder in holders.iter_mut() {
+                    holder.resolved_by = Some(ResolveMethod::Gpa);
+                }
+                (holders, empty)
+            });
+        }
+    }
+    Ok(resolved)
+}
+
+/// The first holder of each `(metadata, mint)` pair, with the owner falling
+/// back to the fee payer of the mint transaction when the first token
+/// account is not an associated token account. Fails for items whose first
+/// holder is not within their oldest `history_limit` transactions.
+fn resolve_first_minters(
+    client: &dyn RpcBackend,
+    items: &[(Pubkey, Pubkey)],
+    history_limit: usize,
+) -> Result<Vec<(Vec<Holder>, Vec<EmptyAccount>)>> {
+    items
+        .iter()
+        .map(|(metadata_pubkey, mint)| {
+            let first = first_holder(client, mint, history_limit)?.ok_or(anyhow!(
+                "Mint {} has no first holder in its first {} transactions!",
+                mint,
+                history_limit
+            ))?;
+            let holder = Holder {
+                owner_wallet: first.owner.unwrap_or(first.fee_payer),
+                associated_token_address: first.token_account,
+                mint_account: mint.to_string(),
+                metadata_account: metadata_pubkey.to_string(),
+                ..Default::default()
+            };
+            Ok((vec![holder], Vec::new()))
+        })
+        .collect()
+}
+
+fn parse_holders(
+    metadata_pubkey: &Pubkey,
+    mint: &Pubkey,
+    token_accounts: Vec<(Pubkey, Account)>,
+) -> (Vec<Holder>, Vec<EmptyAccount>) {
+    let mut holders = Vec::new();
+    let mut empty = Vec::new();
+    for (associated_token_address, account) in token_accounts {
+        let fields = match token_account_fields(mint, 0, &account.data) {
+            Ok(fields) => fields,
+            Err(err) => {
+                error!("Account {} has no token balance: {}", associated_token_address, err);
+                continue;
+            }
+        };
+
+        if fields.amount == 0 {
+            empty.push(EmptyAccount {
+                token_account: associated_token_address.to_string(),
+                mint_account: mint.to_string(),
+                owner_wallet: fields.owner.to_string(),
+                lamports: account.lamports,
+            });
+            continue;
+        }
+
+        // Only include current holder of the NFT.
+        if fields.amount == 1 {
+            let associated_token_address = associated_token_address.to_string();
+            let holder = Holder {
+                owner_wallet: fields.owner.to_string(),
+                associated_token_address,
+                mint_account: mint.to_string(),
+                metadata_account: metadata_pubkey.to_string(),
+                frozen: Some(fields.frozen),
+                ..Default::default()
+            };
+            holders.push(holder);
+        }
+    }
+
+    (holders, empty)
+}
+
+/// Errors callers may want to tell apart from RPC failures.
+#[derive(Debug)]
+pub enum SnapshotError {
+    InvalidCreatorPosition {
+        position: usize,
+    },
+    /// No metadata lists `creator` at `position`; `found_at` are the
+    /// positions it does appear at, if any.
+    CreatorNotFound {
+        creator: String,
+        position: usize,
+        found_at: Vec<usize>,
+    },
+    /// The output was written, but without `missing_items` items that could
+    /// not be looked up and the collections in `failed_collections`.
+    PartialResults {
+        missing_items: usize,
+        failed_collections: usize,
+    },
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SnapshotError::InvalidCreatorPosition { position } => write!(
+                f,
+                "Creator position {} is out of range, metadata has at most {} creators",
+                position, MAX_CREATOR_LIMIT
+            ),
+            SnapshotError::CreatorNotFound {
+                creator,
+                position,
+                found_at,
+            } if found_at.is_empty() => write!(
+                f,
+                "No metadata lists {} as a creator at any position (asked for {})",
+                creator, position
+            ),
+            SnapshotError::CreatorNotFound {
+                creator,
+                position,
+                found_at,
+            } => write!(
+                f,
+                "No metadata lists {} as creator {}, but some list it at position {:?}",
+                creator, position, found_at
+            ),
+            SnapshotError::PartialResults {
+                missing_items,
+                failed_collections,
+            } => write!(
+                f,
+                "Snapshot is incomplete: {} items could not be looked up, {} collections failed",
+                missing_items, failed_collections
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+fn creator_filter(creator: &str, position: usize) -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp {
+        offset: 1 + // key
+        32 + // update auth
+        32 + // mint
+        4 + // name string length
+        MAX_NAME_LENGTH + // name
+        4 + // uri string length
+        MAX_URI_LENGTH + // uri*
+        4 + // symbol string length
+        MAX_SYMBOL_LENGTH + // symbol
+        2 + // seller fee basis points
+        1 + // whether or not there is a creators vec
+        4 + // creators
+        position * // index for each creator
+        (
+            32 + // address
+            1 + // verified
+            1 // share
+        ),
+        bytes: MemcmpEncodedBytes::Base58(creator.to_string()),
+        encoding: None,
+    })
+}
+
+fn update_authority_filter(update_authority: &str) -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp {
+        offset: 1, // key
+        bytes: MemcmpEncodedBytes::Base58(update_authority.to_string()),
+        encoding: None,
+    })
+}
+
+fn metadata_account_config(data_slice: Option<UiDataSliceConfig>) -> RpcAccountInfoConfig {
+    RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        data_slice,
+        // The backend's, i.e. `rpc.commitment`.
+        commitment: None,
+    }
+}
+
+/// Whether any metadata lists `creator` at `position`. The probe asks for no
+/// account data, but is a scan all the same, so it is only run to explain an
+/// empty result.
+fn creator_listed_at(client: &dyn RpcBackend, creator: &str, position: usize) -> Result<bool> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![creator_filter(creator, position)]),
+        account_config: metadata_account_config(Some(UiDataSliceConfig {
+            offset: 0,
+            length: 0,
+        })),
+        with_context: None,
+    };
+
+    let accounts = client.get_program_accounts_with_config(&TOKEN_METADATA_PROGRAM_ID, config)?;
+    Ok(!accounts.is_empty())
+}
+
+/// Metadata listing `creator` at `position`. When none does, probes where the
+/// creator is listed instead.
+pub fn get_cm_creator_accounts(
+    client: &dyn RpcBackend,
+    creator: &String,
+    position: usize,
+) -> Result<Vec<(Pubkey, Account)>> {
+    get_creator_accounts(client, creator, position, None)
+}
+
+/// Like `get_cm_creator_accounts`, additionally requiring `update_authority`
+/// when set. Both memcmp filters go into one getProgramAccounts call, which
+/// keeps out copies of a collection that reuse its creators.
+pub fn get_creator_accounts(
+    client: &dyn RpcBackend,
+    creator: &str,
+    position: usize,
+    update_authority: Option<&str>,
+) -> Result<Vec<(Pubkey, Account)>> {
+    if position >= MAX_CREATOR_LIMIT {
+        return Err(SnapshotError::InvalidCreatorPosition { position }.into());
+    }
+
+    let mut filters = vec![creator_filter(creator, position)];
+    if let Some(update_authority) = update_authority {
+        filters.push(update_authority_filter(update_authority));
+    }
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: metadata_account_config(None),
+        with_context: None,
+    };
+
+    let accounts = client.get_program_accounts_with_config(&TOKEN_METADATA_PROGRAM_ID, config)?;
+    if !accounts.is_empty() {
+        return Ok(accounts);
+    }
+
+    // With an update authority, the creator may be listed at `position` by
+    // other collections only; that is an empty collection, not a wrong
+    // position.
+    if update_authority.is_some() && creator_listed_at(client, creator, position)? {
+        return Ok(accounts);
+    }
+    let mut found_at = Vec::new();
+    for other in (0..MAX_CREATOR_LIMIT).filter(|other| *other != position) {
+        if creator_listed_at(client, creator, other)? {
+            found_at.push(other);
+        }
+    }
+    Err(SnapshotError::CreatorNotFound {
+        creator: creator.to_string(),
+        position,
+        found_at,
+    }
+    .into())
+}
+
+/// Items of `update_authority`, counted without fetching any account data.
+fn count_update_authority_accounts(
+    client: &dyn RpcBackend,
+    update_authority: &str,
+) -> Result<usize> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![update_authority_filter(update_authority)]),
+        account_config: metadata_account_config(Some(UiDataSliceConfig {
+            offset: 0,
+            length: 0,
+        })),
+        with_context: None,
+    };
+
+    Ok(client.get_program_accounts_with_config(&TOKEN_METADATA_PROGRAM_ID, config)?.len())
+}
+
+/// `get_creator_accounts`, plus the items whose metadata was trimmed by
+/// Metaplex's resize. Trimmed strings lose their padding, which moves the
+/// creators before the fixed offset the creator filter matches at. The
+/// update authority stays put, so when it has more items than the filter
+/// found, they are all fetched and matched on creator after decoding.
+/// Without `update_authority`, those of the items found are used, so at least
+/// one item must be untrimmed.
+pub fn get_resized_creator_accounts(
+    client: &dyn RpcBackend,
+    creator: &str,
+    position: usize,
+    update_authority: Option<&str>,
+) -> Result<Vec<(Pubkey, Account)>> {
+    let mut accounts = match get_creator_accounts(client, creator, position, update_authority) {
+        Ok(accounts) => accounts,
+        // Every item may have been trimmed.
+        Err(err)
+            if update_authority.is_some()
+                && matches!(
+                    err.downcast_ref::<SnapshotError>(),
+                    Some(SnapshotError::CreatorNotFound { .. })
+                ) =>
+        {
+            Vec::new()
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut authorities: Vec<String> = match update_authority {
+        Some(update_authority) => vec![update_authority.to_string()],
+        None => accounts
+            .iter()
+            .filter_map(|(_, account)| account.data.get(1..33))
+            .map(|key| Pubkey::new(key).to_string())
+            .collect(),
+    };
+    authorities.sort_unstable();
+    authorities.dedup();
+    let mut total = 0;
+    for authority in &authorities {
+        total += count_update_authority_accounts(client, authority)?;
+    }
+    if total <= accounts.len() {
+        return Ok(accounts);
+    }
+
+    let creator = Pubkey::from_str(creator)?;
+    let found: HashSet<Pubkey> = accounts.iter().map(|(pubkey, _)| *pubkey).collect();
+    let mut resized = 0;
+    for authority in &authorities {
+        for (pubkey, account) in get_update_authority_accounts(client, authority)? {
+            if found.contains(&pubkey) {
+                continue;
+            }
+            let listed = parse_metadata(&account.data)
+                .ok()
+                .and_then(|metadata| metadata.data.creators)
+                .and_then(|creators| creators.get(position).map(|c| c.address == creator))
+                .unwrap_or(false);
+            if listed {
+                accounts.push((pubkey, account));
+                resized += 1;
+            }
+        }
+    }
+    if resized > 0 {
+        warn!("Found {} items of {} with resized metadata", resized, creator);
+    }
+    Ok(accounts)
+}
+
+pub fn get_update_authority_accounts(
+    client: &dyn RpcBackend,
+    update_authority: &String,
+) -> Result<Vec<(Pubkey, Account)>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![update_authority_filter(update_authority)]),
+        account_config: metadata_account_config(None),
+        with_context: None,
+    };
+
+    let accounts = client.get_program_accounts_with_config(&TOKEN_METADATA_PROGRAM_ID, config)?;
+
+    Ok(accounts)
+}
+
+fn holder_token_accounts_config(mint_account: String) -> RpcProgramAccountsConfig {
+    let filter1 = RpcFilterType::Memcmp(Memcmp {
+        offset: 0,
+        bytes: MemcmpEncodedBytes::Base58(mint_account),
+        encoding: None,
+    });
+    let filter2 = RpcFilterType::DataSize(165);
+    let account_config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        data_slice: None,
+        // The backend's, i.e. `rpc.commitment`.
+        commitment: None,
+    };
+
+    RpcProgramAccountsConfig {
+        filters: Some(vec![filter1, filter2]),
+        account_config,
+        with_context: None,
+    }
+}
+
+/// `holder_token_accounts_config` for Token-2022, whose token accounts grow
+/// past the SPL Token size with their extensions.
+fn token_2022_accounts_config(mint_account: String) -> RpcProgramAccountsConfig {
+    let mut config = holder_token_accounts_config(mint_account);
+    if let Some(filters) = config.filters.as_mut() {
+        filters.retain(|filter| !matches!(filter, RpcFilterType::DataSize(_)));
+    }
+    config
+}
+
+fn get_holder_token_accounts(
+    client: &dyn RpcBackend,
+    mint_account: String,
+) -> Result<Vec<(Pubkey, Account)>> {
+    let config = holder_token_accounts_config(mint_account);
+    let holders = client.get_program_accounts_with_config(&TOKEN_PROGRAM_ID, config)?;
+
+    Ok(holders)
+}
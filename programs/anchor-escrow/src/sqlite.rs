@@ -0,0 +1,188 @@
+use {
+    crate::{
+        output::{check_page_limit, stored_holder, HolderPage},
+        sink::OutputSink,
+        snapshot::Holder,
+    },
+    anyhow::{anyhow, Result},
+    log::info,
+    rusqlite::{params, Connection, OptionalExtension},
+    serde::Deserialize,
+    std::time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS holders (
+    snapshot_id TEXT NOT NULL,
+    collection TEXT NOT NULL,
+    wallet TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    token_account TEXT NOT NULL,
+    captured_at INTEGER NOT NULL,
+    cluster TEXT,
+    PRIMARY KEY (snapshot_id, collection, mint)
+);
+CREATE INDEX IF NOT EXISTS holders_wallet_idx ON holders (wallet);
+";
+
+const UPSERT: &str = "
+INSERT INTO holders (snapshot_id, collection, wallet, mint, token_account, captured_at, cluster)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+ON CONFLICT (snapshot_id, collection, mint)
+DO UPDATE SET wallet = excluded.wallet, token_account = excluded.token_account,
+    cluster = excluded.cluster
+";
+
+/// Databases created before the cluster was recorded lack its column.
+const ADD_CLUSTER: &str = "ALTER TABLE holders ADD COLUMN cluster TEXT";
+
+const OTHER_CLUSTER: &str = "
+SELECT cluster FROM holders WHERE collection = ?1 AND cluster <> ?2 LIMIT 1
+";
+
+const PAGE: &str = "
+SELECT wallet, mint, token_account, cluster FROM holders
+WHERE snapshot_id = ?1 AND collection = ?2 AND mint > ?3
+ORDER BY mint LIMIT ?4
+";
+
+/// Collections are snapshotted concurrently, so writers may have to queue
+/// for the database lock.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A local alternative to the PostgreSQL history, e.g. for one-off runs.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SqliteConfig {
+    /// Database file; nothing is written when unset.
+    pub path: Option<String>,
+}
+
+impl SqliteConfig {
+    /// Opens the database and creates the schema if needed. Returns `None`
+    /// when no path is configured.
+    pub fn open(&self, captured_at: SystemTime) -> Result<Option<SqliteSink>> {
+        let connection = match self.connect()? {
+            Some(connection) => connection,
+            None => return Ok(None),
+        };
+
+        let captured_at = captured_at.duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Some(SqliteSink {
+            connection,
+            snapshot_id: captured_at.to_string(),
+            captured_at: captured_at as i64,
+            collection: String::new(),
+            holders: Vec::new(),
+        }))
+    }
+
+    fn connect(&self) -> Result<Option<Connection>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.execute_batch(SCHEMA)?;
+        if connection.prepare("SELECT cluster FROM holders LIMIT 0").is_err() {
+            connection.execute_batch(ADD_CLUSTER)?;
+        }
+        Ok(Some(connection))
+    }
+
+    /// Up to `limit` holders of `collection` in snapshot `snapshot_id`, by
+    /// mint, after the mint `cursor`, see `output::holders_page`.
+    pub fn holders_page(
+        &self,
+        snapshot_id: &str,
+        collection: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<HolderPage> {
+        check_page_limit(limit)?;
+        let connection = self
+            .connect()?
+            .ok_or(anyhow!("No sqlite database is configured!"))?;
+        let mut statement = connection.prepare(PAGE)?;
+        let after = cursor.unwrap_or_default();
+        let rows = statement.query_map(
+            params![snapshot_id, collection, after, (limit + 1) as i64],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let holders = rows
+            .map(|row| {
+                let (wallet, mint, token_account, cluster) = row?;
+                stored_holder(wallet, token_account, mint, cluster)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(HolderPage::by_mint(holders, limit))
+    }
+}
+
+/// Writes one collection's holders in a single transaction on `finish`.
+pub struct SqliteSink {
+    connection: Connection,
+    pub snapshot_id: String,
+    captured_at: i64,
+    collection: String,
+    holders: Vec<Holder>,
+}
+
+impl SqliteSink {
+    pub fn for_collection(mut self, collection: &str) -> Self {
+        self.collection = collection.to_string();
+        self
+    }
+}
+
+impl OutputSink for SqliteSink {
+    fn write_holder(&mut self, holder: &Holder) -> Result<()> {
+        self.holders.push(holder.clone());
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Vec<String>> {
+        let cluster = self.holders.iter().find_map(|holder| holder.cluster.clone());
+        let transaction = self.connection.transaction()?;
+        if let Some(cluster) = &cluster {
+            let recorded: Option<String> = transaction
+                .query_row(OTHER_CLUSTER, params![self.collection, cluster], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+            if let Some(recorded) = recorded {
+                return Err(anyhow!(
+                    "{} is recorded from cluster {}, not {}!",
+                    self.collection,
+                    recorded,
+                    cluster
+                ));
+            }
+        }
+        {
+            let mut statement = transaction.prepare(UPSERT)?;
+            for holder in &self.holders {
+                statement.execute(params![
+                    self.snapshot_id,
+                    self.collection,
+                    holder.owner_wallet,
+                    holder.mint_account,
+                    holder.associated_token_address,
+                    self.captured_at,
+                    cluster,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+
+        info!(
+            "Recorded {} holders of {} as snapshot {}",
+            self.holders.len(),
+            self.collection,
+            self.snapshot_id
+        );
+        Ok(vec![format!("sqlite snapshot {}", self.snapshot_id)])
+    }
+}
@@ -0,0 +1,163 @@
+use {
+    anchor_lang::prelude::Pubkey,
+    solana_sdk::{
+        instruction::InstructionError,
+        transaction::{Transaction, TransactionError},
+    },
+    spl_token::error::TokenError,
+};
+
+/// Custom error codes of programs built with Anchor 0.20 that come from the
+/// framework rather than the program's own error enum.
+const ANCHOR_ERRORS: &[(u32, &str)] = &[
+    (100, "8 byte instruction identifier not provided"),
+    (101, "Fallback functions are not supported"),
+    (102, "The program could not deserialize the given instruction"),
+    (103, "The program could not serialize the given instruction"),
+    (2000, "A mut constraint was violated"),
+    (2001, "A has_one constraint was violated"),
+    (2002, "A signer constraint was violated"),
+    (2003, "A raw constraint was violated"),
+    (2004, "An owner constraint was violated"),
+    (2005, "A rent exempt constraint was violated"),
+    (2006, "A seeds constraint was violated"),
+    (2007, "An executable constraint was violated"),
+    (2008, "A state constraint was violated"),
+    (2009, "An associated constraint was violated"),
+    (2010, "An associated init constraint was violated"),
+    (2011, "A close constraint was violated"),
+    (2012, "An address constraint was violated"),
+    (2013, "Expected zero account discriminant"),
+    (2014, "A token mint constraint was violated"),
+    (2015, "A token owner constraint was violated"),
+    (2016, "A mint mint authority constraint was violated"),
+    (2017, "A mint freeze authority constraint was violated"),
+    (2018, "A mint decimals constraint was violated"),
+    (2019, "A space constraint was violated"),
+    (3000, "The account discriminator did not match what was expected"),
+    (3001, "Failed to deserialize the account"),
+    (3002, "Failed to serialize the account"),
+    (3003, "Not enough account keys given to the instruction"),
+    (3004, "The given account is not mutable"),
+    (3005, "The given account is not owned by the executing program"),
+    (3006, "Program ID was not as expected"),
+    (3007, "Program account is not executable"),
+    (3008, "The given account did not sign"),
+    (3009, "The given account is not owned by the system program"),
+    (3010, "The program expected this account to be already initialized"),
+];
+
+/// Where the program's own error enum starts.
+const ANCHOR_USER_ERROR_START: u32 = 6000;
+
+const TOKEN_ERRORS: &[TokenError] = &[
+    TokenError::NotRentExempt,
+    TokenError::InsufficientFunds,
+    TokenError::InvalidMint,
+    TokenError::MintMismatch,
+    TokenError::OwnerMismatch,
+    TokenError::FixedSupply,
+    TokenError::AlreadyInUse,
+    TokenError::InvalidNumberOfProvidedSigners,
+    TokenError::InvalidNumberOfRequiredSigners,
+    TokenError::UninitializedState,
+    TokenError::NativeNotSupported,
+    TokenError::NonNativeHasBalance,
+    TokenError::InvalidInstruction,
+    TokenError::InvalidState,
+    TokenError::Overflow,
+    TokenError::AuthorityTypeNotSupported,
+    TokenError::MintCannotFreeze,
+    TokenError::AccountFrozen,
+    TokenError::MintDecimalsMismatch,
+];
+
+/// A custom error code in words, where the program it came from is known.
+fn describe_custom(program_id: &Pubkey, code: u32) -> String {
+    if *program_id == spl_token::ID {
+        if let Some(err) = TOKEN_ERRORS.iter().find(|err| (*err).clone() as u32 == code) {
+            return format!("SPL token error: {}", err);
+        }
+    } else if *program_id == crate::ID {
+        if let Some((_, message)) = ANCHOR_ERRORS.iter().find(|(c, _)| *c == code) {
+            return format!("Anchor error {}: {}", code, message);
+        }
+        if code >= ANCHOR_USER_ERROR_START {
+            return format!(
+                "program error {} (variant {} of its error enum)",
+                code,
+                code - ANCHOR_USER_ERROR_START
+            );
+        }
+    }
+    format!("custom program error {:#x}", code)
+}
+
+/// Why `tx` failed, with the failing instruction and its program pointed out
+/// and Anchor and SPL token error codes named, instead of a bare
+/// "custom program error: 0x1".
+pub fn describe_error(tx: &Transaction, err: &TransactionError) -> String {
+    let (index, instruction_err) = match err {
+        TransactionError::InstructionError(index, instruction_err) => (*index, instruction_err),
+        _ => return err.to_string(),
+    };
+    let program_id = tx
+        .message
+        .instructions
+        .get(index as usize)
+        .map(|ix| *ix.program_id(&tx.message.account_keys));
+    let reason = match (instruction_err, program_id) {
+        (InstructionError::Custom(code), Some(program_id)) => describe_custom(&program_id, *code),
+        _ => instruction_err.to_string(),
+    };
+    match program_id {
+        Some(program_id) => format!("Instruction {} ({}) failed: {}", index, program_id, reason),
+        None => format!("Instruction {} failed: {}", index, reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{instruction::Instruction, message::Message},
+    };
+
+    /// Why the instruction of `program_id`, second in its transaction,
+    /// failed with `code`.
+    fn describe_custom_error(program_id: Pubkey, code: u32) -> String {
+        let payer = Pubkey::new_unique();
+        let instructions = [
+            Instruction::new_with_bytes(Pubkey::new_unique(), &[], Vec::new()),
+            Instruction::new_with_bytes(program_id, &[], Vec::new()),
+        ];
+        let tx = Transaction::new_unsigned(Message::new(&instructions, Some(&payer)));
+        let err = TransactionError::InstructionError(1, InstructionError::Custom(code));
+        describe_error(&tx, &err)
+    }
+
+    #[test]
+    fn names_anchor_and_token_errors() {
+        assert_eq!(
+            describe_custom_error(crate::ID, 2003),
+            format!(
+                "Instruction 1 ({}) failed: Anchor error 2003: A raw constraint was violated",
+                crate::ID
+            )
+        );
+        assert!(describe_custom_error(crate::ID, 6002).ends_with("(variant 2 of its error enum)"));
+        let token = describe_custom_error(spl_token::ID, 1);
+        assert!(token.ends_with("SPL token error: Insufficient funds"));
+        let other = describe_custom_error(Pubkey::new_unique(), 1);
+        assert!(other.ends_with("failed: custom program error 0x1"));
+    }
+
+    #[test]
+    fn leaves_other_errors_as_they_are() {
+        let tx = Transaction::default();
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        assert_eq!(describe_error(&tx, &err), "Instruction 0 failed: custom program error: 0x1");
+        let err = TransactionError::AccountNotFound;
+        assert_eq!(describe_error(&tx, &err), err.to_string());
+    }
+}
@@ -0,0 +1,151 @@
+use {
+    crate::{
+        sink::{FileSink, OutputSink},
+        snapshot::Holder,
+    },
+    anyhow::{anyhow, Result},
+    log::info,
+    s3::{bucket::Bucket, creds::Credentials, region::Region},
+    serde::Deserialize,
+    std::{
+        fs,
+        path::Path,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// Where snapshot outputs are uploaded once written. GCS buckets are reached
+/// through its S3 interoperability API, so both take HMAC style keys.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// `s3://bucket/prefix` or `gs://bucket/prefix`; nothing is uploaded when
+    /// unset.
+    pub url: Option<String>,
+    pub region: Option<String>,
+    /// Custom endpoint for S3 compatible stores, e.g. MinIO or R2.
+    pub endpoint: Option<String>,
+    /// Falls back to `AWS_ACCESS_KEY_ID`, then the AWS credentials file.
+    pub access_key: Option<String>,
+    /// Falls back to `AWS_SECRET_ACCESS_KEY`, then the AWS credentials file.
+    pub secret_key: Option<String>,
+}
+
+struct Destination<'a> {
+    scheme: &'a str,
+    bucket: &'a str,
+    prefix: &'a str,
+}
+
+fn parse_url(url: &str) -> Result<Destination> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or(anyhow!("Upload url {} has no scheme!", url))?;
+    if scheme != "s3" && scheme != "gs" {
+        return Err(anyhow!("Unknown upload scheme {}, expected s3 or gs", scheme));
+    }
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(anyhow!("Upload url {} has no bucket!", url));
+    }
+
+    Ok(Destination {
+        scheme,
+        bucket,
+        prefix: prefix.trim_matches('/'),
+    })
+}
+
+impl UploadConfig {
+    fn bucket(&self, destination: &Destination) -> Result<Bucket> {
+        let region_name = self.region.clone().unwrap_or_else(|| "us-east-1".to_string());
+        let region = match (&self.endpoint, destination.scheme) {
+            (Some(endpoint), _) => Region::Custom {
+                region: region_name,
+                endpoint: endpoint.clone(),
+            },
+            (None, "gs") => Region::Custom {
+                region: region_name,
+                endpoint: GCS_ENDPOINT.to_string(),
+            },
+            (None, _) => region_name.parse()?,
+        };
+        let credentials = Credentials::new(
+            self.access_key.as_deref(),
+            self.secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )?;
+
+        let mut bucket = Bucket::new(destination.bucket, region, credentials)?;
+        if self.endpoint.is_some() || destination.scheme == "gs" {
+            bucket.set_path_style();
+        }
+        Ok(bucket)
+    }
+
+    /// Uploads `file` to `{prefix}/{collection}/{timestamp}.{ext}`, keeping
+    /// every extension of the file so compressed outputs stay recognisable.
+    /// Returns the object url, or `None` when no upload url is configured.
+    pub fn upload(&self, collection: &str, file: &Path) -> Result<Option<String>> {
+        let url = match &self.url {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let destination = parse_url(url)?;
+
+        let file_name = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(anyhow!("Invalid output path {}!", file.display()))?;
+        let extension = file_name.split_once('.').map(|(_, ext)| ext).unwrap_or("json");
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let key = match destination.prefix {
+            "" => format!("{}/{}.{}", collection, timestamp, extension),
+            prefix => format!("{}/{}/{}.{}", prefix, collection, timestamp, extension),
+        };
+
+        let contents = fs::read(file)?;
+        let (_, code) = self.bucket(&destination)?.put_object(&key, &contents)?;
+        if code != 200 {
+            return Err(anyhow!("Upload of {} failed with status {}!", key, code));
+        }
+
+        let object = format!("{}://{}/{}", destination.scheme, destination.bucket, key);
+        info!("Uploaded {} to {}", file.display(), object);
+        Ok(Some(object))
+    }
+}
+
+/// Uploads the file of `file` once it is written.
+pub struct UploadSink {
+    file: FileSink,
+    upload: UploadConfig,
+    collection: String,
+}
+
+impl UploadSink {
+    pub fn new(file: FileSink, upload: &UploadConfig, collection: &str) -> Self {
+        UploadSink {
+            file,
+            upload: upload.clone(),
+            collection: collection.to_string(),
+        }
+    }
+}
+
+impl OutputSink for UploadSink {
+    fn write_holder(&mut self, holder: &Holder) -> Result<()> {
+        self.file.write_holder(holder)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<String>> {
+        let path = self.file.path.clone();
+        let mut destinations = Box::new(self.file).finish()?;
+        destinations.extend(self.upload.upload(&self.collection, &path)?);
+        Ok(destinations)
+    }
+}
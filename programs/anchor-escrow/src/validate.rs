@@ -0,0 +1,175 @@
+use {
+    crate::{anonymize::is_pseudonym, snapshot::Holder},
+    anchor_lang::prelude::Pubkey,
+    serde::Serialize,
+    std::{
+        collections::{HashMap, HashSet},
+        str::FromStr,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// The same owner is listed for the same token account more than once.
+    DuplicateHolder,
+    /// Several owners hold one of a one-of-one mint, which no single slot
+    /// can show; usually snapshots concatenated from different times.
+    MultipleHolders,
+    /// A field meant to hold an address does not parse as one, or as a
+    /// pseudonym in a pseudonymized snapshot.
+    MalformedAddress,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    /// Row of the holder in the file, from 0.
+    pub row: usize,
+    pub mint_account: String,
+    pub detail: String,
+}
+
+fn address_fields(holder: &Holder) -> Vec<(&'static str, &str)> {
+    let mut fields = vec![
+        ("owner_wallet", holder.owner_wallet.as_str()),
+        ("associated_token_address", holder.associated_token_address.as_str()),
+        ("mint_account", holder.mint_account.as_str()),
+        ("metadata_account", holder.metadata_account.as_str()),
+    ];
+    if let Some(multisig) = &holder.multisig {
+        fields.push(("multisig", multisig));
+    }
+    if let Some(master_edition) = &holder.master_edition {
+        fields.push(("master_edition", master_edition));
+    }
+    fields
+}
+
+/// Checks a snapshot for duplicate holders, one-of-one mints with several
+/// owners and malformed addresses, in file order. Fungible token holders
+/// and holders expanded from a multisig may share a mint, so are not
+/// checked for several owners. A snapshot whose owners are all pseudonyms
+/// is taken as pseudonymized, and its fields are checked to be pseudonyms
+/// instead of addresses.
+pub fn validate_holders(holders: &[Holder]) -> Vec<Problem> {
+    let pseudonymized =
+        !holders.is_empty() && holders.iter().all(|holder| is_pseudonym(&holder.owner_wallet));
+    let mut problems = Vec::new();
+    let mut seen: HashSet<(&str, &str)> = HashSet::new();
+    let mut owners: HashMap<&str, &str> = HashMap::new();
+    for (row, holder) in holders.iter().enumerate() {
+        let mint = holder.mint_account.as_str();
+        let owner = holder.owner_wallet.as_str();
+        let problem = |kind, detail: String| Problem {
+            kind,
+            row,
+            mint_account: mint.to_string(),
+            detail,
+        };
+
+        for (field, value) in address_fields(holder) {
+            let detail = match pseudonymized {
+                true if !is_pseudonym(value) => format!("{} {:?} is not a pseudonym", field, value),
+                false if Pubkey::from_str(value).is_err() => {
+                    format!("{} {:?} is not an address", field, value)
+                }
+                _ => continue,
+            };
+            problems.push(problem(ProblemKind::MalformedAddress, detail));
+        }
+
+        // Fungible holders may have several token accounts of a mint, and
+        // multisig signers share one.
+        if !seen.insert((owner, holder.associated_token_address.as_str())) {
+            let detail = format!(
+                "{} is listed more than once for {}",
+                owner, holder.associated_token_address
+            );
+            problems.push(problem(ProblemKind::DuplicateHolder, detail));
+            continue;
+        }
+        if holder.amount.is_some() || holder.multisig.is_some() {
+            continue;
+        }
+        match owners.get(mint) {
+            Some(first) if *first != owner => {
+                let detail = format!("held by {} and {}", first, owner);
+                problems.push(problem(ProblemKind::MultipleHolders, detail));
+            }
+            Some(_) => {}
+            None => {
+                owners.insert(mint, owner);
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::snapshot::test_holder, serde_json::json};
+
+    fn holder(owner: &str, token_account: &str, mint: &str) -> Holder {
+        test_holder(json!({
+            "owner_wallet": owner,
+            "associated_token_address": token_account,
+            "mint_account": mint,
+            "metadata_account": mint,
+        }))
+    }
+
+    fn kinds(problems: &[Problem]) -> Vec<(ProblemKind, usize)> {
+        problems.iter().map(|problem| (problem.kind, problem.row)).collect()
+    }
+
+    #[test]
+    fn flags_repeated_token_accounts() {
+        let (owner, account, mint) = (key(), key(), key());
+        let holders = [holder(&owner, &account, &mint), holder(&owner, &account, &mint)];
+        let problems = validate_holders(&holders);
+        assert_eq!(kinds(&problems), [(ProblemKind::DuplicateHolder, 1)]);
+    }
+
+    #[test]
+    fn several_token_accounts_of_one_owner_are_fine() {
+        let (owner, mint) = (key(), key());
+        let mut holders = vec![holder(&owner, &key(), &mint), holder(&owner, &key(), &mint)];
+        for holder in holders.iter_mut() {
+            holder.amount = Some("5".to_string());
+        }
+        assert!(validate_holders(&holders).is_empty());
+    }
+
+    #[test]
+    fn flags_one_of_ones_with_several_owners() {
+        let mint = key();
+        let holders = [holder(&key(), &key(), &mint), holder(&key(), &key(), &mint)];
+        let problems = validate_holders(&holders);
+        assert_eq!(kinds(&problems), [(ProblemKind::MultipleHolders, 1)]);
+    }
+
+    #[test]
+    fn flags_malformed_addresses() {
+        let holders = [holder("not-a-wallet", &key(), &key())];
+        let problems = validate_holders(&holders);
+        assert_eq!(kinds(&problems), [(ProblemKind::MalformedAddress, 0)]);
+        assert!(problems[0].detail.starts_with("owner_wallet"));
+    }
+
+    #[test]
+    fn pseudonymized_snapshots_are_checked_for_pseudonyms() {
+        let pseudonym = |value: &str| crate::anonymize::pseudonym("salt", value);
+        let holders = [
+            holder(&pseudonym("a"), &pseudonym("b"), &pseudonym("c")),
+            holder(&pseudonym("d"), &key(), &pseudonym("e")),
+        ];
+        let problems = validate_holders(&holders);
+        assert_eq!(kinds(&problems), [(ProblemKind::MalformedAddress, 1)]);
+        assert!(problems[0].detail.contains("is not a pseudonym"));
+    }
+
+    fn key() -> String {
+        Pubkey::new_unique().to_string()
+    }
+}
@@ -0,0 +1,214 @@
+use {
+    anchor_lang::prelude::Pubkey,
+    log::warn,
+    mpl_token_metadata::state::Metadata,
+    serde::Serialize,
+    std::collections::{HashMap, HashSet},
+};
+
+/// An item that does not look like the rest of its collection.
+#[derive(Debug, Serialize)]
+pub struct SpoofFlag {
+    pub metadata_account: String,
+    pub mint_account: String,
+    pub name: String,
+    pub reasons: Vec<String>,
+}
+
+fn trimmed(s: &str) -> &str {
+    s.trim_matches(char::from(0)).trim()
+}
+
+/// The URI up to its last path segment, e.g. the arweave host or the IPFS
+/// folder the collection was uploaded to.
+fn uri_pattern(uri: &str) -> &str {
+    let uri = trimmed(uri);
+    match uri.rfind('/') {
+        Some(index) => &uri[..index],
+        None => uri,
+    }
+}
+
+/// The value more than half of `values` share, if any.
+fn majority<'a, I>(values: I) -> Option<&'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut total = 0;
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+        total += 1;
+    }
+    counts
+        .into_iter()
+        .find(|(_, count)| *count * 2 > total)
+        .map(|(value, _)| value)
+}
+
+/// Flags items whose symbol or URI pattern differs from the majority of the
+/// collection, whose collection field is unverified, or which lack the
+/// verified collection most items share.
+pub fn find_spoofs(items: &[(Pubkey, Metadata)]) -> Vec<SpoofFlag> {
+    let symbol = majority(items.iter().map(|(_, m)| trimmed(&m.data.symbol)));
+    let uri = majority(items.iter().map(|(_, m)| uri_pattern(&m.data.uri)));
+    let collection_keys: Vec<String> = items
+        .iter()
+        .filter_map(|(_, m)| m.collection.as_ref())
+        .filter(|collection| collection.verified)
+        .map(|collection| collection.key.to_string())
+        .collect();
+    let collection = majority(collection_keys.iter().map(|key| key.as_str()))
+        .filter(|_| collection_keys.len() * 2 > items.len());
+
+    let mut flags = Vec::new();
+    for (metadata_pubkey, metadata) in items {
+        let mut reasons = Vec::new();
+        if let Some(symbol) = symbol {
+            if trimmed(&metadata.data.symbol) != symbol {
+                reasons.push(format!(
+                    "symbol {} differs from {}",
+                    trimmed(&metadata.data.symbol),
+                    symbol
+                ));
+            }
+        }
+        if let Some(uri) = uri {
+            if uri_pattern(&metadata.data.uri) != uri {
+                reasons.push(format!("uri {} is not under {}", trimmed(&metadata.data.uri), uri));
+            }
+        }
+        match (&metadata.collection, collection) {
+            (Some(item), _) if !item.verified => {
+                reasons.push(format!("collection {} is unverified", item.key))
+            }
+            (Some(item), Some(collection)) if item.key.to_string() != collection => {
+                reasons.push(format!("collection {} differs from {}", item.key, collection))
+            }
+            (None, Some(collection)) => reasons.push(format!("not in collection {}", collection)),
+            _ => {}
+        }
+
+        if !reasons.is_empty() {
+            flags.push(SpoofFlag {
+                metadata_account: metadata_pubkey.to_string(),
+                mint_account: metadata.mint.to_string(),
+                name: trimmed(&metadata.data.name).to_string(),
+                reasons,
+            });
+        }
+    }
+
+    flags
+}
+
+/// Drops the items `find_spoofs` flags, logging each.
+pub fn quarantine_spoofs(items: Vec<(Pubkey, Metadata)>) -> Vec<(Pubkey, Metadata)> {
+    let flags = find_spoofs(&items);
+    for flag in &flags {
+        warn!(
+            "Account {} looks spoofed: {}",
+            flag.metadata_account,
+            flag.reasons.join(", ")
+        );
+    }
+
+    let flagged: HashSet<String> = flags.into_iter().map(|f| f.metadata_account).collect();
+    items
+        .into_iter()
+        .filter(|(metadata_pubkey, _)| !flagged.contains(&metadata_pubkey.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        mpl_token_metadata::state::{Collection, Data, Key},
+    };
+
+    fn item(symbol: &str, uri: &str, collection: Option<Collection>) -> (Pubkey, Metadata) {
+        let metadata = Metadata {
+            key: Key::MetadataV1,
+            update_authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            data: Data {
+                name: "Item".to_string(),
+                symbol: symbol.to_string(),
+                uri: uri.to_string(),
+                seller_fee_basis_points: 0,
+                creators: None,
+            },
+            primary_sale_happened: false,
+            is_mutable: true,
+            edition_nonce: None,
+            token_standard: None,
+            collection,
+            uses: None,
+        };
+        (Pubkey::new_unique(), metadata)
+    }
+
+    fn flagged(items: &[(Pubkey, Metadata)]) -> Vec<usize> {
+        let flags = find_spoofs(items);
+        (0..items.len())
+            .filter(|index| {
+                let account = items[*index].0.to_string();
+                flags.iter().any(|flag| flag.metadata_account == account)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flags_items_unlike_the_majority() {
+        let collection = Collection {
+            verified: true,
+            key: Pubkey::new_unique(),
+        };
+        let unverified = Collection {
+            verified: false,
+            ..collection.clone()
+        };
+        let items = [
+            item("REAL", "https://arweave.net/a/1.json", Some(collection.clone())),
+            item("REAL", "https://arweave.net/a/2.json", Some(collection.clone())),
+            item("REAL", "https://arweave.net/a/3.json", Some(collection.clone())),
+            item("FAKE", "https://arweave.net/a/4.json", Some(collection.clone())),
+            item("REAL", "https://example.com/5.json", Some(collection)),
+            item("REAL", "https://arweave.net/a/6.json", None),
+            item("REAL", "https://arweave.net/a/7.json", Some(unverified)),
+        ];
+        assert_eq!(flagged(&items), [3, 4, 5, 6]);
+        let kept = quarantine_spoofs(items.to_vec());
+        assert_eq!(kept.len(), 3);
+    }
+
+    #[test]
+    fn ties_have_no_majority_to_differ_from() {
+        let items = [
+            item("ONE", "https://arweave.net/a/1.json", None),
+            item("TWO", "https://arweave.net/b/2.json", None),
+        ];
+        assert!(flagged(&items).is_empty());
+    }
+
+    #[test]
+    fn a_single_item_is_its_own_majority() {
+        let items = [item("ONE", "https://arweave.net/a/1.json", None)];
+        assert!(flagged(&items).is_empty());
+    }
+
+    #[test]
+    fn spoofs_in_the_majority_flag_the_genuine_items() {
+        // Flagging only compares items with each other, so a source letting
+        // in more spoofs than genuine items turns the result around.
+        let items = [
+            item("REAL", "https://arweave.net/a/1.json", None),
+            item("FAKE", "https://example.com/x/1.json", None),
+            item("FAKE", "https://example.com/x/2.json", None),
+        ];
+        assert_eq!(flagged(&items), [0]);
+        let reasons = &find_spoofs(&items)[0].reasons;
+        assert_eq!(reasons.len(), 2);
+    }
+}
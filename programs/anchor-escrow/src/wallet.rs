@@ -0,0 +1,271 @@
+use {
+    crate::{
+        alt_metadata::{resolve_alt_metadata, MetadataSource, TOKEN_2022_PROGRAM_ID},
+        enrich::get_multiple_accounts_chunked,
+        metadata::parse_metadata,
+        rpc::RpcBackend,
+        snapshot::EmptyAccount,
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::Result,
+    log::error,
+    mpl_token_metadata::{state::Metadata, ID as TOKEN_METADATA_PROGRAM_ID},
+    serde::Serialize,
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+    },
+    solana_sdk::{instruction::Instruction, program_pack::Pack},
+    spl_token::{
+        state::{Account as TokenAccount, AccountState, Mint},
+        ID as TOKEN_PROGRAM_ID,
+    },
+    std::{collections::BTreeMap, str::FromStr},
+};
+
+/// Token account: mint, then the owner.
+const TOKEN_ACCOUNT_OWNER_OFFSET: usize = 32;
+
+/// Close instructions per transaction, well within the size limit.
+pub const CLOSES_PER_TRANSACTION: usize = 20;
+
+#[derive(Debug, Serialize)]
+pub struct WalletNft {
+    pub mint_account: String,
+    pub token_account: String,
+    pub metadata_account: String,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub frozen: bool,
+    /// Set for items without a Metaplex metadata account, whose
+    /// `metadata_account` is then where the metadata was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata_source: Option<MetadataSource>,
+}
+
+/// What a group of items has in common, strongest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupedBy {
+    /// The verified collection NFT.
+    Collection,
+    /// The first verified creator, for items minted before collections.
+    Creator,
+    /// Nothing verified, so the items could come from anyone.
+    Unverified,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionGroup {
+    pub grouped_by: GroupedBy,
+    /// Collection mint or creator; empty for unverified items.
+    pub key: String,
+    pub items: Vec<WalletNft>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletAudit {
+    pub wallet: String,
+    pub nfts: usize,
+    /// Collections, then creators, then unverified items; largest first.
+    pub collections: Vec<CollectionGroup>,
+}
+
+fn trimmed(s: &str) -> String {
+    s.trim_matches(char::from(0)).trim().to_string()
+}
+
+pub(crate) fn metadata_address(mint: &Pubkey) -> Pubkey {
+    let seeds = &[
+        b"metadata".as_ref(),
+        TOKEN_METADATA_PROGRAM_ID.as_ref(),
+        mint.as_ref(),
+    ];
+    Pubkey::find_program_address(seeds, &TOKEN_METADATA_PROGRAM_ID).0
+}
+
+fn group_of(metadata: &Metadata) -> (GroupedBy, String) {
+    if let Some(collection) = metadata.collection.as_ref().filter(|c| c.verified) {
+        return (GroupedBy::Collection, collection.key.to_string());
+    }
+    let creator = metadata
+        .data
+        .creators
+        .as_ref()
+        .and_then(|creators| creators.iter().find(|creator| creator.verified));
+    match creator {
+        Some(creator) => (GroupedBy::Creator, creator.address.to_string()),
+        None => (GroupedBy::Unverified, String::new()),
+    }
+}
+
+/// Zero decimals, for token program and Token-2022 mints alike.
+fn is_nft_mint(data: &[u8]) -> bool {
+    data.get(..Mint::LEN)
+        .and_then(|data| Mint::unpack(data).ok())
+        .map_or(false, |mint| mint.decimals == 0)
+}
+
+/// Token accounts of `wallet` under `program_id`, the token program or
+/// Token-2022. Token-2022 accounts carry extensions after the fields they
+/// share with token program accounts, so only those are decoded.
+fn get_wallet_token_accounts(
+    client: &dyn RpcBackend,
+    program_id: &Pubkey,
+    wallet: &Pubkey,
+) -> Result<Vec<(Pubkey, TokenAccount)>> {
+    let mut filters = vec![RpcFilterType::Memcmp(Memcmp {
+        offset: TOKEN_ACCOUNT_OWNER_OFFSET,
+        bytes: MemcmpEncodedBytes::Base58(wallet.to_string()),
+        encoding: None,
+    })];
+    if *program_id == TOKEN_PROGRAM_ID {
+        filters.push(RpcFilterType::DataSize(TokenAccount::LEN as u64));
+    }
+    let account_config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        data_slice: None,
+        // The backend's, i.e. `rpc.commitment`.
+        commitment: None,
+    };
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config,
+        with_context: None,
+    };
+
+    let accounts = client.get_program_accounts_with_config(program_id, config)?;
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(pubkey, account)| {
+            let data = account.data.get(..TokenAccount::LEN).unwrap_or_default();
+            match TokenAccount::unpack(data) {
+                Ok(token_account) => Some((pubkey, token_account)),
+                Err(err) => {
+                    error!("Account {} has no data: {}", pubkey, err);
+                    None
+                }
+            }
+        })
+        .collect())
+}
+
+/// Every NFT `wallet` holds, from any collection, grouped by verified
+/// collection or else first verified creator. Works from the wallet's token
+/// accounts, so any RPC endpoint will do: a token counts as an NFT when the
+/// wallet holds one of a zero decimal mint that has metadata. Token-2022
+/// items and items without a Metaplex metadata account are included when
+/// a metadata pointer or inscription has their metadata; they are never
+/// verified.
+pub fn audit_wallet(client: &dyn RpcBackend, wallet: &Pubkey) -> Result<WalletAudit> {
+    let token_2022 = Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?;
+    let mut token_accounts = get_wallet_token_accounts(client, &TOKEN_PROGRAM_ID, wallet)?;
+    token_accounts.extend(get_wallet_token_accounts(client, &token_2022, wallet)?);
+    let held: Vec<(Pubkey, TokenAccount)> = token_accounts
+        .into_iter()
+        .filter(|(_, token_account)| token_account.amount == 1)
+        .collect();
+
+    let mints: Vec<Pubkey> = held.iter().map(|(_, token_account)| token_account.mint).collect();
+    let metadata_pubkeys: Vec<Pubkey> = mints.iter().map(metadata_address).collect();
+    let mint_data = get_multiple_accounts_chunked(client, &mints)?;
+    let metadata_data = get_multiple_accounts_chunked(client, &metadata_pubkeys)?;
+
+    let mut groups: BTreeMap<(GroupedBy, String), Vec<WalletNft>> = BTreeMap::new();
+    let mut without_metadata = Vec::new();
+    let entries = held.iter().zip(metadata_pubkeys).zip(mint_data.into_iter().zip(metadata_data));
+    for (((token_pubkey, token_account), metadata_pubkey), (mint, metadata)) in entries {
+        let mint = match mint {
+            Some(data) if is_nft_mint(&data) => data,
+            _ => continue,
+        };
+        let metadata = match metadata {
+            Some(data) => parse_metadata(&data),
+            None => {
+                without_metadata.push((token_pubkey, token_account, mint));
+                continue;
+            }
+        };
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                error!("Account {} has no metadata", metadata_pubkey);
+                continue;
+            }
+        };
+
+        groups.entry(group_of(&metadata)).or_default().push(WalletNft {
+            mint_account: token_account.mint.to_string(),
+            token_account: token_pubkey.to_string(),
+            metadata_account: metadata_pubkey.to_string(),
+            name: trimmed(&metadata.data.name),
+            symbol: trimmed(&metadata.data.symbol),
+            uri: trimmed(&metadata.data.uri),
+            frozen: token_account.state == AccountState::Frozen,
+            metadata_source: None,
+        });
+    }
+
+    let mints: Vec<(Pubkey, &[u8])> = without_metadata
+        .iter()
+        .map(|(_, token_account, data)| (token_account.mint, data.as_slice()))
+        .collect();
+    let resolved = resolve_alt_metadata(client, &mints)?;
+    for ((token_pubkey, token_account, _), metadata) in without_metadata.iter().zip(resolved) {
+        if let Some(metadata) = metadata {
+            let key = (GroupedBy::Unverified, String::new());
+            groups.entry(key).or_default().push(WalletNft {
+                mint_account: token_account.mint.to_string(),
+                token_account: token_pubkey.to_string(),
+                metadata_account: metadata.address.to_string(),
+                name: metadata.name,
+                symbol: metadata.symbol,
+                uri: metadata.uri,
+                frozen: token_account.state == AccountState::Frozen,
+                metadata_source: Some(metadata.source),
+            });
+        }
+    }
+
+    let mut collections: Vec<CollectionGroup> = groups
+        .into_iter()
+        .map(|((grouped_by, key), items)| CollectionGroup {
+            grouped_by,
+            key,
+            items,
+        })
+        .collect();
+    collections.sort_by(|a, b| (a.grouped_by, b.items.len()).cmp(&(b.grouped_by, a.items.len())));
+
+    Ok(WalletAudit {
+        wallet: wallet.to_string(),
+        nfts: collections.iter().map(|group| group.items.len()).sum(),
+        collections,
+    })
+}
+
+/// Instructions closing the accounts of `empty` owned by `owner`, returning
+/// the rent to `owner`. Accounts that received a token since the snapshot
+/// fail to close, failing their transaction.
+pub fn close_empty_instructions(
+    empty: &[EmptyAccount],
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let owner_wallet = owner.to_string();
+    empty
+        .iter()
+        .filter(|account| account.owner_wallet == owner_wallet)
+        .map(|account| {
+            let token_account = Pubkey::from_str(&account.token_account)?;
+            Ok(spl_token::instruction::close_account(
+                &TOKEN_PROGRAM_ID,
+                &token_account,
+                owner,
+                owner,
+                &[],
+            )?)
+        })
+        .collect()
+}
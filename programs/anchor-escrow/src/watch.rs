@@ -0,0 +1,221 @@
+use {
+    crate::{
+        config::Config,
+        rpc::RpcBackend,
+        wallet::{audit_wallet, GroupedBy, WalletAudit},
+    },
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, Result},
+    log::{error, info},
+    reqwest::{blocking::Client, header::CONTENT_TYPE},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        fs,
+        str::FromStr,
+        thread,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// Wallets monitored by `wallet watch`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    pub wallets: Vec<String>,
+    /// Verified collection mints or first creators to report on; every
+    /// verified item when empty.
+    pub collections: Vec<String>,
+    pub interval_secs: u64,
+    /// Every poll's changes are POSTed here as a JSON array.
+    pub webhook: Option<String>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            wallets: Vec::new(),
+            collections: Vec::new(),
+            interval_secs: 60,
+            webhook: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Acquired,
+    Disposed,
+}
+
+/// An item of a tracked collection entering or leaving a watched wallet.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletChange {
+    pub wallet: String,
+    pub kind: ChangeKind,
+    pub collection: String,
+    pub mint_account: String,
+    pub name: String,
+}
+
+/// Mint to collection and name of the tracked items in `audit`.
+type TrackedItems = BTreeMap<String, (String, String)>;
+
+fn tracked_items(audit: &WalletAudit, collections: &HashSet<String>) -> TrackedItems {
+    audit
+        .collections
+        .iter()
+        .filter(|group| group.grouped_by != GroupedBy::Unverified)
+        .filter(|group| collections.is_empty() || collections.contains(&group.key))
+        .flat_map(|group| {
+            group.items.iter().map(move |item| {
+                (item.mint_account.clone(), (group.key.clone(), item.name.clone()))
+            })
+        })
+        .collect()
+}
+
+fn diff_items(wallet: &Pubkey, before: &TrackedItems, after: &TrackedItems) -> Vec<WalletChange> {
+    let change = |kind, mint: &String, (collection, name): &(String, String)| WalletChange {
+        wallet: wallet.to_string(),
+        kind,
+        collection: collection.clone(),
+        mint_account: mint.clone(),
+        name: name.clone(),
+    };
+    let acquired = after
+        .iter()
+        .filter(|(mint, _)| !before.contains_key(*mint))
+        .map(|(mint, item)| change(ChangeKind::Acquired, mint, item));
+    let disposed = before
+        .iter()
+        .filter(|(mint, _)| !after.contains_key(*mint))
+        .map(|(mint, item)| change(ChangeKind::Disposed, mint, item));
+    acquired.chain(disposed).collect()
+}
+
+/// Audits the watched wallets on every poll and reports what changed since
+/// the previous one. The first poll only records what the wallets hold.
+pub struct Watcher<'a> {
+    client: &'a dyn RpcBackend,
+    wallets: Vec<Pubkey>,
+    collections: HashSet<String>,
+    held: HashMap<Pubkey, TrackedItems>,
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(client: &'a dyn RpcBackend, config: &WatchConfig) -> Result<Self> {
+        if config.wallets.is_empty() {
+            return Err(anyhow!("No wallets to watch, set watch.wallets!"));
+        }
+        let wallets = config
+            .wallets
+            .iter()
+            .map(|wallet| Pubkey::from_str(wallet))
+            .collect::<Result<_, _>>()?;
+        Ok(Watcher {
+            client,
+            wallets,
+            collections: config.collections.iter().cloned().collect(),
+            held: HashMap::new(),
+        })
+    }
+
+    /// Wallets that cannot be audited are logged and compared again on the
+    /// next poll that reaches them.
+    pub fn poll(&mut self) -> Vec<WalletChange> {
+        let mut changes = Vec::new();
+        for wallet in &self.wallets {
+            let audit = match audit_wallet(self.client, wallet) {
+                Ok(audit) => audit,
+                Err(err) => {
+                    error!("Wallet {} could not be audited: {}", wallet, err);
+                    continue;
+                }
+            };
+            let items = tracked_items(&audit, &self.collections);
+            if let Some(before) = self.held.get(wallet) {
+                changes.extend(diff_items(wallet, before, &items));
+            }
+            self.held.insert(*wallet, items);
+        }
+        changes
+    }
+
+    /// Switches to the wallets and collections of `config` between polls.
+    /// Wallets still watched keep what they were last seen holding, so
+    /// nothing is missed or reported twice; if the collections changed,
+    /// every wallet is recorded afresh on the next poll instead.
+    pub fn reconfigure(&mut self, config: &WatchConfig) -> Result<()> {
+        let updated = Watcher::new(self.client, config)?;
+        if updated.collections != self.collections {
+            self.held.clear();
+        }
+        let wallets = updated.wallets;
+        self.held.retain(|wallet, _| wallets.contains(wallet));
+        self.wallets = wallets;
+        self.collections = updated.collections;
+        Ok(())
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Polls the watched wallets every `interval_secs` until stopped, passing
+/// the changes of each poll to `on_changes` and the webhook. Webhook
+/// failures are logged; the changes are not sent again.
+///
+/// When the config file at `config_path` changes, its `[watch]` section is
+/// applied before the next poll without losing what the wallets were seen
+/// holding. A file that no longer loads is logged and the previous watch
+/// config kept. Other sections, e.g. the RPC endpoint, are read at start
+/// only.
+pub fn watch(
+    client: &dyn RpcBackend,
+    config: &WatchConfig,
+    config_path: Option<&str>,
+    mut on_changes: impl FnMut(&[WalletChange]),
+) -> Result<()> {
+    let mut watcher = Watcher::new(client, config)?;
+    let mut config = config.clone();
+    let mut loaded_at = config_path.and_then(modified);
+    let http = Client::new();
+    loop {
+        if let Some(path) = config_path {
+            let modified_at = modified(path);
+            if modified_at != loaded_at {
+                loaded_at = modified_at;
+                let reloaded = Config::load(path).and_then(|reloaded| {
+                    watcher.reconfigure(&reloaded.watch)?;
+                    Ok(reloaded.watch)
+                });
+                match reloaded {
+                    Ok(reloaded) => {
+                        info!("Reloaded the watch config from {}", path);
+                        config = reloaded;
+                    }
+                    Err(err) => error!("Config {} could not be reloaded: {}", path, err),
+                }
+            }
+        }
+
+        let changes = watcher.poll();
+        if !changes.is_empty() {
+            on_changes(&changes);
+            if let Some(webhook) = &config.webhook {
+                let response = http
+                    .post(webhook)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_vec(&changes)?)
+                    .send();
+                if let Err(err) = response.and_then(|response| response.error_for_status()) {
+                    error!("Webhook {} failed: {}", webhook, err);
+                }
+            }
+        }
+        thread::sleep(Duration::from_secs(config.interval_secs));
+    }
+}
@@ -0,0 +1,208 @@
+// Each integration test binary uses a different subset of these helpers.
+#![allow(dead_code)]
+
+use {
+    anchor_escrow::snapshot::derive_cmv2_pda,
+    anchor_lang::{prelude::Pubkey, AnchorSerialize},
+    mpl_token_metadata::{
+        state::{
+            Creator, Data, Key, Metadata, MAX_METADATA_LEN, MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH,
+            MAX_URI_LENGTH,
+        },
+        ID as TOKEN_METADATA_PROGRAM_ID,
+    },
+    solana_sdk::{
+        account::{Account, AccountSharedData},
+        program_option::COption,
+        program_pack::Pack,
+        rent::Rent,
+        signature::{Keypair, Signer},
+    },
+    solana_test_validator::{TestValidator, TestValidatorGenesis},
+    spl_token::state::{Account as TokenAccount, AccountState, Mint},
+};
+
+pub struct MockItem {
+    pub mint: Pubkey,
+    pub metadata: Pubkey,
+    pub token_account: Pubkey,
+    pub owner: Pubkey,
+}
+
+pub struct MockCollection {
+    pub candy_machine: Pubkey,
+    pub creator: Pubkey,
+    pub update_authority: Pubkey,
+    pub items: Vec<MockItem>,
+}
+
+pub fn metadata_address(mint: &Pubkey) -> Pubkey {
+    let seeds = &[
+        b"metadata".as_ref(),
+        TOKEN_METADATA_PROGRAM_ID.as_ref(),
+        mint.as_ref(),
+    ];
+    Pubkey::find_program_address(seeds, &TOKEN_METADATA_PROGRAM_ID).0
+}
+
+fn rent_exempt(owner: Pubkey, data: Vec<u8>) -> AccountSharedData {
+    AccountSharedData::from(Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    })
+}
+
+pub fn mint_account_data(authority: &Pubkey, supply: u64, decimals: u8) -> AccountSharedData {
+    let mut data = vec![0; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::Some(*authority),
+            supply,
+            decimals,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut data,
+    )
+    .unwrap();
+    rent_exempt(spl_token::ID, data)
+}
+
+pub fn token_account_data(mint: &Pubkey, owner: &Pubkey, amount: u64) -> AccountSharedData {
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    rent_exempt(spl_token::ID, data)
+}
+
+// Strings are padded to their max length like the token metadata program
+// does, otherwise the fixed memcmp offsets used by the snapshot miss them.
+pub fn metadata_account_data(
+    update_authority: &Pubkey,
+    mint: &Pubkey,
+    creator: &Pubkey,
+    index: usize,
+) -> AccountSharedData {
+    let pad = |s: String, len: usize| format!("{:\0<width$}", s, width = len);
+    let metadata = Metadata {
+        key: Key::MetadataV1,
+        update_authority: *update_authority,
+        mint: *mint,
+        data: Data {
+            name: pad(format!("Mock #{}", index), MAX_NAME_LENGTH),
+            symbol: pad("MOCK".to_string(), MAX_SYMBOL_LENGTH),
+            uri: pad(format!("https://example.com/{}.json", index), MAX_URI_LENGTH),
+            seller_fee_basis_points: 500,
+            creators: Some(vec![Creator {
+                address: *creator,
+                verified: true,
+                share: 100,
+            }]),
+        },
+        primary_sale_happened: true,
+        is_mutable: true,
+        edition_nonce: None,
+        token_standard: None,
+        collection: None,
+        uses: None,
+    };
+    let mut data = metadata.try_to_vec().unwrap();
+    data.resize(MAX_METADATA_LEN, 0);
+    rent_exempt(TOKEN_METADATA_PROGRAM_ID, data)
+}
+
+/// Metadata as left by Metaplex's resize: unpadded strings and no trailing
+/// space, so the creators sit before the fixed memcmp offsets.
+pub fn trimmed_metadata_account_data(
+    update_authority: &Pubkey,
+    mint: &Pubkey,
+    creator: &Pubkey,
+    index: usize,
+) -> AccountSharedData {
+    let metadata = Metadata {
+        key: Key::MetadataV1,
+        update_authority: *update_authority,
+        mint: *mint,
+        data: Data {
+            name: format!("Mock #{}", index),
+            symbol: "MOCK".to_string(),
+            uri: format!("https://example.com/{}.json", index),
+            seller_fee_basis_points: 500,
+            creators: Some(vec![Creator {
+                address: *creator,
+                verified: true,
+                share: 100,
+            }]),
+        },
+        primary_sale_happened: true,
+        is_mutable: true,
+        edition_nonce: None,
+        token_standard: None,
+        collection: None,
+        uses: None,
+    };
+    rent_exempt(TOKEN_METADATA_PROGRAM_ID, metadata.try_to_vec().unwrap())
+}
+
+/// Adds a candy machine v2 style collection of `size` items, each held by a
+/// fresh wallet, directly to the genesis accounts.
+pub fn mock_collection(genesis: &mut TestValidatorGenesis, size: usize) -> MockCollection {
+    let candy_machine = Keypair::new().pubkey();
+    let creator = derive_cmv2_pda(&candy_machine);
+    let update_authority = Keypair::new().pubkey();
+
+    let items = (0..size)
+        .map(|index| {
+            let mint = Keypair::new().pubkey();
+            let owner = Keypair::new().pubkey();
+            let token_account = Keypair::new().pubkey();
+            let metadata = metadata_address(&mint);
+
+            genesis.add_account(mint, mint_account_data(&update_authority, 1, 0));
+            genesis.add_account(token_account, token_account_data(&mint, &owner, 1));
+            genesis.add_account(
+                metadata,
+                metadata_account_data(&update_authority, &mint, &creator, index),
+            );
+
+            MockItem {
+                mint,
+                metadata,
+                token_account,
+                owner,
+            }
+        })
+        .collect();
+
+    MockCollection {
+        candy_machine,
+        creator,
+        update_authority,
+        items,
+    }
+}
+
+/// Genesis with the escrow program loaded. The program is looked up as
+/// `anchor_escrow.so` in `BPF_OUT_DIR`, so run `anchor build` first and point
+/// it at `target/deploy`.
+pub fn genesis() -> TestValidatorGenesis {
+    let mut genesis = TestValidatorGenesis::default();
+    genesis.add_program("anchor_escrow", anchor_escrow::ID);
+    genesis
+}
+
+pub fn start(genesis: TestValidatorGenesis) -> (TestValidator, Keypair) {
+    genesis.start()
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use {
+    anchor_escrow::{client::ProgramClient, snapshot::get_nftholders},
+    common::{genesis, mint_account_data, mock_collection, start, token_account_data},
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::{
+        account::AccountSharedData,
+        commitment_config::CommitmentConfig,
+        program_pack::Pack,
+        signature::{Keypair, Signer},
+        system_program,
+    },
+    spl_token::state::Account as TokenAccount,
+    std::collections::HashSet,
+};
+
+#[test]
+fn spin_and_transfer_rewards() {
+    let admin = Keypair::new();
+    let reward_mint = Keypair::new().pubkey();
+    let reward_account = Keypair::new().pubkey();
+    let dest_account = Keypair::new().pubkey();
+
+    let mut genesis = genesis();
+    genesis.add_account(
+        admin.pubkey(),
+        AccountSharedData::new(1_000_000_000, 0, &system_program::id()),
+    );
+    genesis.add_account(reward_mint, mint_account_data(&admin.pubkey(), 100, 0));
+    genesis.add_account(reward_account, token_account_data(&reward_mint, &admin.pubkey(), 100));
+    genesis.add_account(dest_account, token_account_data(&reward_mint, &admin.pubkey(), 0));
+    let (validator, payer) = start(genesis);
+
+    let rpc = RpcClient::new_with_commitment(validator.rpc_url(), CommitmentConfig::confirmed());
+    let client = ProgramClient::new(rpc, Box::new(payer));
+
+    client.initialize(&admin).unwrap();
+
+    // A single item with the whole ratio, so the spin can only land on it.
+    let (token_vault, _) = client
+        .set_item(&admin, &reward_mint, &reward_account, 0, 100, 2)
+        .unwrap();
+    client.spin_wheel().unwrap();
+    client
+        .transfer_rewards(&admin, &reward_mint, &token_vault, &dest_account, 0)
+        .unwrap();
+
+    let dest = client.rpc.get_account(&dest_account).unwrap();
+    let dest = TokenAccount::unpack(&dest.data).unwrap();
+    assert_eq!(dest.amount, 2);
+}
+
+#[test]
+fn snapshot_mock_collection() {
+    let mut genesis = genesis();
+    let collection = mock_collection(&mut genesis, 5);
+    let (validator, _) = start(genesis);
+    let rpc = RpcClient::new_with_commitment(validator.rpc_url(), CommitmentConfig::confirmed());
+
+    let holders = get_nftholders(
+        &rpc,
+        &None,
+        &Some(collection.candy_machine.to_string()),
+        0,
+        true,
+    )
+    .unwrap();
+
+    let found: HashSet<(String, String)> = holders
+        .holders
+        .into_iter()
+        .map(|h| (h.mint_account, h.owner_wallet))
+        .collect();
+    let expected: HashSet<(String, String)> = collection
+        .items
+        .iter()
+        .map(|item| (item.mint.to_string(), item.owner.to_string()))
+        .collect();
+    assert_eq!(found, expected);
+}
@@ -0,0 +1,273 @@
+mod common;
+
+use {
+    anchor_escrow::{
+        alt_metadata::TOKEN_2022_PROGRAM_ID,
+        rpc::MockRpc,
+        snapshot::{
+            derive_cmv2_pda, get_cm_creator_accounts, get_nftholders, get_source_accounts,
+            stream_nftholders, PipelineConfig, SnapshotError, Source,
+        },
+    },
+    anchor_lang::prelude::Pubkey,
+    common::{
+        metadata_account_data, metadata_address, mint_account_data, token_account_data,
+        trimmed_metadata_account_data,
+    },
+    solana_sdk::{
+        account::Account,
+        signature::{Keypair, Signer},
+    },
+    std::str::FromStr,
+};
+
+struct Item {
+    mint: Pubkey,
+    owner: Pubkey,
+}
+
+fn add_item(rpc: &mut MockRpc, creator: &Pubkey, index: usize) -> Item {
+    let mint = Keypair::new().pubkey();
+    let owner = Keypair::new().pubkey();
+    let metadata = metadata_address(&mint);
+
+    rpc.add_account(mint, Account::from(mint_account_data(&owner, 1, 0)));
+    rpc.add_account(
+        metadata,
+        Account::from(metadata_account_data(&owner, &mint, creator, index)),
+    );
+    rpc.add_account(
+        Keypair::new().pubkey(),
+        Account::from(token_account_data(&mint, &owner, 1)),
+    );
+
+    Item { mint, owner }
+}
+
+fn snapshot(rpc: &MockRpc, candy_machine: &Pubkey) -> Vec<(String, String)> {
+    let mut holders: Vec<(String, String)> =
+        get_nftholders(rpc, &None, &Some(candy_machine.to_string()), 0, true)
+            .unwrap()
+            .holders
+            .into_iter()
+            .map(|h| (h.mint_account, h.owner_wallet))
+            .collect();
+    holders.sort();
+    holders
+}
+
+#[test]
+fn resolves_current_holders() {
+    let candy_machine = Keypair::new().pubkey();
+    let creator = derive_cmv2_pda(&candy_machine);
+
+    let mut rpc = MockRpc::new();
+    let mut expected: Vec<(String, String)> = (0..3)
+        .map(|i| add_item(&mut rpc, &creator, i))
+        .map(|item| (item.mint.to_string(), item.owner.to_string()))
+        .collect();
+    expected.sort();
+
+    // Items from another candy machine must not leak into the snapshot.
+    add_item(&mut rpc, &derive_cmv2_pda(&Keypair::new().pubkey()), 3);
+
+    assert_eq!(snapshot(&rpc, &candy_machine), expected);
+}
+
+#[test]
+fn skips_emptied_token_accounts() {
+    let candy_machine = Keypair::new().pubkey();
+    let creator = derive_cmv2_pda(&candy_machine);
+
+    let mut rpc = MockRpc::new();
+    let item = add_item(&mut rpc, &creator, 0);
+    // The previous holder keeps an empty token account after transferring.
+    rpc.add_account(
+        Keypair::new().pubkey(),
+        Account::from(token_account_data(&item.mint, &Keypair::new().pubkey(), 0)),
+    );
+
+    assert_eq!(
+        snapshot(&rpc, &candy_machine),
+        vec![(item.mint.to_string(), item.owner.to_string())]
+    );
+}
+
+#[test]
+fn counts_only_items_without_supply_as_burned() {
+    let candy_machine = Keypair::new().pubkey();
+    let creator = derive_cmv2_pda(&candy_machine);
+    let authority = Keypair::new().pubkey();
+
+    let mut rpc = MockRpc::new();
+    add_item(&mut rpc, &creator, 0);
+    // Burned: the supply is gone along with the token account.
+    let burned = Keypair::new().pubkey();
+    rpc.add_account(burned, Account::from(mint_account_data(&authority, 0, 0)));
+    rpc.add_account(
+        metadata_address(&burned),
+        Account::from(metadata_account_data(&authority, &burned, &creator, 1)),
+    );
+    // Still minted, but its token account is nowhere to be found.
+    let missed = Keypair::new().pubkey();
+    rpc.add_account(missed, Account::from(mint_account_data(&authority, 1, 0)));
+    rpc.add_account(
+        metadata_address(&missed),
+        Account::from(metadata_account_data(&authority, &missed, &creator, 2)),
+    );
+
+    let result = get_nftholders(&rpc, &None, &Some(candy_machine.to_string()), 0, true).unwrap();
+    assert_eq!(result.holders.len(), 1);
+    assert_eq!(result.skipped_burned, 1);
+    assert_eq!(result.failed.len(), 1);
+    assert_eq!(result.failed[0].mint_account, missed.to_string());
+}
+
+#[test]
+fn reports_where_creator_is_listed() {
+    let candy_machine = Keypair::new().pubkey();
+    let creator = derive_cmv2_pda(&candy_machine);
+
+    let mut rpc = MockRpc::new();
+    add_item(&mut rpc, &creator, 0);
+
+    let err = get_cm_creator_accounts(&rpc, &creator.to_string(), 1).unwrap_err();
+    match err.downcast_ref::<SnapshotError>() {
+        Some(SnapshotError::CreatorNotFound { found_at, .. }) => assert_eq!(found_at, &vec![0]),
+        other => panic!("unexpected error {:?}", other),
+    }
+
+    let err = get_cm_creator_accounts(&rpc, &creator.to_string(), 5).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SnapshotError>(),
+        Some(SnapshotError::InvalidCreatorPosition { position: 5 })
+    ));
+}
+
+#[test]
+fn finds_resized_metadata_with_fallback() {
+    let creator = Keypair::new().pubkey();
+    let update_authority = Keypair::new().pubkey();
+
+    let mut rpc = MockRpc::new();
+    let (padded, trimmed) = (Keypair::new().pubkey(), Keypair::new().pubkey());
+    rpc.add_account(
+        metadata_address(&padded),
+        Account::from(metadata_account_data(&update_authority, &padded, &creator, 0)),
+    );
+    rpc.add_account(
+        metadata_address(&trimmed),
+        Account::from(trimmed_metadata_account_data(&update_authority, &trimmed, &creator, 1)),
+    );
+    // Another collection of the same update authority must stay out.
+    let other = Keypair::new().pubkey();
+    rpc.add_account(
+        metadata_address(&other),
+        Account::from(trimmed_metadata_account_data(
+            &update_authority,
+            &other,
+            &Keypair::new().pubkey(),
+            2,
+        )),
+    );
+
+    let source = Source::Creator {
+        address: creator.to_string(),
+        position: 0,
+        update_authority: None,
+    };
+    let found = |resized_fallback| {
+        let mut found: Vec<Pubkey> = get_source_accounts(&rpc, &source, resized_fallback)
+            .unwrap()
+            .into_iter()
+            .map(|(pubkey, _)| pubkey)
+            .collect();
+        found.sort();
+        found
+    };
+
+    assert_eq!(found(false), vec![metadata_address(&padded)]);
+    let mut expected = vec![metadata_address(&padded), metadata_address(&trimmed)];
+    expected.sort();
+    assert_eq!(found(true), expected);
+}
+
+/// A Token-2022 mint whose metadata pointer leads to its own token metadata
+/// extension.
+fn token_2022_mint_data(mint: &Pubkey, name: &str, uri: &str) -> Vec<u8> {
+    let mut data = Account::from(mint_account_data(&Pubkey::new_unique(), 1, 0)).data;
+    data.resize(165, 0);
+    // Account type: mint.
+    data.push(1);
+
+    let mut pointer = vec![0; 32];
+    pointer.extend_from_slice(mint.as_ref());
+    let mut metadata = vec![0; 32];
+    metadata.extend_from_slice(mint.as_ref());
+    for field in &[name, "TKN", uri] {
+        metadata.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        metadata.extend_from_slice(field.as_bytes());
+    }
+    for (kind, value) in &[(18u16, pointer), (19u16, metadata)] {
+        data.extend_from_slice(&kind.to_le_bytes());
+        data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        data.extend_from_slice(value);
+    }
+    data
+}
+
+#[test]
+fn snapshots_items_given_by_mint() {
+    let token_2022 = Pubkey::from_str(TOKEN_2022_PROGRAM_ID).unwrap();
+    let mut rpc = MockRpc::new();
+    let account = |owner: Pubkey, data: Vec<u8>| Account {
+        lamports: 1,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    };
+
+    // A Metaplex item, found like any other.
+    let creator = Keypair::new().pubkey();
+    let item = add_item(&mut rpc, &creator, 0);
+
+    // A Token-2022 item, whose token account carries an extension too.
+    let (mint, owner) = (Keypair::new().pubkey(), Keypair::new().pubkey());
+    let mint_data = token_2022_mint_data(&mint, "Pointed", "uri");
+    rpc.add_account(mint, account(token_2022, mint_data));
+    let mut token_account = Account::from(token_account_data(&mint, &owner, 1)).data;
+    // Account type: account, then the immutable owner extension.
+    token_account.extend_from_slice(&[2, 7, 0, 0, 0]);
+    rpc.add_account(Keypair::new().pubkey(), account(token_2022, token_account));
+
+    // A mint without any metadata is left out.
+    let bare = Keypair::new().pubkey();
+    rpc.add_account(bare, Account::from(mint_account_data(&owner, 1, 0)));
+
+    let source = Source::Mints {
+        mints: vec![item.mint.to_string(), mint.to_string(), bare.to_string()],
+    };
+    let mut holders = Vec::new();
+    stream_nftholders(&rpc, &source, &PipelineConfig::default(), |holder| {
+        holders.push((
+            holder.mint_account,
+            holder.owner_wallet,
+            holder.metadata_account,
+        ));
+        Ok(())
+    })
+    .unwrap();
+    holders.sort();
+
+    let mut expected = vec![
+        (
+            item.mint.to_string(),
+            item.owner.to_string(),
+            metadata_address(&item.mint).to_string(),
+        ),
+        (mint.to_string(), owner.to_string(), mint.to_string()),
+    ];
+    expected.sort();
+    assert_eq!(holders, expected);
+}